@@ -0,0 +1,1110 @@
+use std::collections::HashMap;
+use ast::*;
+use diagnostic::Diagnostic;
+use interpreter::last_ordinal;
+use options::BooleanEval;
+use parser::Category;
+use spec::Token;
+use types;
+
+// runtime support copied at the top of every generated file, it does what
+// the prelude of `cgen` does with the C library, errors and output follow
+// `pascalzim run`
+const PRELUDE: &str = r#"	.text
+# message in %rdi and line in %esi, the stack may be unaligned here
+pz_error:
+	andq $-16, %rsp
+	pushq %rdi
+	pushq %rsi
+	xorl %edi, %edi
+	call fflush@PLT
+	popq %rcx
+	popq %rdx
+	movl $2, %edi
+	leaq pz_error_format(%rip), %rsi
+	xorl %eax, %eax
+	call dprintf@PLT
+	movl $3, %edi
+	call exit@PLT
+
+# %edi divided by %esi with the line in %edx, leaves the quotient in %eax
+# and the remainder in %edx
+pz_div:
+	testl %esi, %esi
+	jz 8f
+	cmpl $-1, %esi
+	jne 1f
+	cmpl $-2147483648, %edi
+	je 9f
+1:	movl %edi, %eax
+	cltd
+	idivl %esi
+	ret
+8:	leaq pz_division(%rip), %rdi
+	movl %edx, %esi
+	jmp pz_error
+9:	leaq pz_overflow(%rip), %rdi
+	movl %edx, %esi
+	jmp pz_error
+
+pz_mod:
+	call pz_div
+	movl %edx, %eax
+	ret
+
+# %edi to the power %esi, line in %edx
+pz_pow:
+	movl $1, %eax
+	testl %esi, %esi
+	js 9f
+	jz 3f
+1:	cmpl $1, %esi
+	jle 2f
+	testl $1, %esi
+	jz 4f
+	imull %edi, %eax
+	jo 9f
+4:	sarl %esi
+	imull %edi, %edi
+	jo 9f
+	jmp 1b
+2:	imull %edi, %eax
+	jo 9f
+3:	ret
+9:	leaq pz_overflow(%rip), %rdi
+	movl %edx, %esi
+	jmp pz_error
+
+pz_pow_real:
+	pushq %rbp
+	movq %rsp, %rbp
+	andq $-16, %rsp
+	call pow@PLT
+	leave
+	ret
+
+# sets are bitsets, bit `n` for the ordinal `n`, the ordinals %edi..%esi
+# in %rax, line in %edx
+pz_bits:
+	xorl %eax, %eax
+	cmpl %edi, %esi
+	jl 1f
+	testl %edi, %edi
+	js 9f
+	cmpl $63, %esi
+	jg 9f
+	movl $63, %ecx
+	subl %esi, %ecx
+	addl %edi, %ecx
+	movq $-1, %rax
+	shrq %cl, %rax
+	movl %edi, %ecx
+	shlq %cl, %rax
+1:	ret
+9:	leaq pz_set_range(%rip), %rdi
+	movl %edx, %esi
+	jmp pz_error
+
+# is %edi in the set %rsi
+pz_in:
+	xorl %eax, %eax
+	cmpl $63, %edi
+	ja 1f
+	btq %rdi, %rsi
+	setc %al
+1:	ret
+
+# the show functions are called by `main` with the stack aligned, the name
+# in %rdi
+pz_show_integer:
+	subq $8, %rsp
+	movl %esi, %edx
+	movq %rdi, %rsi
+	leaq pz_integer_format(%rip), %rdi
+	xorl %eax, %eax
+	call printf@PLT
+	addq $8, %rsp
+	ret
+
+pz_show_boolean:
+	subq $8, %rsp
+	leaq pz_true(%rip), %rdx
+	leaq pz_false(%rip), %rax
+	testl %esi, %esi
+	cmovz %rax, %rdx
+	movq %rdi, %rsi
+	leaq pz_text_format(%rip), %rdi
+	xorl %eax, %eax
+	call printf@PLT
+	addq $8, %rsp
+	ret
+
+# names of the values in %rsi, the value in %edx
+pz_show_enum:
+	subq $8, %rsp
+	movslq %edx, %rdx
+	movq (%rsi,%rdx,8), %rdx
+	movq %rdi, %rsi
+	leaq pz_text_format(%rip), %rdi
+	xorl %eax, %eax
+	call printf@PLT
+	addq $8, %rsp
+	ret
+
+# names of the elements in %rsi, 0 for numbers, the set in %rdx
+pz_show_set:
+	pushq %rbx
+	pushq %r12
+	pushq %r13
+	pushq %r14
+	subq $8, %rsp
+	movq %rsi, %r12
+	movq %rdx, %r13
+	xorl %ebx, %ebx
+	movl $1, %r14d
+	movq %rdi, %rsi
+	leaq pz_set_format(%rip), %rdi
+	xorl %eax, %eax
+	call printf@PLT
+1:	btq %rbx, %r13
+	jnc 3f
+	testl %r14d, %r14d
+	jnz 2f
+	leaq pz_comma(%rip), %rdi
+	xorl %eax, %eax
+	call printf@PLT
+2:	xorl %r14d, %r14d
+	testq %r12, %r12
+	jz 4f
+	movq (%r12,%rbx,8), %rsi
+	leaq pz_string_format(%rip), %rdi
+	jmp 5f
+4:	movl %ebx, %esi
+	leaq pz_number_format(%rip), %rdi
+5:	xorl %eax, %eax
+	call printf@PLT
+3:	incl %ebx
+	cmpl $64, %ebx
+	jl 1b
+	leaq pz_set_end(%rip), %rdi
+	xorl %eax, %eax
+	call printf@PLT
+	addq $8, %rsp
+	popq %r14
+	popq %r13
+	popq %r12
+	popq %rbx
+	ret
+
+# shortest digits that read back as the value in %xmm0, without exponent,
+# digits at 0(%rsp), the `%e` text at 32(%rsp) and the value at 64(%rsp)
+pz_show_real:
+	pushq %rbp
+	movq %rsp, %rbp
+	pushq %rbx
+	pushq %r12
+	pushq %r13
+	pushq %r14
+	pushq %r15
+	subq $88, %rsp
+	movq %rdi, %r12
+	movsd %xmm0, 64(%rsp)
+	ucomisd %xmm0, %xmm0
+	jp 7f
+	movq %xmm0, %rax
+	btrq $63, %rax
+	movabsq $0x7ff0000000000000, %rcx
+	cmpq %rcx, %rax
+	je 8f
+	xorl %ebx, %ebx
+1:	leaq 32(%rsp), %rdi
+	movl $32, %esi
+	leaq pz_exponent_format(%rip), %rdx
+	movl %ebx, %ecx
+	movsd 64(%rsp), %xmm0
+	movl $1, %eax
+	call snprintf@PLT
+	cmpl $16, %ebx
+	je 2f
+	leaq 32(%rsp), %rdi
+	xorl %esi, %esi
+	call strtod@PLT
+	ucomisd 64(%rsp), %xmm0
+	jp 6f
+	je 2f
+6:	incl %ebx
+	jmp 1b
+2:	leaq 32(%rsp), %r13
+	xorl %r15d, %r15d
+	cmpb $45, (%r13)
+	jne 3f
+	incq %r13
+	movl $1, %r15d
+3:	xorl %r14d, %r14d
+4:	movzbl (%r13), %eax
+	incq %r13
+	cmpb $101, %al
+	je 5f
+	cmpb $46, %al
+	je 4b
+	movb %al, (%rsp,%r14)
+	incl %r14d
+	jmp 4b
+5:	movq %r13, %rdi
+	call atoi@PLT
+	movl %eax, %ebx
+	movq %r12, %rsi
+	leaq pz_empty(%rip), %rdx
+	leaq pz_minus(%rip), %rax
+	testl %r15d, %r15d
+	cmovnz %rax, %rdx
+	leaq pz_real_format(%rip), %rdi
+	xorl %eax, %eax
+	call printf@PLT
+	testl %ebx, %ebx
+	jns 10f
+	leaq pz_zero_point(%rip), %rdi
+	xorl %eax, %eax
+	call printf@PLT
+	movl $-1, %r15d
+11:	cmpl %ebx, %r15d
+	jle 12f
+	movl $48, %edi
+	call putchar@PLT
+	decl %r15d
+	jmp 11b
+12:	leaq pz_digits_format(%rip), %rdi
+	movl %r14d, %esi
+	movq %rsp, %rdx
+	xorl %eax, %eax
+	call printf@PLT
+	jmp 15f
+10:	xorl %r15d, %r15d
+13:	cmpl %ebx, %r15d
+	jle 14f
+	cmpl %r14d, %r15d
+	jge 15f
+14:	leal 1(%rbx), %eax
+	cmpl %eax, %r15d
+	jne 16f
+	movl $46, %edi
+	call putchar@PLT
+16:	movl $48, %edi
+	cmpl %r14d, %r15d
+	jge 17f
+	movzbl (%rsp,%r15), %edi
+17:	call putchar@PLT
+	incl %r15d
+	jmp 13b
+15:	movl $10, %edi
+	call putchar@PLT
+	jmp 9f
+7:	movq %r12, %rsi
+	leaq pz_nan_format(%rip), %rdi
+	xorl %eax, %eax
+	call printf@PLT
+	jmp 9f
+8:	movq %r12, %rsi
+	leaq pz_empty(%rip), %rdx
+	leaq pz_minus(%rip), %rax
+	movmskpd %xmm0, %ecx
+	testl $1, %ecx
+	cmovnz %rax, %rdx
+	leaq pz_infinity_format(%rip), %rdi
+	xorl %eax, %eax
+	call printf@PLT
+9:	addq $88, %rsp
+	popq %r15
+	popq %r14
+	popq %r13
+	popq %r12
+	popq %rbx
+	popq %rbp
+	ret
+
+	.section .rodata
+pz_error_format: .asciz "Error: %s => line %d\n"
+pz_overflow: .asciz "Integer overflow"
+pz_division: .asciz "Division by zero"
+pz_range: .asciz "Value out of range"
+pz_set_range: .asciz "Set element out of range"
+pz_succ_range: .asciz "Value out of range in `succ`"
+pz_pred_range: .asciz "Value out of range in `pred`"
+pz_integer_format: .asciz "%s = %d\n"
+pz_text_format: .asciz "%s = %s\n"
+pz_set_format: .asciz "%s = ["
+pz_set_end: .asciz "]\n"
+pz_comma: .asciz ", "
+pz_string_format: .asciz "%s"
+pz_number_format: .asciz "%d"
+pz_exponent_format: .asciz "%.*e"
+pz_real_format: .asciz "%s = %s"
+pz_digits_format: .asciz "%.*s"
+pz_zero_point: .asciz "0."
+pz_nan_format: .asciz "%s = NaN\n"
+pz_infinity_format: .asciz "%s = %sinf\n"
+pz_empty: .asciz ""
+pz_minus: .asciz "-"
+pz_true: .asciz "true"
+pz_false: .asciz "false"
+
+	.data
+pz_booleans: .quad pz_false, pz_true
+"#;
+
+struct Scope {
+  // offset from %rbp and type of each variable
+  variables: HashMap<String, (i32, Category)>,
+  // label and parameter types of each procedure
+  procedures: HashMap<String, (String, Vec<Category>)>
+}
+
+struct Generator {
+  text: String,
+  data: String,
+  scopes: Vec<Scope>,
+  routines: usize,
+  labels: usize,
+  strings: usize
+}
+
+// x86-64 assembly for the GNU assembler, System V calling convention, linked
+// with the C library by `cc programa.s -lm`. Every routine keeps its frame
+// in %rbp with the frame of the enclosing routine at -8(%rbp) (static link)
+// and the variables after it, 8 bytes each. Integers, booleans and
+// enumerations are in %eax, sets in %rax and reals in %xmm0, the operands
+// waiting for the other side of an operator are pushed.
+pub fn generate(program: &Program) -> Result<String, Diagnostic> {
+  let mut generator = Generator {
+    text: String::new(),
+    data: String::new(),
+    scopes: Vec::new(),
+    routines: 1,
+    labels: 0,
+    strings: 0
+  };
+  generator.routine(None, &[], &program.block)?;
+
+  // names of the values of each enumeration, to show them
+  for (id, enumeration) in program.enums.iter().enumerate() {
+    let values: Vec<String> = enumeration.values.iter().map(|value| generator.string(value)).collect();
+    generator.data.push_str(&format!("pz_enum{}: .quad {}\n", id, values.join(", ")));
+  }
+
+  let mut out = format!("# generated by pascalzim from program {}\n", program.name);
+  out.push_str(PRELUDE);
+  out.push_str(&generator.data);
+  out.push_str("\n\t.text\n");
+  out.push_str(&generator.text);
+  out.push_str("\t.section .note.GNU-stack,\"\",@progbits\n");
+  Ok(out)
+}
+
+fn key(name: &str) -> String {
+  name.to_lowercase()
+}
+
+// Pascal names are case-insensitive and may have non-ASCII letters
+fn label_name(name: &str) -> String {
+  key(name).chars().map(|c| {
+    if c.is_ascii_alphanumeric() || c == '_' { c.to_string() } else { format!("_u{:04x}", c as u32) }
+  }).collect()
+}
+
+// `.asciz` text, bytes outside printable ASCII in octal
+fn quoted(text: &str) -> String {
+  let mut out = "\"".to_string();
+  for &b in text.as_bytes() {
+    match b {
+      b'"' | b'\\' => { out.push('\\'); out.push(b as char); },
+      0x20..=0x7e => out.push(b as char),
+      _ => out.push_str(&format!("\\{:03o}", b))
+    }
+  }
+  out.push('"');
+  out
+}
+
+fn offset(slot: usize) -> i32 {
+  -16 - 8 * slot as i32
+}
+
+// the bits of `first..last`, None when the bounds do not fit a set
+fn bits(first: i32, last: i32) -> Option<u64> {
+  if last < first {
+    Some(0)
+  } else if first < 0 || last >= types::SET_SIZE {
+    None
+  } else {
+    Some((u64::MAX >> (63 - (last - first))) << first)
+  }
+}
+
+impl Generator {
+  fn emit(&mut self, instruction: &str) {
+    self.text.push('\t');
+    self.text.push_str(instruction);
+    self.text.push('\n');
+  }
+
+  fn label(&mut self) -> String {
+    self.labels += 1;
+    format!(".L{}", self.labels)
+  }
+
+  fn place(&mut self, label: &str) {
+    self.text.push_str(&format!("{}:\n", label));
+  }
+
+  fn string(&mut self, text: &str) -> String {
+    let label = format!("pz_s{}", self.strings);
+    self.strings += 1;
+    self.data.push_str(&format!("{}: .asciz {}\n", label, quoted(text)));
+    label
+  }
+
+  // does not return, `message` is one of the labels of the prelude
+  fn fail(&mut self, message: &str, line: u32) {
+    self.emit(&format!("leaq {}(%rip), %rdi", message));
+    self.emit(&format!("movl ${}, %esi", line));
+    self.emit("call pz_error");
+  }
+
+  // `label` is None for the program, which becomes `main`
+  fn routine(&mut self, label: Option<&str>, params: &[Declaration], block: &Block) -> Result<(), Diagnostic> {
+    let mut scope = Scope { variables: HashMap::new(), procedures: HashMap::new() };
+    let mut slots = Vec::new();
+    for decl in params.iter().chain(block.variables.iter()) {
+      for name in decl.names.iter() {
+        scope.variables.insert(key(name), (offset(slots.len()), decl.category));
+        slots.push(decl);
+      }
+    }
+    let mut procedures = Vec::new();
+    for procedure in block.procedures.iter() {
+      let name = format!("p{}_{}", self.routines, label_name(&procedure.name));
+      let categories = procedure.params.iter().flat_map(|decl| decl.names.iter().map(move |_| decl.category)).collect();
+      scope.procedures.insert(key(&procedure.name), (name.clone(), categories));
+      procedures.push(name);
+      self.routines += 1;
+    }
+    self.scopes.push(scope);
+
+    // the static link and the slots, rounded up to keep %rsp aligned
+    let size = (8 * (slots.len() + 1)).div_ceil(16) * 16;
+    match label {
+      Some(label) => self.text.push_str(&format!("\n{}:\n", label)),
+      None => self.text.push_str("\n\t.globl main\nmain:\n")
+    }
+    self.emit("pushq %rbp");
+    self.emit("movq %rsp, %rbp");
+    self.emit(&format!("subq ${}, %rsp", size));
+    self.emit(if label.is_some() { "movq %rdi, -8(%rbp)" } else { "movq $0, -8(%rbp)" });
+
+    // the arguments were pushed in order, the last one is right above the
+    // return address
+    let count = params.iter().map(|decl| decl.names.len()).sum::<usize>();
+    for (slot, decl) in slots.iter().enumerate() {
+      if slot < count {
+        self.emit(&format!("movq {}(%rbp), %rax", 16 + 8 * (count - 1 - slot)));
+        self.emit(&format!("movq %rax, {}(%rbp)", offset(slot)));
+        continue;
+      }
+      // zero for every variable but the subranges that leave out 0, which
+      // start at their lower bound
+      let first = match decl.range {
+        Some(range) if !types::is_set(decl.category) => range.first,
+        _ => 0
+      };
+      self.emit(&format!("movq ${}, {}(%rbp)", first, offset(slot)));
+    }
+
+    for command in block.body.iter() {
+      self.command(command)?;
+    }
+
+    if label.is_none() {
+      self.show(block);
+      self.emit("xorl %eax, %eax");
+    }
+    self.emit("leave");
+    self.emit("ret");
+
+    for (procedure, name) in block.procedures.iter().zip(procedures.iter()) {
+      self.routine(Some(name), &procedure.params, &procedure.block)?;
+    }
+    self.scopes.pop();
+    Ok(())
+  }
+
+  fn show(&mut self, block: &Block) {
+    for decl in block.variables.iter() {
+      for name in decl.names.iter() {
+        let (at, _) = self.scopes[0].variables[&key(name)];
+        let label = self.string(name);
+        self.emit(&format!("leaq {}(%rip), %rdi", label));
+        match decl.category {
+          Category::Real => {
+            self.emit(&format!("movsd {}(%rbp), %xmm0", at));
+            self.emit("call pz_show_real");
+          },
+          Category::Enum(id, _) => {
+            self.emit(&format!("leaq pz_enum{}(%rip), %rsi", id));
+            self.emit(&format!("movl {}(%rbp), %edx", at));
+            self.emit("call pz_show_enum");
+          },
+          Category::Set(_) => {
+            match types::element_of(decl.category) {
+              Some(Category::Enum(id, _)) => self.emit(&format!("leaq pz_enum{}(%rip), %rsi", id)),
+              Some(Category::Boolean) => self.emit("leaq pz_booleans(%rip), %rsi"),
+              _ => self.emit("xorl %esi, %esi")
+            }
+            self.emit(&format!("movq {}(%rbp), %rdx", at));
+            self.emit("call pz_show_set");
+          },
+          Category::Boolean => {
+            self.emit(&format!("movl {}(%rbp), %esi", at));
+            self.emit("call pz_show_boolean");
+          },
+          _ => {
+            self.emit(&format!("movl {}(%rbp), %esi", at));
+            self.emit("call pz_show_integer");
+          }
+        }
+      }
+    }
+  }
+
+  // leaves the frame `depth` routines out in `register`, %rbp is the
+  // current one
+  fn frame(&mut self, depth: usize, register: &str) -> String {
+    if depth == 0 {
+      return "%rbp".to_string();
+    }
+    self.emit(&format!("movq -8(%rbp), {}", register));
+    for _ in 1..depth {
+      self.emit(&format!("movq -8({}), {}", register, register));
+    }
+    register.to_string()
+  }
+
+  fn variable(&self, name: &str, line: u32) -> Result<(usize, i32, Category), Diagnostic> {
+    for (depth, scope) in self.scopes.iter().rev().enumerate() {
+      if let Some(&(at, category)) = scope.variables.get(&key(name)) {
+        return Ok((depth, at, category));
+      }
+    }
+    Err(Diagnostic::error(format!("Identifier `{}` not declared", name), line))
+  }
+
+  // an integer becomes a real where a real is expected, as C does
+  fn value(&mut self, expr: &Expr, category: Category) -> Result<(), Diagnostic> {
+    self.expr(expr)?;
+    if category == Category::Real && expr.category == Category::Integer {
+      self.emit("cvtsi2sdl %eax, %xmm0");
+    }
+    Ok(())
+  }
+
+  fn push(&mut self, category: Category) {
+    if category == Category::Real {
+      self.emit("subq $8, %rsp");
+      self.emit("movsd %xmm0, (%rsp)");
+    } else {
+      self.emit("pushq %rax");
+    }
+  }
+
+  fn command(&mut self, command: &Command) -> Result<(), Diagnostic> {
+    match *command {
+      Command::Assign { ref name, ref value, line } => {
+        let (depth, at, category) = self.variable(name, line)?;
+        self.value(value, category)?;
+        let frame = self.frame(depth, "%rdx");
+        self.emit(&match category {
+          Category::Real => format!("movsd %xmm0, {}({})", at, frame),
+          Category::Set(_) => format!("movq %rax, {}({})", at, frame),
+          _ => format!("movl %eax, {}({})", at, frame)
+        });
+      },
+      Command::Call { ref name, ref args, line } => {
+        let found = self.scopes.iter().rev().enumerate()
+          .find_map(|(depth, scope)| scope.procedures.get(&key(name)).map(|procedure| (depth, procedure.clone())));
+        let (depth, (label, params)) = found
+          .ok_or_else(|| Diagnostic::error(format!("Procedure `{}` not declared", name), line))?;
+        for (arg, &category) in args.iter().zip(params.iter()) {
+          self.value(arg, category)?;
+          if category == Category::Real {
+            self.emit("movq %xmm0, %rax");
+          }
+          self.emit("pushq %rax");
+        }
+        let frame = self.frame(depth, "%rdi");
+        if frame != "%rdi" {
+          self.emit("movq %rbp, %rdi");
+        }
+        self.emit(&format!("call {}", label));
+        if !args.is_empty() {
+          self.emit(&format!("addq ${}, %rsp", 8 * args.len()));
+        }
+      },
+      Command::Compound(ref commands) => {
+        for command in commands.iter() {
+          self.command(command)?;
+        }
+      },
+      Command::If { ref condition, ref then_branch, ref else_branch, .. } => {
+        let (otherwise, end) = (self.label(), self.label());
+        self.expr(condition)?;
+        self.emit("testl %eax, %eax");
+        self.emit(&format!("jz {}", otherwise));
+        self.command(then_branch)?;
+        self.emit(&format!("jmp {}", end));
+        self.place(&otherwise);
+        if let Some(ref command) = *else_branch {
+          self.command(command)?;
+        }
+        self.place(&end);
+      },
+      Command::While { ref condition, ref body, .. } => {
+        let (top, end) = (self.label(), self.label());
+        self.place(&top);
+        self.expr(condition)?;
+        self.emit("testl %eax, %eax");
+        self.emit(&format!("jz {}", end));
+        self.command(body)?;
+        self.emit(&format!("jmp {}", top));
+        self.place(&end);
+      }
+    }
+    Ok(())
+  }
+
+  // a failure when the last instruction set the overflow flag
+  fn overflow(&mut self, line: u32) {
+    self.emit("jno 1f");
+    self.fail("pz_overflow", line);
+    self.text.push_str("1:\n");
+  }
+
+  fn expr(&mut self, expr: &Expr) -> Result<(), Diagnostic> {
+    let line = expr.line;
+
+    match expr.kind {
+      ExprKind::Integer(i) => self.emit(&format!("movl ${}, %eax", i)),
+      ExprKind::Real(r) => {
+        self.emit(&format!("movabsq ${:#x}, %rax", r.to_bits()));
+        self.emit("movq %rax, %xmm0");
+      },
+      ExprKind::Boolean(b) => self.emit(&format!("movl ${}, %eax", b as i32)),
+      ExprKind::Constant(_, ordinal) => self.emit(&format!("movl ${}, %eax", ordinal)),
+      ExprKind::Variable(ref name) => {
+        let (depth, at, category) = self.variable(name, line)?;
+        let frame = self.frame(depth, "%rdx");
+        self.emit(&match category {
+          Category::Real => format!("movsd {}({}), %xmm0", at, frame),
+          Category::Set(_) => format!("movq {}({}), %rax", at, frame),
+          _ => format!("movl {}({}), %eax", at, frame)
+        });
+      },
+      ExprKind::Call(ref name, _) => {
+        return Err(Diagnostic::error(format!("Procedure `{}` doesn't return a value", name), line));
+      },
+      ExprKind::Builtin(function, ref argument) => {
+        self.expr(argument)?;
+        let last = last_ordinal(argument.category);
+        match function {
+          // booleans are already 0 or 1
+          Builtin::Ord => {},
+          Builtin::Succ => {
+            if last == i32::MAX {
+              self.emit("cmpl $2147483647, %eax");
+              self.emit("jne 1f");
+              self.fail("pz_overflow", line);
+            } else {
+              self.emit(&format!("cmpl ${}, %eax", last));
+              self.emit("jl 1f");
+              self.fail("pz_succ_range", line);
+            }
+            self.text.push_str("1:\n");
+            self.emit("incl %eax");
+          },
+          Builtin::Pred => {
+            if last == i32::MAX {
+              self.emit("cmpl $-2147483648, %eax");
+              self.emit("jne 1f");
+              self.fail("pz_overflow", line);
+            } else {
+              self.emit("testl %eax, %eax");
+              self.emit("jg 1f");
+              self.fail("pz_pred_range", line);
+            }
+            self.text.push_str("1:\n");
+            self.emit("decl %eax");
+          }
+        }
+      },
+      ExprKind::RangeCheck(range, ref value) if types::is_set(value.category) => {
+        self.expr(value)?;
+        match bits(range.first, range.last) {
+          Some(mask) => {
+            self.emit(&format!("movabsq ${:#x}, %rcx", !mask));
+            self.emit("testq %rcx, %rax");
+            self.emit("jz 1f");
+            self.fail("pz_set_range", line);
+            self.text.push_str("1:\n");
+          },
+          None => self.fail("pz_set_range", line)
+        }
+      },
+      ExprKind::RangeCheck(range, ref value) => {
+        self.expr(value)?;
+        self.emit(&format!("cmpl ${}, %eax", range.first));
+        self.emit("jl 2f");
+        self.emit(&format!("cmpl ${}, %eax", range.last));
+        self.emit("jle 1f");
+        self.text.push_str("2:\n");
+        self.fail("pz_range", line);
+        self.text.push_str("1:\n");
+      },
+      ExprKind::Set(ref items) => {
+        self.emit("xorl %eax, %eax");
+        for (first, last) in items {
+          self.emit("pushq %rax");
+          self.expr(first)?;
+          match *last {
+            Some(ref last) => {
+              self.emit("pushq %rax");
+              self.expr(last)?;
+              self.emit("movl %eax, %esi");
+              self.emit("popq %rdi");
+            },
+            None => {
+              self.emit("movl %eax, %edi");
+              self.emit("movl %eax, %esi");
+            }
+          }
+          self.emit(&format!("movl ${}, %edx", line));
+          self.emit("call pz_bits");
+          self.emit("popq %rcx");
+          self.emit("orq %rcx, %rax");
+        }
+      },
+      ExprKind::Unary(ref op, ref operand) => {
+        self.expr(operand)?;
+        match *op {
+          Token::Not => self.emit("xorl $1, %eax"),
+          Token::Sub if operand.category == Category::Integer => {
+            self.emit("negl %eax");
+            self.overflow(line);
+          },
+          Token::Sub => {
+            self.emit("movq %xmm0, %rax");
+            self.emit("btcq $63, %rax");
+            self.emit("movq %rax, %xmm0");
+          },
+          _ => {}
+        }
+      },
+      ExprKind::Logical(ref op, mode, ref lhs, ref rhs) => {
+        self.expr(lhs)?;
+        if mode == BooleanEval::Full {
+          self.emit("pushq %rax");
+          self.expr(rhs)?;
+          self.emit("movl %eax, %ecx");
+          self.emit("popq %rax");
+          match *op {
+            Token::And => self.emit("andl %ecx, %eax"),
+            Token::Or => self.emit("orl %ecx, %eax"),
+            _ => {
+              self.emit("xorl $1, %eax");
+              self.emit("orl %ecx, %eax");
+            }
+          }
+        } else {
+          // the left operand alone may decide the result, which is then in
+          // %eax
+          let end = self.label();
+          self.emit("testl %eax, %eax");
+          match *op {
+            Token::And => self.emit(&format!("jz {}", end)),
+            Token::Or => self.emit(&format!("jnz {}", end)),
+            _ => {
+              self.emit("movl $1, %eax");
+              self.emit(&format!("jz {}", end));
+            }
+          }
+          self.expr(rhs)?;
+          self.place(&end);
+        }
+      },
+      ExprKind::Binary(ref op, ref lhs, ref rhs) => self.binary(op, lhs, rhs, expr)?
+    }
+    Ok(())
+  }
+
+  // the left operand in %rax or %xmm0, the right one in %rcx or %xmm1
+  fn binary(&mut self, op: &Token, lhs: &Expr, rhs: &Expr, expr: &Expr) -> Result<(), Diagnostic> {
+    let line = expr.line;
+    // both operands become reals when either side or the result is real
+    let real = [lhs.category, rhs.category, expr.category].contains(&Category::Real);
+    let category = if real { Category::Real } else { lhs.category };
+
+    self.value(lhs, category)?;
+    self.push(category);
+    self.value(rhs, category)?;
+    if real {
+      self.emit("movapd %xmm0, %xmm1");
+      self.emit("movsd (%rsp), %xmm0");
+      self.emit("addq $8, %rsp");
+    } else {
+      self.emit("movq %rax, %rcx");
+      self.emit("popq %rax");
+    }
+
+    let set = |generator: &mut Generator, condition: &str| {
+      generator.emit(&format!("set{} %al", condition));
+      generator.emit("movzbl %al, %eax");
+    };
+
+    if types::is_set(lhs.category) {
+      match *op {
+        Token::Add => self.emit("orq %rcx, %rax"),
+        Token::Mult => self.emit("andq %rcx, %rax"),
+        Token::Sub => {
+          self.emit("notq %rcx");
+          self.emit("andq %rcx, %rax");
+        },
+        Token::Equal | Token::NotEqual => {
+          self.emit("cmpq %rcx, %rax");
+          set(self, if *op == Token::Equal { "e" } else { "ne" });
+        },
+        // subset and superset
+        Token::LessThanOrEqual => {
+          self.emit("notq %rcx");
+          self.emit("testq %rcx, %rax");
+          set(self, "e");
+        },
+        _ => {
+          self.emit("notq %rax");
+          self.emit("testq %rax, %rcx");
+          set(self, "e");
+        }
+      }
+      return Ok(());
+    }
+
+    let comparison = match *op {
+      Token::Equal | Token::Equiv => Some("e"),
+      Token::NotEqual | Token::Xor => Some("ne"),
+      Token::LessThan => Some("l"),
+      Token::LessThanOrEqual => Some("le"),
+      Token::GreaterThan => Some("g"),
+      Token::GreaterThanOrEqual => Some("ge"),
+      _ => None
+    };
+
+    match (op, comparison) {
+      (&Token::In, _) => {
+        self.emit("movl %eax, %edi");
+        self.emit("movq %rcx, %rsi");
+        self.emit("call pz_in");
+      },
+      // NaN is unordered, it is only different from everything
+      (_, Some(condition)) if real => {
+        match condition {
+          "e" | "ne" => {
+            self.emit("ucomisd %xmm1, %xmm0");
+            self.emit(&format!("set{} %al", condition));
+            self.emit(if condition == "e" { "setnp %cl" } else { "setp %cl" });
+            self.emit(if condition == "e" { "andb %cl, %al" } else { "orb %cl, %al" });
+            self.emit("movzbl %al, %eax");
+          },
+          "l" | "le" => {
+            self.emit("ucomisd %xmm0, %xmm1");
+            set(self, if condition == "l" { "a" } else { "ae" });
+          },
+          _ => {
+            self.emit("ucomisd %xmm1, %xmm0");
+            set(self, if condition == "g" { "a" } else { "ae" });
+          }
+        }
+      },
+      (_, Some(condition)) => {
+        self.emit("cmpl %ecx, %eax");
+        set(self, condition);
+      },
+      (&Token::Add, _) if real => self.emit("addsd %xmm1, %xmm0"),
+      (&Token::Sub, _) if real => self.emit("subsd %xmm1, %xmm0"),
+      (&Token::Mult, _) if real => self.emit("mulsd %xmm1, %xmm0"),
+      (&Token::Div, _) if real => {
+        self.emit("xorpd %xmm2, %xmm2");
+        self.emit("ucomisd %xmm2, %xmm1");
+        self.emit("jne 1f");
+        self.emit("jp 1f");
+        self.fail("pz_division", line);
+        self.text.push_str("1:\n");
+        self.emit("divsd %xmm1, %xmm0");
+      },
+      (_, _) if real => self.emit("call pz_pow_real"),
+      (&Token::Add, _) => {
+        self.emit("addl %ecx, %eax");
+        self.overflow(line);
+      },
+      (&Token::Sub, _) => {
+        self.emit("subl %ecx, %eax");
+        self.overflow(line);
+      },
+      (&Token::Mult, _) => {
+        self.emit("imull %ecx, %eax");
+        self.overflow(line);
+      },
+      (_, _) => {
+        self.emit("movl %eax, %edi");
+        self.emit("movl %ecx, %esi");
+        self.emit(&format!("movl ${}, %edx", line));
+        self.emit(match *op {
+          Token::Div | Token::IntDiv => "call pz_div",
+          Token::Mod => "call pz_mod",
+          _ => "call pz_pow"
+        });
+      }
+    }
+    Ok(())
+  }
+}
+
+#[test]
+fn test_asm_program23() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program23.txt");
+  let code = generate(p1.program().unwrap()).unwrap();
+
+  assert!(code.starts_with("# generated by pascalzim from program fatorial\n"));
+  assert!(code.contains("\t.globl main\n"));
+  assert!(code.contains("p1_fat:\n\tpushq %rbp\n\tmovq %rsp, %rbp\n\tsubq $16, %rsp\n\tmovq %rdi, -8(%rbp)\n"));
+  assert!(code.contains("\tpushq %rax\n\tmovq -8(%rbp), %rdi\n\tcall p1_fat\n\taddq $8, %rsp\n"));
+  assert!(code.contains("\tmovq -8(%rbp), %rdx\n\tmovl %eax, -24(%rdx)\n"));
+  assert!(code.contains("\tleaq pz_overflow(%rip), %rdi\n\tmovl $9, %esi\n\tcall pz_error\n"));
+  assert!(code.contains("\tmovsd -32(%rbp), %xmm0\n\tcall pz_show_real\n"));
+  assert!(code.ends_with("\t.section .note.GNU-stack,\"\",@progbits\n"));
+}
+
+#[test]
+fn test_asm_boolean_eval() {
+  use options::Options;
+  use parser::Parser;
+
+  // the right side of `and` and `->` is skipped by a jump
+  let mut p1: Parser = Parser::with_options(Options { boolean_eval: BooleanEval::ShortCircuit, ..Options::default() });
+  p1.build_ast("files/program24.txt");
+  let code = generate(p1.program().unwrap()).unwrap();
+  assert!(code.contains("\tsetne %al\n\tmovzbl %al, %eax\n\ttestl %eax, %eax\n\tjz .L1\n"));
+  assert!(code.contains("\tmovl $8, %edx\n\tcall pz_div\n"));
+  assert!(code.contains("\ttestl %eax, %eax\n\tmovl $1, %eax\n\tjz .L2\n"));
+  assert!(code.contains("\tmovl $9, %edx\n\tcall pz_mod\n"));
+
+  let mut p2: Parser = Parser::new();
+  p2.build_ast("files/program24.txt");
+  let code = generate(p2.program().unwrap()).unwrap();
+  assert!(!code.contains("jz .L"));
+  assert!(code.contains("\tmovl %eax, %ecx\n\tpopq %rax\n\tandl %ecx, %eax\n\tmovl %eax, -32(%rbp)\n"));
+
+  // each `and` follows the `{$B}` in force where it is written
+  let mut p3: Parser = Parser::new();
+  p3.build_ast("files/program38.txt");
+  let code = generate(p3.program().unwrap()).unwrap();
+  assert!(code.contains("\tjz .L1\n"));
+  assert!(code.contains("\tmovzbl %al, %eax\n.L1:\n\tmovl %eax, -32(%rbp)\n"));
+  assert!(code.contains("\tandl %ecx, %eax\n\tmovl %eax, -40(%rbp)\n"));
+}
+
+#[test]
+fn test_asm_power() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program33.txt");
+  let code = generate(p1.program().unwrap()).unwrap();
+  // `2 ** 3 ** 2` is `2 ** (3 ** 2)`
+  assert!(code.contains("\
+\tmovl $2, %eax\n\tpushq %rax\n\tmovl $3, %eax\n\tpushq %rax\n\tmovl $2, %eax\n\tmovq %rax, %rcx\n\tpopq %rax\n\
+\tmovl %eax, %edi\n\tmovl %ecx, %esi\n\tmovl $5, %edx\n\tcall pz_pow\n\tmovq %rax, %rcx\n\tpopq %rax\n\
+\tmovl %eax, %edi\n\tmovl %ecx, %esi\n\tmovl $5, %edx\n\tcall pz_pow\n\tmovl %eax, -16(%rbp)\n"));
+  // `-2 ** 2` is `-(2 ** 2)`
+  assert!(code.contains("\tmovl $7, %edx\n\tcall pz_pow\n\tnegl %eax\n"));
+  assert!(code.contains("\tcvtsi2sdl %eax, %xmm0\n\tmovapd %xmm0, %xmm1\n\tmovsd (%rsp), %xmm0\n\taddq $8, %rsp\n\tcall pz_pow_real\n\tmovsd %xmm0, -48(%rbp)\n"));
+}
+
+#[test]
+fn test_asm_logic() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program34.txt");
+  let code = generate(p1.program().unwrap()).unwrap();
+  // `p -> q -> r` is `not p or (not q or r)`
+  assert!(code.contains("\
+\tmovl -16(%rbp), %eax\n\tpushq %rax\n\tmovl -24(%rbp), %eax\n\tpushq %rax\n\tmovl -32(%rbp), %eax\n\
+\tmovl %eax, %ecx\n\tpopq %rax\n\txorl $1, %eax\n\torl %ecx, %eax\n\
+\tmovl %eax, %ecx\n\tpopq %rax\n\txorl $1, %eax\n\torl %ecx, %eax\n\tmovl %eax, -40(%rbp)\n"));
+  // `xor` and `<->` compare the two sides
+  assert!(code.contains("\tsetne %al\n\tmovzbl %al, %eax\n\tpushq %rax\n\tmovl -32(%rbp), %eax\n\tmovl %eax, %ecx\n\tpopq %rax\n\torl %ecx, %eax\n\tmovl %eax, -56(%rbp)\n"));
+  assert!(code.contains("\tsete %al\n\tmovzbl %al, %eax\n\tmovl %eax, -64(%rbp)\n"));
+}
+
+#[test]
+fn test_asm_enumerations() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program35.txt");
+  let code = generate(p1.program().unwrap()).unwrap();
+  assert!(code.contains("pz_s8: .asciz \"red\"\n"));
+  assert!(code.contains("pz_enum0: .quad pz_s8, pz_s9, pz_s10\n"));
+  assert!(code.contains("\tcmpl $2, %eax\n\tjl 1f\n\tleaq pz_succ_range(%rip), %rdi\n\tmovl $13, %esi\n\tcall pz_error\n1:\n\tincl %eax\n"));
+  assert!(code.contains("\tleaq pz_s0(%rip), %rdi\n\tleaq pz_enum0(%rip), %rsi\n\tmovl -16(%rbp), %edx\n\tcall pz_show_enum\n"));
+}
+
+#[test]
+fn test_asm_subranges() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program36.txt");
+  let code = generate(p1.program().unwrap()).unwrap();
+  // `t: -5..5` starts at -5, the others at 0
+  assert!(code.contains("\tmovq $0, -32(%rbp)\n\tmovq $-5, -40(%rbp)\n"));
+  assert!(code.contains("\tmovl $9, %eax\n\tmovl %eax, -16(%rbp)\n"));
+  // `e := x + 1` is checked against `0..9` under {$R+}
+  assert!(code.contains("\
+\tcmpl $0, %eax\n\tjl 2f\n\tcmpl $9, %eax\n\tjle 1f\n2:\n\tleaq pz_range(%rip), %rdi\n\tmovl $16, %esi\n\tcall pz_error\n1:\n\
+\tmovq -8(%rbp), %rdx\n\tmovl %eax, -24(%rdx)\n"));
+}
+
+#[test]
+fn test_asm_sets() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program37.txt");
+  let code = generate(p1.program().unwrap()).unwrap();
+  // `[1, 3..5]`
+  assert!(code.contains("\
+\txorl %eax, %eax\n\tpushq %rax\n\tmovl $1, %eax\n\tmovl %eax, %edi\n\tmovl %eax, %esi\n\tmovl $20, %edx\n\tcall pz_bits\n\tpopq %rcx\n\torq %rcx, %rax\n\
+\tpushq %rax\n\tmovl $3, %eax\n\tpushq %rax\n\tmovl $5, %eax\n\tmovl %eax, %esi\n\tpopq %rdi\n\tmovl $20, %edx\n\tcall pz_bits\n\tpopq %rcx\n\torq %rcx, %rax\n\
+\tmovq %rax, -16(%rbp)\n"));
+  // `a - b` and `[3, 4] <= a`
+  assert!(code.contains("\tpopq %rax\n\tnotq %rcx\n\tandq %rcx, %rax\n\tmovq %rax, -48(%rbp)\n"));
+  assert!(code.contains("\tnotq %rcx\n\ttestq %rcx, %rax\n\tsete %al\n\tmovzbl %al, %eax\n\tmovl %eax, -88(%rbp)\n"));
+  assert!(code.contains("\tmovl %eax, %edi\n\tmovq %rcx, %rsi\n\tcall pz_in\n\txorl $1, %eax\n"));
+  assert!(code.contains("\tleaq pz_s5(%rip), %rdi\n\tleaq pz_enum0(%rip), %rsi\n\tmovq -56(%rbp), %rdx\n\tcall pz_show_set\n"));
+  assert!(code.contains("\tleaq pz_s4(%rip), %rdi\n\txorl %esi, %esi\n\tmovq -48(%rbp), %rdx\n\tcall pz_show_set\n"));
+
+  let mut p2: Parser = Parser::new();
+  let program = p2.parse_str("{$R+} program p; var s: set of 1..3; n: integer; begin s := [n] end.").unwrap();
+  let code = generate(program).unwrap();
+  assert!(code.contains("\tmovabsq $0xfffffffffffffff1, %rcx\n\ttestq %rcx, %rax\n\tjz 1f\n\tleaq pz_set_range(%rip), %rdi\n"));
+}
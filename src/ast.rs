@@ -0,0 +1,144 @@
+use options::BooleanEval;
+use parser::Category;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use spec::Token;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Program {
+  pub name: String,
+  pub block: Block,
+  // every enumeration declared in the program, `Category::Enum` indexes it
+  pub enums: Vec<Enumeration>
+}
+
+// `color = (red, green, blue)`, the values are numbered from 0
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Enumeration {
+  pub name: String,
+  pub values: Vec<String>,
+  pub line: u32
+}
+
+// declarations and body shared by the program and its procedures
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Block {
+  pub variables: Vec<Declaration>,
+  pub procedures: Vec<Procedure>,
+  pub body: Vec<Command>
+}
+
+// `a, b: integer` keeps the names grouped as they were written
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Declaration {
+  pub names: Vec<String>,
+  pub category: Category,
+  // bounds of a subrange, `category` is then the type it is taken from, or
+  // of the elements of a set of a subrange
+  pub range: Option<Subrange>,
+  pub line: u32
+}
+
+// `1..10` or `red..green`, by ordinal
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Subrange {
+  pub first: i32,
+  pub last: i32
+}
+
+impl Subrange {
+  pub fn contains(self, ordinal: i32) -> bool {
+    self.first <= ordinal && ordinal <= self.last
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Procedure {
+  pub name: String,
+  pub params: Vec<Declaration>,
+  pub block: Block,
+  pub line: u32
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Command {
+  Assign { name: String, value: Expr, line: u32 },
+  Call { name: String, args: Vec<Expr>, line: u32 },
+  Compound(Vec<Command>),
+  If { condition: Expr, then_branch: Box<Command>, else_branch: Option<Box<Command>>, line: u32 },
+  While { condition: Expr, body: Box<Command>, line: u32 }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Expr {
+  pub kind: ExprKind,
+  // type inferred by the parser
+  pub category: Category,
+  pub line: u32
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ExprKind {
+  Integer(i32),
+  Real(f64),
+  Boolean(bool),
+  // a value of an enumeration and its ordinal
+  Constant(String, i32),
+  Variable(String),
+  Call(String, Vec<Expr>),
+  Builtin(Builtin, Box<Expr>),
+  // a value assigned or passed to a subrange under `{$R+}`, fails at runtime
+  // when it is out of the bounds, for a set when one of its elements is
+  RangeCheck(Subrange, Box<Expr>),
+  // `[1, 3..5]`, the elements and the ends of the ranges
+  Set(Vec<(Expr, Option<Expr>)>),
+  // `not`, `-` and `+`
+  Unary(Token, Box<Expr>),
+  Binary(Token, Box<Expr>, Box<Expr>),
+  // `and`, `or` and `->` with the `{$B}` mode in force where they were
+  // written
+  Logical(Token, BooleanEval, Box<Expr>, Box<Expr>)
+}
+
+// functions every ordinal type has
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Builtin {
+  Ord,
+  Succ,
+  Pred
+}
+
+impl Builtin {
+  pub fn from_name(name: &str) -> Option<Builtin> {
+    match &*name.to_lowercase() {
+      "ord" => Some(Builtin::Ord),
+      "succ" => Some(Builtin::Succ),
+      "pred" => Some(Builtin::Pred),
+      _ => None
+    }
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Builtin::Ord => "ord",
+      Builtin::Succ => "succ",
+      Builtin::Pred => "pred"
+    }
+  }
+}
+
+impl Expr {
+  pub fn new(kind: ExprKind, category: Category, line: u32) -> Expr {
+    Expr { kind, category, line }
+  }
+}
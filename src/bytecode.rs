@@ -0,0 +1,703 @@
+use std::collections::HashMap;
+use std::fmt;
+use ast::*;
+use diagnostic::Diagnostic;
+use interpreter::last_ordinal;
+use options::BooleanEval;
+use parser::{Category, Element};
+use spec::Token;
+use types;
+
+// instructions of the stack machine run by `vm`, arithmetic and comparisons
+// work on two values of the same kind, the compiler inserts `ToReal` where
+// an integer meets a real
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instr {
+  Int(i32),
+  Real(f64),
+  Bool(bool),
+  // slot of the frame found `depth` static links away
+  Load(u8, u16),
+  Store(u8, u16),
+  ToReal,
+  Neg,
+  Not,
+  Add,
+  Sub,
+  Mul,
+  // integer operands only under the legacy division rule
+  Div,
+  IntDiv,
+  Mod,
+  Pow,
+  And,
+  Or,
+  Imp,
+  Equal,
+  NotEqual,
+  Less,
+  LessEqual,
+  Greater,
+  GreaterEqual,
+  // ordinal of a boolean, integers are left as they are
+  Ord,
+  // `succ` and `pred`, the operand is the highest ordinal of the type
+  Succ(i32),
+  Pred(i32),
+  // `{$R+}`, fails unless the ordinal on top, or every element of the set on
+  // top, is within the bounds
+  Check(i32, i32),
+  // sets are built from the empty one, `Include` adds the ordinal on top and
+  // `IncludeRange` the ordinals between the two on top
+  EmptySet,
+  Include,
+  IncludeRange,
+  // an ordinal and then a set, `Add`, `Sub` and `Mul` on two sets are the
+  // union, difference and intersection
+  In,
+  Jump(u32),
+  JumpIfFalse(u32),
+  JumpIfTrue(u32),
+  // routine declared in the frame `depth` static links away
+  Call(u8, u16),
+  Return,
+  Halt
+}
+
+impl fmt::Display for Instr {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Instr::Int(i) => write!(f, "int {}", i),
+      Instr::Real(r) => write!(f, "real {:?}", r),
+      Instr::Bool(b) => write!(f, "bool {}", b),
+      Instr::Succ(last) => write!(f, "succ {}", last),
+      Instr::Pred(last) => write!(f, "pred {}", last),
+      Instr::Check(first, last) => write!(f, "check {} {}", first, last),
+      Instr::Load(depth, slot) => write!(f, "load {} {}", depth, slot),
+      Instr::Store(depth, slot) => write!(f, "store {} {}", depth, slot),
+      Instr::Jump(target) => write!(f, "jump {}", target),
+      Instr::JumpIfFalse(target) => write!(f, "jump_if_false {}", target),
+      Instr::JumpIfTrue(target) => write!(f, "jump_if_true {}", target),
+      Instr::Call(depth, routine) => write!(f, "call {} {}", depth, routine),
+      _ => write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Routine {
+  pub name: String,
+  pub entry: u32,
+  // the first `params` slots are filled by the arguments
+  pub params: u16,
+  // name, type and the bounds of a subrange, see `Declaration`
+  pub slots: Vec<(String, Category, Option<Subrange>)>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+  // routine 0 is the program, its slots are the program variables
+  pub routines: Vec<Routine>,
+  pub code: Vec<Instr>,
+  // source line of each instruction, for runtime errors
+  pub lines: Vec<u32>,
+  // to show the values of enumerations by name
+  pub enums: Vec<Enumeration>
+}
+
+const MAGIC: &[u8] = b"PZB3";
+
+impl Chunk {
+  // files written by `to_bytes` start with a magic number
+  pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+  }
+
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+
+    put_u32(&mut out, self.routines.len() as u32);
+    for routine in self.routines.iter() {
+      put_str(&mut out, &routine.name);
+      put_u32(&mut out, routine.entry);
+      out.extend_from_slice(&routine.params.to_le_bytes());
+      put_u32(&mut out, routine.slots.len() as u32);
+      for (name, category, range) in routine.slots.iter() {
+        put_str(&mut out, name);
+        match *category {
+          Category::Integer => out.push(0),
+          Category::Real => out.push(1),
+          Category::Enum(id, count) => {
+            out.push(3);
+            out.extend_from_slice(&id.to_le_bytes());
+            out.extend_from_slice(&count.to_le_bytes());
+          },
+          Category::Set(element) => {
+            out.push(4);
+            match element {
+              Element::Integer => out.push(0),
+              Element::Boolean => out.push(1),
+              Element::Enum(id, count) => {
+                out.push(2);
+                out.extend_from_slice(&id.to_le_bytes());
+                out.extend_from_slice(&count.to_le_bytes());
+              },
+              Element::Empty => out.push(3)
+            }
+          },
+          _ => out.push(2)
+        }
+        match *range {
+          Some(range) => {
+            out.push(1);
+            out.extend_from_slice(&range.first.to_le_bytes());
+            out.extend_from_slice(&range.last.to_le_bytes());
+          },
+          None => out.push(0)
+        }
+      }
+    }
+
+    put_u32(&mut out, self.enums.len() as u32);
+    for enumeration in self.enums.iter() {
+      put_str(&mut out, &enumeration.name);
+      put_u32(&mut out, enumeration.line);
+      put_u32(&mut out, enumeration.values.len() as u32);
+      for value in enumeration.values.iter() {
+        put_str(&mut out, value);
+      }
+    }
+
+    put_u32(&mut out, self.code.len() as u32);
+    for (instr, line) in self.code.iter().zip(self.lines.iter()) {
+      put_u32(&mut out, *line);
+      put_instr(&mut out, instr);
+    }
+    out
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Result<Chunk, String> {
+    if !Chunk::is_bytecode(bytes) {
+      return Err("Not a pascalzim bytecode file".to_string());
+    }
+    let mut reader = Reader { bytes, position: MAGIC.len() };
+    let mut chunk = Chunk { routines: Vec::new(), code: Vec::new(), lines: Vec::new(), enums: Vec::new() };
+
+    for _ in 0..reader.u32()? {
+      let name = reader.string()?;
+      let entry = reader.u32()?;
+      let params = reader.u16()?;
+      let mut slots = Vec::new();
+      for _ in 0..reader.u32()? {
+        let name = reader.string()?;
+        let category = match reader.u8()? {
+          0 => Category::Integer,
+          1 => Category::Real,
+          2 => Category::Boolean,
+          3 => Category::Enum(reader.u16()?, reader.u16()?),
+          4 => Category::Set(match reader.u8()? {
+            0 => Element::Integer,
+            1 => Element::Boolean,
+            2 => Element::Enum(reader.u16()?, reader.u16()?),
+            3 => Element::Empty,
+            n => return Err(format!("Unknown set type {}", n))
+          }),
+          n => return Err(format!("Unknown type {}", n))
+        };
+        let range = match reader.u8()? {
+          0 => None,
+          1 => Some(Subrange { first: reader.u32()? as i32, last: reader.u32()? as i32 }),
+          n => return Err(format!("Unknown bounds {}", n))
+        };
+        slots.push((name, category, range));
+      }
+      chunk.routines.push(Routine { name, entry, params, slots });
+    }
+
+    for _ in 0..reader.u32()? {
+      let name = reader.string()?;
+      let line = reader.u32()?;
+      let mut values = Vec::new();
+      for _ in 0..reader.u32()? {
+        values.push(reader.string()?);
+      }
+      chunk.enums.push(Enumeration { name, values, line });
+    }
+
+    for _ in 0..reader.u32()? {
+      chunk.lines.push(reader.u32()?);
+      chunk.code.push(reader.instr()?);
+    }
+    if reader.position != bytes.len() {
+      return Err("Unexpected bytes after the code".to_string());
+    }
+    Ok(chunk)
+  }
+}
+
+// the listing printed by `pascalzim build --emit listing`
+impl fmt::Display for Chunk {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for (i, routine) in self.routines.iter().enumerate() {
+      writeln!(f, "routine {} {} entry {} params {}", i, routine.name, routine.entry, routine.params)?;
+      for (slot, (name, category, range)) in routine.slots.iter().enumerate() {
+        let type_name = match *range {
+          Some(range) => self.range_name(*category, range),
+          None => self.type_name(*category)
+        };
+        writeln!(f, "  slot {} {}: {}", slot, name, type_name)?;
+      }
+    }
+    for (i, (instr, line)) in self.code.iter().zip(self.lines.iter()).enumerate() {
+      writeln!(f, "{:04} {:>4}  {}", i, line, instr)?;
+    }
+    Ok(())
+  }
+}
+
+impl Chunk {
+  fn type_name(&self, category: Category) -> String {
+    match category {
+      Category::Enum(id, _) => self.enums[id as usize].name.clone(),
+      Category::Set(_) => match types::element_of(category) {
+        Some(element) => format!("set of {}", self.type_name(element)),
+        None => "[]".to_string()
+      },
+      _ => format!("{:?}", category).to_lowercase()
+    }
+  }
+
+  // `1..10` or `red..green`, `set of 1..3` for a set of a subrange
+  fn range_name(&self, category: Category, range: Subrange) -> String {
+    let name = |ordinal: i32| match category {
+      Category::Enum(id, _) => self.enums[id as usize].values[ordinal as usize].clone(),
+      Category::Boolean => (ordinal == 1).to_string(),
+      _ => ordinal.to_string()
+    };
+    match types::element_of(category) {
+      Some(element) => format!("set of {}", self.range_name(element, range)),
+      None => format!("{}..{}", name(range.first), name(range.last))
+    }
+  }
+}
+
+fn put_u32(out: &mut Vec<u8>, n: u32) {
+  out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+  put_u32(out, s.len() as u32);
+  out.extend_from_slice(s.as_bytes());
+}
+
+fn put_instr(out: &mut Vec<u8>, instr: &Instr) {
+  match *instr {
+    Instr::Int(i) => { out.push(0); out.extend_from_slice(&i.to_le_bytes()); },
+    Instr::Real(r) => { out.push(1); out.extend_from_slice(&r.to_bits().to_le_bytes()); },
+    Instr::Bool(b) => { out.push(2); out.push(b as u8); },
+    Instr::Load(depth, slot) => { out.push(3); out.push(depth); out.extend_from_slice(&slot.to_le_bytes()); },
+    Instr::Store(depth, slot) => { out.push(4); out.push(depth); out.extend_from_slice(&slot.to_le_bytes()); },
+    Instr::Jump(target) => { out.push(5); put_u32(out, target); },
+    Instr::JumpIfFalse(target) => { out.push(6); put_u32(out, target); },
+    Instr::JumpIfTrue(target) => { out.push(7); put_u32(out, target); },
+    Instr::Call(depth, routine) => { out.push(8); out.push(depth); out.extend_from_slice(&routine.to_le_bytes()); },
+    Instr::Succ(last) => { out.push(9); out.extend_from_slice(&last.to_le_bytes()); },
+    Instr::Pred(last) => { out.push(10); out.extend_from_slice(&last.to_le_bytes()); },
+    Instr::Check(first, last) => { out.push(11); out.extend_from_slice(&first.to_le_bytes()); out.extend_from_slice(&last.to_le_bytes()); },
+    _ => out.push(SIMPLE.iter().position(|simple| simple == instr).unwrap() as u8 + 16)
+  }
+}
+
+// instructions without operands, encoded by their position from 16 on
+const SIMPLE: [Instr; 26] = [
+  Instr::ToReal, Instr::Neg, Instr::Not, Instr::Add, Instr::Sub, Instr::Mul, Instr::Div,
+  Instr::IntDiv, Instr::Mod, Instr::Pow, Instr::And, Instr::Or, Instr::Imp, Instr::Equal,
+  Instr::NotEqual, Instr::Less, Instr::LessEqual, Instr::Greater, Instr::GreaterEqual,
+  Instr::Return, Instr::Halt, Instr::Ord, Instr::EmptySet, Instr::Include, Instr::IncludeRange,
+  Instr::In
+];
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+  position: usize
+}
+
+impl<'a> Reader<'a> {
+  fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+    let bytes = self.bytes.get(self.position..self.position + n).ok_or_else(|| "Truncated bytecode".to_string())?;
+    self.position += n;
+    Ok(bytes)
+  }
+
+  fn u8(&mut self) -> Result<u8, String> {
+    Ok(self.take(1)?[0])
+  }
+
+  fn u16(&mut self) -> Result<u16, String> {
+    let bytes = self.take(2)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+  }
+
+  fn u32(&mut self) -> Result<u32, String> {
+    let bytes = self.take(4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+  }
+
+  fn u64(&mut self) -> Result<u64, String> {
+    Ok(self.u32()? as u64 | (self.u32()? as u64) << 32)
+  }
+
+  fn string(&mut self) -> Result<String, String> {
+    let len = self.u32()? as usize;
+    String::from_utf8(self.take(len)?.to_vec()).map_err(|_| "Invalid name in bytecode".to_string())
+  }
+
+  fn instr(&mut self) -> Result<Instr, String> {
+    Ok(match self.u8()? {
+      0 => Instr::Int(self.u32()? as i32),
+      1 => Instr::Real(f64::from_bits(self.u64()?)),
+      2 => Instr::Bool(self.u8()? != 0),
+      3 => Instr::Load(self.u8()?, self.u16()?),
+      4 => Instr::Store(self.u8()?, self.u16()?),
+      5 => Instr::Jump(self.u32()?),
+      6 => Instr::JumpIfFalse(self.u32()?),
+      7 => Instr::JumpIfTrue(self.u32()?),
+      8 => Instr::Call(self.u8()?, self.u16()?),
+      9 => Instr::Succ(self.u32()? as i32),
+      10 => Instr::Pred(self.u32()? as i32),
+      11 => Instr::Check(self.u32()? as i32, self.u32()? as i32),
+      n if n >= 16 && ((n - 16) as usize) < SIMPLE.len() => SIMPLE[(n - 16) as usize],
+      n => return Err(format!("Unknown opcode {}", n))
+    })
+  }
+}
+
+fn key(name: &str) -> String {
+  name.to_lowercase()
+}
+
+#[derive(Default)]
+struct Scope {
+  variables: HashMap<String, (u16, Category)>,
+  procedures: HashMap<String, u16>
+}
+
+struct Compiler {
+  chunk: Chunk,
+  // one scope per routine being compiled, the innermost last
+  scopes: Vec<Scope>,
+  line: u32
+}
+
+pub fn compile(program: &Program) -> Result<Chunk, Diagnostic> {
+  let mut compiler = Compiler {
+    chunk: Chunk { routines: Vec::new(), code: Vec::new(), lines: Vec::new(), enums: program.enums.clone() },
+    scopes: Vec::new(),
+    line: 0
+  };
+  compiler.chunk.routines.push(Routine { name: program.name.clone(), entry: 0, params: 0, slots: slots(&[], &program.block) });
+  compiler.routine(0, &program.block, Instr::Halt)?;
+  Ok(compiler.chunk)
+}
+
+// parameters first, then the variables
+fn slots(params: &[Declaration], block: &Block) -> Vec<(String, Category, Option<Subrange>)> {
+  params.iter().chain(block.variables.iter())
+    .flat_map(|decl| decl.names.iter().map(move |name| (name.clone(), decl.category, decl.range)))
+    .collect()
+}
+
+impl Compiler {
+  fn routine(&mut self, index: usize, block: &Block, last: Instr) -> Result<(), Diagnostic> {
+    let mut scope = Scope::default();
+
+    for (slot, &(ref name, category, _)) in self.chunk.routines[index].slots.iter().enumerate() {
+      scope.variables.insert(key(name), (slot as u16, category));
+    }
+    // registered before the body so calls know their callee
+    for procedure in block.procedures.iter() {
+      let routine = self.chunk.routines.len();
+      if routine > u16::MAX as usize {
+        return Err(Diagnostic::error("Too many procedures".to_string(), procedure.line));
+      }
+      let params: usize = procedure.params.iter().map(|decl| decl.names.len()).sum();
+      scope.procedures.insert(key(&procedure.name), routine as u16);
+      self.chunk.routines.push(Routine {
+        name: procedure.name.clone(),
+        entry: 0,
+        params: params as u16,
+        slots: slots(&procedure.params, &procedure.block)
+      });
+    }
+
+    self.chunk.routines[index].entry = self.chunk.code.len() as u32;
+    self.scopes.push(scope);
+    for command in block.body.iter() {
+      self.command(command)?;
+    }
+    self.emit(last);
+
+    for procedure in block.procedures.iter() {
+      let routine = self.scopes.last().unwrap().procedures[&key(&procedure.name)];
+      self.line = procedure.line;
+      self.routine(routine as usize, &procedure.block, Instr::Return)?;
+    }
+    self.scopes.pop();
+    Ok(())
+  }
+
+  fn emit(&mut self, instr: Instr) -> usize {
+    self.chunk.code.push(instr);
+    self.chunk.lines.push(self.line);
+    self.chunk.code.len() - 1
+  }
+
+  // points the jump at `index` to the next instruction
+  fn patch(&mut self, index: usize) {
+    let target = self.chunk.code.len() as u32;
+    self.chunk.code[index] = match self.chunk.code[index] {
+      Instr::Jump(_) => Instr::Jump(target),
+      Instr::JumpIfFalse(_) => Instr::JumpIfFalse(target),
+      Instr::JumpIfTrue(_) => Instr::JumpIfTrue(target),
+      instr => instr
+    };
+  }
+
+  // frame distance and slot of a variable
+  fn variable(&self, name: &str, line: u32) -> Result<(u8, u16, Category), Diagnostic> {
+    let lowercase = key(name);
+    for (depth, scope) in self.scopes.iter().rev().enumerate() {
+      if let Some(&(slot, category)) = scope.variables.get(&lowercase) {
+        return Ok((self.depth(depth, line)?, slot, category));
+      }
+    }
+    Err(Diagnostic::error(format!("Identifier `{}` not declared", name), line))
+  }
+
+  fn procedure(&self, name: &str, line: u32) -> Result<(u8, u16), Diagnostic> {
+    let lowercase = key(name);
+    for (depth, scope) in self.scopes.iter().rev().enumerate() {
+      if let Some(&routine) = scope.procedures.get(&lowercase) {
+        return Ok((self.depth(depth, line)?, routine));
+      }
+    }
+    Err(Diagnostic::error(format!("Procedure `{}` not declared", name), line))
+  }
+
+  fn depth(&self, depth: usize, line: u32) -> Result<u8, Diagnostic> {
+    if depth > u8::MAX as usize {
+      return Err(Diagnostic::error("Procedures nested too deeply".to_string(), line));
+    }
+    Ok(depth as u8)
+  }
+
+  fn command(&mut self, command: &Command) -> Result<(), Diagnostic> {
+    match *command {
+      Command::Assign { ref name, ref value, line } => {
+        let (depth, slot, category) = self.variable(name, line)?;
+        self.expr_as(value, category)?;
+        self.line = line;
+        self.emit(Instr::Store(depth, slot));
+      },
+      Command::Call { ref name, ref args, line } => {
+        let (depth, routine) = self.procedure(name, line)?;
+        let categories: Vec<Category> = {
+          let routine = &self.chunk.routines[routine as usize];
+          routine.slots.iter().take(routine.params as usize).map(|slot| slot.1).collect()
+        };
+        if categories.len() != args.len() {
+          let message = format!("Procedure `{}` expects {} argument(s) found {}", name, categories.len(), args.len());
+          return Err(Diagnostic::error(message, line));
+        }
+        for (arg, category) in args.iter().zip(categories) {
+          self.expr_as(arg, category)?;
+        }
+        self.line = line;
+        self.emit(Instr::Call(depth, routine));
+      },
+      Command::Compound(ref commands) => {
+        for command in commands.iter() {
+          self.command(command)?;
+        }
+      },
+      Command::If { ref condition, ref then_branch, ref else_branch, .. } => {
+        self.expr(condition)?;
+        let jump = self.emit(Instr::JumpIfFalse(0));
+        self.command(then_branch)?;
+        match *else_branch {
+          Some(ref command) => {
+            let skip = self.emit(Instr::Jump(0));
+            self.patch(jump);
+            self.command(command)?;
+            self.patch(skip);
+          },
+          None => self.patch(jump)
+        }
+      },
+      Command::While { ref condition, ref body, .. } => {
+        let start = self.chunk.code.len() as u32;
+        self.expr(condition)?;
+        let jump = self.emit(Instr::JumpIfFalse(0));
+        self.command(body)?;
+        self.emit(Instr::Jump(start));
+        self.patch(jump);
+      }
+    }
+    Ok(())
+  }
+
+  // value converted to a real when it is stored in a real
+  fn expr_as(&mut self, expr: &Expr, category: Category) -> Result<(), Diagnostic> {
+    self.expr(expr)?;
+    if category == Category::Real && expr.category == Category::Integer {
+      self.emit(Instr::ToReal);
+    }
+    Ok(())
+  }
+
+  fn expr(&mut self, expr: &Expr) -> Result<(), Diagnostic> {
+    self.line = expr.line;
+
+    match expr.kind {
+      ExprKind::Integer(i) => { self.emit(Instr::Int(i)); },
+      ExprKind::Real(r) => { self.emit(Instr::Real(r)); },
+      ExprKind::Boolean(b) => { self.emit(Instr::Bool(b)); },
+      ExprKind::Constant(_, ordinal) => { self.emit(Instr::Int(ordinal)); },
+      ExprKind::Variable(ref name) => {
+        let (depth, slot, _) = self.variable(name, expr.line)?;
+        self.emit(Instr::Load(depth, slot));
+      },
+      ExprKind::Call(ref name, _) => {
+        return Err(Diagnostic::error(format!("Procedure `{}` doesn't return a value", name), expr.line));
+      },
+      ExprKind::Builtin(function, ref argument) => {
+        self.expr(argument)?;
+        self.line = expr.line;
+        let last = last_ordinal(argument.category);
+        self.emit(match function {
+          Builtin::Ord => Instr::Ord,
+          Builtin::Succ => Instr::Succ(last),
+          Builtin::Pred => Instr::Pred(last)
+        });
+      },
+      ExprKind::RangeCheck(range, ref value) => {
+        self.expr(value)?;
+        self.line = expr.line;
+        self.emit(Instr::Check(range.first, range.last));
+      },
+      ExprKind::Set(ref items) => {
+        self.emit(Instr::EmptySet);
+        for (first, last) in items {
+          self.expr(first)?;
+          if let Some(ref last) = *last {
+            self.expr(last)?;
+          }
+          self.line = expr.line;
+          self.emit(if last.is_some() { Instr::IncludeRange } else { Instr::Include });
+        }
+      },
+      ExprKind::Unary(ref op, ref operand) => {
+        self.expr(operand)?;
+        self.line = expr.line;
+        match *op {
+          Token::Not => { self.emit(Instr::Not); },
+          Token::Sub => { self.emit(Instr::Neg); },
+          _ => {}
+        }
+      },
+      ExprKind::Logical(ref op, BooleanEval::ShortCircuit, ref lhs, ref rhs) => self.short_circuit(op, lhs, rhs, expr.line)?,
+      ExprKind::Logical(ref op, BooleanEval::Full, ref lhs, ref rhs) => {
+        self.expr(lhs)?;
+        self.expr(rhs)?;
+        self.line = expr.line;
+        self.emit(match *op {
+          Token::And => Instr::And,
+          Token::Or => Instr::Or,
+          _ => Instr::Imp
+        });
+      },
+      ExprKind::Binary(ref op, ref lhs, ref rhs) => {
+        // both operands become reals when either side or the result is real
+        let real = [lhs.category, rhs.category, expr.category].contains(&Category::Real);
+        let category = if real { Category::Real } else { Category::Undefined };
+        self.expr_as(lhs, category)?;
+        self.expr_as(rhs, category)?;
+        self.line = expr.line;
+        let instr = match *op {
+          // booleans compare as they are, `xor` is `<>` and `<->` is `=`
+          Token::Equal | Token::Equiv => Instr::Equal,
+          Token::NotEqual | Token::Xor => Instr::NotEqual,
+          Token::LessThan => Instr::Less,
+          Token::LessThanOrEqual => Instr::LessEqual,
+          Token::GreaterThan => Instr::Greater,
+          Token::GreaterThanOrEqual => Instr::GreaterEqual,
+          Token::Add => Instr::Add,
+          Token::Sub => Instr::Sub,
+          Token::Mult => Instr::Mul,
+          Token::Div => Instr::Div,
+          Token::IntDiv => Instr::IntDiv,
+          Token::Mod => Instr::Mod,
+          Token::Power => Instr::Pow,
+          Token::In => Instr::In,
+          _ => return Err(Diagnostic::internal(format!("operator `{}`", op), expr.line))
+        };
+        self.emit(instr);
+      }
+    }
+    Ok(())
+  }
+
+  // the right operand is jumped over once the left one decides the result
+  fn short_circuit(&mut self, op: &Token, lhs: &Expr, rhs: &Expr, line: u32) -> Result<(), Diagnostic> {
+    self.expr(lhs)?;
+    self.line = line;
+    let (jump, decided) = match *op {
+      Token::And => (self.emit(Instr::JumpIfFalse(0)), false),
+      Token::Or => (self.emit(Instr::JumpIfTrue(0)), true),
+      _ => (self.emit(Instr::JumpIfFalse(0)), true)
+    };
+    self.expr(rhs)?;
+    self.line = line;
+    let skip = self.emit(Instr::Jump(0));
+    self.patch(jump);
+    self.emit(Instr::Bool(decided));
+    self.patch(skip);
+    Ok(())
+  }
+}
+
+#[test]
+fn test_bytecode_program23() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program23.txt");
+  let chunk = compile(p1.program().unwrap()).unwrap();
+
+  assert_eq!(chunk.routines.len(), 2);
+  assert_eq!(chunk.routines[1].name, "fat");
+  assert_eq!(chunk.routines[1].params, 1);
+  assert_eq!(chunk.code[..3], [Instr::Int(5), Instr::Store(0, 0), Instr::Int(1)]);
+  assert!(chunk.code.contains(&Instr::Call(1, 1)));
+
+  let listing = chunk.to_string();
+  assert!(listing.starts_with("routine 0 fatorial entry 0 params 0\n  slot 0 n: integer\n"));
+  assert!(listing.contains("0000   12  int 5\n"));
+}
+
+#[test]
+fn test_bytecode_round_trip() {
+  use parser::Parser;
+
+  for n in [10, 23, 24, 28, 35, 36, 37] {
+    let mut p1: Parser = Parser::new();
+    p1.build_ast(&format!("files/program{}.txt", n));
+    let chunk = compile(p1.program().unwrap()).unwrap();
+    let bytes = chunk.to_bytes();
+
+    assert!(Chunk::is_bytecode(&bytes));
+    assert_eq!(Chunk::from_bytes(&bytes), Ok(chunk));
+    assert_eq!(Chunk::from_bytes(&bytes[..bytes.len() - 1]), Err("Truncated bytecode".to_string()));
+  }
+  assert_eq!(Chunk::from_bytes(b"program"), Err("Not a pascalzim bytecode file".to_string()));
+}
@@ -0,0 +1,582 @@
+use std::collections::HashMap;
+use ast::*;
+use diagnostic::Diagnostic;
+use interpreter::last_ordinal;
+use options::BooleanEval;
+use parser::Category;
+use spec::Token;
+use types;
+
+// runtime support copied at the top of every generated file, errors and
+// output follow `pascalzim run`
+const PRELUDE: &str = r#"#include <math.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+static inline void pz_error(const char *message, int line) {
+  fflush(stdout);
+  fprintf(stderr, "Error: %s => line %d\n", message, line);
+  exit(3);
+}
+
+static inline int32_t pz_check(int64_t value, int line) {
+  if (value < INT32_MIN || value > INT32_MAX) pz_error("Integer overflow", line);
+  return (int32_t)value;
+}
+
+static inline int32_t pz_add(int32_t a, int32_t b, int line) { return pz_check((int64_t)a + b, line); }
+static inline int32_t pz_sub(int32_t a, int32_t b, int line) { return pz_check((int64_t)a - b, line); }
+static inline int32_t pz_mul(int32_t a, int32_t b, int line) { return pz_check((int64_t)a * b, line); }
+static inline int32_t pz_neg(int32_t a, int line) { return pz_check(-(int64_t)a, line); }
+
+static inline int32_t pz_div(int32_t a, int32_t b, int line) {
+  if (b == 0) pz_error("Division by zero", line);
+  return pz_check((int64_t)a / b, line);
+}
+
+static inline int32_t pz_mod(int32_t a, int32_t b, int line) {
+  if (b == 0) pz_error("Division by zero", line);
+  if (a == INT32_MIN && b == -1) pz_error("Integer overflow", line);
+  return a % b;
+}
+
+static inline int32_t pz_pow(int32_t base, int32_t exp, int line) {
+  int32_t result = 1;
+  if (exp < 0) pz_error("Integer overflow", line);
+  if (exp == 0) return 1;
+  while (exp > 1) {
+    if (exp & 1) result = pz_mul(result, base, line);
+    exp /= 2;
+    base = pz_mul(base, base, line);
+  }
+  return pz_mul(result, base, line);
+}
+
+/* `succ` and `pred`, `last` is the highest ordinal of the type */
+static inline int32_t pz_succ(int32_t value, int32_t last, int line) {
+  if (value >= last) pz_error(last == INT32_MAX ? "Integer overflow" : "Value out of range in `succ`", line);
+  return value + 1;
+}
+
+static inline int32_t pz_pred(int32_t value, int32_t last, int line) {
+  if (last == INT32_MAX ? value == INT32_MIN : value <= 0) pz_error(last == INT32_MAX ? "Integer overflow" : "Value out of range in `pred`", line);
+  return value - 1;
+}
+
+/* `{$R+}`, a value assigned or passed to a subrange */
+static inline int32_t pz_range(int32_t value, int32_t first, int32_t last, int line) {
+  if (value < first || value > last) pz_error("Value out of range", line);
+  return value;
+}
+
+/* sets are bitsets, bit `n` for the ordinal `n` */
+static inline uint64_t pz_bits(int32_t first, int32_t last, int line) {
+  if (last < first) return 0;
+  if (first < 0 || last > 63) pz_error("Set element out of range", line);
+  return (UINT64_MAX >> (63 - (last - first))) << first;
+}
+
+static inline uint64_t pz_bit(int32_t value, int line) { return pz_bits(value, value, line); }
+
+/* `{$R+}`, a set assigned or passed to a set of a subrange */
+static inline uint64_t pz_range_set(uint64_t set, int32_t first, int32_t last, int line) {
+  if (set & ~pz_bits(first, last, line)) pz_error("Set element out of range", line);
+  return set;
+}
+
+static inline int pz_in(int32_t value, uint64_t set) {
+  return value >= 0 && value <= 63 && (set >> value & 1);
+}
+
+static inline double pz_rdiv(double a, double b, int line) {
+  if (b == 0.0) pz_error("Division by zero", line);
+  return a / b;
+}
+
+static inline void pz_show_integer(const char *name, int32_t value) {
+  printf("%s = %ld\n", name, (long)value);
+}
+
+static inline void pz_show_boolean(const char *name, int value) {
+  printf("%s = %s\n", name, value ? "true" : "false");
+}
+
+static inline void pz_show_enum(const char *name, const char *const *values, int32_t value) {
+  printf("%s = %s\n", name, values[value]);
+}
+
+/* `values` names the elements of sets of enumerations or booleans */
+static inline void pz_show_set(const char *name, const char *const *values, uint64_t set) {
+  int n, first = 1;
+  printf("%s = [", name);
+  for (n = 0; n < 64; n++) {
+    if (!(set >> n & 1)) continue;
+    if (!first) printf(", ");
+    if (values) printf("%s", values[n]); else printf("%d", n);
+    first = 0;
+  }
+  printf("]\n");
+}
+
+/* shortest digits that read back as the same value, without exponent */
+static inline void pz_show_real(const char *name, double value) {
+  char buffer[32], digits[20], *p;
+  int precision, exponent, count = 0, i;
+  if (value != value) { printf("%s = NaN\n", name); return; }
+  if (value == HUGE_VAL || value == -HUGE_VAL) { printf("%s = %sinf\n", name, value < 0 ? "-" : ""); return; }
+  for (precision = 0; ; precision++) {
+    snprintf(buffer, sizeof buffer, "%.*e", precision, value);
+    if (precision == 16 || strtod(buffer, NULL) == value) break;
+  }
+  p = buffer[0] == '-' ? buffer + 1 : buffer;
+  for (; *p != 'e'; p++) if (*p != '.') digits[count++] = *p;
+  exponent = atoi(p + 1);
+  printf("%s = %s", name, buffer[0] == '-' ? "-" : "");
+  if (exponent < 0) {
+    printf("0.");
+    for (i = -1; i > exponent; i--) putchar('0');
+    printf("%.*s", count, digits);
+  } else {
+    for (i = 0; i <= exponent || i < count; i++) {
+      if (i == exponent + 1) putchar('.');
+      putchar(i < count ? digits[i] : '0');
+    }
+  }
+  putchar('\n');
+}
+"#;
+
+struct Scope {
+  // frame struct of the routine, `f0` for the program
+  frame: usize,
+  variables: HashMap<String, Category>,
+  // C function and frame struct of each procedure
+  procedures: HashMap<String, (String, usize)>
+}
+
+struct Generator {
+  structs: String,
+  prototypes: String,
+  functions: String,
+  scopes: Vec<Scope>,
+  frames: usize
+}
+
+// C99 translation of a program, nested procedures get the frame of their
+// enclosing routine as first argument so they can reach its variables
+pub fn generate(program: &Program) -> Result<String, Diagnostic> {
+  let mut generator = Generator {
+    structs: String::new(),
+    prototypes: String::new(),
+    functions: String::new(),
+    scopes: Vec::new(),
+    frames: 1
+  };
+  generator.routine(0, None, &[], &program.block)?;
+
+  let mut out = format!("/* generated by pascalzim from program {} */\n", program.name);
+  out.push_str(PRELUDE);
+  // names of the values of each enumeration, to show them
+  for (id, enumeration) in program.enums.iter().enumerate() {
+    let values: Vec<String> = enumeration.values.iter().map(|value| format!("\"{}\"", value)).collect();
+    out.push_str(&format!("\nstatic const char *const pz_enum{}[] = {{ {} }};\n", id, values.join(", ")));
+  }
+  for section in [&generator.structs, &generator.prototypes, &generator.functions] {
+    out.push('\n');
+    out.push_str(section);
+  }
+  Ok(out)
+}
+
+fn key(name: &str) -> String {
+  name.to_lowercase()
+}
+
+// Pascal names are case-insensitive and may have non-ASCII letters
+fn c_name(name: &str) -> String {
+  key(name).chars().map(|c| {
+    if c.is_ascii_alphanumeric() || c == '_' { c.to_string() } else { format!("_u{:04x}", c as u32) }
+  }).collect()
+}
+
+fn c_type(category: Category) -> &'static str {
+  match category {
+    Category::Integer | Category::Enum(..) => "int32_t",
+    Category::Real => "double",
+    Category::Set(_) => "uint64_t",
+    _ => "int"
+  }
+}
+
+// zero for every variable but the subranges that leave out 0, which start at
+// their lower bound
+fn initialiser(block: &Block) -> String {
+  let mut fields = Vec::new();
+  for decl in block.variables.iter() {
+    match decl.range {
+      Some(range) if range.first != 0 && !types::is_set(decl.category) => {
+        fields.extend(decl.names.iter().map(|name| format!(".v_{} = {}", c_name(name), range.first)));
+      },
+      _ => {}
+    }
+  }
+  if fields.is_empty() {
+    "{ 0 }".to_string()
+  } else {
+    format!("{{ {} }}", fields.join(", "))
+  }
+}
+
+impl Generator {
+  // `function` is None for the program, which becomes `main`
+  fn routine(&mut self, frame: usize, function: Option<&str>, params: &[Declaration], block: &Block) -> Result<(), Diagnostic> {
+    let up = match self.scopes.last() {
+      Some(scope) => format!("struct f{} *up", scope.frame),
+      None => "void *up".to_string()
+    };
+    let mut scope = Scope { frame, variables: HashMap::new(), procedures: HashMap::new() };
+
+    self.structs.push_str(&format!("struct f{} {{\n  {};\n", frame, up));
+    for decl in params.iter().chain(block.variables.iter()) {
+      for name in decl.names.iter() {
+        self.structs.push_str(&format!("  {} v_{};\n", c_type(decl.category), c_name(name)));
+        scope.variables.insert(key(name), decl.category);
+      }
+    }
+    self.structs.push_str("};\n\n");
+
+    for procedure in block.procedures.iter() {
+      let name = format!("p{}_{}", self.frames, c_name(&procedure.name));
+      scope.procedures.insert(key(&procedure.name), (name, self.frames));
+      self.frames += 1;
+    }
+    self.scopes.push(scope);
+
+    let mut body = String::new();
+    for command in block.body.iter() {
+      self.command(command, 1, &mut body)?;
+    }
+
+    match function {
+      Some(function) => {
+        let mut signature = format!("static void {}({}", function, up);
+        let mut copies = String::new();
+        let names = params.iter().flat_map(|decl| decl.names.iter().map(move |name| (name, decl.category)));
+        for (i, (name, category)) in names.enumerate() {
+          signature.push_str(&format!(", {} a{}", c_type(category), i));
+          copies.push_str(&format!("  fr.v_{} = a{};\n", c_name(name), i));
+        }
+        signature.push(')');
+        self.prototypes.push_str(&format!("{};\n", signature));
+        self.functions.push_str(&format!("{} {{\n  struct f{} fr = {};\n  fr.up = up;\n{}{}}}\n\n", signature, frame, initialiser(block), copies, body));
+      },
+      None => {
+        let mut show = String::new();
+        for decl in block.variables.iter() {
+          let (kind, values) = match decl.category {
+            Category::Enum(id, _) => ("enum".to_string(), format!("pz_enum{}, ", id)),
+            Category::Set(_) => ("set".to_string(), match types::element_of(decl.category) {
+              Some(Category::Enum(id, _)) => format!("pz_enum{}, ", id),
+              Some(Category::Boolean) => "(const char *const[]){ \"false\", \"true\" }, ".to_string(),
+              _ => "NULL, ".to_string()
+            }),
+            category => (format!("{:?}", category).to_lowercase(), String::new())
+          };
+          for name in decl.names.iter() {
+            show.push_str(&format!("  pz_show_{}(\"{}\", {}fr.v_{});\n", kind, name, values, c_name(name)));
+          }
+        }
+        self.functions.push_str(&format!("int main(void) {{\n  struct f0 fr = {};\n{}{}  return 0;\n}}\n", initialiser(block), body, show));
+      }
+    }
+
+    for procedure in block.procedures.iter() {
+      let (name, frame) = self.scopes.last().unwrap().procedures[&key(&procedure.name)].clone();
+      self.routine(frame, Some(&name), &procedure.params, &procedure.block)?;
+    }
+    self.scopes.pop();
+    Ok(())
+  }
+
+  // `fr`, `fr.up`, `fr.up->up`... for the frame `depth` routines out
+  fn frame(&self, depth: usize) -> String {
+    let mut path = "fr".to_string();
+    for i in 0..depth {
+      path.push_str(if i == 0 { ".up" } else { "->up" });
+    }
+    path
+  }
+
+  fn variable(&self, name: &str, line: u32) -> Result<(String, Category), Diagnostic> {
+    for (depth, scope) in self.scopes.iter().rev().enumerate() {
+      if let Some(&category) = scope.variables.get(&key(name)) {
+        let separator = if depth == 0 { "." } else { "->" };
+        return Ok((format!("{}{}v_{}", self.frame(depth), separator, c_name(name)), category));
+      }
+    }
+    Err(Diagnostic::error(format!("Identifier `{}` not declared", name), line))
+  }
+
+  fn command(&self, command: &Command, indent: usize, out: &mut String) -> Result<(), Diagnostic> {
+    let pad = "  ".repeat(indent);
+
+    match *command {
+      Command::Assign { ref name, ref value, line } => {
+        let (target, _) = self.variable(name, line)?;
+        out.push_str(&format!("{}{} = {};\n", pad, target, self.expr(value)?));
+      },
+      Command::Call { ref name, ref args, line } => {
+        let found = self.scopes.iter().rev().enumerate()
+          .find_map(|(depth, scope)| scope.procedures.get(&key(name)).map(|procedure| (depth, procedure)));
+        let (depth, (function, _)) = found
+          .ok_or_else(|| Diagnostic::error(format!("Procedure `{}` not declared", name), line))?;
+        let mut call = format!("{}{}({}", pad, function, if depth == 0 { "&fr".to_string() } else { self.frame(depth) });
+        for arg in args.iter() {
+          call.push_str(&format!(", {}", self.expr(arg)?));
+        }
+        out.push_str(&call);
+        out.push_str(");\n");
+      },
+      Command::Compound(ref commands) => {
+        for command in commands.iter() {
+          self.command(command, indent, out)?;
+        }
+      },
+      Command::If { ref condition, ref then_branch, ref else_branch, .. } => {
+        out.push_str(&format!("{}if ({}) {{\n", pad, self.expr(condition)?));
+        self.command(then_branch, indent + 1, out)?;
+        if let Some(ref command) = *else_branch {
+          out.push_str(&format!("{}}} else {{\n", pad));
+          self.command(command, indent + 1, out)?;
+        }
+        out.push_str(&format!("{}}}\n", pad));
+      },
+      Command::While { ref condition, ref body, .. } => {
+        out.push_str(&format!("{}while ({}) {{\n", pad, self.expr(condition)?));
+        self.command(body, indent + 1, out)?;
+        out.push_str(&format!("{}}}\n", pad));
+      }
+    }
+    Ok(())
+  }
+
+  fn expr(&self, expr: &Expr) -> Result<String, Diagnostic> {
+    let line = expr.line;
+
+    Ok(match expr.kind {
+      ExprKind::Integer(i) if i == i32::MIN => "(-2147483647 - 1)".to_string(),
+      ExprKind::Integer(i) => i.to_string(),
+      ExprKind::Real(r) => format!("{:?}", r),
+      ExprKind::Boolean(b) => (if b { "1" } else { "0" }).to_string(),
+      ExprKind::Constant(_, ordinal) => ordinal.to_string(),
+      ExprKind::Variable(ref name) => self.variable(name, line)?.0,
+      ExprKind::Call(ref name, _) => {
+        return Err(Diagnostic::error(format!("Procedure `{}` doesn't return a value", name), line));
+      },
+      ExprKind::Builtin(function, ref argument) => {
+        let value = self.expr(argument)?;
+        let last = last_ordinal(argument.category);
+        match function {
+          Builtin::Ord => format!("((int32_t){})", value),
+          Builtin::Succ => format!("pz_succ({}, {}, {})", value, last, line),
+          Builtin::Pred => format!("pz_pred({}, {}, {})", value, last, line)
+        }
+      },
+      ExprKind::RangeCheck(range, ref value) if types::is_set(value.category) => {
+        format!("pz_range_set({}, {}, {}, {})", self.expr(value)?, range.first, range.last, line)
+      },
+      ExprKind::RangeCheck(range, ref value) => {
+        format!("pz_range({}, {}, {}, {})", self.expr(value)?, range.first, range.last, line)
+      },
+      ExprKind::Set(ref items) => {
+        let mut set = "(UINT64_C(0)".to_string();
+        for (first, last) in items {
+          set.push_str(&match *last {
+            Some(ref last) => format!(" | pz_bits({}, {}, {})", self.expr(first)?, self.expr(last)?, line),
+            None => format!(" | pz_bit({}, {})", self.expr(first)?, line)
+          });
+        }
+        set.push(')');
+        set
+      },
+      ExprKind::Unary(ref op, ref operand) => {
+        let value = self.expr(operand)?;
+        match *op {
+          Token::Not => format!("!{}", value),
+          Token::Sub if operand.category == Category::Integer => format!("pz_neg({}, {})", value, line),
+          Token::Sub => format!("(-{})", value),
+          _ => value
+        }
+      },
+      ExprKind::Logical(ref op, mode, ref lhs, ref rhs) => {
+        let (a, b) = (self.expr(lhs)?, self.expr(rhs)?);
+        let full = mode == BooleanEval::Full;
+        match *op {
+          // booleans are 0 or 1, `&` and `|` evaluate both sides
+          Token::And => format!("({} {} {})", a, if full { "&" } else { "&&" }, b),
+          Token::Or => format!("({} {} {})", a, if full { "|" } else { "||" }, b),
+          _ if full => format!("((!{}) | {})", a, b),
+          _ => format!("(!{} || {})", a, b)
+        }
+      },
+      ExprKind::Binary(ref op, ref lhs, ref rhs) => {
+        // both operands become reals when either side or the result is real
+        let real = [lhs.category, rhs.category, expr.category].contains(&Category::Real);
+        let operand = |e: &Expr| -> Result<String, Diagnostic> {
+          let value = self.expr(e)?;
+          Ok(if real && e.category == Category::Integer { format!("(double){}", value) } else { value })
+        };
+        let (a, b) = (operand(lhs)?, operand(rhs)?);
+
+        if types::is_set(lhs.category) {
+          return Ok(match *op {
+            Token::Add => format!("({} | {})", a, b),
+            Token::Mult => format!("({} & {})", a, b),
+            Token::Sub => format!("({} & ~{})", a, b),
+            Token::Equal => format!("({} == {})", a, b),
+            Token::NotEqual => format!("({} != {})", a, b),
+            // subset and superset
+            Token::LessThanOrEqual => format!("(({} & ~{}) == 0)", a, b),
+            _ => format!("(({} & ~{}) == 0)", b, a)
+          });
+        }
+
+        match *op {
+          Token::In => format!("pz_in({}, {})", a, b),
+          Token::Equal | Token::Equiv => format!("({} == {})", a, b),
+          Token::NotEqual | Token::Xor => format!("({} != {})", a, b),
+          Token::LessThan => format!("({} < {})", a, b),
+          Token::LessThanOrEqual => format!("({} <= {})", a, b),
+          Token::GreaterThan => format!("({} > {})", a, b),
+          Token::GreaterThanOrEqual => format!("({} >= {})", a, b),
+          Token::Add if real => format!("({} + {})", a, b),
+          Token::Sub if real => format!("({} - {})", a, b),
+          Token::Mult if real => format!("({} * {})", a, b),
+          Token::Div if real => format!("pz_rdiv({}, {}, {})", a, b, line),
+          Token::Power if real => format!("pow({}, {})", a, b),
+          Token::Add => format!("pz_add({}, {}, {})", a, b, line),
+          Token::Sub => format!("pz_sub({}, {}, {})", a, b, line),
+          Token::Mult => format!("pz_mul({}, {}, {})", a, b, line),
+          Token::Div | Token::IntDiv => format!("pz_div({}, {}, {})", a, b, line),
+          Token::Mod => format!("pz_mod({}, {}, {})", a, b, line),
+          _ => format!("pz_pow({}, {}, {})", a, b, line)
+        }
+      }
+    })
+  }
+}
+
+#[test]
+fn test_cgen_program23() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program23.txt");
+  let code = generate(p1.program().unwrap()).unwrap();
+
+  assert!(code.starts_with("/* generated by pascalzim from program fatorial */\n#include <math.h>\n"));
+  assert!(code.contains("struct f0 {\n  void *up;\n  int32_t v_n;\n  int32_t v_f;\n  double v_media;\n};\n"));
+  assert!(code.contains("struct f1 {\n  struct f0 *up;\n  int32_t v_k;\n};\n"));
+  assert!(code.contains("static void p1_fat(struct f0 *up, int32_t a0);\n"));
+  assert!(code.contains("  if ((fr.v_k > 1)) {\n    p1_fat(fr.up, pz_sub(fr.v_k, 1, 8));\n  }\n"));
+  assert!(code.contains("  fr.up->v_f = pz_mul(fr.up->v_f, fr.v_k, 9);\n"));
+  assert!(code.contains("  p1_fat(&fr, fr.v_n);\n  fr.v_media = pz_rdiv((double)fr.v_f, (double)4, 15);\n"));
+  assert!(code.contains("  pz_show_real(\"media\", fr.v_media);\n  return 0;\n}\n"));
+}
+
+#[test]
+fn test_cgen_boolean_eval() {
+  use options::Options;
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::with_options(Options { boolean_eval: BooleanEval::ShortCircuit, ..Options::default() });
+  p1.build_ast("files/program24.txt");
+  let code = generate(p1.program().unwrap()).unwrap();
+  assert!(code.contains("fr.v_ok = ((fr.v_b != 0) && (pz_div(fr.v_a, fr.v_b, 8) > 1));"));
+  assert!(code.contains("fr.v_imp = (!(fr.v_b != 0) || (pz_mod(fr.v_a, fr.v_b, 9) == 0));"));
+
+  let mut p2: Parser = Parser::new();
+  p2.build_ast("files/program24.txt");
+  let code = generate(p2.program().unwrap()).unwrap();
+  assert!(code.contains("fr.v_ok = ((fr.v_b != 0) & (pz_div(fr.v_a, fr.v_b, 8) > 1));"));
+
+  // each `and` follows the `{$B}` in force where it is written
+  let mut p3: Parser = Parser::new();
+  p3.build_ast("files/program38.txt");
+  let code = generate(p3.program().unwrap()).unwrap();
+  assert!(code.contains("fr.v_ok = ((fr.v_b != 0) && (pz_div(fr.v_a, fr.v_b, 9) > 1));"));
+  assert!(code.contains("fr.v_erro = ((fr.v_b != 0) & (pz_div(fr.v_a, fr.v_b, 11) > 1));"));
+}
+
+#[test]
+fn test_cgen_power() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program33.txt");
+  let code = generate(p1.program().unwrap()).unwrap();
+  assert!(code.contains("fr.v_a = pz_pow(2, pz_pow(3, 2, 5), 5);"));
+  assert!(code.contains("fr.v_c = pz_neg(pz_pow(2, 2, 7), 7);"));
+  assert!(code.contains("fr.v_r = pow((double)2, (double)pz_sub(fr.v_b, 20, 9));"));
+}
+
+#[test]
+fn test_cgen_logic() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program34.txt");
+  let code = generate(p1.program().unwrap()).unwrap();
+  assert!(code.contains("fr.v_a = ((!fr.v_p) | ((!fr.v_q) | fr.v_r));"));
+  assert!(code.contains("fr.v_c = ((fr.v_p != fr.v_q) | fr.v_r);"));
+  assert!(code.contains("fr.v_d = ((fr.v_p == fr.v_q) == fr.v_r);"));
+}
+
+#[test]
+fn test_cgen_enumerations() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program35.txt");
+  let code = generate(p1.program().unwrap()).unwrap();
+  assert!(code.contains("static const char *const pz_enum0[] = { \"red\", \"green\", \"blue\" };"));
+  assert!(code.contains("fr.v_c = 0;"));
+  assert!(code.contains("pz_show_enum(\"c\", pz_enum0, fr.v_c);"));
+}
+
+#[test]
+fn test_cgen_subranges() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program36.txt");
+  let code = generate(p1.program().unwrap()).unwrap();
+  assert!(code.contains("fr.v_d = 9;"));
+  assert!(code.contains("fr.up->v_e = pz_range(pz_add(fr.v_x, 1, 16), 0, 9, 16);"));
+  assert!(code.contains("fr.v_f = (fr.v_d > fr.v_e);"));
+  // `t: -5..5` starts at -5, the others at 0
+  assert!(code.contains("  struct f0 fr = { .v_t = -5 };\n"));
+  assert!(code.contains("  struct f1 fr = { 0 };\n"));
+}
+
+#[test]
+fn test_cgen_sets() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program37.txt");
+  let code = generate(p1.program().unwrap()).unwrap();
+  assert!(code.contains("  uint64_t v_a;\n"));
+  assert!(code.contains("fr.v_a = (UINT64_C(0) | pz_bit(1, 20) | pz_bits(3, 5, 20));"));
+  assert!(code.contains("fr.v_d = (fr.v_a & ~fr.v_b);"));
+  assert!(code.contains("fr.v_sub = (((UINT64_C(0) | pz_bit(3, 29) | pz_bit(4, 29)) & ~fr.v_a) == 0);"));
+  assert!(code.contains("fr.v_tem = (pz_in(fr.v_n, fr.v_a) & !pz_in(2, fr.v_a));"));
+  assert!(code.contains("pz_show_set(\"c\", pz_enum0, fr.v_c);"));
+  assert!(code.contains("pz_show_set(\"a\", NULL, fr.v_a);"));
+
+  let mut p2: Parser = Parser::new();
+  let program = p2.parse_str("{$R+} program p; var s: set of 1..3; n: integer; begin s := [n] end.").unwrap();
+  let code = generate(program).unwrap();
+  assert!(code.contains("fr.v_s = pz_range_set((UINT64_C(0) | pz_bit(fr.v_n, 1)), 1, 3, 1);"));
+}
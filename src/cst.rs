@@ -0,0 +1,960 @@
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+use std::io::Cursor;
+use lexer::{Piece, Scanner};
+use options::Encoding;
+use spec::{Token, Type};
+
+// Lossless syntax tree: every byte of the source, blanks, line breaks,
+// comments and directives included, belongs to exactly one token, so the
+// text is rebuilt exactly as it was read. Green nodes only know their kind
+// and text and can be shared, red nodes add the parent and the offset.
+// Nothing here checks types or scopes, that is still the job of `Parser`.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyntaxKind {
+  // tokens
+  Whitespace,
+  Newline,
+  Comment,
+  Directive,
+  // code left out by {$IFDEF}
+  Inactive,
+  Keyword,
+  Identifier,
+  IntLiteral,
+  RealLiteral,
+  BoolLiteral,
+  Delimiter,
+  // `:=`
+  Command,
+  RelOperator,
+  AddOperator,
+  MulOperator,
+  PowOperator,
+  LogicOperator,
+  // text the scanner rejects
+  Unknown,
+  // nodes
+  Root,
+  Program,
+  Block,
+  TypeSection,
+  TypeDeclaration,
+  // `(red, green, blue)`
+  EnumType,
+  // `1..10`
+  SubrangeType,
+  // `set of color`
+  SetType,
+  VarSection,
+  VarDeclaration,
+  ProcedureDeclaration,
+  ParamList,
+  Param,
+  Compound,
+  Assign,
+  Call,
+  If,
+  While,
+  ArgList,
+  Binary,
+  Unary,
+  Paren,
+  // `[1, 3..5]`
+  SetLiteral,
+  Literal,
+  Name,
+  CallExpr,
+  // tokens that do not fit the grammar
+  Error
+}
+
+impl SyntaxKind {
+  pub fn is_trivia(self) -> bool {
+    matches!(self, SyntaxKind::Whitespace | SyntaxKind::Newline | SyntaxKind::Comment | SyntaxKind::Directive |
+      SyntaxKind::Inactive)
+  }
+
+  fn from_type(category: Type) -> SyntaxKind {
+    match category {
+      Type::Keyword => SyntaxKind::Keyword,
+      Type::Identifier => SyntaxKind::Identifier,
+      Type::IntLiteral => SyntaxKind::IntLiteral,
+      Type::RealLiteral => SyntaxKind::RealLiteral,
+      Type::BoolLiteral => SyntaxKind::BoolLiteral,
+      Type::Delimiter => SyntaxKind::Delimiter,
+      Type::Command => SyntaxKind::Command,
+      Type::RelOperator => SyntaxKind::RelOperator,
+      Type::AddOperator => SyntaxKind::AddOperator,
+      Type::MulOperator => SyntaxKind::MulOperator,
+      Type::PowOperator => SyntaxKind::PowOperator,
+      Type::LogicOperator => SyntaxKind::LogicOperator,
+      Type::Directive => SyntaxKind::Directive,
+      Type::Eof => SyntaxKind::Unknown
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GreenToken {
+  kind: SyntaxKind,
+  // `Token::Empty` for trivia and unknown text
+  token: Token,
+  text: String
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GreenNode {
+  kind: SyntaxKind,
+  len: usize,
+  children: Vec<GreenElement>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GreenElement {
+  Node(Rc<GreenNode>),
+  Token(Rc<GreenToken>)
+}
+
+impl GreenToken {
+  pub fn new(kind: SyntaxKind, token: Token, text: String) -> GreenToken {
+    GreenToken { kind, token, text }
+  }
+
+  pub fn kind(&self) -> SyntaxKind {
+    self.kind
+  }
+
+  pub fn token(&self) -> &Token {
+    &self.token
+  }
+
+  pub fn text(&self) -> &str {
+    &self.text
+  }
+}
+
+impl GreenNode {
+  pub fn new(kind: SyntaxKind, children: Vec<GreenElement>) -> GreenNode {
+    let len = children.iter().map(|child| child.len()).sum();
+    GreenNode { kind, len, children }
+  }
+
+  pub fn kind(&self) -> SyntaxKind {
+    self.kind
+  }
+
+  // in bytes
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn children(&self) -> &[GreenElement] {
+    &self.children
+  }
+}
+
+impl GreenElement {
+  pub fn kind(&self) -> SyntaxKind {
+    match *self {
+      GreenElement::Node(ref node) => node.kind,
+      GreenElement::Token(ref token) => token.kind
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    match *self {
+      GreenElement::Node(ref node) => node.len,
+      GreenElement::Token(ref token) => token.text.len()
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+impl fmt::Display for GreenNode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for child in &self.children {
+      match *child {
+        GreenElement::Node(ref node) => write!(f, "{}", node)?,
+        GreenElement::Token(ref token) => write!(f, "{}", token.text)?
+      }
+    }
+    Ok(())
+  }
+}
+
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+struct NodeData {
+  green: Rc<GreenNode>,
+  parent: Option<SyntaxNode>,
+  offset: usize
+}
+
+#[derive(Clone)]
+pub struct SyntaxToken {
+  green: Rc<GreenToken>,
+  parent: SyntaxNode,
+  offset: usize
+}
+
+#[derive(Clone, Debug)]
+pub enum SyntaxElement {
+  Node(SyntaxNode),
+  Token(SyntaxToken)
+}
+
+impl SyntaxNode {
+  pub fn new_root(green: Rc<GreenNode>) -> SyntaxNode {
+    SyntaxNode(Rc::new(NodeData { green, parent: None, offset: 0 }))
+  }
+
+  pub fn kind(&self) -> SyntaxKind {
+    self.0.green.kind
+  }
+
+  // byte offsets in the source
+  pub fn text_range(&self) -> Range<usize> {
+    self.0.offset..self.0.offset + self.0.green.len
+  }
+
+  pub fn text(&self) -> String {
+    self.0.green.to_string()
+  }
+
+  pub fn green(&self) -> &Rc<GreenNode> {
+    &self.0.green
+  }
+
+  pub fn parent(&self) -> Option<SyntaxNode> {
+    self.0.parent.clone()
+  }
+
+  pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+    let mut offset = self.0.offset;
+
+    self.0.green.children.iter().map(|child| {
+      let element = match *child {
+        GreenElement::Node(ref green) => SyntaxElement::Node(SyntaxNode(Rc::new(
+          NodeData { green: green.clone(), parent: Some(self.clone()), offset }
+        ))),
+        GreenElement::Token(ref green) => SyntaxElement::Token(
+          SyntaxToken { green: green.clone(), parent: self.clone(), offset }
+        )
+      };
+      offset += child.len();
+      element
+    }).collect()
+  }
+
+  pub fn children(&self) -> Vec<SyntaxNode> {
+    self.children_with_tokens().into_iter().filter_map(|element| match element {
+      SyntaxElement::Node(node) => Some(node),
+      SyntaxElement::Token(_) => None
+    }).collect()
+  }
+
+  // tokens directly below this node
+  pub fn child_tokens(&self) -> Vec<SyntaxToken> {
+    self.children_with_tokens().into_iter().filter_map(|element| match element {
+      SyntaxElement::Token(token) => Some(token),
+      SyntaxElement::Node(_) => None
+    }).collect()
+  }
+
+  // this node and every node below it, in source order
+  pub fn descendants(&self) -> Vec<SyntaxNode> {
+    let mut nodes = vec![self.clone()];
+    for child in self.children() {
+      nodes.extend(child.descendants());
+    }
+    nodes
+  }
+
+  // every token below this node, in source order
+  pub fn tokens(&self) -> Vec<SyntaxToken> {
+    let mut tokens = Vec::new();
+    for element in self.children_with_tokens() {
+      match element {
+        SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+        SyntaxElement::Token(token) => tokens.push(token)
+      }
+    }
+    tokens
+  }
+
+  pub fn token_at_offset(&self, offset: usize) -> Option<SyntaxToken> {
+    self.tokens().into_iter().find(|token| token.text_range().contains(&offset))
+  }
+
+  // one line per node and token, indented by depth
+  pub fn debug_tree(&self) -> String {
+    let mut out = String::new();
+    self.write_tree(&mut out, 0);
+    out
+  }
+
+  fn write_tree(&self, out: &mut String, depth: usize) {
+    out.push_str(&format!("{}{:?}\n", "  ".repeat(depth), self));
+    for element in self.children_with_tokens() {
+      match element {
+        SyntaxElement::Node(node) => node.write_tree(out, depth + 1),
+        SyntaxElement::Token(token) => out.push_str(&format!("{}{:?}\n", "  ".repeat(depth + 1), token))
+      }
+    }
+  }
+}
+
+impl fmt::Display for SyntaxNode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.0.green)
+  }
+}
+
+impl fmt::Debug for SyntaxNode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{:?}@{:?}", self.kind(), self.text_range())
+  }
+}
+
+impl SyntaxToken {
+  pub fn kind(&self) -> SyntaxKind {
+    self.green.kind
+  }
+
+  pub fn token(&self) -> &Token {
+    &self.green.token
+  }
+
+  pub fn text(&self) -> &str {
+    &self.green.text
+  }
+
+  pub fn text_range(&self) -> Range<usize> {
+    self.offset..self.offset + self.green.text.len()
+  }
+
+  pub fn parent(&self) -> SyntaxNode {
+    self.parent.clone()
+  }
+}
+
+impl fmt::Debug for SyntaxToken {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{:?}@{:?} {:?}", self.kind(), self.text_range(), self.text())
+  }
+}
+
+impl SyntaxElement {
+  pub fn kind(&self) -> SyntaxKind {
+    match *self {
+      SyntaxElement::Node(ref node) => node.kind(),
+      SyntaxElement::Token(ref token) => token.kind()
+    }
+  }
+
+  pub fn text_range(&self) -> Range<usize> {
+    match *self {
+      SyntaxElement::Node(ref node) => node.text_range(),
+      SyntaxElement::Token(ref token) => token.text_range()
+    }
+  }
+}
+
+// never fails, text that does not fit the grammar ends up in `Error` nodes
+pub fn parse(text: &str) -> SyntaxNode {
+  parse_bytes(text.as_bytes(), Encoding::Utf8).0
+}
+
+// same as `parse` for a source still in bytes, decoded the way `Scanner`
+// decodes files; `lexer::encode` with the returned encoding gives the
+// bytes back
+pub fn parse_bytes(bytes: &[u8], encoding: Encoding) -> (SyntaxNode, Encoding) {
+  let (pieces, encoding) = Scanner::pieces(Cursor::new(bytes.to_vec()), encoding);
+  let tokens = pieces.into_iter().map(green_token).collect();
+  let mut p = TreeBuilder { tokens, pos: 0, stack: vec![(SyntaxKind::Root, Vec::new())] };
+
+  p.program();
+  if !p.at_end() {
+    p.start(SyntaxKind::Error);
+    while !p.at_end() { p.bump(); }
+    p.finish();
+  }
+  p.flush_trivia();
+
+  let (kind, children) = p.stack.pop().unwrap();
+  (SyntaxNode::new_root(Rc::new(GreenNode::new(kind, children))), encoding)
+}
+
+fn green_token(piece: Piece) -> GreenToken {
+  match piece {
+    Piece::Blank(text) => GreenToken::new(SyntaxKind::Whitespace, Token::Empty, text),
+    Piece::Newline(text) => GreenToken::new(SyntaxKind::Newline, Token::Empty, text),
+    Piece::Comment(text) => GreenToken::new(SyntaxKind::Comment, Token::Empty, text),
+    Piece::Directive(text) => GreenToken::new(SyntaxKind::Directive, Token::Empty, text),
+    Piece::Inactive(text) => GreenToken::new(SyntaxKind::Inactive, Token::Empty, text),
+    Piece::Symbol(token, category, text) => GreenToken::new(SyntaxKind::from_type(category), token, text),
+    Piece::Unknown(text) => GreenToken::new(SyntaxKind::Unknown, Token::Empty, text)
+  }
+}
+
+// recursive descent over the same grammar as `Parser`, trivia go into
+// whichever node is open when the next token is added
+struct TreeBuilder {
+  tokens: Vec<GreenToken>,
+  pos: usize,
+  stack: Vec<(SyntaxKind, Vec<GreenElement>)>
+}
+
+impl TreeBuilder {
+  fn program(&mut self) {
+    self.start(SyntaxKind::Program);
+    self.expect(Token::Program);
+    if self.at_kind(SyntaxKind::Identifier) {
+      self.bump();
+    }
+    self.expect(Token::Semicolon);
+    self.block();
+    self.expect(Token::Period);
+    self.finish();
+  }
+
+  fn block(&mut self) {
+    self.start(SyntaxKind::Block);
+    if self.at(&Token::Type) {
+      self.type_section();
+    }
+    if self.at(&Token::Var) {
+      self.var_section();
+    }
+    while self.at(&Token::Procedure) {
+      self.procedure();
+      self.expect(Token::Semicolon);
+    }
+    self.compound();
+    self.finish();
+  }
+
+  fn type_section(&mut self) {
+    self.start(SyntaxKind::TypeSection);
+    self.bump();
+    while self.at_kind(SyntaxKind::Identifier) {
+      self.start(SyntaxKind::TypeDeclaration);
+      self.bump();
+      self.expect(Token::Equal);
+      self.type_name();
+      self.expect(Token::Semicolon);
+      self.finish();
+    }
+    self.finish();
+  }
+
+  // `-5`, `true` or `red` in a subrange
+  fn bound(&mut self) {
+    if self.at(&Token::Add) || self.at(&Token::Sub) {
+      self.bump();
+    }
+    if matches!(self.nth(0), Some(&Token::LitInt(_)) | Some(&Token::True) | Some(&Token::False)) || self.at_kind(SyntaxKind::Identifier) {
+      self.bump();
+    }
+  }
+
+  fn var_section(&mut self) {
+    self.start(SyntaxKind::VarSection);
+    self.bump();
+    while self.at_kind(SyntaxKind::Identifier) {
+      self.start(SyntaxKind::VarDeclaration);
+      self.names();
+      self.expect(Token::Colon);
+      self.type_name();
+      self.expect(Token::Semicolon);
+      self.finish();
+    }
+    self.finish();
+  }
+
+  fn procedure(&mut self) {
+    self.start(SyntaxKind::ProcedureDeclaration);
+    self.bump();
+    if self.at_kind(SyntaxKind::Identifier) {
+      self.bump();
+    }
+    if self.at(&Token::LParentheses) {
+      self.start(SyntaxKind::ParamList);
+      self.bump();
+      self.param();
+      while self.at(&Token::Semicolon) {
+        self.bump();
+        self.param();
+      }
+      self.expect(Token::RParentheses);
+      self.finish();
+    }
+    self.expect(Token::Semicolon);
+    self.block();
+    self.finish();
+  }
+
+  fn param(&mut self) {
+    self.start(SyntaxKind::Param);
+    self.names();
+    self.expect(Token::Colon);
+    self.type_name();
+    self.finish();
+  }
+
+  fn names(&mut self) {
+    if self.at_kind(SyntaxKind::Identifier) {
+      self.bump();
+    }
+    while self.at(&Token::Comma) {
+      self.bump();
+      if self.at_kind(SyntaxKind::Identifier) {
+        self.bump();
+      }
+    }
+  }
+
+  fn type_name(&mut self) {
+    let bound = matches!(self.nth(0), Some(&Token::LitInt(_)) | Some(&Token::Add) | Some(&Token::Sub) | Some(&Token::True) | Some(&Token::False));
+    if bound || (self.at_kind(SyntaxKind::Identifier) && self.nth(1) == Some(&Token::Range)) {
+      self.start(SyntaxKind::SubrangeType);
+      self.bound();
+      self.expect(Token::Range);
+      self.bound();
+      self.finish();
+    } else if self.at(&Token::Integer) || self.at(&Token::Real) || self.at(&Token::Boolean) || self.at_kind(SyntaxKind::Identifier) {
+      self.bump();
+    } else if self.at(&Token::LParentheses) {
+      self.start(SyntaxKind::EnumType);
+      self.bump();
+      self.names();
+      self.expect(Token::RParentheses);
+      self.finish();
+    } else if self.at(&Token::Set) {
+      self.start(SyntaxKind::SetType);
+      self.bump();
+      self.expect(Token::Of);
+      self.type_name();
+      self.finish();
+    }
+  }
+
+  fn compound(&mut self) {
+    self.start(SyntaxKind::Compound);
+    self.expect(Token::Begin);
+    self.statement();
+    while !self.at(&Token::End) && !self.at_end() {
+      if self.at(&Token::Semicolon) {
+        self.bump();
+        self.statement();
+      } else {
+        self.error_bump();
+      }
+    }
+    self.expect(Token::End);
+    self.finish();
+  }
+
+  fn statement(&mut self) {
+    if self.at_kind(SyntaxKind::Identifier) {
+      if self.nth(1) == Some(&Token::Assign) {
+        self.start(SyntaxKind::Assign);
+        self.bump();
+        self.bump();
+        self.expr();
+      } else {
+        self.start(SyntaxKind::Call);
+        self.bump();
+        if self.at(&Token::LParentheses) {
+          self.args();
+        }
+      }
+      self.finish();
+    } else if self.at(&Token::Begin) {
+      self.compound();
+    } else if self.at(&Token::If) {
+      self.start(SyntaxKind::If);
+      self.bump();
+      self.expr();
+      self.expect(Token::Then);
+      self.statement();
+      if self.at(&Token::Else) {
+        self.bump();
+        self.statement();
+      }
+      self.finish();
+    } else if self.at(&Token::While) {
+      self.start(SyntaxKind::While);
+      self.bump();
+      self.expr();
+      self.expect(Token::Do);
+      self.statement();
+      self.finish();
+    }
+  }
+
+  fn args(&mut self) {
+    self.start(SyntaxKind::ArgList);
+    self.bump();
+    self.expr();
+    while self.at(&Token::Comma) {
+      self.bump();
+      self.expr();
+    }
+    self.expect(Token::RParentheses);
+    self.finish();
+  }
+
+  // `<->` is left associative, `->` right associative
+  fn expr(&mut self) {
+    let checkpoint = self.checkpoint();
+    self.implication();
+    while self.at(&Token::Equiv) {
+      self.start_at(checkpoint, SyntaxKind::Binary);
+      self.bump();
+      self.implication();
+      self.finish();
+    }
+  }
+
+  fn implication(&mut self) {
+    let checkpoint = self.checkpoint();
+    self.relation();
+    if self.at(&Token::Imp) {
+      self.start_at(checkpoint, SyntaxKind::Binary);
+      self.bump();
+      self.implication();
+      self.finish();
+    }
+  }
+
+  fn relation(&mut self) {
+    let checkpoint = self.checkpoint();
+    self.simple_expr();
+    if self.at_kind(SyntaxKind::RelOperator) {
+      self.start_at(checkpoint, SyntaxKind::Binary);
+      self.bump();
+      self.simple_expr();
+      self.finish();
+    }
+  }
+
+  fn simple_expr(&mut self) {
+    let checkpoint = self.checkpoint();
+    if self.at(&Token::Add) || self.at(&Token::Sub) {
+      self.start(SyntaxKind::Unary);
+      self.bump();
+      self.term();
+      self.finish();
+    } else {
+      self.term();
+    }
+    while self.at_kind(SyntaxKind::AddOperator) {
+      self.start_at(checkpoint, SyntaxKind::Binary);
+      self.bump();
+      self.term();
+      self.finish();
+    }
+  }
+
+  fn term(&mut self) {
+    let checkpoint = self.checkpoint();
+    self.power();
+    while self.at_kind(SyntaxKind::MulOperator) {
+      self.start_at(checkpoint, SyntaxKind::Binary);
+      self.bump();
+      self.power();
+      self.finish();
+    }
+  }
+
+  // right associative, `a ** b ** c` nests to the right
+  fn power(&mut self) {
+    let checkpoint = self.checkpoint();
+    self.factor();
+    if self.at_kind(SyntaxKind::PowOperator) {
+      self.start_at(checkpoint, SyntaxKind::Binary);
+      self.bump();
+      self.power();
+      self.finish();
+    }
+  }
+
+  fn factor(&mut self) {
+    if self.at_kind(SyntaxKind::Identifier) {
+      if self.nth(1) == Some(&Token::LParentheses) {
+        self.start(SyntaxKind::CallExpr);
+        self.bump();
+        self.args();
+      } else {
+        self.start(SyntaxKind::Name);
+        self.bump();
+      }
+      self.finish();
+    } else if self.at_kind(SyntaxKind::IntLiteral) || self.at_kind(SyntaxKind::RealLiteral) || self.at_kind(SyntaxKind::BoolLiteral) {
+      self.start(SyntaxKind::Literal);
+      self.bump();
+      self.finish();
+    } else if self.at(&Token::LParentheses) {
+      self.start(SyntaxKind::Paren);
+      self.bump();
+      self.expr();
+      self.expect(Token::RParentheses);
+      self.finish();
+    } else if self.at(&Token::LBracket) {
+      self.start(SyntaxKind::SetLiteral);
+      self.bump();
+      if !self.at(&Token::RBracket) {
+        self.set_element();
+        while self.at(&Token::Comma) {
+          self.bump();
+          self.set_element();
+        }
+      }
+      self.expect(Token::RBracket);
+      self.finish();
+    } else if self.at(&Token::Not) {
+      self.start(SyntaxKind::Unary);
+      self.bump();
+      self.factor();
+      self.finish();
+    }
+  }
+
+  // `3` or `3..5`
+  fn set_element(&mut self) {
+    self.expr();
+    if self.at(&Token::Range) {
+      self.bump();
+      self.expr();
+    }
+  }
+
+  // index of the `n`th token after the trivia
+  fn significant(&self, n: usize) -> Option<usize> {
+    (self.pos..self.tokens.len()).filter(|&i| !self.tokens[i].kind.is_trivia()).nth(n)
+  }
+
+  fn nth(&self, n: usize) -> Option<&Token> {
+    self.significant(n).map(|i| &self.tokens[i].token)
+  }
+
+  fn at(&self, token: &Token) -> bool {
+    self.nth(0) == Some(token)
+  }
+
+  fn at_kind(&self, kind: SyntaxKind) -> bool {
+    self.significant(0).is_some_and(|i| self.tokens[i].kind == kind)
+  }
+
+  fn at_end(&self) -> bool {
+    self.significant(0).is_none()
+  }
+
+  // a missing token is left out of the tree
+  fn expect(&mut self, token: Token) {
+    if self.at(&token) {
+      self.bump();
+    }
+  }
+
+  fn error_bump(&mut self) {
+    self.start(SyntaxKind::Error);
+    self.bump();
+    self.finish();
+  }
+
+  fn flush_trivia(&mut self) {
+    while self.pos < self.tokens.len() && self.tokens[self.pos].kind.is_trivia() {
+      self.push_token();
+    }
+  }
+
+  fn bump(&mut self) {
+    self.flush_trivia();
+    if self.pos < self.tokens.len() {
+      self.push_token();
+    }
+  }
+
+  fn push_token(&mut self) {
+    let token = Rc::new(self.tokens[self.pos].clone());
+    self.stack.last_mut().unwrap().1.push(GreenElement::Token(token));
+    self.pos += 1;
+  }
+
+  fn start(&mut self, kind: SyntaxKind) {
+    self.flush_trivia();
+    self.stack.push((kind, Vec::new()));
+  }
+
+  // position a node may later be started at, used for left-recursive
+  // binary expressions
+  fn checkpoint(&mut self) -> usize {
+    self.flush_trivia();
+    self.stack.last().unwrap().1.len()
+  }
+
+  fn start_at(&mut self, checkpoint: usize, kind: SyntaxKind) {
+    let children = self.stack.last_mut().unwrap().1.split_off(checkpoint);
+    self.stack.push((kind, children));
+  }
+
+  fn finish(&mut self) {
+    let (kind, children) = self.stack.pop().unwrap();
+    let node = GreenElement::Node(Rc::new(GreenNode::new(kind, children)));
+    self.stack.last_mut().unwrap().1.push(node);
+  }
+}
+
+#[test]
+fn test_cst_round_trip() {
+  for entry in ::std::fs::read_dir("files").unwrap() {
+    let bytes = ::std::fs::read(entry.unwrap().path()).unwrap();
+    let (root, encoding) = parse_bytes(&bytes, Encoding::Auto);
+
+    assert_eq!(::lexer::encode(&root.text(), encoding), bytes);
+  }
+
+  let bytes = b"\xEF\xBB\xBFprogram p;\r\n(* \xC3\xA9\n *) begin end.";
+  let (root, encoding) = parse_bytes(bytes, Encoding::Auto);
+  assert_eq!(::lexer::encode(&root.text(), encoding), bytes.to_vec());
+}
+
+#[test]
+fn test_cst_round_trip_bytes() {
+  let samples: [(&[u8], Encoding); 7] = [
+    // UTF-8 on one line and Latin-1 on another
+    (b"program p; { caf\xC3\xA9 }\nvar a: integer; { n\xE3o }\nbegin end.\n", Encoding::Auto),
+    (b"program p; { caf\xC3\xA9 }\r\nbegin { n\xE3o }\r\nend.\r\n", Encoding::Auto),
+    (b"program p;\r\nbegin\r\n  a := 1\r\nend.\r\n", Encoding::Auto),
+    (b"program p;\r\nbegin\n  a := 1\rend.", Encoding::Auto),
+    (b"program p;\nbegin end.\n\n\n", Encoding::Auto),
+    (b"program p;\nbegin end.\n", Encoding::Utf8),
+    (b"program p; { n\xE3o }\r\nbegin end.\r\n", Encoding::Latin1)
+  ];
+
+  for &(bytes, encoding) in samples.iter() {
+    let (root, settled) = parse_bytes(bytes, encoding);
+    assert_eq!(::lexer::encode(&root.text(), settled), bytes.to_vec(), "{:?}", String::from_utf8_lossy(bytes));
+  }
+}
+
+#[test]
+fn test_cst_program10() {
+  let text = ::std::fs::read_to_string("files/program10.txt").unwrap();
+  let root = parse(&text);
+
+  assert!(root.descendants().iter().all(|node| node.kind() != SyntaxKind::Error));
+  let procedures = root.descendants().iter().filter(|node| node.kind() == SyntaxKind::ProcedureDeclaration).count();
+  assert!(procedures > 1);
+}
+
+#[test]
+fn test_cst_tree() {
+  let root = parse("program p; {x}\r\nbegin\n\ta := -a + 1\nend.\n");
+
+  assert_eq!(root.debug_tree(), "\
+Root@0..40
+  Program@0..39
+    Keyword@0..7 \"program\"
+    Whitespace@7..8 \" \"
+    Identifier@8..9 \"p\"
+    Delimiter@9..10 \";\"
+    Whitespace@10..11 \" \"
+    Comment@11..14 \"{x}\"
+    Newline@14..16 \"\\r\\n\"
+    Block@16..38
+      Compound@16..38
+        Keyword@16..21 \"begin\"
+        Newline@21..22 \"\\n\"
+        Whitespace@22..23 \"\\t\"
+        Assign@23..34
+          Identifier@23..24 \"a\"
+          Whitespace@24..25 \" \"
+          Command@25..27 \":=\"
+          Whitespace@27..28 \" \"
+          Binary@28..34
+            Unary@28..30
+              AddOperator@28..29 \"-\"
+              Name@29..30
+                Identifier@29..30 \"a\"
+            Whitespace@30..31 \" \"
+            AddOperator@31..32 \"+\"
+            Whitespace@32..33 \" \"
+            Literal@33..34
+              IntLiteral@33..34 \"1\"
+        Newline@34..35 \"\\n\"
+        Keyword@35..38 \"end\"
+    Delimiter@38..39 \".\"
+  Newline@39..40 \"\\n\"
+");
+}
+
+#[test]
+fn test_cst_navigation() {
+  let root = parse("program p;\nbegin\n  b := (a * 2)\nend.");
+  let token = root.token_at_offset(25).unwrap();
+
+  assert_eq!(token.text(), "a");
+  assert_eq!(token.token(), &Token::LitStr("a".to_string()));
+
+  let mut kinds = Vec::new();
+  let mut node = Some(token.parent());
+  while let Some(n) = node {
+    kinds.push(n.kind());
+    node = n.parent();
+  }
+  assert_eq!(kinds, vec![
+    SyntaxKind::Name, SyntaxKind::Binary, SyntaxKind::Paren, SyntaxKind::Assign,
+    SyntaxKind::Compound, SyntaxKind::Block, SyntaxKind::Program, SyntaxKind::Root
+  ]);
+}
+
+#[test]
+fn test_cst_errors() {
+  let text = "program p;\nbegin\n  a := ) # 1;\n  (* aberto\nend";
+  let root = parse(text);
+
+  assert_eq!(root.text(), text);
+  let errors: Vec<String> = root.descendants().iter()
+    .filter(|node| node.kind() == SyntaxKind::Error)
+    .map(|node| node.text())
+    .collect();
+  assert_eq!(errors, vec![")", "#", "1"]);
+}
+
+#[test]
+fn test_cst_type_section() {
+  let text = ::std::fs::read_to_string("files/program35.txt").unwrap();
+  let root = parse(&text);
+
+  assert!(root.descendants().iter().all(|node| node.kind() != SyntaxKind::Error));
+  let declarations = root.descendants().iter().filter(|node| node.kind() == SyntaxKind::TypeDeclaration).count();
+  assert_eq!(declarations, 2);
+  let enumeration = root.descendants().into_iter().find(|node| node.kind() == SyntaxKind::EnumType).unwrap();
+  assert_eq!(enumeration.text(), "(red, green, blue)");
+}
+
+#[test]
+fn test_cst_sets() {
+  let text = ::std::fs::read_to_string("files/program37.txt").unwrap();
+  let root = parse(&text);
+
+  assert!(root.descendants().iter().all(|node| node.kind() != SyntaxKind::Error));
+  let types: Vec<String> = root.descendants().iter().filter(|node| node.kind() == SyntaxKind::SetType).map(|node| node.text()).collect();
+  assert_eq!(types, vec!["set of 0..9", "set of color", "set of boolean"]);
+  let literal = root.descendants().into_iter().find(|node| node.kind() == SyntaxKind::SetLiteral).unwrap();
+  assert_eq!(literal.text(), "[1, 3..5]");
+}
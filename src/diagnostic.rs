@@ -0,0 +1,72 @@
+use std::fmt;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Severity {
+  Error,
+  Warning
+}
+
+// what found the problem, `pascalzim` exits with a different code for each
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Kind {
+  // the scanner, such as `#` or a literal out of range
+  Lexical,
+  // the grammar, such as a missing `;`
+  Syntax,
+  // names and types, such as an undeclared identifier
+  Semantic,
+  // running the program, such as a division by zero
+  Runtime
+}
+
+// error or warning found while reading a program
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Diagnostic {
+  pub severity: Severity,
+  pub kind: Kind,
+  pub message: String,
+  pub line: u32
+}
+
+impl Diagnostic {
+  // a semantic error, the other kinds have their own constructors
+  pub fn error(message: String, line: u32) -> Diagnostic {
+    Diagnostic { severity: Severity::Error, kind: Kind::Semantic, message, line }
+  }
+
+  pub fn lexical(message: String, line: u32) -> Diagnostic {
+    Diagnostic { kind: Kind::Lexical, ..Diagnostic::error(message, line) }
+  }
+
+  pub fn syntax(message: String, line: u32) -> Diagnostic {
+    Diagnostic { kind: Kind::Syntax, ..Diagnostic::error(message, line) }
+  }
+
+  pub fn runtime(message: String, line: u32) -> Diagnostic {
+    Diagnostic { kind: Kind::Runtime, ..Diagnostic::error(message, line) }
+  }
+
+  pub fn warning(message: String, line: u32) -> Diagnostic {
+    Diagnostic { severity: Severity::Warning, kind: Kind::Semantic, message, line }
+  }
+
+  // the checker lets through something the executors can't handle
+  pub fn internal(message: String, line: u32) -> Diagnostic {
+    Diagnostic::error(format!("internal error: {}", message), line)
+  }
+}
+
+impl fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let severity = match self.severity {
+      Severity::Error => "Error",
+      Severity::Warning => "Warning"
+    };
+    write!(f, "{}: {} => line {}", severity, self.message, self.line)
+  }
+}
@@ -0,0 +1,368 @@
+use std::error;
+use std::fmt;
+use serde::ser;
+use serde_json;
+use diagnostic::Diagnostic;
+use lexer::{Scanner, Symbol};
+use spec::Type;
+
+pub use serde::Serialize;
+
+// dumps of the tokens and of the syntax tree for tools written in other
+// languages, anything with the serde derives can be dumped
+
+pub fn to_json<T: Serialize>(value: &T) -> String {
+  // only maps with non-string keys fail and there are none
+  serde_json::to_string_pretty(value).unwrap()
+}
+
+// `(Name (field value) ...)` for structs, `(Variant ...)` for enum variants
+// with data and `Variant` for the others
+pub fn to_sexpr<T: Serialize>(value: &T) -> String {
+  let mut serializer = Sexpr { out: String::new() };
+  value.serialize(&mut serializer).unwrap();
+  serializer.out
+}
+
+// every symbol up to the end of the input
+pub fn tokens(scanner: &mut Scanner) -> Result<Vec<Symbol>, Diagnostic> {
+  let mut symbols = Vec::new();
+  loop {
+    let symbol = scanner.try_next_symbol()?;
+    if symbol.category == Type::Eof {
+      return Ok(symbols);
+    }
+    symbols.push(symbol);
+  }
+}
+
+#[derive(Debug)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+impl error::Error for Error {}
+
+impl ser::Error for Error {
+  fn custom<T: fmt::Display>(message: T) -> Error {
+    Error(message.to_string())
+  }
+}
+
+struct Sexpr {
+  out: String
+}
+
+impl Sexpr {
+  // space between the items of a list
+  fn separate(&mut self) {
+    if !self.out.is_empty() && !self.out.ends_with('(') {
+      self.out.push(' ');
+    }
+  }
+
+  fn open(&mut self, name: &str) {
+    self.out.push('(');
+    self.out.push_str(name);
+  }
+
+  fn field<T: ?Sized + Serialize>(&mut self, key: &str, value: &T) -> Result<(), Error> {
+    self.separate();
+    self.open(key);
+    self.out.push(' ');
+    value.serialize(&mut *self)?;
+    self.out.push(')');
+    Ok(())
+  }
+
+  fn string(&mut self, s: &str) {
+    self.out.push('"');
+    for c in s.chars() {
+      match c {
+        '"' => self.out.push_str("\\\""),
+        '\\' => self.out.push_str("\\\\"),
+        '\n' => self.out.push_str("\\n"),
+        c => self.out.push(c)
+      }
+    }
+    self.out.push('"');
+  }
+}
+
+impl ser::Serializer for &mut Sexpr {
+  type Ok = ();
+  type Error = Error;
+  type SerializeSeq = Self;
+  type SerializeTuple = Self;
+  type SerializeTupleStruct = Self;
+  type SerializeTupleVariant = Self;
+  type SerializeMap = Self;
+  type SerializeStruct = Self;
+  type SerializeStructVariant = Self;
+
+  fn serialize_bool(self, v: bool) -> Result<(), Error> {
+    self.out.push_str(if v { "true" } else { "false" });
+    Ok(())
+  }
+
+  fn serialize_i8(self, v: i8) -> Result<(), Error> { self.serialize_i64(v as i64) }
+  fn serialize_i16(self, v: i16) -> Result<(), Error> { self.serialize_i64(v as i64) }
+  fn serialize_i32(self, v: i32) -> Result<(), Error> { self.serialize_i64(v as i64) }
+  fn serialize_u8(self, v: u8) -> Result<(), Error> { self.serialize_u64(v as u64) }
+  fn serialize_u16(self, v: u16) -> Result<(), Error> { self.serialize_u64(v as u64) }
+  fn serialize_u32(self, v: u32) -> Result<(), Error> { self.serialize_u64(v as u64) }
+  fn serialize_f32(self, v: f32) -> Result<(), Error> { self.serialize_f64(v as f64) }
+
+  fn serialize_i64(self, v: i64) -> Result<(), Error> {
+    self.out.push_str(&v.to_string());
+    Ok(())
+  }
+
+  fn serialize_u64(self, v: u64) -> Result<(), Error> {
+    self.out.push_str(&v.to_string());
+    Ok(())
+  }
+
+  // `{:?}` keeps the `.0` of whole reals
+  fn serialize_f64(self, v: f64) -> Result<(), Error> {
+    self.out.push_str(&format!("{:?}", v));
+    Ok(())
+  }
+
+  fn serialize_char(self, v: char) -> Result<(), Error> {
+    self.string(&v.to_string());
+    Ok(())
+  }
+
+  fn serialize_str(self, v: &str) -> Result<(), Error> {
+    self.string(v);
+    Ok(())
+  }
+
+  fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+    v.serialize(self)
+  }
+
+  fn serialize_none(self) -> Result<(), Error> {
+    self.out.push_str("nil");
+    Ok(())
+  }
+
+  fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), Error> {
+    value.serialize(self)
+  }
+
+  fn serialize_unit(self) -> Result<(), Error> {
+    self.serialize_none()
+  }
+
+  fn serialize_unit_struct(self, name: &'static str) -> Result<(), Error> {
+    self.out.push_str(name);
+    Ok(())
+  }
+
+  fn serialize_unit_variant(self, _: &'static str, _: u32, variant: &'static str) -> Result<(), Error> {
+    self.out.push_str(variant);
+    Ok(())
+  }
+
+  fn serialize_newtype_struct<T: ?Sized + Serialize>(self, name: &'static str, value: &T) -> Result<(), Error> {
+    self.open(name);
+    self.out.push(' ');
+    value.serialize(&mut *self)?;
+    self.out.push(')');
+    Ok(())
+  }
+
+  fn serialize_newtype_variant<T: ?Sized + Serialize>(self, _: &'static str, _: u32, variant: &'static str, value: &T) -> Result<(), Error> {
+    self.serialize_newtype_struct(variant, value)
+  }
+
+  fn serialize_seq(self, _: Option<usize>) -> Result<Self, Error> {
+    self.out.push('(');
+    Ok(self)
+  }
+
+  fn serialize_tuple(self, len: usize) -> Result<Self, Error> {
+    self.serialize_seq(Some(len))
+  }
+
+  fn serialize_tuple_struct(self, name: &'static str, _: usize) -> Result<Self, Error> {
+    self.open(name);
+    Ok(self)
+  }
+
+  fn serialize_tuple_variant(self, _: &'static str, _: u32, variant: &'static str, _: usize) -> Result<Self, Error> {
+    self.open(variant);
+    Ok(self)
+  }
+
+  fn serialize_map(self, _: Option<usize>) -> Result<Self, Error> {
+    self.out.push('(');
+    Ok(self)
+  }
+
+  fn serialize_struct(self, name: &'static str, _: usize) -> Result<Self, Error> {
+    self.open(name);
+    Ok(self)
+  }
+
+  fn serialize_struct_variant(self, _: &'static str, _: u32, variant: &'static str, _: usize) -> Result<Self, Error> {
+    self.open(variant);
+    Ok(self)
+  }
+}
+
+impl ser::SerializeSeq for &mut Sexpr {
+  type Ok = ();
+  type Error = Error;
+
+  fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+    self.separate();
+    value.serialize(&mut **self)
+  }
+
+  fn end(self) -> Result<(), Error> {
+    self.out.push(')');
+    Ok(())
+  }
+}
+
+impl ser::SerializeTuple for &mut Sexpr {
+  type Ok = ();
+  type Error = Error;
+
+  fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+    ser::SerializeSeq::serialize_element(self, value)
+  }
+
+  fn end(self) -> Result<(), Error> {
+    ser::SerializeSeq::end(self)
+  }
+}
+
+impl ser::SerializeTupleStruct for &mut Sexpr {
+  type Ok = ();
+  type Error = Error;
+
+  fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+    ser::SerializeSeq::serialize_element(self, value)
+  }
+
+  fn end(self) -> Result<(), Error> {
+    ser::SerializeSeq::end(self)
+  }
+}
+
+impl ser::SerializeTupleVariant for &mut Sexpr {
+  type Ok = ();
+  type Error = Error;
+
+  fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+    ser::SerializeSeq::serialize_element(self, value)
+  }
+
+  fn end(self) -> Result<(), Error> {
+    ser::SerializeSeq::end(self)
+  }
+}
+
+// entries are written as `(key value)`
+impl ser::SerializeMap for &mut Sexpr {
+  type Ok = ();
+  type Error = Error;
+
+  fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+    self.separate();
+    self.out.push('(');
+    key.serialize(&mut **self)
+  }
+
+  fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+    self.out.push(' ');
+    value.serialize(&mut **self)?;
+    self.out.push(')');
+    Ok(())
+  }
+
+  fn end(self) -> Result<(), Error> {
+    ser::SerializeSeq::end(self)
+  }
+}
+
+impl ser::SerializeStruct for &mut Sexpr {
+  type Ok = ();
+  type Error = Error;
+
+  fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+    self.field(key, value)
+  }
+
+  fn end(self) -> Result<(), Error> {
+    ser::SerializeSeq::end(self)
+  }
+}
+
+impl ser::SerializeStructVariant for &mut Sexpr {
+  type Ok = ();
+  type Error = Error;
+
+  fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+    self.field(key, value)
+  }
+
+  fn end(self) -> Result<(), Error> {
+    ser::SerializeSeq::end(self)
+  }
+}
+
+#[test]
+fn test_dump_sexpr() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.parse_declarations("var a: integer;").unwrap();
+  let command = p1.parse_statement("a := -a * 2").unwrap();
+
+  assert_eq!(to_sexpr(&command),
+    "(Assign (name \"a\") (value (Expr (kind (Unary Sub (Expr (kind (Binary Mult \
+    (Expr (kind (Variable \"a\")) (category Integer) (line 1)) \
+    (Expr (kind (Integer 2)) (category Integer) (line 1)))) (category Integer) (line 1)))) (category Integer) (line 1))) (line 1))");
+
+  let mut scanner = Scanner::from_str("x := 2.0; {fim}");
+  let symbols = tokens(&mut scanner).unwrap();
+  assert_eq!(to_sexpr(&symbols),
+    "((Symbol (token (LitStr \"x\")) (category Identifier) (line 1) (column 1) (trivia ())) \
+    (Symbol (token Assign) (category Command) (line 1) (column 3) (trivia ())) \
+    (Symbol (token (LitReal 2.0)) (category RealLiteral) (line 1) (column 6) (trivia ())) \
+    (Symbol (token Semicolon) (category Delimiter) (line 1) (column 9) (trivia ())))");
+}
+
+#[test]
+fn test_dump_json() {
+  use ast::Program;
+  use parser::Parser;
+
+  for n in [10, 23, 24, 28] {
+    let mut p1: Parser = Parser::new();
+    p1.build_ast(&format!("files/program{}.txt", n));
+    let program = p1.program().unwrap();
+    let json = to_json(program);
+    assert_eq!(serde_json::from_str::<Program>(&json).unwrap(), *program);
+  }
+
+  let mut scanner = Scanner::new();
+  scanner.build_token("files/program23.txt");
+  let symbols = tokens(&mut scanner).unwrap();
+  let value: serde_json::Value = serde_json::from_str(&to_json(&symbols)).unwrap();
+  assert_eq!(value[0]["token"], serde_json::json!("Program"));
+  assert_eq!(value[1]["token"]["LitStr"], serde_json::json!("fatorial"));
+  assert_eq!(value[1]["category"], serde_json::json!("Identifier"));
+  assert_eq!(value[2]["line"], serde_json::json!(1));
+  assert_eq!(value[2]["column"], serde_json::json!(17));
+  assert_eq!(value[3]["trivia"][0]["text"], serde_json::json!("{procedimento recursivo}"));
+}
@@ -0,0 +1,586 @@
+use cst::{self, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken};
+use diagnostic::Diagnostic;
+use lexer;
+use options::Encoding;
+use spec::Token;
+
+// Re-emits a program from its `cst` with two spaces per level, one
+// statement per line, lowercase keywords and the colons of each `var`
+// section aligned. Comments, directives and the code {$IFDEF} leaves out
+// stay where they were, on their own line or after the code, and one blank
+// line is kept wherever the source had blank lines. Identifiers and literals
+// are written as they were.
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Break {
+  Nothing,
+  Space,
+  Line
+}
+
+struct Formatter {
+  out: String,
+  indent: usize,
+  pending: Break,
+  // line breaks in the source since the last thing written
+  newlines: usize,
+  comments: Vec<Pending>
+}
+
+// a comment or directive waiting for the next word
+struct Pending {
+  text: String,
+  indent: usize,
+  newlines: usize
+}
+
+pub fn format(text: &str) -> Result<String, Diagnostic> {
+  format_tree(text, &cst::parse(text))
+}
+
+// same as `format` for a file still in bytes, decoded the way `check` and
+// `run` decode it and written back in the encoding it was read in
+pub fn format_bytes(bytes: &[u8], encoding: Encoding) -> Result<Vec<u8>, Diagnostic> {
+  let (root, encoding) = cst::parse_bytes(bytes, encoding);
+  format_tree(&root.text(), &root).map(|formatted| lexer::encode(&formatted, encoding))
+}
+
+fn format_tree(text: &str, root: &SyntaxNode) -> Result<String, Diagnostic> {
+  // the tree keeps whatever it could not place, the output would not
+  for token in root.tokens() {
+    if token.kind() == SyntaxKind::Unknown || token.parent().kind() == SyntaxKind::Error {
+      let line = text[..token.text_range().start].matches('\n').count() as u32 + 1;
+      return Err(Diagnostic::syntax(format!("Cannot format around `{}`", token.text()), line));
+    }
+  }
+
+  let mut f = Formatter { out: String::new(), indent: 0, pending: Break::Nothing, newlines: 0, comments: Vec::new() };
+  f.node(root);
+  f.flush_comments();
+  if !f.out.is_empty() {
+    f.out.push('\n');
+  }
+  Ok(f.out)
+}
+
+// true when `format` would leave the text as it is
+pub fn check(text: &str) -> Result<bool, Diagnostic> {
+  format(text).map(|formatted| formatted == text)
+}
+
+impl Formatter {
+  fn node(&mut self, node: &SyntaxNode) {
+    match node.kind() {
+      SyntaxKind::Program => self.program(node),
+      SyntaxKind::Block => self.block(node),
+      SyntaxKind::TypeSection => self.type_section(node),
+      SyntaxKind::VarSection => self.var_section(node),
+      SyntaxKind::VarDeclaration | SyntaxKind::Param => self.declaration(node, 0),
+      SyntaxKind::ProcedureDeclaration => self.procedure(node),
+      SyntaxKind::ParamList | SyntaxKind::ArgList | SyntaxKind::Paren | SyntaxKind::EnumType |
+      SyntaxKind::SetLiteral => self.list(node),
+      SyntaxKind::Compound => self.compound(node, false),
+      SyntaxKind::If | SyntaxKind::While => self.conditional(node),
+      SyntaxKind::Unary => self.unary(node),
+      SyntaxKind::SubrangeType => self.subrange(node),
+      _ => self.spaced(node)
+    }
+  }
+
+  fn program(&mut self, node: &SyntaxNode) {
+    self.each(node, |f, element| match element {
+      SyntaxElement::Node(block) => f.node(&block),
+      SyntaxElement::Token(token) => match *token.token() {
+        Token::Program => f.word(&token, Break::Line),
+        Token::Semicolon | Token::Period => f.word(&token, Break::Nothing),
+        _ => f.word(&token, Break::Space)
+      }
+    });
+  }
+
+  fn block(&mut self, node: &SyntaxNode) {
+    self.each(node, |f, element| match element {
+      SyntaxElement::Node(child) => match child.kind() {
+        SyntaxKind::ProcedureDeclaration => {
+          f.indent += 1;
+          f.node(&child);
+          f.indent -= 1;
+        },
+        _ => f.node(&child)
+      },
+      SyntaxElement::Token(token) => f.word(&token, Break::Nothing)
+    });
+  }
+
+  // one `name = type;` per line
+  fn type_section(&mut self, node: &SyntaxNode) {
+    self.each(node, |f, element| match element {
+      SyntaxElement::Node(declaration) => {
+        f.indent += 1;
+        f.sep(Break::Line);
+        f.node(&declaration);
+        f.indent -= 1;
+      },
+      SyntaxElement::Token(token) => f.word(&token, Break::Line)
+    });
+  }
+
+  fn var_section(&mut self, node: &SyntaxNode) {
+    let width = node.children().iter().map(names_width).max().unwrap_or(0);
+
+    self.each(node, |f, element| match element {
+      SyntaxElement::Node(declaration) => {
+        f.indent += 1;
+        f.sep(Break::Line);
+        f.declaration(&declaration, width);
+        f.indent -= 1;
+      },
+      SyntaxElement::Token(token) => f.word(&token, Break::Line)
+    });
+  }
+
+  // `a, b: integer;`, the names are padded to `width` so the colons line up
+  fn declaration(&mut self, node: &SyntaxNode, width: usize) {
+    let mut written = 0;
+
+    self.each(node, |f, element| match element {
+      // `(red, green)` after the colon
+      SyntaxElement::Node(child) => f.node(&child),
+      SyntaxElement::Token(token) => {
+        match *token.token() {
+          Token::Comma => {
+            f.word(&token, Break::Nothing);
+            f.sep(Break::Space);
+            written += 2;
+          },
+          Token::Colon => {
+            f.pad(width.saturating_sub(written));
+            f.word(&token, Break::Nothing);
+            f.sep(Break::Space);
+          },
+          Token::LitStr(_) => {
+            f.word(&token, Break::Nothing);
+            written += token.text().chars().count();
+          },
+          _ => f.word(&token, Break::Nothing)
+        }
+      }
+    });
+  }
+
+  fn procedure(&mut self, node: &SyntaxNode) {
+    self.each(node, |f, element| match element {
+      SyntaxElement::Node(child) => f.node(&child),
+      SyntaxElement::Token(token) => match *token.token() {
+        Token::Procedure => f.word(&token, Break::Line),
+        Token::Semicolon => f.word(&token, Break::Nothing),
+        _ => f.word(&token, Break::Space)
+      }
+    });
+  }
+
+  // `1..10` and `-5..5` are written without spaces
+  fn subrange(&mut self, node: &SyntaxNode) {
+    self.each(node, |f, element| {
+      if let SyntaxElement::Token(token) = element {
+        f.word(&token, Break::Nothing);
+      }
+    });
+  }
+
+  // `(...)` around parameters, arguments or an expression and the `[...]`
+  // of a set, where `3..5` is written without spaces
+  fn list(&mut self, node: &SyntaxNode) {
+    self.each(node, |f, element| match element {
+      SyntaxElement::Node(child) => f.node(&child),
+      SyntaxElement::Token(token) => match *token.token() {
+        Token::Comma | Token::Semicolon => {
+          f.word(&token, Break::Nothing);
+          f.sep(Break::Space);
+        },
+        _ => f.word(&token, Break::Nothing)
+      }
+    });
+  }
+
+  // `inline` keeps `begin` on the line of the `then`, `else` or `do` before it
+  fn compound(&mut self, node: &SyntaxNode, inline: bool) {
+    let indent = self.indent;
+
+    self.each(node, |f, element| match element {
+      SyntaxElement::Node(statement) => {
+        f.sep(Break::Line);
+        f.node(&statement);
+      },
+      SyntaxElement::Token(token) => match *token.token() {
+        Token::Begin => {
+          f.word(&token, if inline { Break::Space } else { Break::Line });
+          f.indent = indent + 1;
+        },
+        Token::End => {
+          f.indent = indent;
+          f.word(&token, Break::Line);
+        },
+        _ => f.word(&token, Break::Nothing)
+      }
+    });
+    self.indent = indent;
+  }
+
+  // `if ... then ... else ...` and `while ... do ...`
+  fn conditional(&mut self, node: &SyntaxNode) {
+    let mut in_branch = false;
+
+    self.each(node, |f, element| match element {
+      SyntaxElement::Node(child) => {
+        if !in_branch {
+          f.sep(Break::Space);
+          f.node(&child);
+        } else if child.kind() == SyntaxKind::Compound {
+          f.compound(&child, true);
+        } else if child.kind() == SyntaxKind::If && f.last_word_is("else") {
+          f.sep(Break::Space);
+          f.node(&child);
+        } else {
+          f.indent += 1;
+          f.sep(Break::Line);
+          f.node(&child);
+          f.indent -= 1;
+        }
+      },
+      SyntaxElement::Token(token) => match *token.token() {
+        Token::Then | Token::Do => {
+          f.word(&token, Break::Space);
+          in_branch = true;
+        },
+        Token::Else => f.word(&token, Break::Line),
+        _ => f.word(&token, Break::Nothing)
+      }
+    });
+  }
+
+  // `-a` is written tight, `not a` is not
+  fn unary(&mut self, node: &SyntaxNode) {
+    let mut after = Break::Nothing;
+
+    self.each(node, |f, element| match element {
+      SyntaxElement::Node(child) => {
+        f.sep(after);
+        f.node(&child);
+      },
+      SyntaxElement::Token(token) => {
+        if *token.token() == Token::Not {
+          after = Break::Space;
+        }
+        f.word(&token, Break::Nothing);
+      }
+    });
+  }
+
+  // statements and binary expressions: one space between the parts,
+  // but `;` and `(` attach to what comes before them
+  fn spaced(&mut self, node: &SyntaxNode) {
+    let mut first = true;
+
+    self.each(node, |f, element| {
+      let sep = if first { Break::Nothing } else { Break::Space };
+      first = false;
+
+      match element {
+        SyntaxElement::Node(child) => {
+          let sep = if child.kind() == SyntaxKind::ArgList { Break::Nothing } else { sep };
+          f.sep(sep);
+          f.node(&child);
+        },
+        SyntaxElement::Token(token) => {
+          let sep = if *token.token() == Token::Semicolon { Break::Nothing } else { sep };
+          f.word(&token, sep);
+        }
+      }
+    });
+  }
+
+  // visits the children of `node`, writing its comments on the way
+  fn each<F>(&mut self, node: &SyntaxNode, mut visit: F) where F: FnMut(&mut Formatter, SyntaxElement) {
+    for element in node.children_with_tokens() {
+      match element {
+        SyntaxElement::Token(ref token) if token.kind().is_trivia() => self.trivia(token),
+        element => visit(self, element)
+      }
+    }
+  }
+
+  // comments wait for the next word, so one on its own line before a
+  // procedure gets the indentation of the procedure and one before `end`
+  // keeps the indentation of the statements
+  fn trivia(&mut self, token: &SyntaxToken) {
+    match token.kind() {
+      SyntaxKind::Newline => self.newlines += 1,
+      SyntaxKind::Whitespace => {},
+      _ => {
+        self.comments.push(Pending { text: token.text().to_string(), indent: self.indent, newlines: self.newlines });
+        self.newlines = 0;
+      }
+    }
+  }
+
+  fn sep(&mut self, sep: Break) {
+    if sep > self.pending {
+      self.pending = sep;
+    }
+  }
+
+  fn pad(&mut self, n: usize) {
+    self.pending = Break::Nothing;
+    self.out.push_str(&" ".repeat(n));
+  }
+
+  fn word(&mut self, token: &SyntaxToken, sep: Break) {
+    self.sep(sep);
+    match token.kind() {
+      SyntaxKind::Keyword | SyntaxKind::BoolLiteral | SyntaxKind::AddOperator |
+      SyntaxKind::MulOperator | SyntaxKind::RelOperator | SyntaxKind::LogicOperator => self.write(&token.text().to_lowercase()),
+      _ => self.write(token.text())
+    }
+  }
+
+  fn write(&mut self, text: &str) {
+    let pending = self.pending;
+    let newlines = self.newlines;
+    let forced = self.flush_comments();
+
+    let indent = self.indent;
+    self.emit(if forced { Break::Line } else { pending }, indent, newlines, text);
+  }
+
+  // returns true when the next word has to start a new line
+  fn flush_comments(&mut self) -> bool {
+    let mut forced = false;
+
+    for comment in ::std::mem::take(&mut self.comments) {
+      let own_line = comment.newlines > 0;
+      let indent = comment.indent.max(self.indent);
+      self.emit(if own_line { Break::Line } else { Break::Space }, indent, comment.newlines, &comment.text);
+      forced = own_line || comment.text.starts_with("//");
+    }
+    forced
+  }
+
+  fn emit(&mut self, before: Break, indent: usize, newlines: usize, text: &str) {
+    if !self.out.is_empty() {
+      match before {
+        Break::Line => {
+          if newlines > 1 {
+            self.out.push('\n');
+          }
+          self.out.push('\n');
+          self.out.push_str(&"  ".repeat(indent));
+        },
+        Break::Space => self.out.push(' '),
+        Break::Nothing => {}
+      }
+    }
+    self.out.push_str(text);
+    self.pending = Break::Nothing;
+    self.newlines = 0;
+  }
+
+  fn last_word_is(&self, word: &str) -> bool {
+    self.out.ends_with(word)
+  }
+}
+
+// length of `a, b` in `a, b: integer;`
+fn names_width(declaration: &SyntaxNode) -> usize {
+  let names: Vec<SyntaxToken> = declaration.child_tokens().into_iter()
+    .take_while(|token| *token.token() != Token::Colon)
+    .filter(|token| token.kind() == SyntaxKind::Identifier)
+    .collect();
+  let letters: usize = names.iter().map(|token| token.text().chars().count()).sum();
+  letters + 2 * names.len().saturating_sub(1)
+}
+
+#[test]
+fn test_formatter_program10() {
+  let text = ::std::fs::read_to_string("files/program10.txt").unwrap();
+
+  assert_eq!(format(&text).unwrap(), "\
+program exemplo;
+var
+  a, b: integer;
+  procedure p(x: integer);
+  var
+    b, c: integer;
+  begin
+    b := x + x;
+  end;
+  procedure q;
+  var
+    c, d: integer;
+    procedure r(y: real; x: integer);
+    var
+      e, f: integer;
+    begin
+      a := (x + x);
+    end;
+  begin
+    c := 4;
+  end;
+begin
+  a := b;
+end.
+");
+}
+
+#[test]
+fn test_formatter_program32() {
+  let text = ::std::fs::read_to_string("files/program32.txt").unwrap();
+
+  assert_eq!(format(&text).unwrap(), "\
+{$B-}
+program Teste; { cabeçalho }
+var
+  NUMERO: integer;
+  x, y  : real;
+  ok    : boolean;
+
+  // rotina
+  procedure p(a: integer; b: real);
+  begin
+    x := a * b
+  end;
+begin
+  if ok and not (x >= y) then
+    numero := -1
+  else if ok then begin
+    x := 1;
+    y := 2
+  end
+  else
+    numero := 2;
+  (* laço *)
+  while numero < 10 do
+    numero := numero + 1;
+
+  p(numero, x) // chamada
+end.
+");
+}
+
+#[test]
+fn test_formatter_keeps_tokens() {
+  for entry in ::std::fs::read_dir("files").unwrap() {
+    let bytes = ::std::fs::read(entry.unwrap().path()).unwrap();
+    let text = String::from_utf8_lossy(&bytes).into_owned();
+
+    let formatted = match format(&text) {
+      Ok(formatted) => formatted,
+      Err(_) => continue
+    };
+    assert!(check(&formatted).unwrap());
+
+    let words = |text: &str| -> Vec<String> {
+      cst::parse(text).tokens().iter()
+        .filter(|token| token.kind() != SyntaxKind::Whitespace && token.kind() != SyntaxKind::Newline)
+        .map(|token| token.text().to_lowercase())
+        .collect()
+    };
+    assert_eq!(words(&formatted), words(&text));
+  }
+}
+
+#[test]
+fn test_formatter_check() {
+  assert!(check("program p;\nbegin\n  a := 1\nend.\n").unwrap());
+  assert!(!check("program p;\nbegin a := 1 end.\n").unwrap());
+  assert_eq!(format("program p;\nbegin\n  a := 1 # 2\nend.").unwrap_err().to_string(), "Error: Cannot format around `#` => line 3");
+}
+
+#[test]
+fn test_formatter_program25() {
+  let text = ::std::fs::read_to_string("files/program25.txt").unwrap();
+
+  // the code {$IFDEF} leaves out is written as it was, like a comment
+  assert_eq!(format(&text).unwrap(), "\
+program diretivas; {$B-} {$R+}
+{$DEFINE DEBUG}
+var
+  a, b     : integer;
+  {$IFDEF DEBUG}
+  depurando: boolean;
+{$ELSE}
+depurando: integer;
+{$ENDIF}
+{$I program26.txt}
+begin
+  a := 1;
+  b := 0;
+  depurando := (b <> 0) and (a div b > 0);
+  {$IFNDEF debug} isso nunca # compila {$ENDIF}
+  ok := true
+end.
+");
+  assert!(check(&format(&text).unwrap()).unwrap());
+}
+
+#[test]
+fn test_formatter_inactive() {
+  let text = "program p;\n{$IFDEF X}\nvar   a : # integer;\n{$ELSE}\nvar\n  a: integer;\n{$ENDIF}\nbegin\n  a := 1\nend.\n";
+  assert_eq!(format(text).unwrap(), "program p;\n{$IFDEF X}\nvar   a : # integer;\n{$ELSE}\nvar\n  a: integer;\n{$ENDIF}\nbegin\n  a := 1\nend.\n");
+}
+
+#[test]
+fn test_formatter_latin1() {
+  // "não" in Latin-1 stays one byte
+  let bytes = b"program p;\r\nbegin a := 1 { n\xe3o } end.\r\n";
+  assert_eq!(format_bytes(bytes, Encoding::Latin1).unwrap(),
+    b"program p;\nbegin\n  a := 1 { n\xe3o }\nend.\n".to_vec());
+}
+
+#[test]
+fn test_formatter_type_section() {
+  let text = "program p; TYPE color=(red,green ,blue); var c:color; d:(up,down); begin c:=red end.";
+
+  assert_eq!(format(text).unwrap(), "\
+program p;
+type
+  color = (red, green, blue);
+var
+  c: color;
+  d: (up, down);
+begin
+  c := red
+end.
+");
+}
+
+#[test]
+fn test_formatter_subranges() {
+  let text = "program p; type d = 0 .. 9; var t: - 5..+5; w: red .. green; begin end.";
+
+  assert_eq!(format(text).unwrap(), "\
+program p;
+type
+  d = 0..9;
+var
+  t: -5..+5;
+  w: red..green;
+begin
+end.
+");
+}
+
+#[test]
+fn test_formatter_sets() {
+  let text = "program p; var s: set   of 0 .. 9; begin s := [ 1 ,3 .. 5 ]+[]; b := 2 in s end.";
+
+  assert_eq!(format(text).unwrap(), "\
+program p;
+var
+  s: set of 0..9;
+begin
+  s := [1, 3..5] + [];
+  b := 2 in s
+end.
+");
+}
@@ -0,0 +1,709 @@
+use std::collections::HashMap;
+use std::fmt;
+use ast::*;
+use diagnostic::Diagnostic;
+use options::BooleanEval;
+use parser::Category;
+use spec::Token;
+use types;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+  Integer(i32),
+  Real(f64),
+  Boolean(bool),
+  // bit `n` is set when the ordinal `n` is an element
+  Set(u64)
+}
+
+impl Value {
+  // a subrange starts at its lower bound, `range` of a set bounds its
+  // elements and it starts empty
+  pub fn default_of(category: Category, range: Option<Subrange>, line: u32) -> Result<Value, Diagnostic> {
+    let first = range.map_or(0, |range| range.first);
+    Ok(match category {
+      Category::Integer => Value::Integer(first),
+      Category::Real => Value::Real(0.0),
+      Category::Boolean => Value::Boolean(first == 1),
+      // enumerations hold the ordinal of their value
+      Category::Enum(..) => Value::Integer(first),
+      Category::Set(_) => Value::Set(0),
+      Category::Procedure | Category::Program | Category::Undefined => {
+        return Err(Diagnostic::internal(format!("`{:?}` has no value", category), line));
+      }
+    })
+  }
+
+  // `succ` moves `by` 1 and `pred` by -1, `last` is the highest ordinal of
+  // the type as `last_ordinal` gives it
+  pub fn step(self, by: i32, last: i32, line: u32) -> Result<Value, Diagnostic> {
+    let next = self.ordinal(line)?.checked_add(by);
+    if last == i32::MAX {
+      return next.map(Value::Integer).ok_or_else(|| Diagnostic::runtime("Integer overflow".to_string(), line));
+    }
+    match next {
+      Some(n) if n >= 0 && n <= last => Ok(if let Value::Boolean(_) = self { Value::Boolean(n == 1) } else { Value::Integer(n) }),
+      _ => Err(Diagnostic::runtime(format!("Value out of range in `{}`", if by > 0 { "succ" } else { "pred" }), line))
+    }
+  }
+
+  pub fn ordinal(self, line: u32) -> Result<i32, Diagnostic> {
+    match self {
+      Value::Integer(i) => Ok(i),
+      Value::Boolean(b) => Ok(b as i32),
+      _ => Err(self.mismatch("an ordinal", line))
+    }
+  }
+
+  fn as_real(self, line: u32) -> Result<f64, Diagnostic> {
+    match self {
+      Value::Integer(i) => Ok(i as f64),
+      Value::Real(f) => Ok(f),
+      _ => Err(self.mismatch("a number", line))
+    }
+  }
+
+  fn as_integer(self, line: u32) -> Result<i32, Diagnostic> {
+    match self {
+      Value::Integer(i) => Ok(i),
+      _ => Err(self.mismatch("an integer", line))
+    }
+  }
+
+  pub fn as_bool(self, line: u32) -> Result<bool, Diagnostic> {
+    match self {
+      Value::Boolean(b) => Ok(b),
+      _ => Err(self.mismatch("a boolean", line))
+    }
+  }
+
+  pub fn as_set(self, line: u32) -> Result<u64, Diagnostic> {
+    match self {
+      Value::Set(s) => Ok(s),
+      _ => Err(self.mismatch("a set", line))
+    }
+  }
+
+  fn mismatch(self, expected: &str, line: u32) -> Diagnostic {
+    Diagnostic::internal(format!("expected {} found `{}`", expected, self), line)
+  }
+}
+
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Value::Integer(i) => write!(f, "{}", i),
+      Value::Real(r) => write!(f, "{}", r),
+      Value::Boolean(b) => write!(f, "{}", b),
+      Value::Set(s) => {
+        let elements = (0..types::SET_SIZE).filter(|&n| member(n, s)).map(|n| n.to_string());
+        write!(f, "[{}]", elements.collect::<Vec<String>>().join(", "))
+      }
+    }
+  }
+}
+
+// the bits of the ordinals `first..last`, a single element when both are the
+// same
+pub fn set_bits(first: i32, last: i32, line: u32) -> Result<u64, Diagnostic> {
+  if last < first {
+    return Ok(0);
+  }
+  if first < 0 || last >= types::SET_SIZE {
+    return Err(Diagnostic::runtime("Set element out of range".to_string(), line));
+  }
+  Ok((u64::MAX >> (types::SET_SIZE - 1 - (last - first))) << first)
+}
+
+// ordinals outside of a set are never in it
+pub fn member(ordinal: i32, set: u64) -> bool {
+  (0..types::SET_SIZE).contains(&ordinal) && set & (1 << ordinal) != 0
+}
+
+// highest ordinal of an ordinal type, Integer has no other limit than
+// overflow
+pub fn last_ordinal(category: Category) -> i32 {
+  match category {
+    Category::Boolean => 1,
+    Category::Enum(_, count) => count as i32 - 1,
+    _ => i32::MAX
+  }
+}
+
+// `green` rather than the ordinal of an enumeration value
+pub fn show(value: Value, category: Category, enums: &[Enumeration]) -> String {
+  match (value, category) {
+    (Value::Integer(i), Category::Enum(id, _)) => enums[id as usize].values[i as usize].clone(),
+    (Value::Set(s), _) => match types::element_of(category) {
+      Some(element) if element != Category::Integer => {
+        let elements = (0..types::SET_SIZE).filter(|&n| member(n, s)).map(|n| {
+          if element == Category::Boolean { Value::Boolean(n == 1).to_string() } else { show(Value::Integer(n), element, enums) }
+        });
+        format!("[{}]", elements.collect::<Vec<String>>().join(", "))
+      },
+      _ => value.to_string()
+    },
+    _ => value.to_string()
+  }
+}
+
+fn division_by_zero(line: u32) -> Diagnostic {
+  Diagnostic::runtime("Division by zero".to_string(), line)
+}
+
+// names are case-insensitive, frames are keyed by the lowercase spelling
+fn key(name: &str) -> String {
+  name.to_lowercase()
+}
+
+struct Frame<'a> {
+  variables: HashMap<String, Value>,
+  procedures: HashMap<String, &'a Procedure>,
+  // frame of the enclosing procedure (static link)
+  parent: Option<usize>
+}
+
+pub struct Interpreter<'a> {
+  frames: Vec<Frame<'a>>
+}
+
+impl<'a> Default for Interpreter<'a> {
+  fn default() -> Interpreter<'a> {
+    Interpreter::new()
+  }
+}
+
+impl<'a> Interpreter<'a> {
+  pub fn new() -> Interpreter<'a> {
+    Interpreter { frames: Vec::new() }
+  }
+
+  // runtime errors abort the program, see `try_run` to get them back
+  pub fn run(&mut self, program: &'a Program) {
+    if let Err(diagnostic) = self.try_run(program) {
+      panic!("{}", diagnostic)
+    }
+  }
+
+  pub fn try_run(&mut self, program: &'a Program) -> Result<(), Diagnostic> {
+    self.frames.clear();
+    self.enter(&program.block, &[], Vec::new(), None)?;
+    self.execute_list(&program.block.body)
+  }
+
+  // runs `block` as the program with its variables starting from `values`,
+  // which is how the REPL keeps its state from one input to the next
+  pub fn run_block(&mut self, block: &'a Block, values: &[(String, Value)]) -> Result<(), Diagnostic> {
+    self.frames.clear();
+    self.enter(block, &[], Vec::new(), None)?;
+    for (name, value) in values.iter() {
+      self.frames[0].variables.insert(key(name), *value);
+    }
+    self.execute_list(&block.body)
+  }
+
+  // value of a variable of the program after `run`
+  pub fn value_of(&self, name: &str) -> Option<Value> {
+    self.frames.first().and_then(|frame| frame.variables.get(&key(name)).cloned())
+  }
+
+  fn enter(&mut self, block: &'a Block, params: &[Declaration], args: Vec<Value>, parent: Option<usize>) -> Result<(), Diagnostic> {
+    let mut frame = Frame { variables: HashMap::new(), procedures: HashMap::new(), parent };
+    let mut args = args.into_iter();
+
+    for decl in params {
+      for name in decl.names.iter() {
+        let value = match args.next() {
+          Some(v) => self.coerce(v, decl.category, decl.line)?,
+          None => Value::default_of(decl.category, decl.range, decl.line)?
+        };
+        frame.variables.insert(key(name), value);
+      }
+    }
+    for decl in block.variables.iter() {
+      for name in decl.names.iter() {
+        frame.variables.insert(key(name), Value::default_of(decl.category, decl.range, decl.line)?);
+      }
+    }
+    for procedure in block.procedures.iter() {
+      frame.procedures.insert(key(&procedure.name), procedure);
+    }
+    self.frames.push(frame);
+    Ok(())
+  }
+
+  fn execute_list(&mut self, commands: &'a [Command]) -> Result<(), Diagnostic> {
+    for command in commands {
+      self.execute(command)?;
+    }
+    Ok(())
+  }
+
+  fn execute(&mut self, command: &'a Command) -> Result<(), Diagnostic> {
+    match *command {
+      Command::Assign { ref name, ref value, line } => {
+        let v = self.eval(value)?;
+        let index = self.resolve(name)
+          .ok_or_else(|| Diagnostic::runtime(format!("Identifier `{}` not declared", name), line))?;
+        let current = self.frames[index].variables[&key(name)];
+        let v = match current {
+          Value::Real(_) => Value::Real(v.as_real(line)?),
+          _ => v
+        };
+        self.frames[index].variables.insert(key(name), v);
+      },
+      Command::Call { ref name, ref args, line } => self.call(name, args, line)?,
+      Command::Compound(ref commands) => self.execute_list(commands)?,
+      Command::If { ref condition, ref then_branch, ref else_branch, .. } => {
+        if self.eval(condition)?.as_bool(condition.line)? {
+          self.execute(then_branch)?;
+        } else if let Some(ref command) = *else_branch {
+          self.execute(command)?;
+        }
+      },
+      Command::While { ref condition, ref body, .. } => {
+        while self.eval(condition)?.as_bool(condition.line)? {
+          self.execute(body)?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  fn call(&mut self, name: &str, args: &[Expr], line: u32) -> Result<(), Diagnostic> {
+    let (procedure, owner) = self.find_procedure(name)
+      .ok_or_else(|| Diagnostic::runtime(format!("Procedure `{}` not declared", name), line))?;
+    let count: usize = procedure.params.iter().map(|decl| decl.names.len()).sum();
+
+    if count != args.len() {
+      let message = format!("Procedure `{}` expects {} argument(s) found {}", name, count, args.len());
+      return Err(Diagnostic::runtime(message, line));
+    }
+
+    let values = args.iter().map(|arg| self.eval(arg)).collect::<Result<Vec<Value>, Diagnostic>>()?;
+    self.enter(&procedure.block, &procedure.params, values, Some(owner))?;
+    let result = self.execute_list(&procedure.block.body);
+    self.frames.pop();
+    result
+  }
+
+  pub fn eval(&mut self, expr: &Expr) -> Result<Value, Diagnostic> {
+    Ok(match expr.kind {
+      ExprKind::Integer(i) => Value::Integer(i),
+      ExprKind::Real(f) => Value::Real(f),
+      ExprKind::Boolean(b) => Value::Boolean(b),
+      ExprKind::Constant(_, ordinal) => Value::Integer(ordinal),
+      ExprKind::Variable(ref name) => {
+        match self.resolve(name) {
+          Some(index) => self.frames[index].variables[&key(name)],
+          None => return Err(Diagnostic::runtime(format!("Identifier `{}` not declared", name), expr.line))
+        }
+      },
+      ExprKind::Call(ref name, _) => {
+        return Err(Diagnostic::runtime(format!("Procedure `{}` doesn't return a value", name), expr.line));
+      },
+      ExprKind::Builtin(function, ref argument) => {
+        let v = self.eval(argument)?;
+        let last = last_ordinal(argument.category);
+        match function {
+          Builtin::Ord => Value::Integer(v.ordinal(expr.line)?),
+          Builtin::Succ => v.step(1, last, expr.line)?,
+          Builtin::Pred => v.step(-1, last, expr.line)?
+        }
+      },
+      ExprKind::RangeCheck(range, ref value) => {
+        let v = self.eval(value)?;
+        if let Value::Set(set) = v {
+          if set & !set_bits(range.first, range.last, expr.line)? != 0 {
+            return Err(Diagnostic::runtime("Set element out of range".to_string(), expr.line));
+          }
+        } else if !range.contains(v.ordinal(expr.line)?) {
+          return Err(Diagnostic::runtime("Value out of range".to_string(), expr.line));
+        }
+        v
+      },
+      ExprKind::Set(ref items) => {
+        let mut set = 0;
+        for (first, last) in items {
+          let first = self.eval(first)?.ordinal(expr.line)?;
+          let last = match *last {
+            Some(ref last) => self.eval(last)?.ordinal(expr.line)?,
+            None => first
+          };
+          set |= set_bits(first, last, expr.line)?;
+        }
+        Value::Set(set)
+      },
+      ExprKind::Unary(ref op, ref operand) => {
+        let v = self.eval(operand)?;
+        match (op, v) {
+          (&Token::Not, Value::Boolean(b)) => Value::Boolean(!b),
+          (&Token::Sub, Value::Integer(i)) => Value::Integer(self.checked(i.checked_neg(), expr.line)?),
+          (&Token::Sub, Value::Real(f)) => Value::Real(-f),
+          (&Token::Add, _) => v,
+          _ => return Err(Diagnostic::internal(format!("operator `{}` on `{}`", op, v), expr.line))
+        }
+      },
+      ExprKind::Binary(ref op, ref lhs, ref rhs) => {
+        let a = self.eval(lhs)?;
+        let b = self.eval(rhs)?;
+        self.eval_binary(op, a, b, expr)?
+      },
+      ExprKind::Logical(ref op, mode, ref lhs, ref rhs) => self.eval_logical(op, mode, lhs, rhs)?
+    })
+  }
+
+  fn eval_logical(&mut self, op: &Token, mode: BooleanEval, lhs: &Expr, rhs: &Expr) -> Result<Value, Diagnostic> {
+    let a = self.eval(lhs)?.as_bool(lhs.line)?;
+
+    if mode == BooleanEval::ShortCircuit {
+      // the left operand alone may decide the result
+      let decided = match *op {
+        Token::And if !a => Some(false),
+        Token::Or if a => Some(true),
+        Token::Imp if !a => Some(true),
+        _ => None
+      };
+      if let Some(result) = decided {
+        return Ok(Value::Boolean(result));
+      }
+    }
+
+    let b = self.eval(rhs)?.as_bool(rhs.line)?;
+    Ok(Value::Boolean(match *op {
+      Token::And => a && b,
+      Token::Or => a || b,
+      _ => !a || b
+    }))
+  }
+
+  fn eval_binary(&self, op: &Token, a: Value, b: Value, expr: &Expr) -> Result<Value, Diagnostic> {
+    let line = expr.line;
+
+    if let (Value::Set(x), Value::Set(y)) = (a, b) {
+      return Ok(match *op {
+        Token::Add => Value::Set(x | y),
+        Token::Mult => Value::Set(x & y),
+        Token::Sub => Value::Set(x & !y),
+        Token::Equal => Value::Boolean(x == y),
+        Token::NotEqual => Value::Boolean(x != y),
+        // subset and superset
+        Token::LessThanOrEqual => Value::Boolean(x & !y == 0),
+        Token::GreaterThanOrEqual => Value::Boolean(y & !x == 0),
+        _ => return Err(Diagnostic::internal(format!("operator `{}` on sets", op), line))
+      });
+    }
+
+    Ok(match *op {
+      Token::In => Value::Boolean(member(a.ordinal(line)?, b.as_set(line)?)),
+      Token::Equal | Token::NotEqual | Token::LessThan | Token::LessThanOrEqual |
+      Token::GreaterThan | Token::GreaterThanOrEqual => {
+        let ordering = match (a, b) {
+          (Value::Boolean(x), Value::Boolean(y)) => x.partial_cmp(&y),
+          (Value::Integer(x), Value::Integer(y)) => x.partial_cmp(&y),
+          _ => a.as_real(line)?.partial_cmp(&b.as_real(line)?)
+        };
+        let ordering = ordering.ok_or_else(|| Diagnostic::runtime(format!("Invalid comparison `{}`", op), line))?;
+        Value::Boolean(match *op {
+          Token::Equal => ordering.is_eq(),
+          Token::NotEqual => ordering.is_ne(),
+          Token::LessThan => ordering.is_lt(),
+          Token::LessThanOrEqual => ordering.is_le(),
+          Token::GreaterThan => ordering.is_gt(),
+          _ => ordering.is_ge()
+        })
+      },
+      // both sides are always needed
+      Token::Xor => Value::Boolean(a.as_bool(line)? != b.as_bool(line)?),
+      Token::Equiv => Value::Boolean(a.as_bool(line)? == b.as_bool(line)?),
+      Token::IntDiv | Token::Mod => {
+        let (x, y) = (a.as_integer(line)?, b.as_integer(line)?);
+        if y == 0 {
+          return Err(division_by_zero(line));
+        }
+        let result = if *op == Token::IntDiv { x.checked_div(y) } else { x.checked_rem(y) };
+        Value::Integer(self.checked(result, line)?)
+      },
+      Token::Div if expr.category == Category::Integer => {
+        // legacy integer `/`
+        let (x, y) = (a.as_integer(line)?, b.as_integer(line)?);
+        if y == 0 {
+          return Err(division_by_zero(line));
+        }
+        Value::Integer(self.checked(x.checked_div(y), line)?)
+      },
+      Token::Div => {
+        let (x, y) = (a.as_real(line)?, b.as_real(line)?);
+        if y == 0.0 {
+          return Err(division_by_zero(line));
+        }
+        Value::Real(x / y)
+      },
+      Token::Add | Token::Sub | Token::Mult | Token::Power => {
+        if expr.category == Category::Integer {
+          let (x, y) = (a.as_integer(line)?, b.as_integer(line)?);
+          let result = match *op {
+            Token::Add => x.checked_add(y),
+            Token::Sub => x.checked_sub(y),
+            Token::Mult => x.checked_mul(y),
+            _ => if y < 0 { None } else { x.checked_pow(y as u32) }
+          };
+          Value::Integer(self.checked(result, line)?)
+        } else {
+          let (x, y) = (a.as_real(line)?, b.as_real(line)?);
+          Value::Real(match *op {
+            Token::Add => x + y,
+            Token::Sub => x - y,
+            Token::Mult => x * y,
+            _ => x.powf(y)
+          })
+        }
+      },
+      _ => return Err(Diagnostic::internal(format!("operator `{}` on `{}` and `{}`", op, a, b), line))
+    })
+  }
+
+  fn checked(&self, result: Option<i32>, line: u32) -> Result<i32, Diagnostic> {
+    result.ok_or_else(|| Diagnostic::runtime("Integer overflow".to_string(), line))
+  }
+
+  fn coerce(&self, value: Value, category: Category, line: u32) -> Result<Value, Diagnostic> {
+    match category {
+      Category::Real => Ok(Value::Real(value.as_real(line)?)),
+      _ => Ok(value)
+    }
+  }
+
+  // index of the frame that holds `name`, following the static links
+  fn resolve(&self, name: &str) -> Option<usize> {
+    let name = key(name);
+    let mut index = self.frames.len().checked_sub(1);
+
+    while let Some(i) = index {
+      if self.frames[i].variables.contains_key(&name) {
+        return Some(i);
+      }
+      index = self.frames[i].parent;
+    }
+    None
+  }
+
+  fn find_procedure(&self, name: &str) -> Option<(&'a Procedure, usize)> {
+    let name = key(name);
+    let mut index = self.frames.len().checked_sub(1);
+
+    while let Some(i) = index {
+      if let Some(procedure) = self.frames[i].procedures.get(&name) {
+        return Some((*procedure, i));
+      }
+      index = self.frames[i].parent;
+    }
+    None
+  }
+}
+
+#[test]
+fn test_interpreter_program23() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program23.txt");
+  let program = p1.program().unwrap();
+
+  let mut interpreter = Interpreter::new();
+  interpreter.run(program);
+
+  assert_eq!(interpreter.value_of("f"), Some(Value::Integer(120)));
+  assert_eq!(interpreter.value_of("media"), Some(Value::Real(30.0)));
+}
+
+#[test]
+fn test_interpreter_short_circuit() {
+  use options::Options;
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::with_options(Options { boolean_eval: BooleanEval::ShortCircuit, ..Options::default() });
+  p1.build_ast("files/program24.txt");
+  let program = p1.program().unwrap();
+
+  let mut interpreter = Interpreter::new();
+  interpreter.run(program);
+
+  assert_eq!(interpreter.value_of("ok"), Some(Value::Boolean(false)));
+  assert_eq!(interpreter.value_of("imp"), Some(Value::Boolean(true)));
+}
+
+#[test]
+#[should_panic(expected = "Division by zero => line 8")]
+fn test_interpreter_full_evaluation() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program24.txt");
+  let program = p1.program().unwrap();
+
+  Interpreter::new().run(program);
+}
+
+#[test]
+fn test_interpreter_boolean_eval_regions() {
+  use parser::Parser;
+
+  // `{$B-}` holds for the first `and` only, the second one is fully
+  // evaluated
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program38.txt");
+  let program = p1.program().unwrap();
+
+  let mut interpreter = Interpreter::new();
+  assert_eq!(interpreter.try_run(program), Err(Diagnostic::runtime("Division by zero".to_string(), 11)));
+  assert_eq!(interpreter.value_of("ok"), Some(Value::Boolean(false)));
+}
+
+#[test]
+fn test_interpreter_case_insensitive_names() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program28.txt");
+  let program = p1.program().unwrap();
+
+  let mut interpreter = Interpreter::new();
+  interpreter.run(program);
+
+  assert_eq!(interpreter.value_of("Numero"), Some(Value::Integer(13)));
+  assert_eq!(interpreter.value_of("final"), Some(Value::Boolean(true)));
+}
+
+#[test]
+fn test_interpreter_power() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program33.txt");
+  let program = p1.program().unwrap();
+
+  let mut interpreter = Interpreter::new();
+  interpreter.run(program);
+
+  assert_eq!(interpreter.value_of("a"), Some(Value::Integer(512)));
+  assert_eq!(interpreter.value_of("b"), Some(Value::Integer(18)));
+  assert_eq!(interpreter.value_of("c"), Some(Value::Integer(-4)));
+  assert_eq!(interpreter.value_of("d"), Some(Value::Integer(784)));
+  assert_eq!(interpreter.value_of("r"), Some(Value::Real(0.25)));
+  assert_eq!(interpreter.value_of("s"), Some(Value::Real(326.0)));
+}
+
+#[test]
+fn test_interpreter_logic() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program34.txt");
+  let program = p1.program().unwrap();
+
+  let mut interpreter = Interpreter::new();
+  interpreter.run(program);
+
+  assert_eq!(interpreter.value_of("a"), Some(Value::Boolean(true)));
+  assert_eq!(interpreter.value_of("b"), Some(Value::Boolean(false)));
+  assert_eq!(interpreter.value_of("c"), Some(Value::Boolean(true)));
+  assert_eq!(interpreter.value_of("d"), Some(Value::Boolean(true)));
+  assert_eq!(interpreter.value_of("e"), Some(Value::Boolean(false)));
+}
+
+#[test]
+fn test_interpreter_enumerations() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program35.txt");
+  let program = p1.program().unwrap();
+
+  let mut interpreter = Interpreter::new();
+  interpreter.run(program);
+
+  assert_eq!(interpreter.value_of("d"), Some(Value::Integer(1)));
+  assert_eq!(interpreter.value_of("n"), Some(Value::Integer(3)));
+  assert_eq!(interpreter.value_of("m"), Some(Value::Integer(0)));
+  assert_eq!(interpreter.value_of("f"), Some(Value::Boolean(true)));
+  assert_eq!(interpreter.value_of("menor"), Some(Value::Boolean(true)));
+  assert_eq!(interpreter.value_of("igual"), Some(Value::Boolean(true)));
+  assert_eq!(interpreter.value_of("antes"), Some(Value::Boolean(true)));
+  assert_eq!(show(Value::Integer(2), Category::Enum(0, 3), &program.enums), "blue");
+
+  let res = Value::Integer(2).step(1, 2, 7);
+  assert_eq!(res, Err(Diagnostic::runtime("Value out of range in `succ`".to_string(), 7)));
+  let res = Value::Boolean(false).step(-1, 1, 7);
+  assert_eq!(res, Err(Diagnostic::runtime("Value out of range in `pred`".to_string(), 7)));
+}
+
+#[test]
+fn test_interpreter_subranges() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program36.txt");
+  let program = p1.program().unwrap();
+
+  let mut interpreter = Interpreter::new();
+  interpreter.run(program);
+
+  assert_eq!(interpreter.value_of("e"), Some(Value::Integer(9)));
+  assert_eq!(interpreter.value_of("w"), Some(Value::Integer(1)));
+  assert_eq!(interpreter.value_of("f"), Some(Value::Boolean(false)));
+  assert_eq!(interpreter.value_of("n"), Some(Value::Integer(-4)));
+
+  let source = "{$R+} program p; var d: 1..10; n: integer; begin n := 11; d := n end.";
+  let mut p2: Parser = Parser::new();
+  let program = p2.parse_str(source).unwrap();
+  let res = Interpreter::new().try_run(program);
+  assert_eq!(res, Err(Diagnostic::runtime("Value out of range".to_string(), 1)));
+
+  let source = "{$R+} program p; var s: set of 1..3; n: integer; begin n := 7; s := [1, n] end.";
+  let mut p3: Parser = Parser::new();
+  let program = p3.parse_str(source).unwrap();
+  let res = Interpreter::new().try_run(program);
+  assert_eq!(res, Err(Diagnostic::runtime("Set element out of range".to_string(), 1)));
+
+  // subranges start at their lower bound
+  let source = "program p; type color = (red, green, blue); var d: 5..9; w: green..blue; b: true..true; s: set of 1..3; begin end.";
+  let mut p4: Parser = Parser::new();
+  let program = p4.parse_str(source).unwrap();
+  let mut interpreter = Interpreter::new();
+  interpreter.run(program);
+  assert_eq!(interpreter.value_of("d"), Some(Value::Integer(5)));
+  assert_eq!(interpreter.value_of("w"), Some(Value::Integer(1)));
+  assert_eq!(interpreter.value_of("b"), Some(Value::Boolean(true)));
+  assert_eq!(interpreter.value_of("s"), Some(Value::Set(0)));
+}
+
+#[test]
+fn test_interpreter_sets() {
+  use parser::{Element, Parser};
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program37.txt");
+  let program = p1.program().unwrap();
+
+  let mut interpreter = Interpreter::new();
+  interpreter.run(program);
+
+  assert_eq!(interpreter.value_of("a"), Some(Value::Set(0b111010)));
+  assert_eq!(interpreter.value_of("u"), Some(Value::Set(0b111011)));
+  assert_eq!(interpreter.value_of("i"), Some(Value::Set(0b10000)));
+  assert_eq!(interpreter.value_of("d"), Some(Value::Set(0b101010)));
+  assert_eq!(interpreter.value_of("tem"), Some(Value::Boolean(true)));
+  assert_eq!(interpreter.value_of("sub"), Some(Value::Boolean(true)));
+  assert_eq!(interpreter.value_of("sup"), Some(Value::Boolean(false)));
+  assert_eq!(interpreter.value_of("igual"), Some(Value::Boolean(true)));
+  assert_eq!(Value::Set(0b1010).to_string(), "[1, 3]");
+  assert_eq!(show(Value::Set(0b101), Category::Set(Element::Enum(0, 3)), &program.enums), "[red, blue]");
+  assert_eq!(show(Value::Set(0b10), Category::Set(Element::Boolean), &program.enums), "[true]");
+
+  assert_eq!(set_bits(3, 5, 1), Ok(0b111000));
+  assert_eq!(set_bits(5, 3, 1), Ok(0));
+  assert_eq!(set_bits(0, 63, 1), Ok(u64::MAX));
+  assert_eq!(set_bits(60, 64, 9), Err(Diagnostic::runtime("Set element out of range".to_string(), 9)));
+  assert!(!member(-1, u64::MAX));
+}
@@ -1,57 +1,549 @@
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, Cursor, Read};
 use std::iter::Peekable;
+use std::path::{Path, PathBuf};
 use std::str::Chars;
+use diagnostic::Diagnostic;
+use options::{Encoding, NonAsciiIdentifiers, Options};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use spec::*;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Symbol {
   pub token: Token,
   pub category: Type,
+  pub line: u32,
+  // 1-based, counted in characters of the decoded line
+  pub column: u32,
+  // comments found since the previous symbol
+  pub trivia: Vec<Comment>
+}
+
+// `{ }`, `(* *)` or `//` comment, text kept with its delimiters
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Comment {
+  pub text: String,
   pub line: u32
 }
 
+// a stretch of the source for a lossless tree, see `Scanner::pieces`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Piece {
+  Blank(String),
+  // the line break as it was, `\n` or `\r\n`
+  Newline(String),
+  // `{ }`, `(* *)` or `//`, a comment over several lines is one piece
+  Comment(String),
+  Directive(String),
+  // code left out by {$IFDEF}, one piece per line with the blanks inside it
+  Inactive(String),
+  Symbol(Token, Type, String),
+  // text the scanner rejects
+  Unknown(String)
+}
+
+// how deep {$I file} may nest before we assume a cycle
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+// a file or string being read, one per open {$I file}
+struct Source {
+  reader: Box<dyn BufRead>,
+  // used to resolve includes, None for in-memory sources
+  path: Option<PathBuf>,
+  line: u32,
+  encoding: Encoding,
+  // comment still open at the end of the previous line
+  block_comment: Option<Comment>,
+  // rest of the line that was interrupted by {$I file} and its column
+  pending: Option<(String, u32)>,
+  // what `read_line` took off the last line, kept for `pieces`
+  bom: bool,
+  line_break: String
+}
+
 pub struct Scanner {
-  deque_token: VecDeque<Symbol>
+  sources: Vec<Source>,
+  // symbols scanned ahead of the parser
+  deque_token: VecDeque<Result<Symbol, Diagnostic>>,
+  // symbols set by {$DEFINE}
+  defines: HashSet<String>,
+  // one entry per open {$IFDEF}, true when its branch is being compiled
+  conditions: Vec<bool>,
+  // comments waiting for the next symbol
+  trivia: Vec<Comment>,
+  // last line of the main source, where the end of file is reported
+  eof_line: u32,
+  finished: bool,
+  // used for the sources opened from now on
+  encoding: Encoding,
+  non_ascii_identifiers: NonAsciiIdentifiers,
+  warnings: Vec<Diagnostic>,
+  // every stretch of the source, only when reading for `pieces`
+  pieces: Option<Vec<Piece>>,
+  // encoding the main source was read in, once it is closed
+  settled: Encoding
+}
+
+impl Default for Scanner {
+  fn default() -> Scanner {
+    Scanner::new()
+  }
 }
 
 impl Scanner {
   pub fn new() -> Scanner {
-    Scanner { deque_token: VecDeque::new() }
+    Scanner { 
+      sources: Vec::new(),
+      deque_token: VecDeque::new(), 
+      defines: HashSet::new(), 
+      conditions: Vec::new(), 
+      trivia: Vec::new(),
+      eof_line: 0,
+      finished: false,
+      encoding: Encoding::Auto,
+      non_ascii_identifiers: NonAsciiIdentifiers::Reject,
+      warnings: Vec::new(),
+      pieces: None,
+      settled: Encoding::Auto
+    }
+  }
+
+  // call before opening a file, strings are always read as UTF-8
+  pub fn set_options(&mut self, options: &Options) {
+    self.encoding = options.encoding;
+    self.non_ascii_identifiers = options.non_ascii_identifiers;
+  }
+
+  // warnings found so far, the scanner keeps no copy
+  pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+    self.warnings.drain(..).collect()
+  }
+
+  #[allow(clippy::should_implement_trait)]
+  pub fn from_str(source: &str) -> Scanner {
+    Scanner::from_reader(Cursor::new(source.to_string().into_bytes()))
+  }
+
+  pub fn from_reader<R: BufRead + 'static>(reader: R) -> Scanner {
+    let mut s = Scanner::new();
+    s.push_source(Box::new(reader), None);
+    s
+  }
+
+  // reader whose bytes are not UTF-8
+  pub fn from_reader_with_encoding<R: BufRead + 'static>(reader: R, encoding: Encoding) -> Scanner {
+    let mut s = Scanner::new();
+    s.encoding = encoding;
+    s.push_source(Box::new(reader), None);
+    s
   }
   
+  // the file a reader was read from, so {$I file} is found next to it
+  pub fn set_path(&mut self, path: &str) {
+    if let Some(source) = self.sources.first_mut() {
+      source.path = Some(PathBuf::from(path));
+    }
+  }
+  
+  // the whole source split into symbols and everything between them, for a
+  // tree that gives the text back as it was. Directives are kept as written
+  // instead of being acted on, except that conditional ones decide which code
+  // is `Inactive`. The encoding the source turned out to be in comes with
+  // them, see `encode`
+  pub fn pieces<R: BufRead + 'static>(reader: R, encoding: Encoding) -> (Vec<Piece>, Encoding) {
+    let mut s = Scanner::from_reader_with_encoding(reader, encoding);
+    s.non_ascii_identifiers = NonAsciiIdentifiers::Allow;
+    s.pieces = Some(Vec::new());
+    while s.fill() {
+      s.deque_token.clear();
+    }
+    (s.pieces.take().unwrap(), s.settled)
+  }
+
   pub fn build_token(&mut self, p: &str) {
-    let reader = BufReader::new(File::open(p).expect("Open failed!"));
-    let mut count = 1;
-    let mut block_comment = false;
-
-    for line in reader.lines() {
-      let mut iter = line.as_ref().unwrap().chars().peekable();
-
-      loop {
-        if let Some(c) = iter.next() {
-          if c == ' ' { 
-            while iter.peek() == Some(&' ') { iter.next(); }
-          } else if c == '{' || block_comment {
-            block_comment = true;
-            while iter.peek() != Some(&'}') && iter.peek() != None { iter.next(); }
-            if iter.next() == Some('}') || c == '}' { block_comment = false; }
-          } else {
-            let (token, category) = match c {
-              '+' | '-' | '/' | '*' | '=' | '<' | '>' | '^' => self.operators(c, &mut iter),
-              ';' | '.' | ':' | '(' | ')' | ',' => self.delimiters(c, &mut iter),
-              _ => self.literal(c, &mut iter)
-            };
-            self.deque_token.push_back(Symbol{ token: token, category: category, line: count });
+    self.open(p).expect("Open failed!");
+  }
+
+  // same as `build_token` but the error is returned
+  pub fn open(&mut self, p: &str) -> io::Result<()> {
+    let file = File::open(p)?;
+    self.push_source(Box::new(BufReader::new(file)), Some(PathBuf::from(p)));
+    Ok(())
+  }
+
+  // next symbol for the parser, lexical errors abort like any other error
+  pub fn next_symbol(&mut self) -> Symbol {
+    match self.try_next_symbol() {
+      Ok(symbol) => symbol,
+      Err(diagnostic) => panic!("{}", diagnostic)
+    }
+  }
+
+  // same as `next_symbol` but lexical errors are returned
+  pub fn try_next_symbol(&mut self) -> Result<Symbol, Diagnostic> {
+    match self.next() {
+      Some(result) => result,
+      None => Ok(Symbol { token: Token::Empty, category: Type::Eof, line: self.eof_line, column: 0, trivia: Vec::new() })
+    }
+  }
+
+  // looks `n` symbols ahead without consuming anything, 0 is the next one
+  pub fn peek_nth(&mut self, n: usize) -> Option<&Result<Symbol, Diagnostic>> {
+    while self.deque_token.len() <= n && self.fill() {}
+    self.deque_token.get(n)
+  }
+
+  fn push_source(&mut self, reader: Box<dyn BufRead>, path: Option<PathBuf>) {
+    let encoding = self.encoding;
+    self.sources.push(Source { reader, path, line: 0, encoding, block_comment: None, pending: None, bom: false, line_break: String::new() });
+  }
+
+  // scans until at least one more symbol or error is queued,
+  // returns false once the end of the main source has been queued
+  fn fill(&mut self) -> bool {
+    let before = self.deque_token.len();
+
+    while self.deque_token.len() == before {
+      if self.finished {
+        return false;
+      }
+
+      let (text, first) = match self.sources.last_mut() {
+        None => {
+          if !self.conditions.is_empty() {
+            self.push_error("Unterminated conditional directive".to_string(), self.eof_line);
+          }
+          // carries the comments after the last symbol
+          let line = self.eof_line;
+          self.push_symbol(Token::Empty, Type::Eof, line, 0);
+          self.finished = true;
+          continue;
+        },
+        Some(source) => match source.pending.take() {
+          Some((rest, column)) => (Ok(Some((rest, true))), column),
+          None => (source.read_line(), 1)
+        }
+      };
+
+      match text {
+        Ok(Some((text, valid))) => {
+          if !valid {
+            let line = self.sources.last().unwrap().line;
+            self.push_error("Invalid UTF-8 in source".to_string(), line);
+          }
+          let (bom, line_break) = {
+            let source = self.sources.last().unwrap();
+            (source.line == 1 && source.bom, source.line_break.clone())
+          };
+          if bom {
+            self.record(Piece::Blank("\u{feff}".to_string()));
           }
+          self.scan_line(&text, first);
+          if self.sources.last().is_some_and(|source| source.block_comment.is_some()) {
+            self.extend_comment(&line_break);
+          } else if line_break.contains('\n') {
+            self.record(Piece::Newline(line_break));
+          } else if !line_break.is_empty() {
+            self.record(Piece::Blank(line_break));
+          }
+        },
+        Ok(None) => self.close_source(),
+        Err(e) => {
+          let line = self.sources.last().unwrap().line + 1;
+          self.push_error(format!("Cannot read source: {}", e), line);
+          self.close_source();
+        }
+      }
+    }
+    true
+  }
+
+  fn close_source(&mut self) {
+    let source = self.sources.pop().unwrap();
+
+    if let Some(comment) = source.block_comment {
+      self.push_error("Unterminated comment".to_string(), comment.line);
+    }
+    if self.sources.is_empty() {
+      self.eof_line = source.line;
+      self.settled = source.encoding;
+    }
+  }
+
+  // `first` is the column of the first character of `text`
+  fn scan_line(&mut self, text: &str, first: u32) {
+    let count = self.sources.last().unwrap().line;
+    let length = text.chars().count() as u32;
+    let mut block_comment = self.sources.last_mut().unwrap().block_comment.take();
+    let mut iter = text.chars().peekable();
+    // byte offset of `iter` in `text`, for `pieces`
+    let offset = |iter: &Peekable<Chars>| text.len() - iter.clone().map(char::len_utf8).sum::<usize>();
+
+    if let Some(ref mut comment) = block_comment {
+      comment.text.push('\n');
+    }
+
+    loop {
+      let from = offset(&iter);
+      if let Some(mut comment) = block_comment.take() {
+        let closed = self.read_block_comment(&mut comment, &mut iter);
+        self.extend_comment(&text[from..offset(&iter)]);
+        if closed {
+          self.trivia.push(comment);
+          continue;
         } else {
+          // still open at the end of the line
+          block_comment = Some(comment);
           break;
         }
       }
-      count += 1;
+
+      let c = match iter.next() {
+        Some(c) => c,
+        None => break
+      };
+      // where `c` is, only worked out for the symbols that keep it
+      let column = |iter: &Peekable<Chars>| first + length - 1 - iter.clone().count() as u32;
+
+      // tabs and a `\r` left by mixed line endings are blanks too
+      if c.is_ascii_whitespace() { 
+        while iter.peek().is_some_and(|c| c.is_ascii_whitespace()) { iter.next(); }
+        self.record(Piece::Blank(text[from..offset(&iter)].to_string()));
+      } else if c == '{' && iter.peek() == Some(&'$') {
+        let start = column(&iter);
+        iter.next();
+        let mut body = String::new();
+        let mut closed = false;
+        for ch in iter.by_ref() {
+          if ch == '}' { closed = true; break; }
+          body.push(ch);
+        }
+        if self.pieces.is_some() {
+          self.record(Piece::Directive(text[from..offset(&iter)].to_string()));
+          // never opens the include, its text is not part of this source
+          if closed {
+            self.directive(&body, count, start);
+          }
+          continue;
+        }
+        if !closed {
+          self.push_error("Unterminated directive".to_string(), count);
+          break;
+        }
+
+        if let Some(included) = self.directive(&body, count, start) {
+          // finish the rest of this line after the included file
+          let next = first + length - iter.clone().count() as u32;
+          let rest: String = iter.collect();
+          if !rest.is_empty() {
+            self.sources.last_mut().unwrap().pending = Some((rest, next));
+          }
+          self.open_include(included, count);
+          return;
+        }
+      } else if c == '{' {
+        block_comment = Some(Comment { text: c.to_string(), line: count });
+        self.record(Piece::Comment(c.to_string()));
+      } else if c == '(' && iter.peek() == Some(&'*') {
+        iter.next();
+        block_comment = Some(Comment { text: "(*".to_string(), line: count });
+        self.record(Piece::Comment("(*".to_string()));
+      } else if c == '/' && iter.peek() == Some(&'/') {
+        let text: String = Some(c).into_iter().chain(iter.by_ref()).collect();
+        self.record(Piece::Comment(text.clone()));
+        self.trivia.push(Comment { text, line: count });
+      } else if !self.is_active() {
+        // skipped by {$IFDEF}
+        self.record_inactive(&text[from..offset(&iter)]);
+      } else {
+        let start = column(&iter);
+        let result = self.token(c, &mut iter, count);
+        let written = text[from..offset(&iter)].to_string();
+        match result {
+          Ok((token, category)) => {
+            self.record(Piece::Symbol(token.clone(), category.clone(), written));
+            self.push_symbol(token, category, count, start)
+          },
+          Err(diagnostic) => {
+            self.record(Piece::Unknown(written));
+            self.deque_token.push_back(Err(diagnostic))
+          }
+        }
+      }
+    }
+    self.sources.last_mut().unwrap().block_comment = block_comment;
+  }
+
+  fn open_include(&mut self, file: String, line: u32) {
+    if self.sources.len() > MAX_INCLUDE_DEPTH {
+      self.push_error(format!("Includes nested too deeply `{}`", file), line);
+      return;
+    }
+
+    let base = self.sources.last().and_then(|source| source.path.clone());
+    let path = match base {
+      Some(ref p) => p.parent().unwrap_or_else(|| Path::new("")).join(&file),
+      None => PathBuf::from(&file)
+    };
+    match File::open(&path) {
+      Ok(f) => self.push_source(Box::new(BufReader::new(f)), Some(path)),
+      Err(_) => self.push_error(format!("Cannot open include file `{}`", file), line)
+    }
+  }
+
+  // reads up to the closing `}` or `*)`, returns false if the line ends first
+  fn read_block_comment(&self, comment: &mut Comment, iter: &mut Peekable<Chars>) -> bool {
+    let paren = comment.text.starts_with("(*");
+
+    while let Some(c) = iter.next() {
+      comment.text.push(c);
+      if !paren && c == '}' {
+        return true;
+      }
+      if paren && c == '*' && iter.peek() == Some(&')') {
+        comment.text.push(iter.next().unwrap());
+        return true;
+      }
+    }
+    false
+  }
+
+  fn record(&mut self, piece: Piece) {
+    if let Some(ref mut pieces) = self.pieces {
+      pieces.push(piece);
+    }
+  }
+
+  // the blanks between two stretches of skipped code belong to them
+  fn record_inactive(&mut self, text: &str) {
+    if let Some(ref mut pieces) = self.pieces {
+      let n = pieces.len();
+      if n > 1 && matches!(pieces[n - 2], Piece::Inactive(_)) {
+        if let Some(Piece::Blank(blank)) = pieces.last().cloned() {
+          pieces.pop();
+          if let Some(Piece::Inactive(ref mut inactive)) = pieces.last_mut() {
+            inactive.push_str(&blank);
+          }
+        }
+      }
+      match pieces.last_mut() {
+        Some(Piece::Inactive(ref mut inactive)) => inactive.push_str(text),
+        _ => pieces.push(Piece::Inactive(text.to_string()))
+      }
+    }
+  }
+
+  // more of the block comment recorded last
+  fn extend_comment(&mut self, text: &str) {
+    if let Some(Piece::Comment(ref mut comment)) = self.pieces.as_mut().and_then(|pieces| pieces.last_mut()) {
+      comment.push_str(text);
+    }
+  }
+
+  fn push_symbol(&mut self, token: Token, category: Type, line: u32, column: u32) {
+    let trivia = self.trivia.drain(..).collect();
+    self.deque_token.push_back(Ok(Symbol { token, category, line, column, trivia }));
+  }
+
+  fn push_error(&mut self, message: String, line: u32) {
+    self.deque_token.push_back(Err(Diagnostic::lexical(message, line)));
+  }
+
+  // acts on the directives the scanner owns (includes and conditional
+  // compilation) and passes every directive in compiled code on to the parser,
+  // returns the file to read when it is an active {$I file}
+  fn directive(&mut self, text: &str, line: u32, column: u32) -> Option<String> {
+    let mut include = None;
+
+    for directive in Scanner::parse_directive(text) {
+      let was_active = self.is_active();
+
+      match directive {
+        Directive::IfDef(ref name) | Directive::IfNDef(ref name) => {
+          let defined = self.defines.contains(name);
+          let taken = if let Directive::IfDef(_) = directive { defined } else { !defined };
+          self.conditions.push(was_active && taken);
+        },
+        Directive::Else => {
+          match self.conditions.pop() {
+            Some(taken) => {
+              let parent = self.is_active();
+              self.conditions.push(parent && !taken);
+            },
+            None => self.push_error("`{$ELSE}` without `{$IFDEF}`".to_string(), line)
+          }
+        },
+        Directive::EndIf => {
+          let closed = self.conditions.pop();
+          if closed.is_none() {
+            self.push_error("`{$ENDIF}` without `{$IFDEF}`".to_string(), line);
+          }
+        },
+        _ if !was_active => {},
+        Directive::Define(ref name) => { self.defines.insert(name.clone()); },
+        Directive::Undef(ref name) => { self.defines.remove(name); },
+        Directive::Include(ref file) => include = Some(file.clone()),
+        _ => {}
+      }
+
+      if was_active || self.is_active() {
+        self.push_symbol(Token::Directive(directive.clone()), Type::Directive, line, column);
+      }
+    }
+    include
+  }
+
+  fn parse_directive(text: &str) -> Vec<Directive> {
+    let text = text.trim();
+    let (name, arg) = match text.find(char::is_whitespace) {
+      Some(i) => (&text[..i], text[i..].trim()),
+      None => (text, "")
+    };
+
+    // {$R+} or a list of switches such as {$R+,B-}
+    let is_switch = |s: &str| {
+      let mut chars = s.chars();
+      match (chars.next(), chars.next(), chars.next()) {
+        (Some(c), Some('+'), None) | (Some(c), Some('-'), None) => c.is_ascii_alphabetic(),
+        _ => false
+      }
+    };
+    if arg.is_empty() && text.split(',').all(|s| is_switch(s.trim())) {
+      return text.split(',')
+        .map(|s| {
+          let s = s.trim();
+          Directive::Switch(s.chars().next().unwrap().to_ascii_uppercase(), s.ends_with('+'))
+        })
+        .collect();
+    }
+
+    let directive = match &*name.to_uppercase() {
+      "I" | "INCLUDE" if !arg.is_empty() => Directive::Include(arg.to_string()),
+      "DEFINE" if !arg.is_empty() => Directive::Define(arg.to_uppercase()),
+      "UNDEF" if !arg.is_empty() => Directive::Undef(arg.to_uppercase()),
+      "IFDEF" if !arg.is_empty() => Directive::IfDef(arg.to_uppercase()),
+      "IFNDEF" if !arg.is_empty() => Directive::IfNDef(arg.to_uppercase()),
+      "ELSE" => Directive::Else,
+      "ENDIF" => Directive::EndIf,
+      _ => Directive::Other(text.to_string())
+    };
+    vec![directive]
+  }
+
+  fn is_active(&self) -> bool {
+    self.conditions.last().cloned().unwrap_or(true)
+  }
+
+  fn token(&mut self, c: char, iter: &mut Peekable<Chars>, line: u32) -> Result<(Token, Type), Diagnostic> {
+    match c {
+      '+' | '-' | '/' | '*' | '=' | '<' | '>' | '^' => Ok(self.operators(c, iter)),
+      ';' | '.' | ':' | '(' | ')' | '[' | ']' | ',' => Ok(self.delimiters(c, iter)),
+      _ => self.literal(c, iter, line)
     }
-    if block_comment { panic!("Error: Unterminated comment"); }
   }
 
   fn operators(&self, c: char, iter:&mut Peekable<Chars>) -> (Token, Type) {
@@ -60,24 +552,30 @@ impl Scanner {
       '-' => {
         if iter.peek() == Some(&'>') {
           iter.next();
-          (Token::Imp, Type::RelOperator)  
+          (Token::Imp, Type::LogicOperator)  
         } else {
           (Token::Sub, Type::AddOperator)
         }
       },
       '/' => (Token::Div, Type::MulOperator),
-      '^' => (Token::Power, Type::MulOperator),
+      '^' => (Token::Power, Type::PowOperator),
       '*' => {
         if iter.peek() == Some(&'*'){
           iter.next();
-          (Token::Power, Type::MulOperator)  
+          (Token::Power, Type::PowOperator)  
         } else {
           (Token::Mult, Type::MulOperator)
         }
       },
       '=' => (Token::Equal, Type::RelOperator),
       '<' => {
-        if iter.peek() == Some(&'=') {
+        // `a<-1` is still `a < -1`
+        let mut ahead = iter.clone();
+        if ahead.next() == Some('-') && ahead.next() == Some('>') {
+          iter.next();
+          iter.next();
+          (Token::Equiv, Type::LogicOperator)
+        } else if iter.peek() == Some(&'=') {
           iter.next();
           (Token::LessThanOrEqual, Type::RelOperator)
         } else if iter.peek() == Some(&'>') {
@@ -102,9 +600,18 @@ impl Scanner {
   fn delimiters(&self, c: char, iter: &mut Peekable<Chars>) -> (Token, Type) {
     match c {
       ';' => (Token::Semicolon, Type::Delimiter),
-      '.' => (Token::Period, Type::Delimiter),
+      '.' => {
+        if iter.peek() == Some(&'.') {
+          iter.next();
+          (Token::Range, Type::Delimiter)
+        } else {
+          (Token::Period, Type::Delimiter)
+        }
+      },
       '(' => (Token::LParentheses, Type::Delimiter),
       ')' => (Token::RParentheses, Type::Delimiter),
+      '[' => (Token::LBracket, Type::Delimiter),
+      ']' => (Token::RBracket, Type::Delimiter),
       ',' => (Token::Comma, Type::Delimiter),
       ':' => {
         if iter.peek() == Some(&'=') {
@@ -118,43 +625,107 @@ impl Scanner {
     }
   }
 
-  fn literal(&self, c: char, iter: &mut Peekable<Chars>) -> (Token, Type) {
-    if c.is_digit(10) {
-      self.literal_num(c, iter)
+  fn literal(&mut self, c: char, iter: &mut Peekable<Chars>, line: u32) -> Result<(Token, Type), Diagnostic> {
+    if c.is_ascii_digit() || c == '$' || c == '%' {
+      self.literal_num(c, iter, line)
     } else if c.is_alphabetic() {
-      self.literal_str(c, iter)
+      self.literal_str(c, iter, line)
     } else {
-      panic!("Error: Unexpected Symbol => {}", c)
+      Err(Diagnostic::lexical(format!("Unexpected symbol `{}`", c), line))
     }
   }
 
-  fn literal_num(&self, c: char, iter: &mut Peekable<Chars>) -> (Token, Type) {
+  fn literal_num(&self, c: char, iter: &mut Peekable<Chars>, line: u32) -> Result<(Token, Type), Diagnostic> {
+    // $FF hexadecimal, %1010 binary
+    if c == '$' || c == '%' {
+      let radix = if c == '$' { 16 } else { 2 };
+      let mut num = String::new();
+
+      while iter.peek().is_some_and(|d| d.is_digit(radix)) {
+        num.push(iter.next().unwrap());
+      }
+      if num.is_empty() {
+        return Err(Diagnostic::lexical(format!("Expected digits after `{}`", c), line));
+      }
+      return match i32::from_str_radix(&num, radix) {
+        Ok(i) => Ok((Token::LitInt(i), Type::IntLiteral)),
+        Err(_) => Err(Diagnostic::lexical(format!("Integer literal `{}{}` out of range", c, num), line))
+      };
+    }
+
     let mut num = c.to_string();
+    let mut real = false;
 
     while self.is_digit(iter) {
       num.push(iter.next().unwrap());
     }
     
-    if iter.peek() == Some(&'.') {
+    // `1..10` is a range, not the real `1.`
+    let mut ahead = iter.clone();
+    ahead.next();
+    if iter.peek() == Some(&'.') && ahead.peek() != Some(&'.') {
+      real = true;
       num.push(iter.next().unwrap());
       
       while self.is_digit(iter) {
         num.push(iter.next().unwrap());
       } 
-      (Token::LitReal(num.parse().unwrap()), Type::RealLiteral)
+    }
+
+    // exponent, only when digits follow: 1e10, 1.5E-3
+    if iter.peek() == Some(&'e') || iter.peek() == Some(&'E') {
+      let mut ahead = iter.clone();
+      ahead.next();
+      if ahead.peek() == Some(&'+') || ahead.peek() == Some(&'-') { ahead.next(); }
+
+      if ahead.peek().is_some_and(|d| d.is_ascii_digit()) {
+        real = true;
+        num.push(iter.next().unwrap());
+        if iter.peek() == Some(&'+') || iter.peek() == Some(&'-') {
+          num.push(iter.next().unwrap());
+        }
+        while self.is_digit(iter) {
+          num.push(iter.next().unwrap());
+        }
+      }
+    }
+
+    if real {
+      match num.parse::<f64>() {
+        Ok(f) if f.is_finite() => Ok((Token::LitReal(f), Type::RealLiteral)),
+        _ => Err(Diagnostic::lexical(format!("Real literal `{}` out of range", num), line))
+      }
     } else {
-      (Token::LitInt(num.parse().unwrap()), Type::IntLiteral)
+      match num.parse::<i32>() {
+        Ok(i) => Ok((Token::LitInt(i), Type::IntLiteral)),
+        Err(_) => Err(Diagnostic::lexical(format!("Integer literal `{}` out of range", num), line))
+      }
     }
   }
 
-  fn literal_str(&self, c: char, iter: &mut Peekable<Chars>) -> (Token, Type) {
+  fn literal_str(&mut self, c: char, iter: &mut Peekable<Chars>, line: u32) -> Result<(Token, Type), Diagnostic> {
     let mut word = c.to_string();
 
     while self.is_alphanumeric_or_underl(iter) {
       word.push(iter.next().unwrap());
     }
-    match &*word {
+
+    if !word.is_ascii() {
+      let message = format!("Identifier `{}` has non-ASCII letters", word);
+      match self.non_ascii_identifiers {
+        NonAsciiIdentifiers::Reject => return Err(Diagnostic::lexical(message, line)),
+        NonAsciiIdentifiers::Warn => self.warnings.push(Diagnostic::warning(message, line)),
+        NonAsciiIdentifiers::Allow => {}
+      }
+    }
+
+    // keywords are case-insensitive, identifiers keep their spelling
+    Ok(match &*word.to_lowercase() {
       "program" => (Token::Program, Type::Keyword),
+      "type" => (Token::Type, Type::Keyword),
+      "set" => (Token::Set, Type::Keyword),
+      "of" => (Token::Of, Type::Keyword),
+      "in" => (Token::In, Type::RelOperator),
       "var"  => (Token::Var, Type::Keyword),
       "integer" => (Token::Integer, Type::Keyword),
       "real" => (Token::Real, Type::Keyword),
@@ -171,38 +742,123 @@ impl Scanner {
       "true" => (Token::True, Type::BoolLiteral),
       "false" => (Token::False, Type::BoolLiteral),  
       "or" => (Token::Or, Type::AddOperator),
+      "xor" => (Token::Xor, Type::AddOperator),
       "and" => (Token::And, Type::MulOperator),
+      "div" => (Token::IntDiv, Type::MulOperator),
+      "mod" => (Token::Mod, Type::MulOperator),
       _ => (Token::LitStr(word), Type::Identifier)
-    }
-  }
-
-  pub fn next_symbol(&mut self) -> Symbol {
-    self.deque_token.pop_front().expect("Error: Deque token is empty!")
+    })
   }
 
   fn is_digit(&self, iter:&mut Peekable<Chars>) -> bool {
     match iter.peek() {
-      Some(c) => c.is_digit(10),
+      Some(c) => c.is_ascii_digit(),
       _ => false
     }
   }
 
   fn is_alphanumeric_or_underl(&self, iter: &mut Peekable<Chars>) -> bool {
     match iter.peek() {
-      Some(c) => if c.is_alphanumeric() || c==&'_' { true } else { false },
+      Some(c) => c.is_alphanumeric() || c == &'_',
       _ => false
     }
   }
 }
 
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+impl Source {
+  // next line without its line break, the flag is false when the bytes are
+  // not valid in the encoding of the source
+  fn read_line(&mut self) -> io::Result<Option<(String, bool)>> {
+    if self.encoding == Encoding::Auto {
+      self.detect()?;
+    }
+    let mut bytes = Vec::new();
+    if self.reader.read_until(b'\n', &mut bytes)? == 0 {
+      return Ok(None);
+    }
+    self.line += 1;
+
+    let text = bytes.iter().rposition(|&b| b != b'\n' && b != b'\r').map_or(0, |i| i + 1);
+    self.line_break = bytes.drain(text..).map(|b| b as char).collect();
+    self.bom = self.line == 1 && bytes.starts_with(UTF8_BOM);
+    if self.bom {
+      bytes.drain(..UTF8_BOM.len());
+      self.encoding = Encoding::Utf8;
+    }
+    Ok(Some(decode(&bytes, self.encoding)))
+  }
+
+  // settles `Auto` on one encoding for the whole source before its first line
+  // is scanned: UTF-8 when every byte of it is valid UTF-8, else Windows-1252.
+  // Deciding line by line would read a file mixing both in two encodings,
+  // and writing it back in either one would change the other lines
+  fn detect(&mut self) -> io::Result<()> {
+    let mut bytes = Vec::new();
+    self.reader.read_to_end(&mut bytes)?;
+    self.encoding = if bytes.starts_with(UTF8_BOM) || ::std::str::from_utf8(&bytes).is_ok() {
+      Encoding::Utf8
+    } else {
+      Encoding::Windows1252
+    };
+    self.reader = Box::new(Cursor::new(bytes));
+    Ok(())
+  }
+}
+
+fn decode(bytes: &[u8], encoding: Encoding) -> (String, bool) {
+  match encoding {
+    Encoding::Auto | Encoding::Utf8 => match String::from_utf8(bytes.to_vec()) {
+      Ok(text) => (text, true),
+      Err(_) => (String::from_utf8_lossy(bytes).into_owned(), false)
+    },
+    Encoding::Latin1 => (bytes.iter().map(|&b| b as char).collect(), true),
+    Encoding::Windows1252 => (bytes.iter().map(|&b| windows_1252(b)).collect(), true)
+  }
+}
+
+// back to the bytes `decode` read, characters the encoding lacks become `?`
+pub fn encode(text: &str, encoding: Encoding) -> Vec<u8> {
+  match encoding {
+    Encoding::Auto | Encoding::Utf8 => text.as_bytes().to_vec(),
+    Encoding::Latin1 => text.chars().map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' }).collect(),
+    Encoding::Windows1252 => text.chars().map(|c| (0..=255u8).find(|&b| windows_1252(b) == c).unwrap_or(b'?')).collect()
+  }
+}
+
+// Windows-1252 only differs from Latin-1 in 0x80..0x9F,
+// the five unassigned bytes are kept as the Latin-1 control characters
+fn windows_1252(b: u8) -> char {
+  const HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ'
+  ];
+  match b {
+    0x80..=0x9F => HIGH[(b - 0x80) as usize],
+    _ => b as char
+  }
+}
+
+impl Iterator for Scanner {
+  type Item = Result<Symbol, Diagnostic>;
+
+  // scans lazily, one line at a time
+  fn next(&mut self) -> Option<Result<Symbol, Diagnostic>> {
+    if self.deque_token.is_empty() {
+      self.fill();
+    }
+    self.deque_token.pop_front()
+  }
+}
+
 #[test]
 fn test_print_vecdeque() {
   let mut s: Scanner = Scanner::new();
   s.build_token("files/program10.txt");
-  for num in s.deque_token.iter() {
+  for num in s {
     println!("{:?}", num);
   }
-  assert!(true);
 }
 
 #[test]
@@ -347,4 +1003,328 @@ fn test_token_power() {
   //  end.
   assert_eq!(s.next_symbol().token, Token::End);
   assert_eq!(s.next_symbol().token, Token::Period);
-}
\ No newline at end of file
+}
+#[test]
+fn test_token_div_mod() {
+  let mut s: Scanner = Scanner::new();
+  s.build_token("files/program17.txt");
+
+  assert_eq!(s.next_symbol().token, Token::LitInt(7));
+  assert_eq!(s.next_symbol().token, Token::IntDiv);
+  assert_eq!(s.next_symbol().token, Token::LitInt(2));
+  assert_eq!(s.next_symbol().token, Token::Mod);
+  assert_eq!(s.next_symbol().token, Token::LitInt(3));
+  assert_eq!(s.next_symbol().token, Token::Div);
+  assert_eq!(s.next_symbol().token, Token::LitInt(4));
+}
+
+#[test]
+fn test_token_directives() {
+  let mut s: Scanner = Scanner::new();
+  s.build_token("files/program25.txt");
+
+  assert_eq!(s.next_symbol().token, Token::Program);
+  assert_eq!(s.next_symbol().token, Token::LitStr("diretivas".to_string()));
+  assert_eq!(s.next_symbol().token, Token::Semicolon);
+  assert_eq!(s.next_symbol().token, Token::Directive(Directive::Switch('B', false)));
+  assert_eq!(s.next_symbol().token, Token::Directive(Directive::Switch('R', true)));
+  assert_eq!(s.next_symbol().token, Token::Directive(Directive::Define("DEBUG".to_string())));
+  for _ in 0..7 { s.next_symbol(); }
+
+  // {$IFDEF DEBUG} depurando: boolean; {$ELSE} ... {$ENDIF}
+  assert_eq!(s.next_symbol().token, Token::Directive(Directive::IfDef("DEBUG".to_string())));
+  assert_eq!(s.next_symbol().token, Token::LitStr("depurando".to_string()));
+  assert_eq!(s.next_symbol().token, Token::Colon);
+  assert_eq!(s.next_symbol().token, Token::Boolean);
+  assert_eq!(s.next_symbol().token, Token::Semicolon);
+  assert_eq!(s.next_symbol().token, Token::Directive(Directive::Else));
+  assert_eq!(s.next_symbol().token, Token::Directive(Directive::EndIf));
+
+  // {$I program26.txt}
+  assert_eq!(s.next_symbol().token, Token::Directive(Directive::Include("program26.txt".to_string())));
+  assert_eq!(s.next_symbol().token, Token::LitStr("ok".to_string()));
+  assert_eq!(s.next_symbol().token, Token::Colon);
+  assert_eq!(s.next_symbol().token, Token::Boolean);
+  assert_eq!(s.next_symbol().token, Token::Semicolon);
+  assert_eq!(s.next_symbol().token, Token::Begin);
+}
+
+#[test]
+fn test_token_comments() {
+  let mut s: Scanner = Scanner::new();
+  s.build_token("files/program27.txt");
+
+  assert_eq!(s.next_symbol().token, Token::Program);
+  assert_eq!(s.next_symbol().token, Token::LitStr("comentarios".to_string()));
+  assert_eq!(s.next_symbol().token, Token::Semicolon);
+
+  let var = s.next_symbol();
+  assert_eq!(var.token, Token::Var);
+  assert_eq!(var.trivia, vec![Comment { text: "(* estilo\n  com parenteses *)".to_string(), line: 1 }]);
+
+  for _ in 0..4 { s.next_symbol(); }
+  let begin = s.next_symbol();
+  assert_eq!(begin.token, Token::Begin);
+  assert_eq!(begin.trivia[0].text, "// ate o fim da linha");
+
+  let a = s.next_symbol();
+  assert_eq!(a.trivia[0].text, "{ chaves }");
+  assert_eq!(s.next_symbol().token, Token::Assign);
+  assert_eq!(s.next_symbol().token, Token::LParentheses);
+  assert_eq!(s.next_symbol().token, Token::LitInt(2));
+  assert_eq!(s.next_symbol().token, Token::RParentheses);
+  assert_eq!(s.next_symbol().token, Token::Mult);
+
+  let three = s.next_symbol();
+  assert_eq!(three.token, Token::LitInt(3));
+  assert_eq!(three.trivia[0].text, "(* no meio *)");
+
+  assert_eq!(s.next_symbol().token, Token::End);
+  assert_eq!(s.next_symbol().token, Token::Period);
+
+  let eof = s.next_symbol();
+  assert_eq!(eof.category, Type::Eof);
+  assert_eq!(eof.trivia[0].text, "// depois do fim");
+}
+
+#[test]
+fn test_token_keywords_case_insensitive() {
+  let mut s: Scanner = Scanner::new();
+  s.build_token("files/program28.txt");
+
+  assert_eq!(s.next_symbol().token, Token::Program);
+  assert_eq!(s.next_symbol().token, Token::LitStr("Maiusculas".to_string()));
+  assert_eq!(s.next_symbol().token, Token::Semicolon);
+  assert_eq!(s.next_symbol().token, Token::Var);
+  assert_eq!(s.next_symbol().token, Token::LitStr("NUMERO".to_string()));
+  assert_eq!(s.next_symbol().token, Token::Colon);
+  assert_eq!(s.next_symbol().token, Token::Integer);
+  assert_eq!(s.next_symbol().token, Token::Semicolon);
+  assert_eq!(s.next_symbol().token, Token::LitStr("Final".to_string()));
+  assert_eq!(s.next_symbol().token, Token::Colon);
+  assert_eq!(s.next_symbol().token, Token::Boolean);
+}
+
+#[test]
+fn test_token_literal_num_formats() {
+  let mut s: Scanner = Scanner::new();
+  s.build_token("files/program29.txt");
+
+  assert_eq!(s.next_symbol().token, Token::LitReal(1e10));
+  assert_eq!(s.next_symbol().token, Token::LitReal(1.5E-3));
+  assert_eq!(s.next_symbol().token, Token::LitReal(2.5e+2));
+  assert_eq!(s.next_symbol().token, Token::LitInt(255));
+  assert_eq!(s.next_symbol().token, Token::LitInt(10));
+  assert_eq!(s.next_symbol().token, Token::LitInt(2147483647));
+  assert_eq!(s.next_symbol().token, Token::LitReal(0.1));
+  // `e` not followed by digits belongs to the next word
+  assert_eq!(s.next_symbol().token, Token::LitInt(3));
+  assert_eq!(s.next_symbol().token, Token::Else);
+}
+
+#[test]
+fn test_token_literal_int_overflow() {
+  let mut s: Scanner = Scanner::new();
+  s.build_token("files/program30.txt");
+
+  assert_eq!(s.next_symbol().token, Token::LitInt(1));
+  assert_eq!(s.next_symbol().token, Token::LitInt(2));
+  assert_eq!(s.next_symbol().token, Token::LitInt(3));
+  assert_eq!(s.next().unwrap().unwrap_err().to_string(), "Error: Integer literal `99999999999` out of range => line 2");
+  assert_eq!(s.next_symbol().category, Type::Eof);
+}
+
+#[test]
+fn test_scanner_from_str() {
+  let mut s: Scanner = Scanner::from_str("a := 1 # 2;\n{ aberto");
+
+  assert_eq!(s.peek_nth(2).unwrap().as_ref().unwrap().token, Token::LitInt(1));
+  assert_eq!(s.next_symbol().token, Token::LitStr("a".to_string()));
+  assert_eq!(s.next_symbol().token, Token::Assign);
+  assert_eq!(s.next_symbol().token, Token::LitInt(1));
+  assert_eq!(s.next().unwrap().unwrap_err(), Diagnostic::lexical("Unexpected symbol `#`".to_string(), 1));
+  assert_eq!(s.next_symbol().token, Token::LitInt(2));
+  assert_eq!(s.next_symbol().token, Token::Semicolon);
+  assert_eq!(s.next().unwrap().unwrap_err().to_string(), "Error: Unterminated comment => line 2");
+  assert_eq!(s.next_symbol().category, Type::Eof);
+  assert!(s.next().is_none());
+}
+
+#[test]
+fn test_scanner_columns() {
+  let mut s: Scanner = Scanner::from_str("x := { não } 1; {$I files/program26.txt} y\n\tz");
+  let symbols: Vec<(u32, u32)> = s.by_ref().map(|symbol| symbol.unwrap()).map(|symbol| (symbol.line, symbol.column)).collect();
+
+  // columns count characters, the text after an include keeps its own
+  assert_eq!(symbols, vec![(1, 1), (1, 3), (1, 14), (1, 15), (1, 17), (1, 3), (1, 5), (1, 7), (1, 14), (1, 42), (2, 2), (2, 0)]);
+  assert!(s.next().is_none());
+}
+
+#[test]
+fn test_scanner_from_reader() {
+  let reader = BufReader::new(File::open("files/program6.txt").unwrap());
+  let tokens: Vec<Token> = Scanner::from_reader(reader).map(|symbol| symbol.unwrap().token).collect();
+
+  assert_eq!(tokens.len(), 20);
+  assert_eq!(tokens[0], Token::Program);
+  assert_eq!(tokens[18], Token::Period);
+  assert_eq!(tokens[19], Token::Empty);
+}
+
+#[test]
+fn test_scanner_program31_latin1() {
+  let mut s: Scanner = Scanner::new();
+  s.build_token("files/program31.txt");
+
+  let symbols: Vec<Symbol> = s.map(|symbol| symbol.unwrap()).collect();
+  let tokens: Vec<Token> = symbols.iter().map(|symbol| symbol.token.clone()).collect();
+
+  // tabs separate tokens and the `\r` of each line is dropped
+  assert_eq!(tokens[4], Token::LitStr("contador".to_string()));
+  assert_eq!(tokens[5], Token::Colon);
+  assert_eq!(symbols[8].trivia[0].text, "{ não é acentuado }");
+  assert_eq!(symbols[12].trivia[0].text, "// variável");
+  assert_eq!(symbols[12].line, 6);
+  assert_eq!((symbols[4].column, symbols[5].column), (2, 11));
+}
+
+#[test]
+fn test_scanner_program31_utf8() {
+  let reader = BufReader::new(File::open("files/program31.txt").unwrap());
+  let mut s: Scanner = Scanner::from_reader_with_encoding(reader, Encoding::Utf8);
+
+  for _ in 0..4 {
+    assert!(s.next().unwrap().is_ok());
+  }
+  assert_eq!(s.next().unwrap().unwrap_err().to_string(), "Error: Invalid UTF-8 in source => line 3");
+  // the line is still scanned
+  assert_eq!(s.next_symbol().token, Token::LitStr("contador".to_string()));
+}
+
+#[test]
+fn test_scanner_encodings() {
+  assert_eq!(decode(b"n\xE3o", Encoding::Latin1), ("não".to_string(), true));
+  assert_eq!(decode(b"\x93sim\x94 \x80", Encoding::Windows1252), ("“sim” €".to_string(), true));
+  assert_eq!(decode(b"\x93sim\x94", Encoding::Latin1), ("\u{93}sim\u{94}".to_string(), true));
+  assert_eq!(decode(b"n\xE3o", Encoding::Utf8), ("n\u{fffd}o".to_string(), false));
+
+  let mut s: Scanner = Scanner::from_str("\u{feff}a");
+  assert_eq!(s.next_symbol().token, Token::LitStr("a".to_string()));
+}
+
+#[test]
+fn test_scanner_inactive_pieces() {
+  let (pieces, _) = Scanner::pieces(Cursor::new(b"{$IFDEF X} a  # b {c}\n{$ELSE}d{$ENDIF}".to_vec()), Encoding::Auto);
+
+  assert_eq!(pieces, vec![
+    Piece::Directive("{$IFDEF X}".to_string()),
+    Piece::Blank(" ".to_string()),
+    Piece::Inactive("a  # b".to_string()),
+    Piece::Blank(" ".to_string()),
+    Piece::Comment("{c}".to_string()),
+    Piece::Newline("\n".to_string()),
+    Piece::Directive("{$ELSE}".to_string()),
+    Piece::Symbol(Token::LitStr("d".to_string()), Type::Identifier, "d".to_string()),
+    Piece::Directive("{$ENDIF}".to_string())
+  ]);
+}
+
+#[test]
+fn test_scanner_mixed_encodings() {
+  // UTF-8 `café` on line 1 and Latin-1 `não` on line 3, the whole file is
+  // read as Windows-1252 so both lines go back to the same bytes
+  let bytes = ::std::fs::read("files/program39.txt").unwrap();
+  let (pieces, encoding) = Scanner::pieces(Cursor::new(bytes.clone()), Encoding::Auto);
+  assert_eq!(encoding, Encoding::Windows1252);
+  assert!(pieces.contains(&Piece::Comment("{ cafÃ© em UTF-8 }".to_string())));
+  assert!(pieces.contains(&Piece::Comment("{ não em Latin-1 }".to_string())));
+
+  let text: String = pieces.iter().map(|piece| match *piece {
+    Piece::Blank(ref text) | Piece::Newline(ref text) | Piece::Comment(ref text) |
+    Piece::Directive(ref text) | Piece::Inactive(ref text) | Piece::Symbol(_, _, ref text) |
+    Piece::Unknown(ref text) => text.as_str()
+  }).collect();
+  assert_eq!(encode(&text, encoding), bytes);
+
+  let (_, encoding) = Scanner::pieces(Cursor::new("{ café }\n{ não }".as_bytes().to_vec()), Encoding::Auto);
+  assert_eq!(encoding, Encoding::Utf8);
+}
+
+#[test]
+fn test_scanner_pieces() {
+  let (pieces, encoding) = Scanner::pieces(Cursor::new(b"a:=1; { n\xE3o\r\n x } // b\r\n{$I x}@".to_vec()), Encoding::Auto);
+
+  assert_eq!(encoding, Encoding::Windows1252);
+  assert_eq!(pieces, vec![
+    Piece::Symbol(Token::LitStr("a".to_string()), Type::Identifier, "a".to_string()),
+    Piece::Symbol(Token::Assign, Type::Command, ":=".to_string()),
+    Piece::Symbol(Token::LitInt(1), Type::IntLiteral, "1".to_string()),
+    Piece::Symbol(Token::Semicolon, Type::Delimiter, ";".to_string()),
+    Piece::Blank(" ".to_string()),
+    Piece::Comment("{ não\r\n x }".to_string()),
+    Piece::Blank(" ".to_string()),
+    Piece::Comment("// b".to_string()),
+    Piece::Newline("\r\n".to_string()),
+    Piece::Directive("{$I x}".to_string()),
+    Piece::Unknown("@".to_string())
+  ]);
+  assert_eq!(encode("{ não }", encoding), b"{ n\xE3o }");
+  assert_eq!(encode("“€” ✓", Encoding::Windows1252), b"\x93\x80\x94 ?");
+  assert_eq!(encode("✓", Encoding::Latin1), b"?");
+}
+
+#[test]
+fn test_scanner_non_ascii_identifiers() {
+  let mut s: Scanner = Scanner::from_str("ação := 1");
+  assert_eq!(s.next().unwrap().unwrap_err().to_string(), "Error: Identifier `ação` has non-ASCII letters => line 1");
+  assert_eq!(s.next_symbol().token, Token::Assign);
+
+  let mut s: Scanner = Scanner::from_str("ação := 1");
+  s.set_options(&Options { non_ascii_identifiers: NonAsciiIdentifiers::Warn, ..Options::default() });
+  assert_eq!(s.next_symbol().token, Token::LitStr("ação".to_string()));
+  assert_eq!(s.take_warnings(), vec![Diagnostic::warning("Identifier `ação` has non-ASCII letters".to_string(), 1)]);
+
+  let mut s: Scanner = Scanner::from_str("ação := 1");
+  s.set_options(&Options { non_ascii_identifiers: NonAsciiIdentifiers::Allow, ..Options::default() });
+  assert_eq!(s.next_symbol().token, Token::LitStr("ação".to_string()));
+  assert!(s.take_warnings().is_empty());
+}
+
+#[test]
+fn test_scanner_logic_operators() {
+  let tokens: Vec<(Token, Type)> = Scanner::from_str("p <-> q xor r -> s; a<-1")
+    .map(|symbol| symbol.unwrap())
+    .map(|symbol| (symbol.token, symbol.category))
+    .collect();
+
+  assert_eq!(tokens[1], (Token::Equiv, Type::LogicOperator));
+  assert_eq!(tokens[3], (Token::Xor, Type::AddOperator));
+  assert_eq!(tokens[5], (Token::Imp, Type::LogicOperator));
+  // `<-` without `>` is still less than a negative number
+  assert_eq!(tokens[9], (Token::LessThan, Type::RelOperator));
+  assert_eq!(tokens[10], (Token::Sub, Type::AddOperator));
+}
+
+#[test]
+fn test_scanner_range() {
+  let tokens: Vec<Token> = Scanner::from_str("1..10 red .. blue 1.5.")
+    .map(|symbol| symbol.unwrap().token)
+    .collect();
+
+  assert_eq!(tokens[..3], [Token::LitInt(1), Token::Range, Token::LitInt(10)]);
+  assert_eq!(tokens[4], Token::Range);
+  assert_eq!(tokens[6..8], [Token::LitReal(1.5), Token::Period]);
+}
+
+#[test]
+fn test_scanner_sets() {
+  let tokens: Vec<(Token, Type)> = Scanner::from_str("set of [1, 3..5]; n in s")
+    .map(|symbol| symbol.unwrap())
+    .map(|symbol| (symbol.token, symbol.category))
+    .collect();
+
+  assert_eq!(tokens[..2], [(Token::Set, Type::Keyword), (Token::Of, Type::Keyword)]);
+  assert_eq!(tokens[2], (Token::LBracket, Type::Delimiter));
+  assert_eq!(tokens[8], (Token::RBracket, Type::Delimiter));
+  assert_eq!(tokens[11], (Token::In, Type::RelOperator));
+}
@@ -1,5 +1,26 @@
 #![crate_type = "lib"]
 #![crate_name = "pascalzim"]
+#[cfg(feature = "serde")]
+extern crate serde;
+extern crate serde_json;
 pub mod spec;
+pub mod options;
+pub mod diagnostic;
 pub mod lexer;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
+pub mod ast;
+pub mod types;
+pub mod symbols;
+pub mod cst;
+pub mod formatter;
+pub mod lsp;
+pub mod interpreter;
+pub mod bytecode;
+pub mod vm;
+pub mod cgen;
+pub mod asm;
+pub mod wasm;
+pub mod repl;
+pub mod truth_table;
+#[cfg(feature = "serde")]
+pub mod dump;
\ No newline at end of file
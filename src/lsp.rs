@@ -0,0 +1,537 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use diagnostic::{Diagnostic, Severity};
+use options::Options;
+use parser::Parser;
+use serde_json::{self, Value};
+use symbols::{Entry, Position, SymbolKind, SymbolTable};
+
+// Language server over stdio. Diagnostics come from `Parser`, and so does
+// everything that needs an exact position (hover, definitions, references,
+// symbols and completion): the `SymbolTable` it filled while checking the
+// document, up to the first error.
+
+pub struct Server {
+  documents: HashMap<String, Document>,
+  shutdown: bool,
+  exit_code: Option<i32>
+}
+
+struct Document {
+  text: String,
+  symbols: SymbolTable,
+  // the type of each entry as Pascal writes it, `color` or `set of color`
+  types: Vec<String>
+}
+
+impl Default for Server {
+  fn default() -> Server {
+    Server::new()
+  }
+}
+
+// reads messages until `exit` or the end of the input,
+// returns the exit code the protocol asks for
+pub fn run<R: BufRead, W: Write>(mut reader: R, mut writer: W) -> i32 {
+  let mut server = Server::new();
+
+  loop {
+    let body = match read_message(&mut reader) {
+      Ok(Some(body)) => body,
+      _ => return 1
+    };
+    let replies = match serde_json::from_str(&body) {
+      Ok(message) => server.handle(&message),
+      Err(e) => vec![error_response(Value::Null, -32700, &e.to_string())]
+    };
+    for reply in replies {
+      if write_message(&mut writer, &reply).is_err() {
+        return 1;
+      }
+    }
+    if let Some(code) = server.exit_code {
+      return code;
+    }
+  }
+}
+
+// one `Content-Length` framed message, None at the end of the input
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+  let mut length = None;
+
+  loop {
+    let mut header = String::new();
+    if reader.read_line(&mut header)? == 0 {
+      return Ok(None);
+    }
+    let header = header.trim_end();
+    if header.is_empty() {
+      break;
+    }
+    if let Some(value) = header.strip_prefix("Content-Length:") {
+      length = value.trim().parse::<usize>().ok();
+    }
+  }
+
+  let length = match length {
+    Some(length) => length,
+    None => return Err(io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))
+  };
+  let mut body = vec![0; length];
+  reader.read_exact(&mut body)?;
+  String::from_utf8(body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+  let body = message.to_string();
+  write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+  writer.flush()
+}
+
+impl Server {
+  pub fn new() -> Server {
+    Server { documents: HashMap::new(), shutdown: false, exit_code: None }
+  }
+
+  // set once `exit` has been received
+  pub fn exit_code(&self) -> Option<i32> {
+    self.exit_code
+  }
+
+  // answers one request or notification, with whatever has to be sent back
+  pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+    let method = message.get("method").and_then(Value::as_str).unwrap_or("");
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+    let id = match message.get("id") {
+      Some(id) => id.clone(),
+      None => return self.notification(method, &params)
+    };
+
+    if self.shutdown {
+      return vec![error_response(id, -32600, "Server is shutting down")];
+    }
+    let result = match method {
+      "initialize" => Some(capabilities()),
+      "shutdown" => {
+        self.shutdown = true;
+        Some(Value::Null)
+      },
+      "textDocument/hover" => self.with_position(&params, hover),
+      "textDocument/definition" => self.with_position(&params, definition),
+      "textDocument/references" => {
+        let declaration = params.pointer("/context/includeDeclaration") == Some(&Value::Bool(true));
+        self.with_position(&params, |uri, document, offset| references(uri, document, offset, declaration))
+      },
+      "textDocument/completion" => self.with_position(&params, completion),
+      "textDocument/documentSymbol" => {
+        let uri = params.pointer("/textDocument/uri").and_then(Value::as_str).unwrap_or("");
+        Some(self.documents.get(uri).map_or(Value::Null, document_symbols))
+      },
+      _ => None
+    };
+
+    match result {
+      Some(result) => vec![serde_json::json!({"jsonrpc": "2.0", "id": id, "result": result})],
+      None => vec![error_response(id, -32601, &format!("Unknown method `{}`", method))]
+    }
+  }
+
+  fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+    let uri = params.pointer("/textDocument/uri").and_then(Value::as_str).unwrap_or("").to_string();
+
+    match method {
+      "exit" => {
+        self.exit_code = Some(if self.shutdown { 0 } else { 1 });
+        Vec::new()
+      },
+      "textDocument/didOpen" => {
+        let text = params.pointer("/textDocument/text").and_then(Value::as_str).unwrap_or("");
+        self.update(uri, text.to_string())
+      },
+      "textDocument/didChange" => {
+        // the server asks for full text synchronisation
+        let changes = params.get("contentChanges").and_then(Value::as_array);
+        match changes.and_then(|changes| changes.last()).and_then(|change| change.get("text")).and_then(Value::as_str) {
+          Some(text) => self.update(uri, text.to_string()),
+          None => Vec::new()
+        }
+      },
+      "textDocument/didClose" => {
+        self.documents.remove(&uri);
+        vec![publish(&uri, Vec::new())]
+      },
+      _ => Vec::new()
+    }
+  }
+
+  fn update(&mut self, uri: String, text: String) -> Vec<Value> {
+    let (found, symbols, types) = check(&text);
+    let diagnostics = found.iter().map(|diagnostic| lsp_diagnostic(&text, diagnostic)).collect();
+    self.documents.insert(uri.clone(), Document { text, symbols, types });
+    vec![publish(&uri, diagnostics)]
+  }
+
+  fn with_position<F>(&self, params: &Value, answer: F) -> Option<Value>
+    where F: FnOnce(&str, &Document, Position) -> Value {
+    let uri = params.pointer("/textDocument/uri").and_then(Value::as_str).unwrap_or("");
+    let line = params.pointer("/position/line").and_then(Value::as_u64).unwrap_or(0) as usize;
+    let character = params.pointer("/position/character").and_then(Value::as_u64).unwrap_or(0) as usize;
+
+    Some(match self.documents.get(uri) {
+      Some(document) => answer(uri, document, position_at(&document.text, line, character)),
+      None => Value::Null
+    })
+  }
+}
+
+fn capabilities() -> Value {
+  serde_json::json!({
+    "capabilities": {
+      "textDocumentSync": 1,
+      "hoverProvider": true,
+      "definitionProvider": true,
+      "referencesProvider": true,
+      "documentSymbolProvider": true,
+      "completionProvider": {}
+    },
+    "serverInfo": {"name": "pascalzim-lsp"}
+  })
+}
+
+fn error_response(id: Value, code: i32, message: &str) -> Value {
+  serde_json::json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+  serde_json::json!({
+    "jsonrpc": "2.0",
+    "method": "textDocument/publishDiagnostics",
+    "params": {"uri": uri, "diagnostics": diagnostics}
+  })
+}
+
+// the parser stops at the first error, warnings come with it
+fn check(text: &str) -> (Vec<Diagnostic>, SymbolTable, Vec<String>) {
+  let mut parser = Parser::with_options(Options { warn_case: true, ..Options::default() });
+  let error = parser.parse_str(text).err();
+
+  let mut found: Vec<Diagnostic> = parser.warnings().to_vec();
+  found.extend(error);
+  let types = parser.symbols().entries().iter().map(|entry| parser.type_name(entry.category)).collect();
+  (found, parser.symbols().clone(), types)
+}
+
+// our diagnostics only know their line, so they cover all of it
+fn lsp_diagnostic(text: &str, diagnostic: &Diagnostic) -> Value {
+  let line = (diagnostic.line as usize).saturating_sub(1);
+  let length = text.lines().nth(line).map_or(0, |l| l.encode_utf16().count());
+  let severity = match diagnostic.severity {
+    Severity::Error => 1,
+    Severity::Warning => 2
+  };
+
+  serde_json::json!({
+    "range": {
+      "start": {"line": line, "character": 0},
+      "end": {"line": line, "character": length}
+    },
+    "severity": severity,
+    "source": "pascalzim",
+    "message": diagnostic.message
+  })
+}
+
+fn hover(_: &str, document: &Document, position: Position) -> Value {
+  let symbols = &document.symbols;
+  match symbols.entry_at(position) {
+    Some(id) => {
+      let entry = symbols.entry(id);
+      let start = symbols.name_at(position).unwrap_or(entry.position);
+      serde_json::json!({
+        "contents": {"kind": "plaintext", "value": format!("{}: {}", entry.name, document.types[id])},
+        "range": name_range(&document.text, entry, start)
+      })
+    },
+    None => Value::Null
+  }
+}
+
+fn definition(uri: &str, document: &Document, position: Position) -> Value {
+  match document.symbols.entry_at(position) {
+    Some(id) => {
+      let entry = document.symbols.entry(id);
+      location(uri, name_range(&document.text, entry, entry.position))
+    },
+    None => Value::Null
+  }
+}
+
+fn references(uri: &str, document: &Document, position: Position, declaration: bool) -> Value {
+  match document.symbols.entry_at(position) {
+    Some(id) => {
+      let entry = document.symbols.entry(id);
+      let mut starts = Vec::new();
+      if declaration {
+        starts.push(entry.position);
+      }
+      starts.extend(entry.references.iter().cloned());
+      Value::Array(starts.into_iter().map(|start| location(uri, name_range(&document.text, entry, start))).collect())
+    },
+    None => Value::Array(Vec::new())
+  }
+}
+
+fn completion(_: &str, document: &Document, position: Position) -> Value {
+  let symbols = &document.symbols;
+  let items = symbols.visible(symbols.scope_at(position)).into_iter()
+    .map(|id| (symbols.entry(id), &document.types[id]))
+    .filter(|&(entry, _)| entry.kind != SymbolKind::Program)
+    .map(|(entry, detail)| {
+      // Function, Enum, EnumMember or Variable
+      let kind = match entry.kind {
+        SymbolKind::Procedure => 3,
+        SymbolKind::Type => 13,
+        SymbolKind::Constant => 20,
+        _ => 6
+      };
+      serde_json::json!({"label": entry.name, "kind": kind, "detail": detail})
+    })
+    .collect();
+  Value::Array(items)
+}
+
+fn document_symbols(document: &Document) -> Value {
+  Value::Array(document.symbols.children(None).into_iter().map(|id| symbol(document, id)).collect())
+}
+
+fn symbol(document: &Document, id: usize) -> Value {
+  let entry = document.symbols.entry(id);
+  // Module, Function, Enum, EnumMember or Variable
+  let kind: usize = match entry.kind {
+    SymbolKind::Program => 2,
+    SymbolKind::Procedure => 12,
+    SymbolKind::Type => 10,
+    SymbolKind::Constant => 22,
+    _ => 13
+  };
+  let children: Vec<Value> = document.symbols.children(Some(id)).into_iter().map(|child| symbol(document, child)).collect();
+  let selection = name_range(&document.text, entry, entry.position);
+  // a program or procedure spans its scope, up to the end of the text when
+  // the parse stopped inside it
+  let range = match document.symbols.scope_of(id) {
+    Some(scope) => {
+      let end = scope.end.map_or_else(|| json_position(&document.text, None), |end| json_position(&document.text, Some(end)));
+      serde_json::json!({"start": json_position(&document.text, Some(scope.start)), "end": end})
+    },
+    None => selection.clone()
+  };
+
+  serde_json::json!({
+    "name": entry.name,
+    "detail": document.types[id],
+    "kind": kind,
+    "range": range,
+    "selectionRange": selection,
+    "children": children
+  })
+}
+
+fn location(uri: &str, range: Value) -> Value {
+  serde_json::json!({"uri": uri, "range": range})
+}
+
+// the name of `entry` written at `start`
+fn name_range(text: &str, entry: &Entry, start: Position) -> Value {
+  let end = Position { line: start.line, column: start.column + entry.name.chars().count() as u32 };
+  serde_json::json!({"start": json_position(text, Some(start)), "end": json_position(text, Some(end))})
+}
+
+// LSP counts lines from 0 and characters in UTF-16 code units, the lexer
+// counts both from 1 and characters as chars; None is the end of the text
+fn json_position(text: &str, position: Option<Position>) -> Value {
+  let (line, character) = match position {
+    Some(position) => {
+      let line = (position.line as usize).saturating_sub(1);
+      let before = text.split('\n').nth(line).unwrap_or("").chars().take((position.column as usize).saturating_sub(1));
+      (line, before.map(char::len_utf16).sum::<usize>())
+    },
+    None => {
+      let line = text.matches('\n').count();
+      (line, text.split('\n').next_back().unwrap_or("").encode_utf16().count())
+    }
+  };
+  serde_json::json!({"line": line, "character": character})
+}
+
+fn position_at(text: &str, line: usize, character: usize) -> Position {
+  let mut units = 0;
+  let mut column = 1;
+  for c in text.split('\n').nth(line).unwrap_or("").chars() {
+    if units >= character {
+      break;
+    }
+    units += c.len_utf16();
+    column += 1;
+  }
+  Position { line: line as u32 + 1, column }
+}
+
+#[cfg(test)]
+const PROGRAM: &str = "program exemplo;
+var a, b: integer;
+  procedure p(x: integer);
+  var c: real;
+  begin
+    c := x + a
+  end;
+begin
+  a := b;
+  p(a)
+end.";
+
+// frames `messages` as a client would and returns what the server wrote
+#[cfg(test)]
+fn session(messages: &[Value]) -> (i32, Vec<Value>) {
+  let mut input = Vec::new();
+  for message in messages {
+    write_message(&mut input, message).unwrap();
+  }
+  let mut output = Vec::new();
+  let code = run(io::Cursor::new(input), &mut output);
+
+  let mut reader = io::Cursor::new(output);
+  let mut replies = Vec::new();
+  while let Some(body) = read_message(&mut reader).unwrap() {
+    replies.push(serde_json::from_str(&body).unwrap());
+  }
+  (code, replies)
+}
+
+#[cfg(test)]
+fn request(id: usize, method: &str, params: &str) -> Value {
+  serde_json::from_str(&format!(r#"{{"jsonrpc":"2.0","id":{},"method":"{}","params":{}}}"#, id, method, params)).unwrap()
+}
+
+#[cfg(test)]
+fn notify(method: &str, params: &str) -> Value {
+  serde_json::from_str(&format!(r#"{{"jsonrpc":"2.0","method":"{}","params":{}}}"#, method, params)).unwrap()
+}
+
+#[cfg(test)]
+fn at(line: usize, character: usize) -> String {
+  format!(r#"{{"textDocument":{{"uri":"file:///p.pas"}},"position":{{"line":{},"character":{}}},"context":{{"includeDeclaration":true}}}}"#, line, character)
+}
+
+#[test]
+fn test_lsp_session() {
+  let open = format!(r#"{{"textDocument":{{"uri":"file:///p.pas","languageId":"pascal","version":1,"text":{}}}}}"#, Value::from(PROGRAM));
+  let (code, replies) = session(&[
+    request(1, "initialize", "{}"),
+    notify("initialized", "{}"),
+    notify("textDocument/didOpen", &open),
+    request(2, "textDocument/hover", &at(5, 9)),
+    request(3, "textDocument/definition", &at(9, 4)),
+    request(4, "textDocument/references", &at(1, 4)),
+    request(5, "textDocument/completion", &at(5, 4)),
+    request(6, "textDocument/documentSymbol", r#"{"textDocument":{"uri":"file:///p.pas"}}"#),
+    request(7, "textDocument/formatting", "{}"),
+    request(8, "shutdown", "null"),
+    notify("exit", "null")
+  ]);
+
+  assert_eq!(code, 0);
+  assert_eq!(replies.len(), 9);
+  assert_eq!(replies[0].pointer("/result/capabilities/hoverProvider"), Some(&Value::Bool(true)));
+  assert_eq!(replies[1].pointer("/params/diagnostics"), Some(&Value::Array(Vec::new())));
+
+  assert_eq!(replies[2].pointer("/result/contents/value").and_then(Value::as_str), Some("x: Integer"));
+  assert_eq!(replies[3]["result"], serde_json::json!({
+    "uri": "file:///p.pas",
+    "range": {"start": {"line": 1, "character": 4}, "end": {"line": 1, "character": 5}}
+  }));
+
+  let lines: Vec<u64> = replies[4].get("result").and_then(Value::as_array).unwrap().iter()
+    .map(|location| location.pointer("/range/start/line").and_then(Value::as_u64).unwrap())
+    .collect();
+  assert_eq!(lines, vec![1, 5, 8, 9]);
+
+  let labels: Vec<&str> = replies[5].get("result").and_then(Value::as_array).unwrap().iter()
+    .map(|item| item.get("label").and_then(Value::as_str).unwrap())
+    .collect();
+  assert_eq!(labels, vec!["x", "c", "a", "b", "p"]);
+
+  let program = &replies[6].get("result").and_then(Value::as_array).unwrap()[0];
+  assert_eq!(program.get("name").and_then(Value::as_str), Some("exemplo"));
+  let children: Vec<&str> = program.get("children").and_then(Value::as_array).unwrap().iter()
+    .map(|child| child.get("name").and_then(Value::as_str).unwrap())
+    .collect();
+  assert_eq!(children, vec!["a", "b", "p"]);
+
+  assert_eq!(replies[7].pointer("/error/code").and_then(Value::as_i64), Some(-32601));
+  assert_eq!(replies[8].get("result"), Some(&Value::Null));
+}
+
+#[test]
+fn test_lsp_type_names() {
+  let text = "program cores;\ntype cor = (red, green, blue);\nvar c: cor;\n  s: set of cor;\nbegin\n  c := red;\n  s := [c]\nend.";
+  let open = format!(r#"{{"textDocument":{{"uri":"file:///p.pas","text":{}}}}}"#, Value::from(text));
+  let (_, replies) = session(&[
+    notify("textDocument/didOpen", &open),
+    request(1, "textDocument/hover", &at(5, 2)),
+    request(2, "textDocument/hover", &at(6, 2)),
+    request(3, "textDocument/completion", &at(6, 2)),
+    request(4, "textDocument/documentSymbol", r#"{"textDocument":{"uri":"file:///p.pas"}}"#),
+    notify("exit", "null")
+  ]);
+
+  assert_eq!(replies[1].pointer("/result/contents/value").and_then(Value::as_str), Some("c: cor"));
+  assert_eq!(replies[2].pointer("/result/contents/value").and_then(Value::as_str), Some("s: set of cor"));
+  let details: Vec<(&str, &str)> = replies[3].get("result").and_then(Value::as_array).unwrap().iter()
+    .map(|item| (item.get("label").and_then(Value::as_str).unwrap(), item.get("detail").and_then(Value::as_str).unwrap()))
+    .collect();
+  assert_eq!(details, vec![("red", "cor"), ("green", "cor"), ("blue", "cor"), ("cor", "cor"), ("c", "cor"), ("s", "set of cor")]);
+
+  let program = &replies[4].get("result").and_then(Value::as_array).unwrap()[0];
+  assert_eq!(program.get("detail").and_then(Value::as_str), Some("Program"));
+  let details: Vec<(&str, &str)> = program.get("children").and_then(Value::as_array).unwrap().iter()
+    .map(|child| (child.get("name").and_then(Value::as_str).unwrap(), child.get("detail").and_then(Value::as_str).unwrap()))
+    .collect();
+  assert_eq!(details, vec![("red", "cor"), ("green", "cor"), ("blue", "cor"), ("cor", "cor"), ("c", "cor"), ("s", "set of cor")]);
+}
+
+#[test]
+fn test_lsp_diagnostics() {
+  let mut server = Server::new();
+  let open = notify("textDocument/didOpen",
+    r#"{"textDocument":{"uri":"file:///e.pas","text":"program e;\nvar A: integer;\nbegin\n  a := 1;\n  b := 2\nend."}}"#);
+  let replies = server.handle(&open);
+
+  assert_eq!(replies[0]["params"]["diagnostics"], serde_json::json!([
+    {
+      "range": {"start": {"line": 3, "character": 0}, "end": {"line": 3, "character": 9}},
+      "severity": 2,
+      "source": "pascalzim",
+      "message": "Identifier `a` was declared as `A`"
+    },
+    {
+      "range": {"start": {"line": 4, "character": 0}, "end": {"line": 4, "character": 8}},
+      "severity": 1,
+      "source": "pascalzim",
+      "message": "Identifier `b` not declared"
+    }
+  ]));
+
+  let (code, _) = session(&[notify("exit", "null")]);
+  assert_eq!(code, 1);
+}
+
+#[test]
+fn test_lsp_positions() {
+  let text = "ação\n😀x";
+  assert_eq!(position_at(text, 1, 2), Position { line: 2, column: 2 });
+  assert_eq!(json_position(text, Some(Position { line: 2, column: 3 })), serde_json::json!({"line": 1, "character": 3}));
+  assert_eq!(json_position(text, None), serde_json::json!({"line": 1, "character": 3}));
+  assert_eq!(position_at(text, 0, 99), Position { line: 1, column: 5 });
+  assert_eq!(position_at(text, 5, 0), Position { line: 6, column: 1 });
+}
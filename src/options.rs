@@ -0,0 +1,54 @@
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+// how `and`, `or` and `->` evaluate their operands
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BooleanEval {
+  // both operands are always evaluated (ISO Pascal)
+  #[default]
+  Full,
+  // the right operand is skipped once the left one decides the result,
+  // as Turbo Pascal does under {$B-}
+  ShortCircuit
+}
+
+// how the bytes of a source file are turned into text
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Encoding {
+  // UTF-8 when the whole file is valid UTF-8, else Windows-1252, which
+  // covers files saved by the usual Windows editors
+  #[default]
+  Auto,
+  Utf8,
+  Latin1,
+  Windows1252
+}
+
+// what to do with identifiers such as `ação`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NonAsciiIdentifiers {
+  // rejected, as in standard Pascal
+  #[default]
+  Reject,
+  Warn,
+  Allow
+}
+
+// settings shared by the scanner, the parser and the later passes
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+  // old pascalzim rule where `/` between two integers yields an integer,
+  // kept for legacy programs such as files/program9.txt
+  pub legacy_division: bool,
+  // mode at the start of the program, {$B±} changes it from there on
+  // and each `and`/`or`/`->` node keeps the one in force
+  pub boolean_eval: BooleanEval,
+  // {$R+}, values assigned or passed to a subrange are checked at runtime
+  pub range_checks: bool,
+  // warn when an identifier is not written the way it was declared
+  pub warn_case: bool,
+  pub encoding: Encoding,
+  pub non_ascii_identifiers: NonAsciiIdentifiers
+}
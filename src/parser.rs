@@ -1,54 +1,257 @@
+use std::io::BufRead;
+use ast::*;
+use diagnostic::Diagnostic;
 use lexer::*;
+use options::{BooleanEval, Options};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use spec::*;
+use symbols::{same_name, Entry, Position, SymbolKind, SymbolTable};
+use types;
 
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Category {
   Integer,
   Real,
   Boolean,
+  // index in `Program::enums` and number of values
+  Enum(u16, u16),
+  // `set of` an ordinal type
+  Set(Element),
   Procedure,
   Program,
-  Sentinel,
   Undefined
 }
 
-#[derive(Debug)]
-pub struct Identifier {
-  name: String,
-  category: Category
+// type of the elements of a set, `Empty` is the type of `[]` which goes
+// with any set
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Element {
+  Integer,
+  Boolean,
+  Enum(u16, u16),
+  Empty
 }
 
 pub struct Parser {
   scanner: Scanner,
   symbol: Symbol,
-  stack: Vec<Identifier>,
-  // temporary buffer to store identifiers before pushing to stack
-  // used to bind the types
-  identifiers_buffer: Vec<Identifier>,
-  // temporary buffer to store the acceptable types of a category 
-  acceptable_categories: Vec<Category>,
-  types_stack : Vec<Category>
+  // every name declared so far and where it is used
+  symbols: SymbolTable,
+  types_stack : Vec<Category>,
+  // every enumeration declared so far, `Category::Enum` indexes it
+  enums: Vec<Enumeration>,
+  options: Options,
+  // options given to `with_options`, directives only change `options`
+  defaults: Options,
+  // compiler directives met so far, in source order
+  directives: Vec<Symbol>,
+  warnings: Vec<Diagnostic>,
+  program: Option<Program>
 } 
 
+impl Default for Parser {
+  fn default() -> Parser {
+    Parser::new()
+  }
+}
+
 impl Parser {
   pub fn new() -> Parser {
+    Parser::with_options(Options::default())
+  }
+
+  pub fn with_options(options: Options) -> Parser {
+    let mut scanner = Scanner::new();
+    scanner.set_options(&options);
+
     Parser { 
-      scanner: Scanner::new(), 
-      stack: Vec::new(),
-      acceptable_categories: Vec::new(),
-      identifiers_buffer: Vec::new(), 
+      scanner, 
+      symbols: SymbolTable::new(),
       types_stack : Vec::new(),
+      enums: Vec::new(),
+      defaults: options.clone(),
+      options,
+      directives: Vec::new(),
+      warnings: Vec::new(),
+      program: None,
       symbol: Symbol { 
         token: Token::Empty, 
         category: Type::Eof, 
-        line: 0 } 
+        line: 0,
+        column: 0,
+        trivia: Vec::new() } 
       }
   }
+  // errors abort the compilation, see `parse_str` for a recoverable parse
   pub fn build_ast(&mut self, p: &str) -> bool {
+    self.reset();
     self.scanner.build_token(p);
-    self.set_next_symbol();
-    self.parse_program()
+    match self.parse_source() {
+      Ok(_) => true,
+      Err(diagnostic) => panic!("{}", diagnostic)
+    }
+  }
+
+  // same as `build_ast` but errors are returned
+  pub fn parse_file(&mut self, p: &str) -> Result<&Program, Diagnostic> {
+    self.reset();
+    if let Err(e) = self.scanner.open(p) {
+      return Err(Diagnostic::error(format!("Cannot open `{}`: {}", p, e), 0));
+    }
+    self.parse_source()
+  }
+
+  // bytes already read from `path`, or from standard input when it is None,
+  // decoded as the encoding option says. {$I file} is found next to `path`
+  pub fn parse_reader<R: BufRead + 'static>(&mut self, reader: R, path: Option<&str>) -> Result<&Program, Diagnostic> {
+    self.reset();
+    self.scanner = Scanner::from_reader_with_encoding(reader, self.options.encoding);
+    self.scanner.set_options(&self.options);
+    if let Some(path) = path {
+      self.scanner.set_path(path);
+    }
+    self.parse_source()
+  }
+
+  pub fn parse_str(&mut self, source: &str) -> Result<&Program, Diagnostic> {
+    self.reset();
+    self.scanner = Scanner::from_str(source);
+    self.scanner.set_options(&self.options);
+    self.parse_source()
+  }
+
+  // a single expression such as `a + 1 > b`
+  pub fn parse_expression(&mut self, source: &str) -> Result<Expr, Diagnostic> {
+    self.parse_fragment(source, |parser| parser.parse_operand())
+  }
+
+  // a single command, a trailing `;` is allowed
+  pub fn parse_statement(&mut self, source: &str) -> Result<Command, Diagnostic> {
+    self.parse_fragment(source, |parser| parser.parse_command(false).map(|command| command.unwrap()))
+  }
+
+  // `type color = (red, green); ...`
+  pub fn parse_type_declarations(&mut self, source: &str) -> Result<(), Diagnostic> {
+    self.parse_fragment(source, |parser| parser.parse_declare_types())
+  }
+
+  // `var a, b: integer; ...`
+  pub fn parse_declarations(&mut self, source: &str) -> Result<Vec<Declaration>, Diagnostic> {
+    self.parse_fragment(source, |parser| parser.parse_declare_var())
+  }
+
+  // `procedure p(x: integer); ... end`
+  pub fn parse_procedure(&mut self, source: &str) -> Result<Procedure, Diagnostic> {
+    self.parse_fragment(source, |parser| parser.parse_declare_subprogram(false).map(|procedure| procedure.unwrap()))
+  }
+
+  // a whole program starts from nothing, only the fragments below build on
+  // what was parsed before
+  fn reset(&mut self) {
+    self.symbols = SymbolTable::new();
+    self.types_stack.clear();
+    self.enums.clear();
+    self.options = self.defaults.clone();
+    self.scanner.set_options(&self.options);
+    self.directives.clear();
+    self.warnings.clear();
+    self.program = None;
+  }
+
+  fn parse_source(&mut self) -> Result<&Program, Diagnostic> {
+    self.set_next_symbol()?;
+    let program = self.parse_program()?;
+    self.program = Some(program);
+    Ok(self.program.as_ref().unwrap())
+  }
+
+  // fragments see every identifier declared by earlier fragments and keep
+  // their own declarations unless they fail
+  fn parse_fragment<T, F>(&mut self, source: &str, parse: F) -> Result<T, Diagnostic>
+    where F: FnOnce(&mut Parser) -> Result<T, Diagnostic> {
+    let (mark, enums) = (self.symbols.mark(), self.enums.len());
+    self.scanner = Scanner::from_str(source);
+    self.scanner.set_options(&self.options);
+
+    let result = self.set_next_symbol()
+      .and_then(|_| parse(self))
+      .and_then(|node| self.expect_end().map(|_| node));
+
+    self.types_stack.clear();
+    if result.is_err() {
+      self.symbols.rollback(mark);
+      self.enums.truncate(enums);
+    }
+    result
+  }
+
+  fn expect_end(&mut self) -> Result<(), Diagnostic> {
+    if self.symbol.token == Token::Semicolon {
+      self.set_next_symbol()?;
+    }
+    if self.symbol.category == Type::Eof {
+      Ok(())
+    } else {
+      Err(Diagnostic::syntax(format!("Unexpected `{}` after the end of the input", self.symbol.token), self.symbol.line))
+    }
+  }
+
+  // tree built by the last successful `build_ast` or `parse_str`
+  pub fn program(&self) -> Option<&Program> {
+    self.program.as_ref()
+  }
+
+  // options after applying the switches found in the source
+  pub fn options(&self) -> &Options {
+    &self.options
+  }
+
+  pub fn directives(&self) -> &[Symbol] {
+    &self.directives
+  }
+
+  // names declared by the last parse, kept even when it failed
+  pub fn symbols(&self) -> &SymbolTable {
+    &self.symbols
+  }
+
+  pub fn warnings(&self) -> &[Diagnostic] {
+    &self.warnings
+  }
+
+  // enumerations declared so far, `Category::Enum(id, _)` indexes them
+  pub fn enums(&self) -> &[Enumeration] {
+    &self.enums
+  }
+
+  // `color` for an enumeration, the category otherwise
+  pub fn type_name(&self, category: Category) -> String {
+    match category {
+      Category::Enum(id, _) => self.enums[id as usize].name.clone(),
+      Category::Set(Element::Empty) => "[]".to_string(),
+      Category::Set(_) => format!("set of {}", self.type_name(types::element_of(category).unwrap())),
+      _ => format!("{:?}", category)
+    }
+  }
+
+  // `1..10` or `red..green`, `set of 1..3` for a set of a subrange
+  pub fn range_name(&self, category: Category, range: Subrange) -> String {
+    if let Some(element) = types::element_of(category) {
+      return format!("set of {}", self.range_name(element, range));
+    }
+    format!("{}..{}", self.ordinal_name(category, range.first), self.ordinal_name(category, range.last))
+  }
+
+  fn ordinal_name(&self, category: Category, ordinal: i32) -> String {
+    match category {
+      Category::Enum(id, _) => self.enums[id as usize].values[ordinal as usize].clone(),
+      Category::Boolean => (ordinal == 1).to_string(),
+      _ => ordinal.to_string()
+    }
   }
 
 /*
@@ -60,75 +263,130 @@ programa →
 	.
 */
 
-  fn parse_program(&mut self) -> bool {   
+  fn parse_program(&mut self) -> Result<Program, Diagnostic> {   
 
     //	program 
     if self.symbol.token == Token::Program {
-      
-      // pushing
-      self.stack.push(
-        Identifier {
-          name: "$".to_string(), 
-          category: Category::Sentinel
-        });
-
-      self.set_next_symbol();
+      let start = self.position();
+      self.set_next_symbol()?;
 
       // id
       if self.symbol.category == Type::Identifier {
+        let name: String = match self.symbol.token {
+                Token::LitStr(ref s) => s.to_string(),
+                _ => unimplemented!() };
         
-      //TODO: encapsular
-      // pushing
-      self.stack.push(
-        Identifier {
-          name: match self.symbol.token {
-            Token::LitStr(ref s) => s.to_string(),
-            _ => unimplemented!()
-          }, 
-          category: Category::Program
-        });
-        
-        self.set_next_symbol();
+        let program = self.symbols.declare(&name, SymbolKind::Program, Category::Program, self.position());
+        self.symbols.open_scope(Some(program), start);
+        self.set_next_symbol()?;
 
         //;
         if self.symbol.token == Token::Semicolon {
-          self.set_next_symbol();
-          // declarações_variáveis
-          self.parse_declare_var();
-          // declarações_de_subprogramas
-          self.parse_declare_subprograms();
-          //comando_composto
-          self.parse_compound_command();
+          self.set_next_symbol()?;
+          // declarações_variáveis declarações_de_subprogramas comando_composto
+          let block = self.parse_block()?;
          
          // .
           if self.symbol.token == Token::Period  {
-            true
+            Ok(Program { name, block, enums: self.enums.clone() })
           } else {
-            panic!("Expected delimiter `.` found `{}` => line {}", self.symbol.token, self.symbol.line);
+            Err(Diagnostic::syntax(format!("Expected delimiter `.` found `{}`", self.symbol.token), self.symbol.line))
           }
         } else {
-          panic!("Expected delimiter `;`  found `{}` => line {}", self.symbol.token, self.symbol.line);
+          Err(Diagnostic::syntax(format!("Expected delimiter `;`  found `{}`", self.symbol.token), self.symbol.line))
         }  
       } else {
-        panic!("Expected identifier  found `{:?}` => line {}", self.symbol.category, self.symbol.line);
+        Err(Diagnostic::syntax(format!("Expected identifier  found `{:?}`", self.symbol.category), self.symbol.line))
       }
     } else {
-      panic!("Expected keyword `program`  found `{}` => line {}", self.symbol.token, self.symbol.line);
+      Err(Diagnostic::syntax(format!("Expected keyword `program`  found `{}`", self.symbol.token), self.symbol.line))
     }
   }
 
 
+/*
+bloco →
+	declarações_tipos
+	declarações_variáveis
+	declarações_de_subprogramas
+	comando_composto
+*/
+  fn parse_block(&mut self) -> Result<Block, Diagnostic> {
+    self.parse_declare_types()?;
+    let variables = self.parse_declare_var()?;
+    let procedures = self.parse_declare_subprograms()?;
+    let body = self.parse_compound_command()?;
+    // only the `end` of a block closes its scope, not nested `begin ... end`
+    let end = self.position();
+    self.symbols.close_scope(end);
+
+    Ok(Block { variables, procedures, body })
+  }
+
+/*
+declarações_tipos →
+	type id = tipo; lista_declarações_tipos | ε
+
+lista_declarações_tipos →
+	id = tipo; lista_declarações_tipos | ε
+*/
+  // the names go into `symbols`, the enumerations into `enums`
+  fn parse_declare_types(&mut self) -> Result<(), Diagnostic> {
+    if self.symbol.token != Token::Type {
+      return Ok(());
+    }
+    self.set_next_symbol()?;
+    if self.symbol.category != Type::Identifier {
+      return Err(Diagnostic::syntax(format!("Expected identifier  found `{:?}`", self.symbol.category), self.symbol.line));
+    }
+
+    while self.symbol.category == Type::Identifier {
+      let name: String = match self.symbol.token {
+                Token::LitStr(ref s) => s.to_string(),
+                _ => unimplemented!() };
+      if self.already_declared(&name)? {
+        return Err(Diagnostic::error(format!("Identifier `{}` already declared", name), self.symbol.line));
+      }
+      let position = self.position();
+      self.set_next_symbol()?;
+
+      // =
+      if self.symbol.token != Token::Equal {
+        return Err(Diagnostic::syntax(format!("Expected `=` found `{}`", self.symbol.token), self.symbol.line));
+      }
+      self.set_next_symbol()?;
+
+      // tipo, an enumeration written here takes the name
+      let count = self.enums.len();
+      let (category, range) = self.parse_types()?;
+      if self.enums.len() > count && types::is_enum(category) {
+        self.enums[count].name = name.clone();
+      }
+      let id = self.symbols.declare(&name, SymbolKind::Type, category, position);
+      self.symbols.define(id, category, range);
+
+      // ;
+      if self.symbol.token != Token::Semicolon {
+        return Err(Diagnostic::syntax(format!("Expected delimiter `;` found `{}`", self.symbol.token), self.symbol.line));
+      }
+      self.set_next_symbol()?;
+    }
+    Ok(())
+  }
+
 /*
 declarações_variáveis →
 	var lista_declarações_variáveis | ε
 */
-  fn parse_declare_var(&mut self) {
+  fn parse_declare_var(&mut self) -> Result<Vec<Declaration>, Diagnostic> {
 
     //  var
     if self.symbol.token == Token::Var {
-      self.set_next_symbol();
+      self.set_next_symbol()?;
       //  lista_declarações_variáveis
-      self.parse_list_declare_var(false); //nao pode ser vazio
+      self.parse_list_declare_var(false) //nao pode ser vazio
+    } else {
+      Ok(Vec::new())
     }
   }
 
@@ -136,25 +394,31 @@ declarações_variáveis →
 lista_declarações_variáveis →
 	lista_de_identificadores: tipo; lista_declarações_variáveis'
 */
-  fn parse_list_declare_var(&mut self, ep_closure: bool) {
+  fn parse_list_declare_var(&mut self, ep_closure: bool) -> Result<Vec<Declaration>, Diagnostic> {
+    let line = self.symbol.line;
     
     //  lista_de_identificadores
-    self.parse_list_identfiers(ep_closure);
+    let ids = self.parse_list_identfiers(SymbolKind::Variable, ep_closure)?;
   
     // :
     if self.symbol.token == Token::Colon {
-      self.set_next_symbol();  
+      self.set_next_symbol()?;  
       // tipo
-      self.parse_types();
+      let (category, range) = self.parse_types()?;
+      let names = self.define_all(&ids, category, range);
+      let mut declarations = vec![Declaration { names, category, range, line }];
 
       // ;
       if self.symbol.token == Token::Semicolon {
-        self.set_next_symbol();
+        self.set_next_symbol()?;
         // lista_declarações_variáveis'
-        self.parse_list_declare_var(true); //pode ser vazio
+        declarations.extend(self.parse_list_declare_var(true)?); //pode ser vazio
       }
+      Ok(declarations)
     }  else if !ep_closure {
-      panic!("Expected delimiter `:` found `{}` => line {}", self.symbol.token, self.symbol.line);
+      Err(Diagnostic::syntax(format!("Expected delimiter `:` found `{}`", self.symbol.token), self.symbol.line))
+    } else {
+      Ok(Vec::new())
     }
   }
 
@@ -163,29 +427,30 @@ lista_declarações_variáveis →
 	id lista_de_identificadores'
  
 */
-  fn parse_list_identfiers(&mut self, ep_closure: bool) {
+  // the names are declared untyped, so they clash with each other but can't
+  // be used before `define_all` gives them their type
+  fn parse_list_identfiers(&mut self, kind: SymbolKind, ep_closure: bool) -> Result<Vec<usize>, Diagnostic> {
+    let mut ids = Vec::new();
+
     // id
     if self.symbol.category == Type::Identifier {
       let name: String = match self.symbol.token {
                 Token::LitStr(ref s) => s.to_string(),
                 _ => unimplemented!() };
-      if !self.search_scope(&name) {
-        // pushing
-        self.identifiers_buffer.push(
-          Identifier {
-            name: name, 
-            category: Category::Undefined
-          });
+      if !self.already_declared(&name)? {
+        let position = self.position();
+        ids.push(self.symbols.declare(&name, kind, Category::Undefined, position));
         
-        self.set_next_symbol();  
+        self.set_next_symbol()?;  
       } else {
-        panic!("Identifier `{}` already declared => line `{}`", name, self.symbol.line);
+        return Err(Diagnostic::error(format!("Identifier `{}` already declared", name), self.symbol.line));
       }
       // lista_de_identificadores'
-      self.parse_list_identfiers_recursive();
+      self.parse_list_identfiers_recursive(kind, &mut ids)?;
     } else if !ep_closure {
-      panic!("Expected identifier  found `{:?}` => line {}", self.symbol.category, self.symbol.line);
+      return Err(Diagnostic::syntax(format!("Expected identifier  found `{:?}`", self.symbol.category), self.symbol.line));
     }
+    Ok(ids)
   }
 
 /*
@@ -193,181 +458,319 @@ lista_de_identificadores' →
 	, id lista_de_identificadores'
 	| ε
 */
-  fn parse_list_identfiers_recursive(&mut self) {
+  fn parse_list_identfiers_recursive(&mut self, kind: SymbolKind, ids: &mut Vec<usize>) -> Result<(), Diagnostic> {
 
     // ,
     if self.symbol.token == Token::Comma {
-      self.set_next_symbol();
+      self.set_next_symbol()?;
       // id
       if self.symbol.category == Type::Identifier {
         let name: String = match self.symbol.token {
                 Token::LitStr(ref s) => s.to_string(),
                 _ => unimplemented!() };
 
-        if !self.search_scope(&name) {
-          // pushing
-          self.identifiers_buffer.push(
-            Identifier {
-              name: match self.symbol.token {
-                Token::LitStr(ref s) => s.to_string(),
-                _ => unimplemented!()
-              }, 
-              category: Category::Integer
-            });
-          self.set_next_symbol();
+        if !self.already_declared(&name)? {
+          let position = self.position();
+          ids.push(self.symbols.declare(&name, kind, Category::Undefined, position));
+          self.set_next_symbol()?;
         } else {
-          panic!("Identifier `{}` already declared => line `{}`", name, self.symbol.line);
+          return Err(Diagnostic::error(format!("Identifier `{}` already declared", name), self.symbol.line));
         }
         // lista_de_identificadores'
-        self.parse_list_identfiers_recursive();
+        self.parse_list_identfiers_recursive(kind, ids)
       } else {
-        panic!("Expected identifier  found `{:?}` => line {}", self.symbol.category, self.symbol.line);
+        Err(Diagnostic::syntax(format!("Expected identifier  found `{:?}`", self.symbol.category), self.symbol.line))
       } 
+    } else {
+      Ok(())
     }
   }
 /*
 tipo →
-	integer | real | boolean
+	integer | real | boolean | id | ( lista_de_identificadores )
 */
-  fn parse_types(&mut self) {
+  // the type and, for a subrange, its bounds
+  fn parse_types(&mut self) -> Result<(Category, Option<Subrange>), Diagnostic> {
+    let (category, range) = match self.symbol.token {
+      //integer | real | boolean
+      Token::Integer => (Category::Integer, None),
+      Token::Real => (Category::Real, None),
+      Token::Boolean => (Category::Boolean, None),
+      // constante .. constante
+      Token::LitInt(_) | Token::LitReal(_) | Token::Add | Token::Sub | Token::True | Token::False => return self.parse_subrange(),
+      // id
+      Token::LitStr(ref name) => match self.find_declaration(name) {
+        Some(declaration) if declaration.kind == SymbolKind::Type => (declaration.category, declaration.range),
+        Some(declaration) if declaration.constant.is_some() => return self.parse_subrange(),
+        Some(_) => return Err(Diagnostic::error(format!("Identifier `{}` is not a type", name), self.symbol.line)),
+        None => return Err(Diagnostic::error(format!("Type `{}` not declared", name), self.symbol.line))
+      },
+      // ( lista_de_identificadores )
+      Token::LParentheses => return self.parse_enumeration().map(|category| (category, None)),
+      // set of tipo
+      Token::Set => return self.parse_set_type(),
+      _ => return Err(Diagnostic::syntax(format!("Expected type `boolean` or `integer` or `real`  found `{}`", self.symbol.token), self.symbol.line))
+    };
+    if let Token::LitStr(ref name) = self.symbol.token {
+      let name = name.clone();
+      self.note_use(&name);
+    }
+    self.set_next_symbol()?;
+    Ok((category, range))
+  }
 
-    //integer | real | boolean
-    if self.symbol.token == Token::Integer || self.symbol.token == Token::Real || self.symbol.token == Token::Boolean {
-      
-      self.bind_type_and_erase();
+  // `set of 1..10`, the ordinals of the elements must fit in a set
+  fn parse_set_type(&mut self) -> Result<(Category, Option<Subrange>), Diagnostic> {
+    let line = self.symbol.line;
+    self.set_next_symbol()?;
+    if self.symbol.token != Token::Of {
+      return Err(Diagnostic::syntax(format!("Expected keyword `of` found `{}`", self.symbol.token), self.symbol.line));
+    }
+    self.set_next_symbol()?;
+
+    let (element, range) = self.parse_types()?;
+
+    let whole = whole_range(element).unwrap_or(Subrange { first: 0, last: types::SET_SIZE - 1 });
+    let category = match (types::set_of(element), range.or_else(|| whole_range(element))) {
+      (Some(category), Some(range)) if range.first >= 0 && range.last < types::SET_SIZE => category,
+      _ => {
+        let found = match range {
+          Some(range) => self.range_name(element, range),
+          None => self.type_name(element)
+        };
+        let message = format!("Set elements must have ordinals within 0..{} found `{}`", types::SET_SIZE - 1, found);
+        return Err(Diagnostic::error(message, line));
+      }
+    };
+    // the elements of a set of a subrange are checked like the values of
+    // the subrange
+    let range = range.filter(|&range| range != whole);
+    Ok((category, range))
+  }
 
-      self.set_next_symbol();
-    } else {
-      panic!("Expected type `boolean` or `integer` or `real`  found `{}` => line {}", self.symbol.token, self.symbol.line); 
+  // `1..10`, `-5..5`, `false..true` or `red..green`, the type is the one of
+  // the bounds
+  fn parse_subrange(&mut self) -> Result<(Category, Option<Subrange>), Diagnostic> {
+    let line = self.symbol.line;
+    let (category, first) = self.parse_bound()?;
+    if self.symbol.token != Token::Range {
+      return Err(Diagnostic::syntax(format!("Expected `..` found `{}`", self.symbol.token), self.symbol.line));
+    }
+    self.set_next_symbol()?;
+    let (other, last) = self.parse_bound()?;
+    if other != category {
+      let message = format!("Mismatched types `{:?}` is different from `{:?}` for `..`", category, other);
+      return Err(Diagnostic::error(self.name_types(message), line));
+    }
+
+    let range = Subrange { first, last };
+    if first > last {
+      return Err(Diagnostic::error(format!("Subrange `{}` is empty", self.range_name(category, range)), line));
+    }
+    Ok((category, Some(range)))
+  }
+
+  // an integer with an optional sign, `true`, `false` or a value of an
+  // enumeration, with its ordinal
+  fn parse_bound(&mut self) -> Result<(Category, i32), Diagnostic> {
+    let sign = match self.symbol.token {
+      Token::Sub => Some(-1),
+      Token::Add => Some(1),
+      _ => None
+    };
+    if sign.is_some() {
+      self.set_next_symbol()?;
+    }
+
+    let bound = match (&self.symbol.token, sign) {
+      (Token::LitInt(i), _) => (Category::Integer, sign.unwrap_or(1) * i),
+      (Token::LitReal(_), _) => return Err(Diagnostic::error("Bounds of a subrange must be ordinal found `Real`".to_string(), self.symbol.line)),
+      (Token::True, None) | (Token::False, None) => (Category::Boolean, (self.symbol.token == Token::True) as i32),
+      (Token::LitStr(name), None) => match self.find_declaration(name) {
+        Some(declaration) if declaration.constant.is_some() => (declaration.category, declaration.constant.unwrap()),
+        Some(_) => return Err(Diagnostic::error(format!("Identifier `{}` is not a constant", name), self.symbol.line)),
+        None => return Err(Diagnostic::error(format!("Identifier `{}` not declared", name), self.symbol.line))
+      },
+      _ => return Err(Diagnostic::syntax(format!("Expected constant found `{}`", self.symbol.token), self.symbol.line))
+    };
+    if let Token::LitStr(ref name) = self.symbol.token {
+      let name = name.clone();
+      self.note_use(&name);
+    }
+    self.set_next_symbol()?;
+    Ok(bound)
+  }
+
+  // `(red, green, blue)`, each value is a constant of the new type
+  fn parse_enumeration(&mut self) -> Result<Category, Diagnostic> {
+    let line = self.symbol.line;
+    self.set_next_symbol()?;
+
+    let mut values: Vec<String> = Vec::new();
+    let mut positions = Vec::new();
+    loop {
+      let name: String = match self.symbol.token {
+        Token::LitStr(ref s) => s.to_string(),
+        _ => return Err(Diagnostic::syntax(format!("Expected identifier  found `{:?}`", self.symbol.category), self.symbol.line))
+      };
+      if self.already_declared(&name)? || values.iter().any(|value| same_name(value, &name)) {
+        return Err(Diagnostic::error(format!("Identifier `{}` already declared", name), self.symbol.line));
+      }
+      values.push(name);
+      positions.push(self.position());
+      self.set_next_symbol()?;
+
+      if self.symbol.token != Token::Comma {
+        break;
+      }
+      self.set_next_symbol()?;
+    }
+    if self.symbol.token != Token::RParentheses {
+      return Err(Diagnostic::syntax(format!("Expected delimiter `)`  found `{}`", self.symbol.token), self.symbol.line));
     }
+    if self.enums.len() > u16::MAX as usize || values.len() > u16::MAX as usize {
+      return Err(Diagnostic::error("Too many enumerations".to_string(), line));
+    }
+
+    let category = Category::Enum(self.enums.len() as u16, values.len() as u16);
+    for (ordinal, (value, &position)) in values.iter().zip(positions.iter()).enumerate() {
+      let id = self.symbols.declare(value, SymbolKind::Constant, category, position);
+      self.symbols.entry_mut(id).constant = Some(ordinal as i32);
+    }
+    self.enums.push(Enumeration { name: format!("({})", values.join(", ")), values, line });
+    self.set_next_symbol()?;
+    Ok(category)
   }
 
-  fn parse_declare_subprograms(&mut self) {
+  fn parse_declare_subprograms(&mut self) -> Result<Vec<Procedure>, Diagnostic> {
+    let mut procedures = Vec::new();
+
     if self.symbol.token == Token::Procedure {
-      self.parse_declare_subprogram(true);
+      procedures.extend(self.parse_declare_subprogram(true)?);
       
       if self.symbol.token == Token::Semicolon {
-        self.set_next_symbol();
-        self.parse_declare_subprograms();
+        self.set_next_symbol()?;
+        procedures.extend(self.parse_declare_subprograms()?);
       }
     }
+    Ok(procedures)
   }
 
-  fn parse_declare_subprogram(&mut self, ep_closure: bool) {
+  fn parse_declare_subprogram(&mut self, ep_closure: bool) -> Result<Option<Procedure>, Diagnostic> {
     if self.symbol.token == Token::Procedure {
-      self.set_next_symbol();
+      let (line, start) = (self.symbol.line, self.position());
+      self.set_next_symbol()?;
 
       if self.symbol.category == Type::Identifier {
+        let name: String = match self.symbol.token {
+                Token::LitStr(ref s) => s.to_string(),
+                _ => unimplemented!() };
         
-        //pushing
-        self.stack.push(
-          Identifier {
-            name: match self.symbol.token {
-              Token::LitStr(ref s) => s.to_string(),
-              _ => unimplemented!()
-            }, 
-            category: Category::Procedure
-          }
-        );
-
-        self.stack.push(
-          Identifier {
-            name: "$".to_string(), 
-            category: Category::Sentinel
-          }
-        );
-        
-
-        self.set_next_symbol();
-        self.parse_args();
+        let entry = self.symbols.declare(&name, SymbolKind::Procedure, Category::Procedure, self.position());
+        self.symbols.open_scope(Some(entry), start);
+
+        self.set_next_symbol()?;
+        let params = self.parse_args()?;
+        // known before the body so recursive calls are checked too
+        self.symbols.entry_mut(entry).params = params.iter()
+          .flat_map(|declaration| declaration.names.iter().map(move |_| (declaration.category, declaration.range)))
+          .collect();
         
         if self.symbol.token == Token::Semicolon {
-          self.set_next_symbol();
-          self.parse_declare_var();
-          self.parse_declare_subprograms();
-          self.parse_compound_command();
+          self.set_next_symbol()?;
+          let block = self.parse_block()?;
+
+          Ok(Some(Procedure { name, params, block, line }))
+        } else {
+          Err(Diagnostic::syntax(format!("Expected delimiter `;`  found `{}`", self.symbol.token), self.symbol.line))
         }
       } else {
-        panic!("Expected identifier  found `{:?}` => line {}", self.symbol.category, self.symbol.line);
+        Err(Diagnostic::syntax(format!("Expected identifier  found `{:?}`", self.symbol.category), self.symbol.line))
       }
     } else if !ep_closure {
-      panic!("Expected keyword `procedure`  found `{}` => line {}", self.symbol.token, self.symbol.line);  
+      Err(Diagnostic::syntax(format!("Expected keyword `procedure`  found `{}`", self.symbol.token), self.symbol.line))
+    } else {
+      Ok(None)
     }
   }
 
-  fn parse_args(&mut self) {
+  fn parse_args(&mut self) -> Result<Vec<Declaration>, Diagnostic> {
     if self.symbol.token == Token::LParentheses {
-      self.set_next_symbol();
-      self.parse_list_params();
+      self.set_next_symbol()?;
+      let params = self.parse_list_params()?;
 
       if self.symbol.token == Token::RParentheses {
-        self.set_next_symbol();
+        self.set_next_symbol()?;
+        Ok(params)
       } else {
-        panic!("Expected delimiter `)`  found `{}` => line {}", self.symbol.token, self.symbol.line);
+        Err(Diagnostic::syntax(format!("Expected delimiter `)`  found `{}`", self.symbol.token), self.symbol.line))
       }
+    } else {
+      Ok(Vec::new())
     }
   }
 
-  fn parse_list_params(&mut self) {
-    self.parse_list_identfiers(false);
+  fn parse_list_params(&mut self) -> Result<Vec<Declaration>, Diagnostic> {
+    let line = self.symbol.line;
+    let ids = self.parse_list_identfiers(SymbolKind::Parameter, false)?;
 
     if self.symbol.token == Token::Colon {
-      self.set_next_symbol();
-      self.parse_types();
-      self.parse_list_params_recursive();
+      self.set_next_symbol()?;
+      let (category, range) = self.parse_types()?;
+      let names = self.define_all(&ids, category, range);
+      let mut params = vec![Declaration { names, category, range, line }];
+      params.extend(self.parse_list_params_recursive()?);
+      Ok(params)
 
     } else {
-      panic!("Expected delimiter `:`  found `{}` => line {}", self.symbol.token, self.symbol.line);
+      Err(Diagnostic::syntax(format!("Expected delimiter `:`  found `{}`", self.symbol.token), self.symbol.line))
     }
   }
 
-  fn parse_list_params_recursive(&mut self) {
+  fn parse_list_params_recursive(&mut self) -> Result<Vec<Declaration>, Diagnostic> {
     if self.symbol.token == Token::Semicolon {
-      self.set_next_symbol();
-      self.parse_list_identfiers(false);
-    
-      if self.symbol.token == Token::Colon {
-        self.set_next_symbol();
-        self.parse_types();
-        self.parse_list_params_recursive();
-
-      } else {
-        panic!("Expected delimiter `:`  found `{}` => line {}", self.symbol.token, self.symbol.line);
-      }
+      self.set_next_symbol()?;
+      self.parse_list_params()
+    } else {
+      Ok(Vec::new())
     }
   }
 
-  fn parse_compound_command(&mut self) {
+  fn parse_compound_command(&mut self) -> Result<Vec<Command>, Diagnostic> {
     if self.symbol.token == Token::Begin {
-      self.set_next_symbol();
-      self.parse_list_command(true);
+      self.set_next_symbol()?;
+      let commands = self.parse_list_command(true)?;
      
       if self.symbol.token == Token::End {
-        self.clear_scope();
-        self.set_next_symbol();
+        self.set_next_symbol()?;
+        Ok(commands)
       } else {
-        panic!("Expected keyword `end`  found `{}` => line {}", self.symbol.token, self.symbol.line);
+        Err(Diagnostic::syntax(format!("Expected keyword `end`  found `{}`", self.symbol.token), self.symbol.line))
       }
     } else {
-      panic!("Expected keyword `begin`  found `{}` => line {}", self.symbol.token, self.symbol.line);
+      Err(Diagnostic::syntax(format!("Expected keyword `begin`  found `{}`", self.symbol.token), self.symbol.line))
     }
   }
 
-  fn parse_list_command(&mut self, ep_closure: bool) {
-    self.parse_command(ep_closure); 
-    self.parse_list_command_recursive();
+  fn parse_list_command(&mut self, ep_closure: bool) -> Result<Vec<Command>, Diagnostic> {
+    let mut commands: Vec<Command> = self.parse_command(ep_closure)?.into_iter().collect(); 
+    self.parse_list_command_recursive(&mut commands)?;
+    Ok(commands)
   }
 
-  fn parse_list_command_recursive(&mut self) {
+  fn parse_list_command_recursive(&mut self, commands: &mut Vec<Command>) -> Result<(), Diagnostic> {
     if self.symbol.token == Token::Semicolon {
-      self.set_next_symbol(); 
-      self.parse_command(true);
+      self.set_next_symbol()?; 
+      commands.extend(self.parse_command(true)?);
 
-      self.parse_list_command_recursive();
+      self.parse_list_command_recursive(commands)?;
     }
+    Ok(())
   }
 
-  fn parse_command(&mut self, ep_closure: bool) {
+  fn parse_command(&mut self, ep_closure: bool) -> Result<Option<Command>, Diagnostic> {
+    let line = self.symbol.line;
+
     if self.symbol.category == Type::Identifier {
       
       let name: String = match self.symbol.token {
@@ -376,338 +779,590 @@ tipo →
                               };
 
       let category = self.search_stack(&name); 
+      self.note_use(&name);
 
       if category != Category::Undefined {
         
-        self.acceptable_types(category); //refresh the acceptable_categories vector
-        self.set_next_symbol();
+        self.set_next_symbol()?;
         
         if self.symbol.token == Token::Assign {
-          let mut saved_line = self.symbol.line;
-          self.set_next_symbol();
-          self.parse_expr();
-          let mut exp_result : Category = self.types_stack.pop().unwrap(); 
-          if !self.acceptable_categories.contains(&exp_result){
-
-              panic!("Mismatched types expected `{:?}` found `{:?}` => line {}", self.acceptable_categories[0], exp_result, saved_line);
+          let saved_line = self.symbol.line;
+          let declaration = self.find_declaration(&name).unwrap();
+          let kind = match category {
+            Category::Procedure => Some("procedure"),
+            Category::Program => Some("program"),
+            _ if declaration.kind == SymbolKind::Type => Some("type"),
+            _ if declaration.constant.is_some() => Some("constant"),
+            _ => None
+          };
+          if let Some(kind) = kind {
+            return Err(Diagnostic::error(format!("Cannot assign to {} `{}`", kind, name), saved_line));
           }
+          let range = declaration.range;
+          self.set_next_symbol()?;
+          let value = self.parse_operand()?;
+          if !types::assignable(category, value.category) {
+            let message = format!("Mismatched types expected `{:?}` found `{:?}`", category, value.category);
+            return Err(Diagnostic::error(self.name_types(message), saved_line));
+          }
+          let value = self.check_range(category, range, value)?;
+          Ok(Some(Command::Assign { name, value, line }))
         } else {
-          self.parse_active_procedure();
+          let args = self.parse_active_procedure()?;
+          let args = self.check_call(&name, args, line)?;
+          Ok(Some(Command::Call { name, args, line }))
         }
       } else {
-        panic!("Identifier `{}` not declared => line {}", name, self.symbol.line);
+        Err(Diagnostic::error(format!("Identifier `{}` not declared", name), self.symbol.line))
       }
     } else if self.symbol.token == Token::Begin {
-      self.parse_compound_command();
+      Ok(Some(Command::Compound(self.parse_compound_command()?)))
 
     } else if self.symbol.token == Token::If {
-      self.set_next_symbol();
-      self.parse_expr();
-      
-      self.acceptable_types(Category::Boolean);
-
+      self.set_next_symbol()?;
+      let condition = self.parse_condition(Token::If)?;
 
       if self.symbol.token == Token::Then {
-        self.set_next_symbol();
-        self.parse_command(false);
+        self.set_next_symbol()?;
+        let then_branch = Box::new(self.parse_command(false)?.unwrap());
       
-        self.parse_else();
+        let else_branch = self.parse_else()?;
+        Ok(Some(Command::If { condition, then_branch, else_branch, line }))
       } else {
-        panic!("Expected keyword `then`  found `{}` => line {}", self.symbol.token, self.symbol.line);
+        Err(Diagnostic::syntax(format!("Expected keyword `then`  found `{}`", self.symbol.token), self.symbol.line))
       }
     } else if self.symbol.token == Token::While {
-      self.set_next_symbol();
-      self.parse_expr();
-
-      self.acceptable_types(Category::Boolean);
+      self.set_next_symbol()?;
+      let condition = self.parse_condition(Token::While)?;
 
       if self.symbol.token == Token::Do {
-        self.set_next_symbol();
-        self.parse_command(false);
+        self.set_next_symbol()?;
+        let body = Box::new(self.parse_command(false)?.unwrap());
+        Ok(Some(Command::While { condition, body, line }))
 
       } else {
-         panic!("Expected keyword `do`  found `{}` => line {}", self.symbol.token, self.symbol.line);        
+        Err(Diagnostic::syntax(format!("Expected keyword `do`  found `{}`", self.symbol.token), self.symbol.line))
       }
     } else if !ep_closure {
-       panic!("Expected identifier found `{}` => line {}", self.symbol.token, self.symbol.line);
+      Err(Diagnostic::syntax(format!("Expected identifier found `{}`", self.symbol.token), self.symbol.line))
+    } else {
+      Ok(None)
     }
   }
 
-  fn parse_active_procedure(&mut self) {
+  // the condition of `if` and `while` must be Boolean
+  fn parse_condition(&mut self, keyword: Token) -> Result<Expr, Diagnostic> {
+    let condition = self.parse_operand()?;
+    if condition.category != Category::Boolean {
+      let message = format!("Condition of `{}` must be Boolean found `{:?}`", keyword, condition.category);
+      return Err(Diagnostic::error(self.name_types(message), condition.line));
+    }
+    Ok(condition)
+  }
+
+  // an expression that is not part of a larger one, its type is taken off
+  // `types_stack` so nothing is left there between commands
+  fn parse_operand(&mut self) -> Result<Expr, Diagnostic> {
+    let expr = self.parse_expr()?;
+    self.types_stack.pop();
+    Ok(expr)
+  }
+
+  // only procedures can be called, with one argument per parameter, the
+  // arguments come back with the range checks of subrange parameters
+  fn check_call(&self, name: &str, args: Vec<Expr>, line: u32) -> Result<Vec<Expr>, Diagnostic> {
+    let declaration = self.find_declaration(name).unwrap();
+    if declaration.category != Category::Procedure {
+      return Err(Diagnostic::error(format!("Identifier `{}` is not a procedure", name), line));
+    }
+    if declaration.params.len() != args.len() {
+      let message = format!("Procedure `{}` expects {} argument(s) found {}", name, declaration.params.len(), args.len());
+      return Err(Diagnostic::error(message, line));
+    }
+    let mut checked = Vec::new();
+    for (&(param, range), arg) in declaration.params.iter().zip(args) {
+      if !types::assignable(param, arg.category) {
+        let message = format!("Mismatched types expected `{:?}` found `{:?}`", param, arg.category);
+        return Err(Diagnostic::error(self.name_types(message), arg.line));
+      }
+      checked.push(self.check_range(param, range, arg)?);
+    }
+    Ok(checked)
+  }
+
+  // a constant out of a subrange is rejected here, any other value is
+  // checked when it runs under `{$R+}`
+  fn check_range(&self, category: Category, range: Option<Subrange>, value: Expr) -> Result<Expr, Diagnostic> {
+    let range = match range {
+      Some(range) => range,
+      None => return Ok(value)
+    };
+    if let Some(element) = types::element_of(category) {
+      return self.check_set_range(element, range, value);
+    }
+    match constant_ordinal(&value) {
+      Some(ordinal) if range.contains(ordinal) => Ok(value),
+      Some(ordinal) => {
+        let message = format!("Value `{}` out of range `{}`", self.ordinal_name(category, ordinal), self.range_name(category, range));
+        Err(Diagnostic::error(message, value.line))
+      },
+      // `false..true` or every value of an enumeration holds anything
+      None if Some(range) == whole_range(category) => Ok(value),
+      None if self.options.range_checks => {
+        let (category, line) = (value.category, value.line);
+        Ok(Expr::new(ExprKind::RangeCheck(range, Box::new(value)), category, line))
+      },
+      None => Ok(value)
+    }
+  }
+
+  // the same for the elements of a set, a literal made only of constants is
+  // checked here
+  fn check_set_range(&self, element: Category, range: Subrange, value: Expr) -> Result<Expr, Diagnostic> {
+    if let ExprKind::Set(ref items) = value.kind {
+      let mut constant = true;
+      for (first, last) in items {
+        let bounds = match *last {
+          Some(ref last) => (constant_ordinal(first), constant_ordinal(last)),
+          None => (constant_ordinal(first), constant_ordinal(first))
+        };
+        match bounds {
+          // an empty `a..b`
+          (Some(a), Some(b)) if a > b => {},
+          (Some(a), Some(b)) => {
+            if let Some(ordinal) = [a, b].iter().cloned().find(|&ordinal| !range.contains(ordinal)) {
+              let message = format!("Set element `{}` out of range `{}`", self.ordinal_name(element, ordinal), self.range_name(element, range));
+              return Err(Diagnostic::error(message, first.line));
+            }
+          },
+          _ => constant = false
+        }
+      }
+      if constant {
+        return Ok(value);
+      }
+    }
+    if self.options.range_checks {
+      let (category, line) = (value.category, value.line);
+      Ok(Expr::new(ExprKind::RangeCheck(range, Box::new(value)), category, line))
+    } else {
+      Ok(value)
+    }
+  }
+
+  fn parse_active_procedure(&mut self) -> Result<Vec<Expr>, Diagnostic> {
     if self.symbol.token == Token::LParentheses {
-      self.set_next_symbol();
-      self.parse_list_expr();
+      self.set_next_symbol()?;
+      let args = self.parse_list_expr()?;
 
       if self.symbol.token == Token::RParentheses {
-        self.set_next_symbol();
+        self.set_next_symbol()?;
+        Ok(args)
       } else {
-        panic!("Expected delimiter `)`  found `{}` => line {}", self.symbol.token, self.symbol.line);
+        Err(Diagnostic::syntax(format!("Expected delimiter `)`  found `{}`", self.symbol.token), self.symbol.line))
       }
+    } else {
+      Ok(Vec::new())
     }
   }
 
-  fn parse_else(&mut self) {
+  fn parse_else(&mut self) -> Result<Option<Box<Command>>, Diagnostic> {
     if self.symbol.token == Token::Else {
-      self.set_next_symbol();
-      self.parse_command(false);
+      self.set_next_symbol()?;
+      Ok(Some(Box::new(self.parse_command(false)?.unwrap())))
+    } else {
+      Ok(None)
     }
   } 
 
-  fn parse_list_expr(&mut self) {
-    self.parse_expr();
-    self.parse_list_expr_recursive();
+  fn parse_list_expr(&mut self) -> Result<Vec<Expr>, Diagnostic> {
+    let mut exprs = vec![self.parse_operand()?];
+    self.parse_list_expr_recursive(&mut exprs)?;
+    Ok(exprs)
   }
 
-  fn parse_list_expr_recursive(&mut self) {
+  fn parse_list_expr_recursive(&mut self, exprs: &mut Vec<Expr>) -> Result<(), Diagnostic> {
     if self.symbol.token == Token::Comma {
-      self.set_next_symbol();
-      self.parse_expr();
-      self.parse_list_expr_recursive();
+      self.set_next_symbol()?;
+      exprs.push(self.parse_operand()?);
+      self.parse_list_expr_recursive(exprs)?;
     }
+    Ok(())
   }
 
-  fn parse_expr(&mut self) {
-    self.parse_simple_expr();
-
-    if self.symbol.category == Type::RelOperator {
-      let mut saved_operator = self.symbol.clone();
-      self.set_next_symbol(); 
-      self.parse_simple_expr();
-      let mut op1 = self.types_stack.pop().unwrap();
-      let mut op2 = self.types_stack.pop().unwrap();
-
-      if saved_operator.token == Token::Equal || saved_operator.token == Token::NotEqual {
-        
-        if op1 != op2 {
-            panic!("Mismatched types `{:?}` is different from `{:?}` for `{}` => line {}", op1, op2, saved_operator.token, saved_operator.line);    
-        }
-
-      } else if saved_operator.token == Token::Imp { 
+/*
+expressão →
+	implicação <-> expressão | implicação
+*/
+  fn parse_expr(&mut self) -> Result<Expr, Diagnostic> {
+    let lhs = self.parse_implication()?;
+    self.parse_expr_recursive(lhs)
+  }
 
-        if op1 != Category::Boolean || op2 != Category::Boolean {
-          panic!("Logic operator `{}` only supports Boolean operands => line {}", saved_operator.token, saved_operator.line);
-        }
+  // `<->` is associative, the tree nests to the left
+  fn parse_expr_recursive(&mut self, lhs: Expr) -> Result<Expr, Diagnostic> {
+    if self.symbol.token == Token::Equiv {
+      let saved_operator = self.symbol.clone();
+      self.set_next_symbol()?;
+      let rhs = self.parse_implication()?;
+      self.check_binary(&saved_operator)?;
 
-      } else {
+      let node = self.binary_node(saved_operator, lhs, rhs);
+      self.parse_expr_recursive(node)
+    } else {
+      Ok(lhs)
+    }
+  }
 
-        if op1 != Category::Real && op1 != Category::Integer {  
-          panic!("Type `{:?}` doesn't support relational operator `{}` => line {}", op1, saved_operator.token, saved_operator.line)
-        }
+/*
+implicação →
+	relação -> implicação | relação
+*/
+  fn parse_implication(&mut self) -> Result<Expr, Diagnostic> {
+    let lhs = self.parse_relation()?;
 
-        if op2 != Category::Real && op2 != Category::Integer {  
-          panic!("Type `{:?}` doesn't support relational operator `{}` => line {}", op2, saved_operator.token, saved_operator.line)
-        }
+    if self.symbol.token == Token::Imp {
+      let saved_operator = self.symbol.clone();
+      self.set_next_symbol()?;
+      // a -> b -> c is a -> (b -> c)
+      let rhs = self.parse_implication()?;
+      self.check_binary(&saved_operator)?;
 
+      Ok(self.binary_node(saved_operator, lhs, rhs))
+    } else {
+      Ok(lhs)
+    }
+  }
 
-      }
+/*
+relação →
+	expressão_simples op_relacional expressão_simples | expressão_simples
+*/
+  fn parse_relation(&mut self) -> Result<Expr, Diagnostic> {
+    let lhs = self.parse_simple_expr()?;
 
-      self.types_stack.push(Category::Boolean);
+    if self.symbol.category == Type::RelOperator {
+      let saved_operator = self.symbol.clone();
+      self.set_next_symbol()?; 
+      let rhs = self.parse_simple_expr()?;
+      self.check_binary(&saved_operator)?;
 
+      Ok(self.binary_node(saved_operator, lhs, rhs))
+    } else {
+      Ok(lhs)
     }
   }
 
-  fn parse_simple_expr(&mut self) {
+  fn parse_simple_expr(&mut self) -> Result<Expr, Diagnostic> {
     if self.symbol.token == Token::Add || self.symbol.token == Token::Sub {
-      self.set_next_symbol();
-      self.parse_term();
-      self.parse_simple_expr_recursive();
+      let saved_operator = self.symbol.clone();
+      self.set_next_symbol()?;
+      // the sign applies to the whole first term: -a * b is -(a * b)
+      let term = self.parse_term()?;
+
+      let op = types::unary(&saved_operator.token, *self.types_stack.last().unwrap())
+        .map_err(|message| Diagnostic::error(self.name_types(message), saved_operator.line))?;
+
+      let signed = Expr::new(ExprKind::Unary(saved_operator.token, Box::new(term)), op, saved_operator.line);
+      self.parse_simple_expr_recursive(signed)
     } else {
-      self.parse_term();
-      self.parse_simple_expr_recursive();
+      let term = self.parse_term()?;
+      self.parse_simple_expr_recursive(term)
     }
   }
 
-  fn parse_simple_expr_recursive(&mut self) {
+  fn parse_simple_expr_recursive(&mut self, lhs: Expr) -> Result<Expr, Diagnostic> {
     if self.symbol.category == Type::AddOperator {
-      let mut saved_operator = self.symbol.clone();
-      self.set_next_symbol();
-      self.parse_term();
-
-      let mut op1 = self.types_stack.pop().unwrap();
-      let mut op2 = self.types_stack.pop().unwrap();
-
-      if saved_operator.token == Token::Add || saved_operator.token == Token::Sub {
-        if op1 == Category::Integer && op2 == Category::Integer {
-          self.types_stack.push(Category::Integer);
-        } else if op1 == Category::Boolean || op2 == Category::Boolean {
-          panic!("Type `{:?}` doesn't support arithmetic operator `{}` => line {}", Category::Boolean, saved_operator.token, saved_operator.line);
-        } else {
-          self.types_stack.push(Category::Real);
-        }
-      } else {
-        //or
-        if op1 != Category::Boolean || op2 != Category::Boolean {
-          panic!("Logic operator `{}` only supports Boolean operands => line {}", saved_operator.token, saved_operator.line);
-        }
-          self.types_stack.push(Category::Boolean);
-      }
+      let saved_operator = self.symbol.clone();
+      self.set_next_symbol()?;
+      let rhs = self.parse_term()?;
+      self.check_binary(&saved_operator)?;
 
-      self.parse_simple_expr_recursive();
+      let node = self.binary_node(saved_operator, lhs, rhs);
+      self.parse_simple_expr_recursive(node)
+    } else {
+      Ok(lhs)
     }
   }
 
-  fn parse_term(&mut self) {
-    self.parse_factor();
-    self.parse_term_recursive();
+  fn parse_term(&mut self) -> Result<Expr, Diagnostic> {
+    let factor = self.parse_power()?;
+    self.parse_term_recursive(factor)
   }
 
-  fn parse_term_recursive(&mut self) {
+  fn parse_term_recursive(&mut self, lhs: Expr) -> Result<Expr, Diagnostic> {
     if self.symbol.category == Type::MulOperator {
-      let mut saved_operator = self.symbol.clone();
-
-      self.set_next_symbol();
-      self.parse_factor();
+      let saved_operator = self.symbol.clone();
 
-      let mut op1 = self.types_stack.pop().unwrap();
-      let mut op2 = self.types_stack.pop().unwrap();
+      self.set_next_symbol()?;
+      let rhs = self.parse_power()?;
+      self.check_binary(&saved_operator)?;
 
-      if saved_operator.token == Token::Mult || saved_operator.token == Token::Div {
-        if op1 == Category::Integer && op2 == Category::Integer {
-          self.types_stack.push(Category::Integer);
-        } else if op1 == Category::Boolean || op2 == Category::Boolean {
-          panic!("Type `{:?}` doesn't support arithmetic operator `{}` => line {}", Category::Boolean, saved_operator.token, saved_operator.line);
-        } else {
-          self.types_stack.push(Category::Real);
-        }
-      } else {
-        //and
-        if op1 != Category::Boolean || op2 != Category::Boolean {
-          panic!("Logic operator `{}` only supports Boolean operands => line {}", saved_operator.token, saved_operator.line);
-        }
-          self.types_stack.push(Category::Boolean);
-      }
+      let node = self.binary_node(saved_operator, lhs, rhs);
+      self.parse_term_recursive(node)
+    } else {
+      Ok(lhs)
+    }
+  }
 
-      self.parse_term_recursive();
+/*
+potência →
+	fator ** potência | fator
+*/
+  fn parse_power(&mut self) -> Result<Expr, Diagnostic> {
+    let base = self.parse_factor()?;
+
+    if self.symbol.category == Type::PowOperator {
+      let saved_operator = self.symbol.clone();
+      self.set_next_symbol()?;
+      // a ** b ** c is a ** (b ** c)
+      let exponent = self.parse_power()?;
+
+      let exponent_type = self.types_stack.pop().unwrap();
+      let base_type = self.types_stack.pop().unwrap();
+      let category = types::power(base_type, exponent_type, non_negative(&exponent))
+        .map_err(|message| Diagnostic::error(self.name_types(message), saved_operator.line))?;
+      self.types_stack.push(category);
+
+      Ok(self.binary_node(saved_operator, base, exponent))
+    } else {
+      Ok(base)
     }
   }
 
-  fn parse_factor(&mut self) {
+  fn parse_factor(&mut self) -> Result<Expr, Diagnostic> {
+    let line = self.symbol.line;
+
     if self.symbol.category == Type::Identifier {
        let name: String = match self.symbol.token {
                               Token::LitStr(ref s) => s.to_string(),
                               _ => unimplemented!() };
 
+      // `ord`, `succ` and `pred` unless the program declares the name
+      if let (None, Some(function)) = (self.find_declaration(&name), Builtin::from_name(&name)) {
+        return self.parse_builtin(function, line);
+      }
+
+      let cat = self.search_stack(&name);
+      self.note_use(&name);
 
-      if self.search_stack(&name) != Category::Undefined {
+      let constant = if cat != Category::Undefined {
+        let declaration = self.find_declaration(&name).unwrap();
+        if declaration.kind == SymbolKind::Type {
+          return Err(Diagnostic::error(format!("Type `{}` is not a value", name), self.symbol.line));
+        }
+        let constant = declaration.constant;
         //coloca o tipo na pilha
-        let mut cat = self.match_token_category(self.symbol.clone());
         self.types_stack.push(cat);
-        self.set_next_symbol();
+        self.set_next_symbol()?;
+        constant
+      } else {
+        return Err(Diagnostic::error(format!("Identifier `{}` not declared", name), self.symbol.line));
+      };
+
+      if self.symbol.token == Token::LParentheses {
+        let args = self.parse_active_procedure()?;
+        let args = self.check_call(&name, args, line)?;
+        Ok(Expr::new(ExprKind::Call(name, args), cat, line))
+      } else if let Some(ordinal) = constant {
+        Ok(Expr::new(ExprKind::Constant(name, ordinal), cat, line))
       } else {
-        panic!("Identifier `{}` not declared => line {}", name, self.symbol.line);
+        Ok(Expr::new(ExprKind::Variable(name), cat, line))
       }
 
-      
-
-      self.parse_active_procedure();
-
     } else if self.symbol.token == Token::LParentheses {
-      self.set_next_symbol();
-      self.parse_expr();
+      self.set_next_symbol()?;
+      let expr = self.parse_expr()?;
 
       if self.symbol.token == Token::RParentheses {
-        self.set_next_symbol();
+        self.set_next_symbol()?;
       }
+      Ok(expr)
     } else if self.symbol.category == Type::RealLiteral || self.symbol.category == Type::IntLiteral || 
               self.symbol.token == Token::True || self.symbol.token == Token::False {
       //coloca o tipo na pilha
-      let mut cat = self.match_token_category(self.symbol.clone());
+      let cat = self.match_token_category(self.symbol.clone());
       self.types_stack.push(cat);    
+
+      let kind = match self.symbol.token {
+        Token::LitInt(i) => ExprKind::Integer(i),
+        Token::LitReal(f) => ExprKind::Real(f),
+        ref t => ExprKind::Boolean(*t == Token::True)
+      };
       
-      self.set_next_symbol();
+      self.set_next_symbol()?;
+      Ok(Expr::new(kind, cat, line))
+    } else if self.symbol.token == Token::LBracket {
+      self.parse_set()
     } else if self.symbol.token == Token::Not {
-      self.set_next_symbol();
-      self.parse_factor();
+      self.set_next_symbol()?;
+      let operand = self.parse_factor()?;
+
+      types::unary(&Token::Not, *self.types_stack.last().unwrap())
+        .map_err(|message| Diagnostic::error(self.name_types(message), line))?;
+      Ok(Expr::new(ExprKind::Unary(Token::Not, Box::new(operand)), Category::Boolean, line))
     } else{
-      panic!("Expected Factor `id` or `real` or `integer` or `true` or false` or `(` or `not` found `{}` => line {}",
-             self.symbol.token, self.symbol.line)
+      Err(Diagnostic::syntax(format!("Expected Factor `id` or `real` or `integer` or `true` or false` or `(` or `[` or `not` found `{}`", self.symbol.token), self.symbol.line))
     }   
   }
 
-  fn bind_type_and_erase(&mut self){
+  // `[1, 3..5]` or `[]`, the elements are ordinals of one type
+  fn parse_set(&mut self) -> Result<Expr, Diagnostic> {
+    let line = self.symbol.line;
+    self.set_next_symbol()?;
+
+    let mut items = Vec::new();
+    let mut element = None;
+    while self.symbol.token != Token::RBracket {
+      let first = self.parse_set_element(&mut element)?;
+      let last = if self.symbol.token == Token::Range {
+        self.set_next_symbol()?;
+        Some(self.parse_set_element(&mut element)?)
+      } else {
+        None
+      };
+      items.push((first, last));
 
-    let cat: Category = match self.symbol.token {
-                              Token::Integer => Category::Integer,
-                              Token::Real => Category::Real,
-                              Token::Boolean => Category::Boolean,
-                              _ => unimplemented!() };
-    
-    while !self.identifiers_buffer.is_empty() {
-      let mut tmp = self.identifiers_buffer.pop().unwrap();
-      tmp.category = cat;
-      self.stack.push(tmp);
+      if self.symbol.token != Token::Comma {
+        break;
+      }
+      self.set_next_symbol()?;
     }
+    if self.symbol.token != Token::RBracket {
+      return Err(Diagnostic::syntax(format!("Expected delimiter `]` found `{}`", self.symbol.token), self.symbol.line));
+    }
+    self.set_next_symbol()?;
 
+    let category = element.and_then(types::set_of).unwrap_or(Category::Set(Element::Empty));
+    self.types_stack.push(category);
+    Ok(Expr::new(ExprKind::Set(items), category, line))
   }
 
-  fn acceptable_types(&mut self, category: Category){
-    self.acceptable_categories = match category {
-      Category::Integer => vec![Category::Integer],
-      Category::Real => vec![Category::Real, Category::Integer],
-      Category::Boolean => vec![Category::Boolean],
-      _ => vec![Category::Undefined]
-    };
+  // an element of a set of the same type as the ones before it, a constant
+  // must fit in the set
+  fn parse_set_element(&mut self, element: &mut Option<Category>) -> Result<Expr, Diagnostic> {
+    let expr = self.parse_operand()?;
+    if !types::is_ordinal(expr.category) {
+      let message = format!("Set elements must be ordinal found `{:?}`", expr.category);
+      return Err(Diagnostic::error(self.name_types(message), expr.line));
+    }
+    match *element {
+      Some(category) if category != expr.category => {
+        let message = format!("Mismatched types `{:?}` is different from `{:?}` in a set", category, expr.category);
+        return Err(Diagnostic::error(self.name_types(message), expr.line));
+      },
+      _ => *element = Some(expr.category)
+    }
+    match constant_ordinal(&expr) {
+      Some(ordinal) if !(0..types::SET_SIZE).contains(&ordinal) => {
+        let message = format!("Set element `{}` out of range `0..{}`", self.ordinal_name(expr.category, ordinal), types::SET_SIZE - 1);
+        Err(Diagnostic::error(message, expr.line))
+      },
+      _ => Ok(expr)
+    }
   }
 
-  fn search_scope(&self, id: &String) -> bool {
-    let len = self.stack.len();
-   
-    if self.is_program_or_procedure(id) {
-      panic!("You can't define variables with name of the program or procedure `{}`=> line {}", self.symbol.token, self.symbol.line);
+  // `ord(x)`, `succ(x)` or `pred(x)`, the name is already read
+  fn parse_builtin(&mut self, function: Builtin, line: u32) -> Result<Expr, Diagnostic> {
+    self.set_next_symbol()?;
+    let args = self.parse_active_procedure()?;
+    if args.len() != 1 {
+      let message = format!("Function `{}` expects 1 argument(s) found {}", function.name(), args.len());
+      return Err(Diagnostic::error(message, line));
     }
 
-    for e in self.identifiers_buffer.iter() {
-      if e.name == *id { return true; }
-    }
-    for x in (0..len).rev() {  
-      if self.stack[x].name == "$" {
-        return false;
-      }
-      if self.stack[x].name == *id {
-        return true;
-      } 
+    let argument = args.into_iter().next().unwrap();
+    let category = types::builtin(function, argument.category)
+      .map_err(|message| Diagnostic::error(self.name_types(message), line))?;
+    self.types_stack.push(category);
+    Ok(Expr::new(ExprKind::Builtin(function, Box::new(argument)), category, line))
+  }
+
+  // `types` knows enumerations by number only, messages show their names
+  fn name_types(&self, message: String) -> String {
+    let message = self.enums.iter().enumerate().fold(message, |message, (id, enumeration)| {
+      let category = Category::Enum(id as u16, enumeration.values.len() as u16);
+      message.replace(&format!("`{:?}`", Category::Set(Element::Enum(id as u16, enumeration.values.len() as u16))), &format!("`set of {}`", enumeration.name))
+        .replace(&format!("`{:?}`", category), &format!("`{}`", enumeration.name))
+    });
+    [Element::Integer, Element::Boolean, Element::Empty].iter().fold(message, |message, &element| {
+      message.replace(&format!("`{:?}`", Category::Set(element)), &format!("`{}`", self.type_name(Category::Set(element))))
+    })
+  }
+
+  // replaces the types of both operands on `types_stack` by the type of the
+  // result, see `types::binary`
+  fn check_binary(&mut self, operator: &Symbol) -> Result<(), Diagnostic> {
+    let rhs = self.types_stack.pop().unwrap();
+    let lhs = self.types_stack.pop().unwrap();
+    let category = types::binary(&operator.token, lhs, rhs, self.options.legacy_division)
+      .map_err(|message| Diagnostic::error(self.name_types(message), operator.line))?;
+    self.types_stack.push(category);
+    Ok(())
+  }
+
+  // the result type is already on top of `types_stack`
+  fn binary_node(&self, operator: Symbol, lhs: Expr, rhs: Expr) -> Expr {
+    let cat = *self.types_stack.last().unwrap();
+    let kind = match operator.token {
+      Token::And | Token::Or | Token::Imp => ExprKind::Logical(operator.token, self.options.boolean_eval, Box::new(lhs), Box::new(rhs)),
+      token => ExprKind::Binary(token, Box::new(lhs), Box::new(rhs))
+    };
+    Expr::new(kind, cat, operator.line)
+  }
+
+  // gives the names of `a, b: integer` their type
+  fn define_all(&mut self, ids: &[usize], category: Category, range: Option<Subrange>) -> Vec<String> {
+    for &id in ids {
+      self.symbols.define(id, category, range);
     }
-    false
+    ids.iter().map(|&id| self.symbols.entry(id).name.clone()).collect()
   }
 
-  fn is_program_or_procedure(&self, id: &String) -> bool {  
-    for e in self.identifiers_buffer.iter() {
-      if e.name == *id && (e.category == Category::Program || e.category == Category::Procedure) {
-        return true;
-      }
+  // whether `id` is already declared in the current scope, it can't take the
+  // name of the program or of a procedure around it
+  fn already_declared(&self, id: &str) -> Result<bool, Diagnostic> {
+    if self.symbols.is_routine(id) {
+      return Err(Diagnostic::error(format!("You can't define variables with name of the program or procedure `{}`", self.symbol.token), self.symbol.line));
     }
-    for e in self.stack.iter() {
-      if e.name == *id  && (e.category == Category::Program || e.category == Category::Procedure) {
-        return true;
-      } 
+    Ok(self.symbols.declared_here(id))
+  }
+
+  fn search_stack(&self, id: &str) -> Category {
+    match self.find_declaration(id) {
+      Some(e) => e.category,
+      None => Category::Undefined
     }
-    false
   }
 
-  fn search_stack(&self, id: &String) -> Category {
-    let len = self.stack.len();
+  fn find_declaration(&self, id: &str) -> Option<&Entry> {
+    self.symbols.lookup(id).map(|entry| self.symbols.entry(entry))
+  }
 
-    for x in (0..len).rev() {
-      if self.stack[x].name == *id {
-        return self.stack[x].category;
-      } 
+  // records where `id` is used, the current symbol
+  fn note_use(&mut self, id: &str) {
+    let position = self.position();
+    if let Some(entry) = self.symbols.lookup(id) {
+      self.symbols.refer(entry, position);
     }
-    Category::Undefined
+    self.check_spelling(id);
   }
 
+  fn position(&self) -> Position {
+    Position { line: self.symbol.line, column: self.symbol.column }
+  }
 
-  fn clear_scope(&mut self) {
-    let len = self.stack.len();
+  // warns when `id` is not written the way it was declared
+  fn check_spelling(&mut self, id: &str) {
+    if !self.options.warn_case { return; }
 
-    for x in (0..len).rev() {
-      if self.stack[x].name == "$" {
-        self.stack.pop();
-        break;
-      } else {
-        self.stack.pop();
-      }
-    }
+    let declared = match self.find_declaration(id) {
+      Some(e) if e.name != id => e.name.clone(),
+      _ => return
+    };
+    self.warnings.push(Diagnostic::warning(format!("Identifier `{}` was declared as `{}`", id, declared), self.symbol.line));
   }
+
 /*
   fn evaluate_expr(&mut self) {
     // atomic expression
@@ -782,30 +1437,94 @@ tipo →
     }
   } 
 
+  fn apply_directive(&mut self, directive: Symbol) {
+    match directive.token {
+      Token::Directive(Directive::Switch('B', on)) => {
+        self.options.boolean_eval = if on { BooleanEval::Full } else { BooleanEval::ShortCircuit };
+      },
+      Token::Directive(Directive::Switch('R', on)) => self.options.range_checks = on,
+      _ => {}
+    }
+    self.directives.push(directive);
+  }
+
   #[inline]
-  fn set_next_symbol(&mut self) {
-    self.symbol = self.scanner.next_symbol();
+  fn set_next_symbol(&mut self) -> Result<(), Diagnostic> {
+    self.symbol = self.scanner.try_next_symbol()?;
+
+    while self.symbol.category == Type::Directive {
+      let directive = self.symbol.clone();
+      self.apply_directive(directive);
+      self.symbol = self.scanner.try_next_symbol()?;
+    }
+    self.warnings.extend(self.scanner.take_warnings());
+    Ok(())
   }
 }
 
 
 
+// ordinal of a literal, a signed integer literal or a value of an
+// enumeration
+fn constant_ordinal(expr: &Expr) -> Option<i32> {
+  match expr.kind {
+    ExprKind::Integer(i) | ExprKind::Constant(_, i) => Some(i),
+    ExprKind::Boolean(b) => Some(b as i32),
+    ExprKind::Unary(Token::Add, ref operand) => constant_ordinal(operand),
+    ExprKind::Unary(Token::Sub, ref operand) => match operand.kind {
+      ExprKind::Integer(i) => Some(-i),
+      _ => None
+    },
+    _ => None
+  }
+}
+
+// bounds of Boolean and of an enumeration
+fn whole_range(category: Category) -> Option<Subrange> {
+  match category {
+    Category::Boolean => Some(Subrange { first: 0, last: 1 }),
+    Category::Enum(_, count) => Some(Subrange { first: 0, last: count as i32 - 1 }),
+    _ => None
+  }
+}
+
+// whether an Integer expression is known not to be negative without
+// running it: literals and `+`, `*`, `div` and `**` between them
+fn non_negative(expr: &Expr) -> bool {
+  match expr.kind {
+    ExprKind::Integer(i) => i >= 0,
+    ExprKind::Unary(Token::Add, ref operand) => non_negative(operand),
+    ExprKind::Binary(ref op, ref lhs, ref rhs) if expr.category == Category::Integer => {
+      matches!(*op, Token::Add | Token::Mult | Token::IntDiv | Token::Power) && non_negative(lhs) && non_negative(rhs)
+    },
+    _ => false
+  }
+}
+
 #[test]
 fn test_program_final(){
   let mut p1: Parser = Parser::new();
-  let res = p1.build_ast("files/programFinal.txt");
-  for id in p1.stack.iter() {
-    println!("{:?}", id);
-  }
-  assert!(res);
+  let res = p1.parse_file("files/programFinal.txt");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Identifier `x` already declared => line 5");
+}
+
+#[test]
+fn test_error_kinds(){
+  use diagnostic::Kind;
+
+  let mut p1: Parser = Parser::new();
+  let kind = |p1: &mut Parser, source: &str| p1.parse_str(source).unwrap_err().kind;
+  assert_eq!(kind(&mut p1, "program p; var a: integer; begin a := 1 # 2 end."), Kind::Lexical);
+  assert_eq!(kind(&mut p1, "program p; var a: integer; begin a := end."), Kind::Syntax);
+  assert_eq!(kind(&mut p1, "program p; begin a := 1 end."), Kind::Semantic);
 }
 
 #[test]
 fn test_stack(){
   let mut p1: Parser = Parser::new();
   let res = p1.build_ast("files/program6.txt");
-  for id in p1.stack.iter() {
-    println!("{:?}", id);
+  for entry in p1.symbols().entries() {
+    println!("{:?}", entry);
   }
   assert!(res);
 }
@@ -828,13 +1547,14 @@ fn test_parser_program7() {
 
 #[test]
 fn test_parser_program9() {
-  let mut p1: Parser = Parser::new();
+  // NUMERO := 10 / 3 relies on the old integer division rule
+  let mut p1: Parser = Parser::with_options(Options { legacy_division: true, ..Options::default() });
   let res = p1.build_ast("files/program9.txt");
 
   assert!(res);
 }
 
-//#[test]
+#[test]
 fn test_parser_program10() {
   let mut p1: Parser = Parser::new();
   let res = p1.build_ast("files/program10.txt");
@@ -850,7 +1570,8 @@ fn test_parser_program11() {
   assert!(res);
 }
 
-//#[test]
+#[test]
+#[should_panic(expected = "Type `Boolean` doesn't support relational operator `>`")]
 fn test_parser_program12() {
   let mut p1: Parser = Parser::new();
   let res = p1.build_ast("files/program12.txt");
@@ -858,7 +1579,8 @@ fn test_parser_program12() {
   assert!(res);
 }
 
-//#[test]
+#[test]
+#[should_panic(expected = "Type `Boolean` doesn't support arithmetic operator `+`")]
 fn test_parser_program13() {
   let mut p1: Parser = Parser::new();
   let res = p1.build_ast("files/program13.txt");
@@ -881,3 +1603,441 @@ fn test_parser_program15() {
   
   assert!(res);
 }
+
+#[test]
+#[should_panic(expected = "Mismatched types expected `Integer` found `Real`")]
+fn test_parser_program9_real_division() {
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program9.txt");
+}
+
+#[test]
+fn test_parser_program18() {
+  let mut p1: Parser = Parser::new();
+  let res = p1.build_ast("files/program18.txt");
+
+  assert!(res);
+}
+
+#[test]
+#[should_panic(expected = "Arithmetic operator `mod` only supports Integer operands")]
+fn test_parser_program19() {
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program19.txt");
+}
+
+#[test]
+fn test_parser_program20() {
+  let mut p1: Parser = Parser::new();
+  let res = p1.build_ast("files/program20.txt");
+  assert!(res);
+
+  // a := -a * b;
+  let body = &p1.program().unwrap().block.body;
+  match body[0] {
+    Command::Assign { ref value, .. } => match value.kind {
+      ExprKind::Unary(Token::Sub, ref operand) => match operand.kind {
+        ExprKind::Binary(Token::Mult, _, _) => assert_eq!(value.category, Category::Integer),
+        ref k => panic!("unexpected operand {:?}", k)
+      },
+      ref k => panic!("unexpected expression {:?}", k)
+    },
+    ref c => panic!("unexpected command {:?}", c)
+  }
+
+  // p := not p and (a < -b);
+  match body[2] {
+    Command::Assign { ref value, .. } => match value.kind {
+      ExprKind::Logical(Token::And, _, ref lhs, _) => match lhs.kind {
+        ExprKind::Unary(Token::Not, _) => assert_eq!(lhs.category, Category::Boolean),
+        ref k => panic!("unexpected operand {:?}", k)
+      },
+      ref k => panic!("unexpected expression {:?}", k)
+    },
+    ref c => panic!("unexpected command {:?}", c)
+  }
+}
+
+#[test]
+#[should_panic(expected = "Logic operator `not` only supports Boolean operands")]
+fn test_parser_program21() {
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program21.txt");
+}
+
+#[test]
+#[should_panic(expected = "Type `Boolean` doesn't support arithmetic operator `-`")]
+fn test_parser_program22() {
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program22.txt");
+}
+
+#[test]
+fn test_parser_program25() {
+  let mut p1: Parser = Parser::new();
+  let res = p1.build_ast("files/program25.txt");
+
+  assert!(res);
+  assert!(p1.options().range_checks);
+  match p1.program().unwrap().block.body[2] {
+    Command::Assign { ref value, .. } => assert!(matches!(value.kind, ExprKind::Logical(Token::And, BooleanEval::ShortCircuit, _, _))),
+    ref command => panic!("{:?}", command)
+  }
+}
+
+#[test]
+fn test_parser_program27() {
+  let mut p1: Parser = Parser::new();
+  let res = p1.build_ast("files/program27.txt");
+
+  assert!(res);
+}
+
+#[test]
+fn test_parser_program28() {
+  let mut p1: Parser = Parser::new();
+  let res = p1.build_ast("files/program28.txt");
+
+  assert!(res);
+  assert!(p1.warnings().is_empty());
+}
+
+#[test]
+fn test_parser_program28_case_warnings() {
+  let mut p1: Parser = Parser::with_options(Options { warn_case: true, ..Options::default() });
+  let res = p1.build_ast("files/program28.txt");
+
+  assert!(res);
+  let warnings: Vec<String> = p1.warnings().iter().map(|w| w.to_string()).collect();
+  assert_eq!(warnings, vec![
+    "Warning: Identifier `numero` was declared as `NUMERO` => line 6",
+    "Warning: Identifier `Numero` was declared as `NUMERO` => line 7",
+    "Warning: Identifier `FINAL` was declared as `Final` => line 8"
+  ]);
+}
+
+#[test]
+fn test_parser_parse_str() {
+  let mut p1: Parser = Parser::new();
+  let program = p1.parse_str("program p;\nvar a: integer;\nbegin\n  begin a := 1 end;\n  a := a + 1\nend.").unwrap();
+
+  assert_eq!(program.name, "p");
+  assert_eq!(program.block.body.len(), 2);
+}
+
+#[test]
+fn test_parser_parse_str_error() {
+  let mut p1: Parser = Parser::new();
+  let res = p1.parse_str("program p;\nbegin\n  a := 1\nend.");
+
+  assert_eq!(res.unwrap_err().to_string(), "Error: Identifier `a` not declared => line 3");
+}
+
+#[test]
+fn test_parser_fragments() {
+  let mut p1: Parser = Parser::new();
+  let declarations = p1.parse_declarations("var a, b: integer; c: boolean;").unwrap();
+  assert_eq!(declarations.len(), 2);
+
+  let expr = p1.parse_expression("a + 1 > b").unwrap();
+  assert_eq!(expr.category, Category::Boolean);
+
+  let command = p1.parse_statement("c := a = b;").unwrap();
+  assert!(match command { Command::Assign { ref name, .. } => name == "c", _ => false });
+
+  let res = p1.parse_expression("a b");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Unexpected `b` after the end of the input => line 1");
+
+  // declarations of a failed fragment are dropped
+  assert!(p1.parse_declarations("var d: real; e: string;").is_err());
+  assert!(p1.parse_expression("d").is_err());
+  assert!(p1.parse_expression("a * 2").is_ok());
+}
+
+#[test]
+fn test_parser_str_resets() {
+  let mut p1: Parser = Parser::with_options(Options { warn_case: true, ..Options::default() });
+  p1.parse_str("{$B-} {$R+} program p; type color = (red, green); var X: color; begin x := red end.").unwrap();
+  assert_eq!(p1.options().boolean_eval, BooleanEval::ShortCircuit);
+  assert_eq!((p1.enums().len(), p1.directives().len(), p1.warnings().len()), (1, 2, 1));
+
+  // nothing of the previous program is left, the options given to the
+  // parser stay
+  p1.parse_str("program q; var a: integer; begin a := 1 end.").unwrap();
+  assert_eq!(p1.options().boolean_eval, BooleanEval::Full);
+  assert!(!p1.options().range_checks && p1.options().warn_case);
+  assert_eq!((p1.enums().len(), p1.directives().len(), p1.warnings().len()), (0, 0, 0));
+  let res = p1.parse_str("program r; begin x := red end.");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Identifier `x` not declared => line 1");
+}
+
+#[test]
+fn test_parser_program31() {
+  let mut p1: Parser = Parser::new();
+  let res = p1.build_ast("files/program31.txt");
+
+  assert!(res);
+}
+
+#[test]
+fn test_parser_statement_checks() {
+  let mut p1: Parser = Parser::new();
+  p1.parse_declarations("var a: integer; x: real; c: boolean;").unwrap();
+  p1.parse_procedure("procedure p(n: integer; r: real); begin end").unwrap();
+
+  let errors = [
+    ("while 3 do a := 1", "Condition of `while` must be Boolean found `Integer` => line 1"),
+    ("if a + 1 then a := 1", "Condition of `if` must be Boolean found `Integer` => line 1"),
+    ("p := 1", "Cannot assign to procedure `p` => line 1"),
+    ("a", "Identifier `a` is not a procedure => line 1"),
+    ("a(1)", "Identifier `a` is not a procedure => line 1"),
+    ("p(1)", "Procedure `p` expects 2 argument(s) found 1 => line 1"),
+    ("p(1, c)", "Mismatched types expected `Real` found `Boolean` => line 1"),
+    ("p(x, x)", "Mismatched types expected `Integer` found `Real` => line 1")
+  ];
+  for &(source, message) in errors.iter() {
+    assert_eq!(p1.parse_statement(source).unwrap_err().to_string(), format!("Error: {}", message), "{}", source);
+  }
+
+  assert!(p1.parse_statement("while c do p(a, a)").is_ok());
+  assert!(p1.parse_statement("if not c then x := a").is_ok());
+
+  let res = p1.parse_str("program q;\nbegin\n  q := 1\nend.");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Cannot assign to program `q` => line 3");
+
+  // every command takes its types off the stack
+  let mut p2: Parser = Parser::new();
+  p2.build_ast("files/program23.txt");
+  assert!(p2.types_stack.is_empty());
+}
+
+#[test]
+fn test_parser_promotion() {
+  let mut p1: Parser = Parser::new();
+  p1.parse_declarations("var x: integer; r: real; c: boolean;").unwrap();
+
+  assert_eq!(p1.parse_expression("x = 1.0").unwrap().category, Category::Boolean);
+  assert_eq!(p1.parse_expression("1.5 <> x * 2").unwrap().category, Category::Boolean);
+  assert_eq!(p1.parse_expression("x + r").unwrap().category, Category::Real);
+  assert!(p1.parse_statement("r := x div 2").is_ok());
+
+  let res = p1.parse_expression("c = 1");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Mismatched types `Boolean` is different from `Integer` for `=` => line 1");
+  let res = p1.parse_statement("x := r");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Mismatched types expected `Integer` found `Real` => line 1");
+}
+
+#[test]
+fn test_parser_power() {
+  let mut p1: Parser = Parser::new();
+  p1.parse_declarations("var n: integer; r: real;").unwrap();
+
+  // above `*` and right associative
+  let expr = p1.parse_expression("2 * n ** 3 ^ 2").unwrap();
+  let (lhs, rhs) = match expr.kind {
+    ExprKind::Binary(Token::Mult, lhs, rhs) => (lhs, rhs),
+    ref kind => panic!("{:?}", kind)
+  };
+  assert_eq!(lhs.kind, ExprKind::Integer(2));
+  match rhs.kind {
+    ExprKind::Binary(Token::Power, ref base, ref exponent) => {
+      assert_eq!(base.kind, ExprKind::Variable("n".to_string()));
+      assert!(matches!(exponent.kind, ExprKind::Binary(Token::Power, _, _)));
+    },
+    ref kind => panic!("{:?}", kind)
+  }
+  assert_eq!(expr.category, Category::Integer);
+
+  assert_eq!(p1.parse_expression("n ** (2 + 1)").unwrap().category, Category::Integer);
+  assert_eq!(p1.parse_expression("2 ** n").unwrap().category, Category::Real);
+  assert_eq!(p1.parse_expression("n ** (0 - 1)").unwrap().category, Category::Real);
+  assert_eq!(p1.parse_expression("r ^ 2").unwrap().category, Category::Real);
+
+  let res = p1.parse_expression("true ** 2");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Type `Boolean` doesn't support arithmetic operator `** | ^` => line 1");
+  let res = p1.parse_statement("n := 2 ** n");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Mismatched types expected `Integer` found `Real` => line 1");
+}
+
+#[test]
+fn test_parser_logic_precedence() {
+  let mut p1: Parser = Parser::new();
+  p1.parse_declarations("var p, q, r: boolean; n: integer;").unwrap();
+
+  // `->` is right associative
+  match p1.parse_expression("p -> q -> r").unwrap().kind {
+    ExprKind::Logical(Token::Imp, _, ref lhs, ref rhs) => {
+      assert_eq!(lhs.kind, ExprKind::Variable("p".to_string()));
+      assert!(matches!(rhs.kind, ExprKind::Logical(Token::Imp, ..)));
+    },
+    ref kind => panic!("{:?}", kind)
+  }
+
+  // `<->` is left associative and below `->`
+  match p1.parse_expression("p <-> q <-> q -> r").unwrap().kind {
+    ExprKind::Binary(Token::Equiv, ref lhs, ref rhs) => {
+      assert!(matches!(lhs.kind, ExprKind::Binary(Token::Equiv, _, _)));
+      assert!(matches!(rhs.kind, ExprKind::Logical(Token::Imp, ..)));
+    },
+    ref kind => panic!("{:?}", kind)
+  }
+
+  // `xor` sits with `or`, the comparisons bind tighter than `->`
+  match p1.parse_expression("p xor q and r").unwrap().kind {
+    ExprKind::Binary(Token::Xor, _, ref rhs) => assert!(matches!(rhs.kind, ExprKind::Logical(Token::And, ..))),
+    ref kind => panic!("{:?}", kind)
+  }
+  match p1.parse_expression("n < 2 -> p = q").unwrap().kind {
+    ExprKind::Logical(Token::Imp, _, ref lhs, ref rhs) => {
+      assert!(matches!(lhs.kind, ExprKind::Binary(Token::LessThan, _, _)));
+      assert!(matches!(rhs.kind, ExprKind::Binary(Token::Equal, _, _)));
+    },
+    ref kind => panic!("{:?}", kind)
+  }
+
+  let res = p1.parse_expression("n xor p");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Logic operator `xor` only supports Boolean operands => line 1");
+  let res = p1.parse_expression("p <-> 1");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Logic operator `<->` only supports Boolean operands => line 1");
+}
+
+#[test]
+fn test_parser_enumerations() {
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program35.txt");
+  let program = p1.program().unwrap();
+  assert_eq!(program.enums.len(), 1);
+  assert_eq!(program.enums[0].name, "color");
+  assert_eq!(program.enums[0].values, vec!["red", "green", "blue"]);
+  assert_eq!(program.block.variables[0].category, Category::Enum(0, 3));
+  assert_eq!(program.block.variables[1].category, Category::Boolean);
+
+  let mut p2: Parser = Parser::new();
+  p2.parse_declarations("var c: (red, green, blue); n: integer; b: boolean;").unwrap();
+  assert_eq!(p2.parse_expression("green").unwrap().kind, ExprKind::Constant("green".to_string(), 1));
+  assert_eq!(p2.parse_expression("false < true").unwrap().category, Category::Boolean);
+  assert_eq!(p2.parse_expression("c <= blue").unwrap().category, Category::Boolean);
+  assert_eq!(p2.parse_expression("ord(c)").unwrap().category, Category::Integer);
+  assert_eq!(p2.parse_expression("succ(c)").unwrap().category, Category::Enum(0, 3));
+  assert_eq!(p2.parse_expression("pred(n)").unwrap().category, Category::Integer);
+
+  let res = p2.parse_statement("c := 1");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Mismatched types expected `(red, green, blue)` found `Integer` => line 1");
+  let res = p2.parse_expression("c < b");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Mismatched types `(red, green, blue)` is different from `Boolean` for `<` => line 1");
+  let res = p2.parse_statement("red := c");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Cannot assign to constant `red` => line 1");
+  let res = p2.parse_expression("succ(1.5)");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Function `succ` expects an ordinal argument found `Real` => line 1");
+  let res = p2.parse_expression("ord(c, n)");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Function `ord` expects 1 argument(s) found 2 => line 1");
+  let res = p2.parse_declarations("var d: (green, yellow);");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Identifier `green` already declared => line 1");
+  let res = p2.parse_declarations("var d: shade;");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Type `shade` not declared => line 1");
+}
+
+#[test]
+fn test_parser_subranges() {
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program36.txt");
+  let program = p1.program().unwrap();
+  let variables = &program.block.variables;
+  assert_eq!((variables[0].category, variables[0].range), (Category::Integer, Some(Subrange { first: 0, last: 9 })));
+  assert_eq!((variables[1].category, variables[1].range), (Category::Enum(0, 3), Some(Subrange { first: 0, last: 1 })));
+  assert_eq!(variables[2].range, Some(Subrange { first: -5, last: 5 }));
+  assert_eq!(variables[4].range, None);
+
+  // constants need no check, `w := succ(red)` does
+  match program.block.body[0] {
+    Command::Assign { ref value, .. } => assert_eq!(value.kind, ExprKind::Integer(9)),
+    ref command => panic!("{:?}", command)
+  }
+  match program.block.body[3] {
+    Command::Assign { ref value, .. } => assert!(matches!(value.kind, ExprKind::RangeCheck(Subrange { first: 0, last: 1 }, _))),
+    ref command => panic!("{:?}", command)
+  }
+  // `false..true` holds every Boolean
+  match program.block.body[5] {
+    Command::Assign { ref value, .. } => assert!(matches!(value.kind, ExprKind::Binary(Token::GreaterThan, _, _))),
+    ref command => panic!("{:?}", command)
+  }
+
+  // without {$R+} nothing is checked at runtime
+  let mut p2: Parser = Parser::new();
+  p2.parse_declarations("var d: 1..10; n: integer; c: (red, green, blue); w: red..green;").unwrap();
+  match p2.parse_statement("d := n").unwrap() {
+    Command::Assign { ref value, .. } => assert_eq!(value.kind, ExprKind::Variable("n".to_string())),
+    ref command => panic!("{:?}", command)
+  }
+
+  let res = p2.parse_statement("d := 11");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Value `11` out of range `1..10` => line 1");
+  let res = p2.parse_statement("w := blue");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Value `blue` out of range `red..green` => line 1");
+  let res = p2.parse_statement("d := -1");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Value `-1` out of range `1..10` => line 1");
+  let res = p2.parse_declarations("var e: 10..1;");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Subrange `10..1` is empty => line 1");
+  let res = p2.parse_declarations("var e: 1..green;");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Mismatched types `Integer` is different from `(red, green, blue)` for `..` => line 1");
+  let res = p2.parse_declarations("var e: 0.5..1;");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Bounds of a subrange must be ordinal found `Real` => line 1");
+  let res = p2.parse_declarations("var e: 1 10;");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Expected `..` found `10` => line 1");
+}
+
+#[test]
+fn test_parser_sets() {
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program37.txt");
+  let program = p1.program().unwrap();
+  let variables = &program.block.variables;
+  assert_eq!(variables[0].category, Category::Set(Element::Integer));
+  assert_eq!(variables[1].category, Category::Set(Element::Enum(0, 3)));
+  assert_eq!(variables[2].category, Category::Set(Element::Boolean));
+  match program.block.body[1] {
+    Command::Assign { ref value, .. } => match value.kind {
+      ExprKind::Set(ref items) => {
+        assert_eq!(items.len(), 2);
+        assert_eq!((&items[0].0.kind, &items[0].1), (&ExprKind::Integer(1), &None));
+        assert_eq!(items[1].1.as_ref().map(|last| &last.kind), Some(&ExprKind::Integer(5)));
+      },
+      ref kind => panic!("{:?}", kind)
+    },
+    ref command => panic!("{:?}", command)
+  }
+
+  let mut p2: Parser = Parser::new();
+  p2.parse_declarations("var s: set of 0..9; c: set of (red, green); b: boolean;").unwrap();
+  assert!(p2.parse_statement("s := []").is_ok());
+  assert!(p2.parse_statement("b := [] <= s").is_ok());
+
+  let res = p2.parse_statement("s := [red]");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Mismatched types expected `set of Integer` found `set of (red, green)` => line 1");
+  let res = p2.parse_statement("b := red in s");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Mismatched types `(red, green)` is not an element of `set of Integer` => line 1");
+  let res = p2.parse_statement("b := s < s");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Type `set of Integer` doesn't support relational operator `<` => line 1");
+  let res = p2.parse_statement("s := [1, true]");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Mismatched types `Integer` is different from `Boolean` in a set => line 1");
+  let res = p2.parse_statement("s := [1.5]");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Set elements must be ordinal found `Real` => line 1");
+  let res = p2.parse_statement("s := [1, 64]");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Set element `64` out of range `0..63` => line 1");
+  let res = p2.parse_statement("s := [3, 12]");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Set element `12` out of range `0..9` => line 1");
+  let res = p2.parse_statement("s := [2..11]");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Set element `11` out of range `0..9` => line 1");
+  assert!(p2.parse_statement("s := [9..2]").is_ok());
+  match p2.parse_statement("s := s + [1]").unwrap() {
+    Command::Assign { ref value, .. } => assert!(matches!(value.kind, ExprKind::Binary(..))),
+    ref command => panic!("{:?}", command)
+  }
+  let res = p2.parse_declarations("var e: set of integer;");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Set elements must have ordinals within 0..63 found `Integer` => line 1");
+  let res = p2.parse_declarations("var e: set of 1..100;");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Set elements must have ordinals within 0..63 found `1..100` => line 1");
+  let res = p2.parse_declarations("var e: set 1..10;");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Expected keyword `of` found `1` => line 1");
+}
@@ -1,10 +1,745 @@
-extern crate lib;
-use lib::parser::*;
+extern crate pascalzim;
+use pascalzim::bytecode::{self, Chunk};
+use pascalzim::asm;
+use pascalzim::cgen;
+use pascalzim::diagnostic::{Diagnostic, Kind};
+#[cfg(feature = "serde")]
+use pascalzim::dump;
+use pascalzim::formatter;
+use pascalzim::interpreter::{self, Interpreter};
+use pascalzim::lexer::{Scanner, Symbol};
+use pascalzim::options::*;
+use pascalzim::parser::*;
+use pascalzim::repl;
+use pascalzim::spec::Type;
+use pascalzim::truth_table::{TruthTable, Verdict};
+use pascalzim::types;
+use pascalzim::vm::Vm;
+use pascalzim::wasm;
 use std::env;
+use std::fs;
+use std::io::{self, Cursor, Read, Write};
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "usage: pascalzim <command> [options] [file]
+
+commands:
+  check    report the errors and warnings of the program
+  run      run the program and print its variables, bytecode files run in the VM
+  build    compile the program, see --target and --emit
+  fmt      format the files in place, or stdin to stdout
+  tokens   print the tokens, one per line
+  ast      print the syntax tree
+  repl     read declarations, statements and expressions one by one
+  types    print the operator and type rules as Markdown
+  truth-table EXPR
+           print the truth table of a Boolean expression, every name in
+           it is a Boolean variable
+
+options:
+  --target T      build for c, asm, wasm or bytecode (default);
+                  `run --target bytecode` runs in the VM
+  --emit E        what build writes: code (default), listing, tokens or ast
+  --format F      tokens and syntax tree as text (default), json or sexpr,
+                  json and sexpr need the serde feature; truth tables as
+                  text (default), markdown or csv
+  -o FILE         output file, `-` for stdout
+  --check         fmt only reports the files that are not formatted,
+                  truth-table only prints whether the expression is a
+                  tautology, a contradiction or a contingency
+  -W case         warn about names not written as they were declared
+  -W non-ascii    warn about non-ASCII identifiers instead of rejecting them
+  -W error        treat warnings as errors
+  -w              do not print warnings
+  --encoding E    auto (default), utf8, latin1 or windows-1252
+  --short-circuit, --legacy-division, --range-checks
+                  same as {$B-}, the legacy `/` rule and {$R+}
+
+Without a file, or with `-`, the source is read from stdin.
+
+exit codes:
+  0  success
+  1  warnings under -W error, fmt --check found files that are not
+     formatted, truth-table --check found an expression that is not a
+     tautology, or a bytecode file is not valid
+  2  bad command line
+  3  runtime error
+  4  a file cannot be read or written
+  5  lexical error, such as `#` or a literal out of range
+  6  syntax error, such as a missing `;`
+  7  semantic error, such as an undeclared name or mismatched types
+";
+
+const PROGRAM_ERROR: i32 = 1;
+const USAGE_ERROR: i32 = 2;
+const RUNTIME_ERROR: i32 = 3;
+const IO_ERROR: i32 = 4;
+const LEXICAL_ERROR: i32 = 5;
+const SYNTAX_ERROR: i32 = 6;
+const SEMANTIC_ERROR: i32 = 7;
+
+const COMMANDS: [&str; 9] = ["check", "run", "build", "fmt", "tokens", "ast", "repl", "types", "truth-table"];
+
+struct Args {
+    command: String,
+    files: Vec<String>,
+    output: Option<String>,
+    target: Option<String>,
+    emit: Option<String>,
+    format: Option<String>,
+    check: bool,
+    warnings_as_errors: bool,
+    quiet: bool,
+    options: Options
+}
+
+enum Input {
+    File(String, Vec<u8>),
+    Stdin(Vec<u8>)
+}
+
+impl Input {
+    fn name(&self) -> &str {
+        match *self {
+            Input::File(ref path, _) => path,
+            Input::Stdin(_) => "<stdin>"
+        }
+    }
+
+    // None for stdin
+    fn path(&self) -> Option<&str> {
+        match *self {
+            Input::File(ref path, _) => Some(path),
+            Input::Stdin(_) => None
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match *self {
+            Input::File(_, ref bytes) | Input::Stdin(ref bytes) => bytes
+        }
+    }
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if args.is_empty() {
+        eprint!("{}", USAGE);
+        process::exit(USAGE_ERROR);
+    }
+    if args[0] == "help" || args[0] == "--help" || args[0] == "-h" {
+        print!("{}", USAGE);
+        return;
+    }
+
+    let code = match parse_args(&args) {
+        Ok(args) => execute(&args),
+        Err(message) => {
+            eprintln!("pascalzim: {}\n\n{}", message, USAGE);
+            USAGE_ERROR
+        }
+    };
+    process::exit(code);
+}
+
+// runs the command and returns the exit code
+fn execute(args: &Args) -> i32 {
+    match args.command.as_str() {
+        "fmt" => fmt(args),
+        "tokens" => with_input(args, tokens),
+        "ast" => with_input(args, ast),
+        "run" => with_input(args, run),
+        "build" => with_input(args, build),
+        "types" => write_output(args, None, types::matrix().as_bytes()),
+        "truth-table" => truth_table(args),
+        "repl" => {
+            let stdin = io::stdin();
+            let stdout = io::stdout();
+            match repl::run(stdin.lock(), stdout.lock(), args.options.clone()) {
+                Ok(_) => 0,
+                Err(e) => {
+                    eprintln!("pascalzim: {}", e);
+                    IO_ERROR
+                }
+            }
+        },
+        _ => with_input(args, |args, input| parse(args, input).map(|_| 0).unwrap_or_else(|code| code))
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args {
+        command: args[0].clone(),
+        files: Vec::new(),
+        output: None,
+        target: None,
+        emit: None,
+        format: None,
+        check: false,
+        warnings_as_errors: false,
+        quiet: false,
+        options: Options::default()
+    };
+    let mut rest = args[1..].iter();
+
+    // `pascalzim file` is kept from the old driver and means `check file`
+    if !COMMANDS.contains(&args[0].as_str()) {
+        if args[0].starts_with('-') && args[0] != "-" {
+            return Err(format!("unknown command `{}`", args[0]));
+        }
+        parsed.command = "check".to_string();
+        rest = args.iter();
+    }
+
+    while let Some(arg) = rest.next() {
+        // `--target=c` and `--target c` are the same, so are `-Werror` and `-W error`
+        let (flag, inline) = match arg.find('=') {
+            Some(i) if arg.starts_with("--") => (&arg[..i], Some(arg[i + 1..].to_string())),
+            _ if arg.starts_with("-W") && arg.len() > 2 => ("-W", Some(arg[2..].to_string())),
+            _ => (arg.as_str(), None)
+        };
+        let mut value = |name: &str| -> Result<String, String> {
+            inline.clone().or_else(|| rest.next().cloned()).ok_or_else(|| format!("{} expects a value", name))
+        };
+
+        match flag {
+            "--target" => parsed.target = Some(value(flag)?),
+            "--emit" => parsed.emit = Some(value(flag)?),
+            "--format" => parsed.format = Some(value(flag)?),
+            "-o" => parsed.output = Some(value(flag)?),
+            "--check" => parsed.check = true,
+            "-w" => parsed.quiet = true,
+            "-W" => match value(flag)?.as_str() {
+                "case" => parsed.options.warn_case = true,
+                "non-ascii" => parsed.options.non_ascii_identifiers = NonAsciiIdentifiers::Warn,
+                "error" => parsed.warnings_as_errors = true,
+                other => return Err(format!("unknown warning `{}`", other))
+            },
+            "--encoding" => parsed.options.encoding = match value(flag)?.as_str() {
+                "auto" => Encoding::Auto,
+                "utf8" | "utf-8" => Encoding::Utf8,
+                "latin1" | "latin-1" => Encoding::Latin1,
+                "windows-1252" | "cp1252" => Encoding::Windows1252,
+                other => return Err(format!("unknown encoding `{}`", other))
+            },
+            "--short-circuit" => parsed.options.boolean_eval = BooleanEval::ShortCircuit,
+            "--legacy-division" => parsed.options.legacy_division = true,
+            "--range-checks" => parsed.options.range_checks = true,
+            "-" => parsed.files.push(arg.clone()),
+            _ if flag.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ => parsed.files.push(arg.clone())
+        }
+    }
+
+    let command = parsed.command.as_str();
+    if parsed.target.is_some() && command != "build" && command != "run" {
+        return Err(format!("--target is not used by `{}`", command));
+    }
+    if parsed.emit.is_some() && command != "build" {
+        return Err(format!("--emit is not used by `{}`", command));
+    }
+    if let Some(ref format) = parsed.format {
+        if command != "tokens" && command != "ast" && command != "build" && command != "truth-table" {
+            return Err(format!("--format is not used by `{}`", command));
+        }
+        match format.as_str() {
+            "text" => {},
+            "markdown" | "csv" if command == "truth-table" => {},
+            _ if command == "truth-table" => return Err(format!("unknown format `{}`", format)),
+            "json" | "sexpr" if cfg!(feature = "serde") => {},
+            "json" | "sexpr" => return Err(format!("--format {} needs pascalzim built with the serde feature", format)),
+            other => return Err(format!("unknown format `{}`", other))
+        }
+    }
+    if parsed.check && command != "fmt" && command != "truth-table" {
+        return Err(format!("--check is not used by `{}`", command));
+    }
+    if !parsed.files.is_empty() && (command == "repl" || command == "types") {
+        return Err(format!("`{}` takes no file", command));
+    }
+    if parsed.files.len() > 1 && command == "truth-table" {
+        return Err("`truth-table` takes a single expression, quote it".to_string());
+    }
+    if parsed.files.len() > 1 && command != "fmt" {
+        return Err(format!("`{}` takes a single file", command));
+    }
+    Ok(parsed)
+}
+
+// the exit code for an error of this kind
+fn error_code(diagnostic: &Diagnostic) -> i32 {
+    match diagnostic.kind {
+        Kind::Lexical => LEXICAL_ERROR,
+        Kind::Syntax => SYNTAX_ERROR,
+        Kind::Semantic => SEMANTIC_ERROR,
+        Kind::Runtime => RUNTIME_ERROR
+    }
+}
+
+fn with_input<F: Fn(&Args, &Input) -> i32>(args: &Args, command: F) -> i32 {
+    let input = match args.files.first() {
+        Some(path) if path != "-" => match fs::read(path) {
+            Ok(bytes) => Input::File(path.clone(), bytes),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                return IO_ERROR;
+            }
+        },
+        _ => {
+            let mut bytes = Vec::new();
+            if let Err(e) = io::stdin().read_to_end(&mut bytes) {
+                eprintln!("<stdin>: {}", e);
+                return IO_ERROR;
+            }
+            Input::Stdin(bytes)
+        }
+    };
+    command(args, &input)
+}
+
+// the parser after a successful parse, or the exit code
+fn parse(args: &Args, input: &Input) -> Result<Parser, i32> {
+    let mut parser = Parser::with_options(args.options.clone());
+    // the path is needed to resolve {$I file}
+    let result = parser.parse_reader(Cursor::new(input.bytes().to_vec()), input.path()).map(|_| ());
+
+    if !args.quiet {
+        for warning in parser.warnings() {
+            eprintln!("{}: {}", input.name(), warning);
+        }
+    }
+    if let Err(diagnostic) = result {
+        eprintln!("{}: {}", input.name(), diagnostic);
+        return Err(error_code(&diagnostic));
+    }
+    if args.warnings_as_errors && !parser.warnings().is_empty() {
+        eprintln!("{}: warnings are treated as errors", input.name());
+        return Err(PROGRAM_ERROR);
+    }
+    Ok(parser)
+}
+
+// writes to -o, else to `default`, else to stdout
+fn write_output(args: &Args, default: Option<String>, bytes: &[u8]) -> i32 {
+    let path = args.output.clone().or(default).filter(|path| path != "-");
+    let result = match path {
+        Some(ref path) => fs::write(path, bytes),
+        None => {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            out.write_all(bytes).and_then(|_| out.flush())
+        }
+    };
+
+    match result {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("{}: {}", path.as_deref().unwrap_or("<stdout>"), e);
+            IO_ERROR
+        }
+    }
+}
+
+// line, column, category and token, separated by tabs
+fn tokens(args: &Args, input: &Input) -> i32 {
+    let mut scanner = Scanner::from_reader_with_encoding(Cursor::new(input.bytes().to_vec()), args.options.encoding);
+    scanner.set_options(&args.options);
+    if let Some(path) = input.path() {
+        scanner.set_path(path);
+    }
+
+    let mut symbols: Vec<Symbol> = Vec::new();
+    let mut error = None;
+    loop {
+        match scanner.try_next_symbol() {
+            Ok(ref symbol) if symbol.category == Type::Eof => break,
+            Ok(symbol) => symbols.push(symbol),
+            Err(diagnostic) => {
+                error = Some(diagnostic);
+                break;
+            }
+        }
+    }
+
+    let out = dump(args, &symbols).unwrap_or_else(|| {
+        symbols.iter().map(|symbol| format!("{}\t{}\t{:?}\t{}\n", symbol.line, symbol.column, symbol.category, symbol.token)).collect()
+    });
+    let code = write_output(args, None, out.as_bytes());
+    let warnings = scanner.take_warnings();
+    if !args.quiet {
+        for warning in warnings.iter() {
+            eprintln!("{}: {}", input.name(), warning);
+        }
+    }
+    if let Some(diagnostic) = error {
+        eprintln!("{}: {}", input.name(), diagnostic);
+        return error_code(&diagnostic);
+    }
+    if args.warnings_as_errors && !warnings.is_empty() {
+        return PROGRAM_ERROR;
+    }
+    code
+}
+
+fn ast(args: &Args, input: &Input) -> i32 {
+    match parse(args, input) {
+        Ok(parser) => {
+            let program = parser.program().unwrap();
+            let out = dump(args, program).unwrap_or_else(|| format!("{:#?}\n", program));
+            write_output(args, None, out.as_bytes())
+        },
+        Err(code) => code
+    }
+}
+
+// the --format json and sexpr dumps, None for text
+#[cfg(feature = "serde")]
+fn dump<T: dump::Serialize>(args: &Args, value: &T) -> Option<String> {
+    match args.format.as_deref() {
+        Some("json") => Some(dump::to_json(value) + "\n"),
+        Some("sexpr") => Some(dump::to_sexpr(value) + "\n"),
+        _ => None
+    }
+}
+
+// parse_args rejects json and sexpr without the serde feature
+#[cfg(not(feature = "serde"))]
+fn dump<T>(_: &Args, _: &T) -> Option<String> {
+    None
+}
+
+// prints the program variables once the program ends
+fn run(args: &Args, input: &Input) -> i32 {
+    if Chunk::is_bytecode(input.bytes()) {
+        return match Chunk::from_bytes(input.bytes()) {
+            Ok(chunk) => run_chunk(args, input, &chunk),
+            Err(message) => {
+                eprintln!("{}: {}", input.name(), message);
+                PROGRAM_ERROR
+            }
+        };
+    }
+
+    let parser = match parse(args, input) {
+        Ok(parser) => parser,
+        Err(code) => return code
+    };
+    let program = parser.program().unwrap();
+
+    match args.target.as_deref() {
+        None => {},
+        Some("bytecode") => return match bytecode::compile(program) {
+            Ok(chunk) => run_chunk(args, input, &chunk),
+            Err(diagnostic) => {
+                eprintln!("{}: {}", input.name(), diagnostic);
+                error_code(&diagnostic)
+            }
+        },
+        Some(target) => {
+            eprintln!("pascalzim: `run` cannot use the target `{}`", target);
+            return USAGE_ERROR;
+        }
+    }
+
+    let mut interpreter = Interpreter::new();
+    if let Err(diagnostic) = interpreter.try_run(program) {
+        eprintln!("{}: {}", input.name(), diagnostic);
+        return RUNTIME_ERROR;
+    }
+    let mut out = String::new();
+    for decl in program.block.variables.iter() {
+        for name in decl.names.iter() {
+            let value = interpreter.value_of(name).unwrap();
+            out.push_str(&format!("{} = {}\n", name, interpreter::show(value, decl.category, &program.enums)));
+        }
+    }
+    write_output(args, None, out.as_bytes())
+}
+
+fn run_chunk(args: &Args, input: &Input, chunk: &Chunk) -> i32 {
+    let mut vm = Vm::new(chunk);
+    if let Err(diagnostic) = vm.run() {
+        eprintln!("{}: {}", input.name(), diagnostic);
+        return RUNTIME_ERROR;
+    }
+    let mut out = String::new();
+    for &(ref name, category, _) in chunk.routines[0].slots.iter() {
+        let value = vm.value_of(name).unwrap();
+        out.push_str(&format!("{} = {}\n", name, interpreter::show(value, category, &chunk.enums)));
+    }
+    write_output(args, None, out.as_bytes())
+}
+
+fn build(args: &Args, input: &Input) -> i32 {
+    let target = args.target.as_deref().unwrap_or("bytecode");
+    match target {
+        "c" | "asm" | "wasm" | "bytecode" => {},
+        _ => {
+            eprintln!("pascalzim: unknown target `{}`", target);
+            return USAGE_ERROR;
+        }
+    }
+
+    let emit = args.emit.as_deref().unwrap_or("code");
+    match emit {
+        "tokens" => return tokens(args, input),
+        "ast" => return ast(args, input),
+        "code" | "listing" => {},
+        _ => {
+            eprintln!("pascalzim: unknown --emit `{}`", emit);
+            return USAGE_ERROR;
+        }
+    }
+
+    let parser = match parse(args, input) {
+        Ok(parser) => parser,
+        Err(code) => return code
+    };
+    let program = parser.program().unwrap();
+
+    let result = if target == "c" {
+        cgen::generate(program).map(String::into_bytes)
+    } else if target == "asm" {
+        asm::generate(program).map(String::into_bytes)
+    } else if target == "wasm" && emit == "listing" {
+        wasm::generate(program).map(|module| module.to_string().into_bytes())
+    } else if target == "wasm" {
+        wasm::generate(program).map(|module| module.to_bytes())
+    } else if emit == "listing" {
+        bytecode::compile(program).map(|chunk| chunk.to_string().into_bytes())
+    } else {
+        bytecode::compile(program).map(|chunk| chunk.to_bytes())
+    };
+    let bytes = match result {
+        Ok(bytes) => bytes,
+        Err(diagnostic) => {
+            eprintln!("{}: {}", input.name(), diagnostic);
+            return error_code(&diagnostic);
+        }
+    };
+
+    // `prog.txt` is built into `prog.c`, `prog.s`, `prog.wasm` or `prog.pzb`,
+    // listings go to stdout
+    let default = match *input {
+        Input::File(ref path, _) if emit == "code" => {
+            let extension = match target {
+                "c" => "c",
+                "asm" => "s",
+                "wasm" => "wasm",
+                _ => "pzb"
+            };
+            Some(Path::new(path).with_extension(extension).to_string_lossy().into_owned())
+        },
+        _ => None
+    };
+    write_output(args, default, &bytes)
+}
+
+// pascalzim truth-table [--format F] [--check] [expression]
+// the expression is the argument itself, or stdin when it is missing
+fn truth_table(args: &Args) -> i32 {
+    let expression = match args.files.first() {
+        Some(expression) if expression != "-" => expression.clone(),
+        _ => {
+            let mut text = String::new();
+            if let Err(e) = io::stdin().read_to_string(&mut text) {
+                eprintln!("<stdin>: {}", e);
+                return IO_ERROR;
+            }
+            text
+        }
+    };
+
+    let table = match TruthTable::new(&expression) {
+        Ok(table) => table,
+        Err(diagnostic) => {
+            eprintln!("<expression>: {}", diagnostic);
+            return error_code(&diagnostic);
+        }
+    };
+    if args.check {
+        let verdict = table.verdict();
+        println!("{}", verdict);
+        return if verdict == Verdict::Tautology { 0 } else { PROGRAM_ERROR };
+    }
+    let out = match args.format.as_deref() {
+        Some("markdown") => table.to_markdown(),
+        Some("csv") => table.to_csv(),
+        _ => table.to_text()
+    };
+    write_output(args, None, out.as_bytes())
+}
+
+// pascalzim fmt [--check] [files...]
+// rewrites the files in place, or stdin to stdout when no file is given;
+// with --check nothing is written and files that are not formatted are
+// listed
+fn fmt(args: &Args) -> i32 {
+    let check = args.check;
+    let files: Vec<&String> = args.files.iter().filter(|file| *file != "-").collect();
+
+    let encoding = args.options.encoding;
+
+    if files.is_empty() {
+        let mut bytes = Vec::new();
+        if let Err(e) = io::stdin().read_to_end(&mut bytes) {
+            eprintln!("<stdin>: {}", e);
+            return IO_ERROR;
+        }
+        return match formatter::format_bytes(&bytes, encoding) {
+            Ok(ref formatted) if check => if *formatted == bytes { 0 } else { PROGRAM_ERROR },
+            Ok(formatted) => write_output(args, None, &formatted),
+            Err(diagnostic) => { eprintln!("<stdin>: {}", diagnostic); error_code(&diagnostic) }
+        };
+    }
+
+    let mut code = 0;
+    for file in files {
+        let bytes = match fs::read(file) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                code = IO_ERROR;
+                continue;
+            }
+        };
+
+        match formatter::format_bytes(&bytes, encoding) {
+            Ok(ref formatted) if *formatted == bytes => {},
+            Ok(_) if check => {
+                println!("{}: not formatted", file);
+                code = code.max(PROGRAM_ERROR);
+            },
+            Ok(formatted) => {
+                if let Err(e) = fs::write(file, formatted) {
+                    eprintln!("{}: {}", file, e);
+                    code = IO_ERROR;
+                }
+            },
+            Err(diagnostic) => {
+                eprintln!("{}: {}", file, diagnostic);
+                code = code.max(error_code(&diagnostic));
+            }
+        }
+    }
+    code
+}
+
+// runs `pascalzim` with `args` the way `main` does, without exiting
+#[cfg(test)]
+fn pascalzim(args: &[&str]) -> i32 {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    parse_args(&args).map(|args| execute(&args)).unwrap_or(USAGE_ERROR)
+}
+
+// an empty directory for the files a test writes
+#[cfg(test)]
+fn scratch(name: &str) -> String {
+    let dir = env::temp_dir().join(format!("pascalzim-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir.to_string_lossy().into_owned()
+}
+
+#[test]
+fn test_parse_args() {
+    let args = |args: &[&str]| parse_args(&args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>());
+
+    let parsed = args(&["build", "--target=asm", "-Werror", "-W", "case", "x.txt"]).unwrap();
+    assert_eq!(parsed.target.as_deref(), Some("asm"));
+    assert!(parsed.warnings_as_errors && parsed.options.warn_case);
+    assert_eq!(parsed.files, vec!["x.txt"]);
+
+    // the old `pascalzim file`
+    let parsed = args(&["x.txt", "--encoding", "latin1"]).unwrap();
+    assert_eq!(parsed.command, "check");
+    assert_eq!(parsed.options.encoding, Encoding::Latin1);
+
+    assert_eq!(args(&["--frobnicate"]).err().unwrap(), "unknown command `--frobnicate`");
+    assert_eq!(args(&["check", "--target", "c", "x.txt"]).err().unwrap(), "--target is not used by `check`");
+    assert_eq!(args(&["run", "--emit", "ast"]).err().unwrap(), "--emit is not used by `run`");
+    assert_eq!(args(&["build", "--target"]).err().unwrap(), "--target expects a value");
+    assert_eq!(args(&["build", "--encoding", "ebcdic"]).err().unwrap(), "unknown encoding `ebcdic`");
+    assert_eq!(args(&["run", "a.txt", "b.txt"]).err().unwrap(), "`run` takes a single file");
+    assert_eq!(args(&["types", "a.txt"]).err().unwrap(), "`types` takes no file");
+    assert_eq!(args(&["tokens", "--format", "yaml"]).err().unwrap(), "unknown format `yaml`");
+}
+
+#[test]
+fn test_exit_codes() {
+    let dir = scratch("exit-codes");
+    let out = format!("{}/out.txt", dir);
+    let lexical = format!("{}/lexico.txt", dir);
+    fs::write(&lexical, "program p; var a: integer; begin a := 1 # 2 end.").unwrap();
+    let case = format!("{}/caixa.txt", dir);
+    fs::write(&case, "program p; var A: integer; begin a := 1 end.").unwrap();
+
+    assert_eq!(pascalzim(&["check", "files/program23.txt"]), 0);
+    assert_eq!(pascalzim(&["check", &lexical]), LEXICAL_ERROR);
+    assert_eq!(pascalzim(&["tokens", "-o", &out, &lexical]), LEXICAL_ERROR);
+    assert_eq!(pascalzim(&["check", "files/program2.txt"]), SYNTAX_ERROR);
+    assert_eq!(pascalzim(&["build", "-o", &out, "files/program2.txt"]), SYNTAX_ERROR);
+    assert_eq!(pascalzim(&["check", "files/program12.txt"]), SEMANTIC_ERROR);
+    assert_eq!(pascalzim(&["run", "files/program24.txt"]), RUNTIME_ERROR);
+    assert_eq!(pascalzim(&["run", "--target", "bytecode", "files/program24.txt"]), RUNTIME_ERROR);
+    assert_eq!(pascalzim(&["check", "files/nenhum.txt"]), IO_ERROR);
+
+    assert_eq!(pascalzim(&["check", "-W", "case", &case]), 0);
+    assert_eq!(pascalzim(&["check", "-W", "case", "-W", "error", &case]), PROGRAM_ERROR);
+
+    assert_eq!(pascalzim(&["check", "--bogus", "files/program23.txt"]), USAGE_ERROR);
+    assert_eq!(pascalzim(&["build", "--target", "pdp11", "files/program23.txt"]), USAGE_ERROR);
+    assert_eq!(pascalzim(&["build", "--emit", "ir", "files/program23.txt"]), USAGE_ERROR);
+    assert_eq!(pascalzim(&["run", "--target", "c", "files/program23.txt"]), USAGE_ERROR);
+
+    let program = format!("{}/p.txt", dir);
+    fs::write(&program, "program p;\nbegin a := 1 end.\n").unwrap();
+    assert_eq!(pascalzim(&["fmt", "--check", &program]), PROGRAM_ERROR);
+    fs::write(&program, "program p;\nbegin\n  a := 1\nend.\n").unwrap();
+    assert_eq!(pascalzim(&["fmt", "--check", &program]), 0);
+    fs::write(&program, "program p;\nbegin a := 1 # end.\n").unwrap();
+    assert_eq!(pascalzim(&["fmt", &program]), SYNTAX_ERROR);
+}
+
+#[test]
+fn test_build_emit() {
+    let dir = scratch("build-emit");
+    let out = format!("{}/out", dir);
+
+    let outputs: [(&str, &str, &[u8]); 8] = [
+        ("c", "code", b"/* generated by pascalzim from program fatorial */\n#include <math.h>\n"),
+        ("c", "listing", b"/* generated by pascalzim from program fatorial */\n#include <math.h>\n"),
+        ("asm", "code", b"# generated by pascalzim from program fatorial\n\t.text\n"),
+        ("wasm", "code", b"\0asm\x01\0\0\0"),
+        ("wasm", "listing", b";; generated by pascalzim from program fatorial\n(module\n"),
+        ("bytecode", "code", b"PZB3"),
+        ("bytecode", "listing", b"routine 0 fatorial entry 0 params 0\n  slot 0 n: integer\n"),
+        ("bytecode", "tokens", b"1\t1\tKeyword\tprogram\n1\t9\tIdentifier\tfatorial\n")
+    ];
+    for &(target, emit, start) in outputs.iter() {
+        assert_eq!(pascalzim(&["build", "--target", target, "--emit", emit, "-o", &out, "files/program23.txt"]), 0);
+        let bytes = fs::read(&out).unwrap();
+        assert!(bytes.starts_with(start), "{} {}: {:?}", target, emit, String::from_utf8_lossy(&bytes));
+    }
+    assert_eq!(pascalzim(&["build", "--emit", "ast", "-o", &out, "files/program23.txt"]), 0);
+    assert!(fs::read_to_string(&out).unwrap().starts_with("Program {\n    name: \"fatorial\",\n"));
+
+    // next to the source when there is no -o
+    let program = format!("{}/p.txt", dir);
+    fs::copy("files/program23.txt", &program).unwrap();
+    for &(target, extension) in [("c", "c"), ("asm", "s"), ("wasm", "wasm"), ("bytecode", "pzb")].iter() {
+        assert_eq!(pascalzim(&["build", "--target", target, &program]), 0);
+        assert!(Path::new(&format!("{}/p.{}", dir, extension)).exists(), "{}", target);
+    }
 
-    let mut p1: Parser = Parser::new();
-    p1.build_ast(&args[1]);
-}
\ No newline at end of file
+    // the bytecode runs in the VM and agrees with the interpreter
+    let expected = "n = 5\nf = 120\nmedia = 30\n";
+    assert_eq!(pascalzim(&["run", "-o", &out, &format!("{}/p.pzb", dir)]), 0);
+    assert_eq!(fs::read_to_string(&out).unwrap(), expected);
+    assert_eq!(pascalzim(&["run", "--target", "bytecode", "-o", &out, &program]), 0);
+    assert_eq!(fs::read_to_string(&out).unwrap(), expected);
+    assert_eq!(pascalzim(&["run", "-o", &out, &program]), 0);
+    assert_eq!(fs::read_to_string(&out).unwrap(), expected);
+}
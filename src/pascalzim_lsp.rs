@@ -0,0 +1,11 @@
+extern crate pascalzim;
+use pascalzim::lsp;
+use std::io;
+use std::process;
+
+// language server speaking LSP over stdin and stdout
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    process::exit(lsp::run(stdin.lock(), stdout.lock()));
+}
@@ -0,0 +1,380 @@
+use std::io::{self, BufRead, Write};
+use ast::*;
+use diagnostic::Diagnostic;
+use interpreter::{self, Interpreter, Value};
+use lexer::Scanner;
+use options::Options;
+use parser::{Category, Parser};
+use spec::{Token, Type};
+
+const HELP: &str = "Enter declarations (`type t = (a, b);`, `var x: integer;`,
+`procedure p; begin ... end;`), statements or expressions. An input that is not
+complete goes on in the next lines, an empty line ends it.
+  :type expr     type of an expression and of each of its parts
+  :tokens text   tokens of a text
+  :ast text      syntax tree of an input
+  :env           variables with their values, and procedures
+  :help          this text
+  :quit          leave, as the end of the input does";
+
+// state kept between the inputs of `pascalzim repl`
+pub struct Repl {
+  parser: Parser,
+  options: Options,
+  variables: Vec<Declaration>,
+  procedures: Vec<Procedure>,
+  values: Vec<(String, Value)>,
+  // declarations accepted so far, replayed by `:ast` so it declares nothing
+  sources: Vec<String>,
+  // lines of an input that is not complete yet
+  pending: String,
+  warnings: usize
+}
+
+impl Repl {
+  pub fn new(options: Options) -> Repl {
+    Repl {
+      parser: Parser::with_options(options.clone()),
+      options,
+      variables: Vec::new(),
+      procedures: Vec::new(),
+      values: Vec::new(),
+      sources: Vec::new(),
+      pending: String::new(),
+      warnings: 0
+    }
+  }
+
+  pub fn prompt(&self) -> &'static str {
+    if self.pending.is_empty() { "> " } else { ". " }
+  }
+
+  // adds a line of input, None while the input goes on in the next line
+  pub fn feed(&mut self, line: &str) -> Option<Result<String, Diagnostic>> {
+    self.pending.push_str(line);
+    self.pending.push('\n');
+
+    let input = self.pending.clone();
+    let result = self.eval(&input);
+    match result {
+      Err(ref diagnostic) if !line.trim().is_empty() && !input.trim_start().starts_with(':') && incomplete(diagnostic) => None,
+      _ => {
+        self.pending.clear();
+        Some(result)
+      }
+    }
+  }
+
+  // one complete input, returns the text to show
+  pub fn eval(&mut self, input: &str) -> Result<String, Diagnostic> {
+    let text = input.trim();
+    let result = if let Some(command) = text.strip_prefix(':') {
+      self.command(command)
+    } else {
+      self.input(text)
+    };
+
+    // warnings such as -W case come before the result
+    let warnings: Vec<String> = self.parser.warnings()[self.warnings..].iter().map(|w| w.to_string()).collect();
+    self.warnings = self.parser.warnings().len();
+    match result {
+      Ok(output) if !warnings.is_empty() => {
+        let mut lines = warnings;
+        lines.extend(Some(output).filter(|output| !output.is_empty()));
+        Ok(lines.join("\n"))
+      },
+      result => result
+    }
+  }
+
+  fn input(&mut self, text: &str) -> Result<String, Diagnostic> {
+    match first_word(text).as_str() {
+      "" => Ok(String::new()),
+      "type" => {
+        self.parser.parse_type_declarations(text)?;
+        self.sources.push(text.to_string());
+        Ok(String::new())
+      },
+      "var" => {
+        let declarations = self.parser.parse_declarations(text)?;
+        self.variables.extend(declarations);
+        self.sources.push(text.to_string());
+        Ok(String::new())
+      },
+      "procedure" => {
+        let procedure = self.parser.parse_procedure(text)?;
+        self.procedures.push(procedure);
+        self.sources.push(text.to_string());
+        Ok(String::new())
+      },
+      "begin" | "if" | "while" => self.statement(text),
+      _ if text.contains(":=") => self.statement(text),
+      _ => match self.parser.parse_expression(text) {
+        Ok(ref expr) if expr.category != Category::Procedure => {
+          let value = self.run(Vec::new(), Some(expr))?.unwrap();
+          Ok(format!("{}: {}", interpreter::show(value, expr.category, self.parser.enums()), self.parser.type_name(expr.category)))
+        },
+        // `p` or `p(1)` calls a procedure
+        Ok(_) => self.statement(text),
+        Err(error) => self.statement(text).map_err(|_| error)
+      }
+    }
+  }
+
+  fn statement(&mut self, text: &str) -> Result<String, Diagnostic> {
+    let command = self.parser.parse_statement(text)?;
+    self.run(vec![command], None).map(|_| String::new())
+  }
+
+  // runs the commands with every declaration made so far, the variables
+  // keep what was assigned before a runtime error
+  fn run(&mut self, body: Vec<Command>, expr: Option<&Expr>) -> Result<Option<Value>, Diagnostic> {
+    let block = Block { variables: self.variables.clone(), procedures: self.procedures.clone(), body };
+    let mut interpreter = Interpreter::new();
+
+    let mut result = interpreter.run_block(&block, &self.values).map(|_| None);
+    if let (true, Some(expr)) = (result.is_ok(), expr) {
+      result = interpreter.eval(expr).map(Some);
+    }
+    self.values = self.variables.iter()
+      .flat_map(|decl| decl.names.iter())
+      .filter_map(|name| interpreter.value_of(name).map(|value| (name.clone(), value)))
+      .collect();
+    result
+  }
+
+  fn command(&mut self, command: &str) -> Result<String, Diagnostic> {
+    let (name, argument) = match command.find(char::is_whitespace) {
+      Some(i) => (&command[..i], command[i..].trim()),
+      None => (command, "")
+    };
+
+    match name {
+      "help" | "h" => Ok(HELP.to_string()),
+      "type" | "t" => {
+        let expr = self.parser.parse_expression(argument)?;
+        let mut out = String::new();
+        describe(&self.parser, &expr, 0, &mut out);
+        Ok(out.trim_end().to_string())
+      },
+      "tokens" => {
+        let mut scanner = Scanner::from_str(argument);
+        scanner.set_options(&self.options);
+        let mut lines = Vec::new();
+        loop {
+          let symbol = scanner.try_next_symbol()?;
+          if symbol.category == Type::Eof {
+            break;
+          }
+          lines.push(format!("{:?} {}", symbol.category, symbol.token));
+        }
+        Ok(lines.join("\n"))
+      },
+      "ast" => {
+        // a parser of its own so the input declares nothing
+        let mut parser = Parser::with_options(self.options.clone());
+        for source in self.sources.iter() {
+          match first_word(source).as_str() {
+            "type" => parser.parse_type_declarations(source)?,
+            "var" => parser.parse_declarations(source).map(|_| ())?,
+            _ => parser.parse_procedure(source).map(|_| ())?
+          }
+        }
+        let tree = match first_word(argument).as_str() {
+          "var" => format!("{:#?}", parser.parse_declarations(argument)?),
+          "procedure" => format!("{:#?}", parser.parse_procedure(argument)?),
+          "begin" | "if" | "while" => format!("{:#?}", parser.parse_statement(argument)?),
+          _ if argument.contains(":=") => format!("{:#?}", parser.parse_statement(argument)?),
+          _ => match parser.parse_expression(argument) {
+            Ok(expr) => format!("{:#?}", expr),
+            Err(error) => format!("{:#?}", parser.parse_statement(argument).map_err(|_| error)?)
+          }
+        };
+        Ok(tree)
+      },
+      "env" => {
+        let mut lines = Vec::new();
+        for decl in self.variables.iter() {
+          for name in decl.names.iter() {
+            let value = match self.values.iter().find(|entry| entry.0 == *name) {
+              Some(entry) => entry.1,
+              None => Value::default_of(decl.category, decl.range, decl.line)?
+            };
+            let value = interpreter::show(value, decl.category, self.parser.enums());
+            lines.push(format!("{}: {} = {}", name, self.declared_type(decl), value));
+          }
+        }
+        for procedure in self.procedures.iter() {
+          let params: Vec<String> = procedure.params.iter()
+            .map(|decl| format!("{}: {}", decl.names.join(", "), self.declared_type(decl)))
+            .collect();
+          if params.is_empty() {
+            lines.push(format!("procedure {}", procedure.name));
+          } else {
+            lines.push(format!("procedure {}({})", procedure.name, params.join("; ")));
+          }
+        }
+        Ok(lines.join("\n"))
+      },
+      _ => Err(Diagnostic::error(format!("Unknown command `:{}`, see :help", name), 1))
+    }
+  }
+
+  // `1..10` for a subrange, the type name otherwise
+  fn declared_type(&self, declaration: &Declaration) -> String {
+    match declaration.range {
+      Some(range) => self.parser.range_name(declaration.category, range),
+      None => self.parser.type_name(declaration.category)
+    }
+  }
+}
+
+fn first_word(text: &str) -> String {
+  text.split(|c: char| !c.is_alphanumeric() && c != '_').next().unwrap_or("").to_lowercase()
+}
+
+// the parser ran out of input, the next line may complete it
+fn incomplete(diagnostic: &Diagnostic) -> bool {
+  diagnostic.message.contains("`EOF`") || diagnostic.message.contains("`Eof`")
+}
+
+// expression and category, then its parts one level further in
+fn describe(parser: &Parser, expr: &Expr, depth: usize, out: &mut String) {
+  out.push_str(&format!("{}{}: {}\n", "  ".repeat(depth), source(expr), parser.type_name(expr.category)));
+  match expr.kind {
+    ExprKind::Unary(_, ref operand) | ExprKind::Builtin(_, ref operand) => describe(parser, operand, depth + 1, out),
+    ExprKind::Binary(_, ref lhs, ref rhs) | ExprKind::Logical(_, _, ref lhs, ref rhs) => {
+      describe(parser, lhs, depth + 1, out);
+      describe(parser, rhs, depth + 1, out);
+    },
+    ExprKind::Call(_, ref args) => {
+      for arg in args.iter() {
+        describe(parser, arg, depth + 1, out);
+      }
+    },
+    ExprKind::Set(ref items) => {
+      for (first, last) in items.iter() {
+        describe(parser, first, depth + 1, out);
+        if let Some(ref last) = *last {
+          describe(parser, last, depth + 1, out);
+        }
+      }
+    },
+    _ => {}
+  }
+}
+
+// Pascal text of an expression, parts that are operations in parentheses
+fn source(expr: &Expr) -> String {
+  let operand = |e: &Expr| match e.kind {
+    ExprKind::Unary(..) | ExprKind::Binary(..) | ExprKind::Logical(..) => format!("({})", source(e)),
+    _ => source(e)
+  };
+
+  match expr.kind {
+    ExprKind::Integer(i) => i.to_string(),
+    ExprKind::Real(r) => format!("{:?}", r),
+    ExprKind::Boolean(b) => b.to_string(),
+    ExprKind::Constant(ref name, _) | ExprKind::Variable(ref name) => name.clone(),
+    ExprKind::Builtin(function, ref argument) => format!("{}({})", function.name(), source(argument)),
+    ExprKind::RangeCheck(_, ref value) => source(value),
+    ExprKind::Set(ref items) => {
+      let items = items.iter().map(|(first, last)| match *last {
+        Some(ref last) => format!("{}..{}", source(first), source(last)),
+        None => source(first)
+      });
+      format!("[{}]", items.collect::<Vec<String>>().join(", "))
+    },
+    ExprKind::Call(ref name, ref args) => {
+      format!("{}({})", name, args.iter().map(source).collect::<Vec<String>>().join(", "))
+    },
+    ExprKind::Unary(Token::Not, ref e) => format!("not {}", operand(e)),
+    ExprKind::Unary(ref op, ref e) => format!("{}{}", op, operand(e)),
+    ExprKind::Binary(Token::Power, ref lhs, ref rhs) => format!("{} ** {}", operand(lhs), operand(rhs)),
+    ExprKind::Binary(ref op, ref lhs, ref rhs) | ExprKind::Logical(ref op, _, ref lhs, ref rhs) => {
+      format!("{} {} {}", operand(lhs), op, operand(rhs))
+    }
+  }
+}
+
+pub fn run<R: BufRead, W: Write>(reader: R, mut writer: W, options: Options) -> io::Result<()> {
+  let mut repl = Repl::new(options);
+
+  write!(writer, "{}", repl.prompt())?;
+  writer.flush()?;
+  for line in reader.lines() {
+    let line = line?;
+    if repl.pending.is_empty() && (line.trim() == ":quit" || line.trim() == ":q") {
+      return Ok(());
+    }
+    match repl.feed(&line) {
+      Some(Ok(ref output)) if output.is_empty() => {},
+      Some(Ok(output)) => writeln!(writer, "{}", output)?,
+      Some(Err(diagnostic)) => writeln!(writer, "{}", diagnostic)?,
+      None => {}
+    }
+    write!(writer, "{}", repl.prompt())?;
+    writer.flush()?;
+  }
+  writeln!(writer)
+}
+
+#[test]
+fn test_repl_session() {
+  let mut repl = Repl::new(Options::default());
+
+  assert_eq!(repl.eval("var a, b: integer; x: real;"), Ok(String::new()));
+  assert_eq!(repl.eval("a := 3"), Ok(String::new()));
+  assert_eq!(repl.eval("a + 1.5"), Ok("4.5: Real".to_string()));
+  assert_eq!(repl.eval("a div 2 = 1"), Ok("true: Boolean".to_string()));
+
+  assert_eq!(repl.feed("procedure dobro(n: integer);"), None);
+  assert_eq!(repl.prompt(), ". ");
+  assert_eq!(repl.feed("begin b := n * 2 end;"), Some(Ok(String::new())));
+  assert_eq!(repl.eval("dobro(a)"), Ok(String::new()));
+  assert_eq!(repl.eval("b"), Ok("6: Integer".to_string()));
+
+  assert_eq!(repl.eval("z := 1").unwrap_err().to_string(), "Error: Identifier `z` not declared => line 1");
+  assert_eq!(repl.eval("a := a div 0").unwrap_err().to_string(), "Error: Division by zero => line 1");
+  assert_eq!(repl.eval(":env"), Ok("a: Integer = 3\nb: Integer = 6\nx: Real = 0\nprocedure dobro(n: Integer)".to_string()));
+}
+
+#[test]
+fn test_repl_commands() {
+  let mut repl = Repl::new(Options::default());
+  repl.eval("var a: integer; x: real").unwrap();
+
+  assert_eq!(repl.eval(":type a * 2 + x"), Ok("(a * 2) + x: Real\n  a * 2: Integer\n    a: Integer\n    2: Integer\n  x: Real".to_string()));
+  assert_eq!(repl.eval(":tokens a := 1"), Ok("Identifier a\nCommand :=\nIntLiteral 1".to_string()));
+  assert!(repl.eval(":ast var c: boolean").unwrap().starts_with("[\n    Declaration {\n        names: [\n            \"c\",\n"));
+  // :ast declared nothing
+  assert!(repl.eval("c").is_err());
+  assert_eq!(repl.eval(":frob").unwrap_err().message, "Unknown command `:frob`, see :help");
+
+  repl.eval("var c: (red, green)").unwrap();
+  assert_eq!(repl.eval("succ(c)"), Ok("green: (red, green)".to_string()));
+  assert_eq!(repl.eval(":type ord(c)"), Ok("ord(c): Integer\n  c: (red, green)".to_string()));
+
+  repl.eval("var s: set of (lo, hi)").unwrap();
+  repl.eval("s := [hi]").unwrap();
+  assert_eq!(repl.eval("s + [lo, pred(hi)..hi]"), Ok("[lo, hi]: set of (lo, hi)".to_string()));
+  assert_eq!(repl.eval(":type c in []"), Ok("c in []: Boolean\n  c: (red, green)\n  []: []".to_string()));
+
+  repl.eval("type color = (cyan, magenta); digit = 0..9; digits = set of digit;").unwrap();
+  repl.eval("var k: color; d: digit; ds: digits").unwrap();
+  assert_eq!(repl.eval("succ(k)"), Ok("magenta: color".to_string()));
+  assert_eq!(repl.eval("d := 12").unwrap_err().message, "Value `12` out of range `0..9`");
+  assert_eq!(repl.eval("ds := [10]").unwrap_err().message, "Set element `10` out of range `0..9`");
+  // :ast replays the type declarations before the ones that use them
+  assert!(repl.eval(":ast ds := [d]").unwrap().starts_with("Assign {"));
+  assert!(repl.eval("type color = (black)").is_err());
+}
+
+#[test]
+fn test_repl_run() {
+  let input = "var a: integer;\nwhile a < 5 do\n  a := a + 2\n\na\n:quit\na\n";
+  let mut output = Vec::new();
+  run(input.as_bytes(), &mut output, Options::default()).unwrap();
+
+  assert_eq!(String::from_utf8(output).unwrap(), "> > . > > 6: Integer\n> ");
+}
@@ -1,13 +1,20 @@
 use std::fmt;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Token {
   // keywords
   Program,
+  Type,
   Var,
   Integer,
   Real,
   Boolean,
+  // `set of`
+  Set,
+  Of,
   Procedure,
   Begin,
   End,
@@ -22,9 +29,14 @@ pub enum Token {
   // delimiters
   Semicolon,
   Period,
+  // `..` in `1..10`
+  Range,
   Colon,
   LParentheses,
   RParentheses,
+  // `[1, 3..5]`
+  LBracket,
+  RBracket,
   Comma,
   // operators
   Assign,
@@ -40,24 +52,71 @@ pub enum Token {
   Sub,
   Mult,
   Div,
+  IntDiv,
+  Mod,
   Power,
+  Xor,
   Imp,
+  Equiv,
+  // `x in s`
+  In,
   // literal
   LitInt(i32),
-  LitReal(f32),
+  LitReal(f64),
   LitStr(String),
+  // {$...}
+  Directive(Directive),
   Empty
 }
 
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Directive {
+  // {$R+}, {$B-}
+  Switch(char, bool),
+  // {$I file}
+  Include(String),
+  Define(String),
+  Undef(String),
+  IfDef(String),
+  IfNDef(String),
+  Else,
+  EndIf,
+  // anything else is kept as written and ignored
+  Other(String)
+}
+
+impl fmt::Display for Directive {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Directive::Switch(c, on) => write!(f, "{{${}{}}}", c, if on { '+' } else { '-' }),
+      Directive::Include(ref s) => write!(f, "{{$I {}}}", s),
+      Directive::Define(ref s) => write!(f, "{{$DEFINE {}}}", s),
+      Directive::Undef(ref s) => write!(f, "{{$UNDEF {}}}", s),
+      Directive::IfDef(ref s) => write!(f, "{{$IFDEF {}}}", s),
+      Directive::IfNDef(ref s) => write!(f, "{{$IFNDEF {}}}", s),
+      Directive::Else => write!(f, "{{$ELSE}}"),
+      Directive::EndIf => write!(f, "{{$ENDIF}}"),
+      Directive::Other(ref s) => write!(f, "{{${}}}", s)
+    }
+  }
+}
+
 impl fmt::Display for Token {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     let result = match *self {
       Token::Imp => "->".to_string(),
+      Token::Equiv => "<->".to_string(),
+      Token::Xor => "xor".to_string(),
       Token::Program => "program".to_string(),
+      Token::Type => "type".to_string(),
       Token::Var => "var".to_string(),
       Token::Integer => "integer".to_string(),
       Token::Real => "real".to_string(),
       Token::Boolean => "boolean".to_string(),
+      Token::Set => "set".to_string(),
+      Token::Of => "of".to_string(),
+      Token::In => "in".to_string(),
       Token::Procedure => "procedure".to_string(),
       Token::Begin => "begin".to_string(),
       Token::End => "end".to_string(),
@@ -72,8 +131,11 @@ impl fmt::Display for Token {
       Token::Semicolon => ";".to_string(),
       Token::Colon => ":".to_string(),
       Token::Period => ".".to_string(),
+      Token::Range => "..".to_string(),
       Token::LParentheses => "(".to_string(),
       Token::RParentheses => ")".to_string(),
+      Token::LBracket => "[".to_string(),
+      Token::RBracket => "]".to_string(),
       Token::Comma => ",".to_string(),
       Token::Assign => ":=".to_string(),
       Token::Equal => "=".to_string(),
@@ -88,10 +150,13 @@ impl fmt::Display for Token {
       Token::Sub => "-".to_string(),
       Token::Mult => "*".to_string(),
       Token::Div => "/".to_string(),
+      Token::IntDiv => "div".to_string(),
+      Token::Mod => "mod".to_string(),
       Token::Power => "** | ^".to_string(),
       Token::LitInt(i) => i.to_string(),
       Token::LitReal(f) => f.to_string(),
       Token::LitStr(ref s) => s.to_string(),
+      Token::Directive(ref d) => d.to_string(),
       Token::Empty => "EOF".to_string()
     };
     write!(f, "{}", result)
@@ -99,6 +164,7 @@ impl fmt::Display for Token {
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Type {
   Keyword,
   Identifier,
@@ -110,5 +176,10 @@ pub enum Type {
   RelOperator,
   AddOperator,
   MulOperator,
+  // `**` and `^`, above `*` and right associative
+  PowOperator,
+  // `->` and `<->`, below the relational operators
+  LogicOperator,
+  Directive,
   Eof
 }
@@ -0,0 +1,452 @@
+use ast::Subrange;
+use parser::Category;
+
+// Every name of a program with where it is declared and used. The parser
+// declares and looks names up here as it reads the source: the outermost
+// scope holds the program name, then there is one per program or procedure
+// with its parameters, variables, types and nested procedures. A scope keeps
+// its entries once it is closed, so tools can look a name up anywhere in the
+// source afterwards. Positions are the lines and columns of the lexer.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+  pub line: u32,
+  pub column: u32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+  Program,
+  Procedure,
+  Variable,
+  Parameter,
+  // `color` in `type color = (red, green);`
+  Type,
+  // `red`, a value of an enumeration
+  Constant
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+  pub name: String,
+  pub kind: SymbolKind,
+  // Undefined until the type of a variable or parameter is read
+  pub category: Category,
+  // parameter types of a procedure
+  pub params: Vec<(Category, Option<Subrange>)>,
+  // ordinal of a value of an enumeration
+  pub constant: Option<i32>,
+  // bounds of a subrange variable or type
+  pub range: Option<Subrange>,
+  // the name where it is declared
+  pub position: Position,
+  // every use of the name, in source order
+  pub references: Vec<Position>,
+  // program or procedure it belongs to
+  pub owner: Option<usize>,
+  pub scope: usize
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scope {
+  pub parent: Option<usize>,
+  // program or procedure that opens it, None for the outermost scope
+  pub owner: Option<usize>,
+  // from the `program` or `procedure` keyword to the symbol after the
+  // `end` of the block, None while it is open or when the parse stopped
+  // inside it
+  pub start: Position,
+  pub end: Option<Position>,
+  // declared here, in source order
+  pub entries: Vec<usize>
+}
+
+// what `rollback` goes back to
+#[derive(Debug, Clone)]
+pub struct Mark {
+  entries: usize,
+  scopes: usize,
+  current: usize,
+  // how many uses each entry had
+  references: Vec<usize>
+}
+
+#[derive(Debug, Clone)]
+pub struct SymbolTable {
+  entries: Vec<Entry>,
+  scopes: Vec<Scope>,
+  // where names are declared and looked up from
+  current: usize
+}
+
+impl Default for SymbolTable {
+  fn default() -> SymbolTable {
+    SymbolTable::new()
+  }
+}
+
+impl SymbolTable {
+  pub fn new() -> SymbolTable {
+    let outermost = Scope { parent: None, owner: None, start: Position { line: 0, column: 0 }, end: None, entries: Vec::new() };
+    SymbolTable { entries: Vec::new(), scopes: vec![outermost], current: 0 }
+  }
+
+  pub fn entries(&self) -> &[Entry] {
+    &self.entries
+  }
+
+  pub fn entry(&self, id: usize) -> &Entry {
+    &self.entries[id]
+  }
+
+  pub fn entry_mut(&mut self, id: usize) -> &mut Entry {
+    &mut self.entries[id]
+  }
+
+  pub fn scopes(&self) -> &[Scope] {
+    &self.scopes
+  }
+
+  // adds `name` to the current scope, see `define` for the names whose
+  // type comes after them
+  pub fn declare(&mut self, name: &str, kind: SymbolKind, category: Category, position: Position) -> usize {
+    self.entries.push(Entry {
+      name: name.to_string(),
+      kind,
+      category,
+      params: Vec::new(),
+      constant: None,
+      range: None,
+      position,
+      references: Vec::new(),
+      owner: self.scopes[self.current].owner,
+      scope: self.current
+    });
+    let id = self.entries.len() - 1;
+    self.scopes[self.current].entries.push(id);
+    id
+  }
+
+  // the type of `a, b` once `: integer` is read
+  pub fn define(&mut self, id: usize, category: Category, range: Option<Subrange>) {
+    self.entries[id].category = category;
+    self.entries[id].range = range;
+  }
+
+  // `owner` is the program or procedure being declared
+  pub fn open_scope(&mut self, owner: Option<usize>, start: Position) {
+    self.scopes.push(Scope { parent: Some(self.current), owner, start, end: None, entries: Vec::new() });
+    self.current = self.scopes.len() - 1;
+  }
+
+  pub fn close_scope(&mut self, end: Position) {
+    self.scopes[self.current].end = Some(end);
+    if let Some(parent) = self.scopes[self.current].parent {
+      self.current = parent;
+    }
+  }
+
+  pub fn mark(&self) -> Mark {
+    let references = self.entries.iter().map(|entry| entry.references.len()).collect();
+    Mark { entries: self.entries.len(), scopes: self.scopes.len(), current: self.current, references }
+  }
+
+  // forgets the names, scopes and uses added after `mark`
+  pub fn rollback(&mut self, mark: Mark) {
+    self.entries.truncate(mark.entries);
+    for (entry, &count) in self.entries.iter_mut().zip(mark.references.iter()) {
+      entry.references.truncate(count);
+    }
+    self.scopes.truncate(mark.scopes);
+    for scope in self.scopes.iter_mut() {
+      scope.entries.retain(|&id| id < mark.entries);
+    }
+    self.current = mark.current;
+  }
+
+  // whether the current scope already has `name`, typed or not
+  pub fn declared_here(&self, name: &str) -> bool {
+    self.scopes[self.current].entries.iter().any(|&id| same_name(&self.entries[id].name, name))
+  }
+
+  // whether a program or procedure around the current scope is called `name`
+  pub fn is_routine(&self, name: &str) -> bool {
+    self.chain(self.current).any(|scope| self.scopes[scope].entries.iter().any(|&id| {
+      let entry = &self.entries[id];
+      same_name(&entry.name, name) && (entry.kind == SymbolKind::Program || entry.kind == SymbolKind::Procedure)
+    }))
+  }
+
+  // what `name` refers to from the current scope
+  pub fn lookup(&self, name: &str) -> Option<usize> {
+    self.resolve(self.current, name)
+  }
+
+  pub fn refer(&mut self, id: usize, position: Position) {
+    self.entries[id].references.push(position);
+  }
+
+  // innermost first, names still waiting for their type are skipped
+  pub fn resolve(&self, scope: usize, name: &str) -> Option<usize> {
+    for scope in self.chain(scope) {
+      let found = self.scopes[scope].entries.iter().rev()
+        .find(|&&id| same_name(&self.entries[id].name, name) && self.entries[id].category != Category::Undefined);
+      if found.is_some() {
+        return found.cloned();
+      }
+    }
+    None
+  }
+
+  // what `name` written at `position` refers to; when the name is not
+  // written there, what it would refer to in the innermost scope around it
+  pub fn resolve_at(&self, name: &str, position: Position) -> Option<&Entry> {
+    let written = self.entries.iter().find(|entry| {
+      same_name(&entry.name, name) &&
+        Some(&entry.position).into_iter().chain(entry.references.iter()).any(|&at| covers(entry, at, position))
+    });
+    if written.is_some() {
+      return written;
+    }
+    self.resolve(self.scope_at(position), name).map(|id| &self.entries[id])
+  }
+
+  // the entry declared or used at `position`
+  pub fn entry_at(&self, position: Position) -> Option<usize> {
+    self.entries.iter().position(|entry| covers(entry, entry.position, position))
+      .or_else(|| self.entries.iter().position(|entry| entry.references.iter().any(|&at| covers(entry, at, position))))
+  }
+
+  // where the name, declaration or use, under `position` starts
+  pub fn name_at(&self, position: Position) -> Option<Position> {
+    let id = self.entry_at(position)?;
+    let entry = &self.entries[id];
+    Some(entry.position).into_iter().chain(entry.references.iter().cloned()).find(|&at| covers(entry, at, position))
+  }
+
+  pub fn scope_at(&self, position: Position) -> usize {
+    (0..self.scopes.len())
+      .filter(|&s| self.scopes[s].start <= position && self.scopes[s].end.is_none_or(|end| position <= end))
+      .max_by_key(|&s| self.scopes[s].start)
+      .unwrap_or(0)
+  }
+
+  // names usable from `scope`, inner declarations hide outer ones
+  pub fn visible(&self, scope: usize) -> Vec<usize> {
+    let mut found: Vec<usize> = Vec::new();
+    for scope in self.chain(scope) {
+      for &id in self.scopes[scope].entries.iter() {
+        if !found.iter().any(|&f| same_name(&self.entries[f].name, &self.entries[id].name)) {
+          found.push(id);
+        }
+      }
+    }
+    found
+  }
+
+  // what a program or procedure declares, the program itself for None
+  pub fn children(&self, owner: Option<usize>) -> Vec<usize> {
+    (0..self.entries.len()).filter(|&id| self.entries[id].owner == owner).collect()
+  }
+
+  // the scope opened by a program or procedure
+  pub fn scope_of(&self, owner: usize) -> Option<&Scope> {
+    self.scopes.iter().find(|scope| scope.owner == Some(owner))
+  }
+
+  // `scope` and the ones around it, innermost first
+  fn chain(&self, scope: usize) -> impl Iterator<Item = usize> + '_ {
+    ::std::iter::successors(Some(scope), move |&scope| self.scopes[scope].parent)
+  }
+}
+
+// Pascal names are case-insensitive
+pub fn same_name(a: &str, b: &str) -> bool {
+  a.to_lowercase() == b.to_lowercase()
+}
+
+// a cursor right after the last letter still points at the name
+fn covers(entry: &Entry, at: Position, position: Position) -> bool {
+  at.line == position.line && at.column <= position.column && position.column <= at.column + entry.name.chars().count() as u32
+}
+
+// what the parser declared in `source`
+#[cfg(test)]
+fn symbols(source: &str) -> SymbolTable {
+  use parser::Parser;
+
+  let mut parser = Parser::new();
+  parser.parse_str(source).unwrap();
+  parser.symbols().clone()
+}
+
+#[test]
+fn test_symbols_scopes() {
+  let table = symbols("program exemplo;
+var a, b: integer;
+  procedure p(b: real);
+  var c: boolean;
+  begin
+    c := b > a
+  end;
+begin
+  a := b;
+  p(a)
+end.");
+
+  let names: Vec<(&str, SymbolKind, Category, usize)> = table.entries().iter()
+    .map(|entry| (entry.name.as_str(), entry.kind, entry.category, entry.scope))
+    .collect();
+  assert_eq!(names, vec![
+    ("exemplo", SymbolKind::Program, Category::Program, 0),
+    ("a", SymbolKind::Variable, Category::Integer, 1),
+    ("b", SymbolKind::Variable, Category::Integer, 1),
+    ("p", SymbolKind::Procedure, Category::Procedure, 1),
+    ("b", SymbolKind::Parameter, Category::Real, 2),
+    ("c", SymbolKind::Variable, Category::Boolean, 2)
+  ]);
+
+  // the scope of `p` is closed but keeps its entries
+  assert_eq!(table.scopes()[2].entries, vec![4, 5]);
+  assert_eq!(table.scopes()[2].owner, Some(3));
+  assert_eq!(table.entry(3).references.len(), 1);
+  assert_eq!(table.entry(1).references.iter().map(|position| position.line).collect::<Vec<u32>>(), vec![6, 9, 10]);
+}
+
+#[test]
+fn test_symbols_resolve_at() {
+  let source = "program exemplo;
+var a, b: integer;
+  procedure p(b: real);
+  begin
+    b := a
+  end;
+begin
+  a := b
+end.";
+  let table = symbols(source);
+
+  let b = table.resolve_at("b", Position { line: 5, column: 5 }).unwrap();
+  assert_eq!((b.kind, b.category, b.position), (SymbolKind::Parameter, Category::Real, Position { line: 3, column: 15 }));
+
+  let b = table.resolve_at("B", Position { line: 8, column: 8 }).unwrap();
+  assert_eq!((b.kind, b.category, b.position.line), (SymbolKind::Variable, Category::Integer, 2));
+
+  // not written there, resolved in the scope around it
+  assert_eq!(table.resolve_at("p", Position { line: 5, column: 1 }).map(|entry| entry.kind), Some(SymbolKind::Procedure));
+  assert_eq!(table.resolve_at("b", Position { line: 4, column: 1 }).map(|entry| entry.kind), Some(SymbolKind::Parameter));
+  assert_eq!(table.resolve_at("x", Position { line: 5, column: 1 }), None);
+  assert_eq!(table.resolve_at("a", Position { line: 0, column: 1 }), None);
+  assert_eq!(table.resolve_at("a", Position { line: 42, column: 1 }), None);
+
+  // the same name in two scopes of one line
+  let table = symbols("program p; var x: integer; procedure q(x: boolean); begin x := not x end; begin x := 1 end.");
+  let x = |column: u32| table.resolve_at("x", Position { line: 1, column }).map(|entry| (entry.kind, entry.position.column));
+  assert_eq!(x(16), Some((SymbolKind::Variable, 16)));
+  assert_eq!(x(40), Some((SymbolKind::Parameter, 40)));
+  assert_eq!(x(59), Some((SymbolKind::Parameter, 40)));
+  assert_eq!(x(68), Some((SymbolKind::Parameter, 40)));
+  assert_eq!(x(81), Some((SymbolKind::Variable, 16)));
+  // not written there
+  assert_eq!(x(53), Some((SymbolKind::Parameter, 40)));
+  assert_eq!(x(75), Some((SymbolKind::Variable, 16)));
+}
+
+#[test]
+fn test_symbols_enumerations() {
+  let source = "program cores;
+type color = (red, green);
+var c: color; d: (up, down);
+begin
+  c := green
+end.";
+  let table = symbols(source);
+
+  let names: Vec<(&str, SymbolKind, Category)> = table.entries().iter()
+    .map(|entry| (entry.name.as_str(), entry.kind, entry.category))
+    .collect();
+  assert_eq!(names, vec![
+    ("cores", SymbolKind::Program, Category::Program),
+    ("red", SymbolKind::Constant, Category::Enum(0, 2)),
+    ("green", SymbolKind::Constant, Category::Enum(0, 2)),
+    ("color", SymbolKind::Type, Category::Enum(0, 2)),
+    ("c", SymbolKind::Variable, Category::Enum(0, 2)),
+    // `d` is declared before the type written after it
+    ("d", SymbolKind::Variable, Category::Enum(1, 2)),
+    ("up", SymbolKind::Constant, Category::Enum(1, 2)),
+    ("down", SymbolKind::Constant, Category::Enum(1, 2))
+  ]);
+  assert_eq!(table.resolve_at("color", Position { line: 3, column: 1 }).unwrap().references.len(), 1);
+  assert_eq!(table.resolve_at("green", Position { line: 5, column: 1 }).unwrap().references.len(), 1);
+}
+
+#[test]
+fn test_symbols_subranges() {
+  let source = "program faixas;
+type color = (red, green, blue); warm = red..green;
+var w: warm; d: 0..9;
+begin
+end.";
+  let table = symbols(source);
+
+  assert_eq!(table.resolve_at("warm", Position { line: 3, column: 1 }).map(|entry| entry.category), Some(Category::Enum(0, 3)));
+  assert_eq!(table.resolve_at("w", Position { line: 3, column: 1 }).map(|entry| entry.category), Some(Category::Enum(0, 3)));
+  assert_eq!(table.resolve_at("d", Position { line: 3, column: 1 }).map(|entry| entry.category), Some(Category::Integer));
+  assert_eq!(table.resolve_at("green", Position { line: 2, column: 1 }).unwrap().references.len(), 1);
+}
+
+#[test]
+fn test_symbols_sets() {
+  use parser::Element;
+
+  let source = "program conjuntos;
+type color = (red, green, blue); digits = set of 0..9;
+var c: set of color; d: digits; f: set of (on, off);
+begin
+end.";
+  let table = symbols(source);
+
+  assert_eq!(table.resolve_at("c", Position { line: 3, column: 1 }).map(|entry| entry.category), Some(Category::Set(Element::Enum(0, 3))));
+  assert_eq!(table.resolve_at("d", Position { line: 3, column: 1 }).map(|entry| entry.category), Some(Category::Set(Element::Integer)));
+  assert_eq!(table.resolve_at("f", Position { line: 3, column: 1 }).map(|entry| entry.category), Some(Category::Set(Element::Enum(1, 2))));
+  assert_eq!(table.resolve_at("off", Position { line: 3, column: 1 }).map(|entry| entry.category), Some(Category::Enum(1, 2)));
+  assert_eq!(table.resolve_at("color", Position { line: 2, column: 1 }).unwrap().references.len(), 1);
+}
+
+#[test]
+fn test_symbols_failed_parse() {
+  use parser::Parser;
+
+  let mut parser = Parser::new();
+  assert!(parser.parse_str("program p;\nvar a: integer;\n  procedure q(x: boolean);\n  begin\n    x := a\n  end;\nbegin\nend.").is_err());
+  let table = parser.symbols();
+
+  // names declared before the error are kept, the scope of `q` stays open
+  assert_eq!(table.resolve_at("x", Position { line: 5, column: 1 }).map(|entry| entry.kind), Some(SymbolKind::Parameter));
+  assert_eq!(table.resolve_at("a", Position { line: 5, column: 1 }).unwrap().references, vec![Position { line: 5, column: 10 }]);
+  assert_eq!(table.scope_of(2).unwrap().end, None);
+  assert_eq!(table.scope_at(Position { line: 8, column: 1 }), 2);
+}
+
+#[test]
+fn test_symbols_fragments() {
+  use parser::Parser;
+
+  let mut parser = Parser::new();
+  parser.parse_declarations("var a: integer;").unwrap();
+  assert!(parser.parse_declarations("var b, a: boolean;").is_err());
+  assert!(parser.parse_procedure("procedure p(x: integer); begin x := y end").is_err());
+
+  // the failed fragments leave nothing behind
+  let names: Vec<&str> = parser.symbols().entries().iter().map(|entry| entry.name.as_str()).collect();
+  assert_eq!(names, vec!["a"]);
+  assert_eq!(parser.symbols().scopes().len(), 1);
+  assert_eq!(parser.symbols().lookup("a").map(|id| parser.symbols().entry(id).scope), Some(0));
+
+  // nor do they keep the uses they made of the names that stay
+  parser.parse_statement("a := a + 1").unwrap();
+  assert!(parser.parse_statement("a := a + true").is_err());
+  assert!(parser.parse_statement("a := a +").is_err());
+  let a = parser.symbols().lookup("a").unwrap();
+  assert_eq!(parser.symbols().entry(a).references.len(), 2);
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+use ast::Block;
+use diagnostic::Diagnostic;
+use interpreter::{Interpreter, Value};
+use lexer::Scanner;
+use parser::{Category, Parser};
+use spec::{Token, Type};
+
+// Truth table of a Boolean expression, printed by `pascalzim truth-table`.
+// Every name in the expression is a Boolean variable, in the order they are
+// first written, and the rows count from all false to all true with the
+// first variable changing slowest.
+
+// 2^16 rows is already more than anyone reads
+pub const MAX_VARIABLES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+  // true in every row
+  Tautology,
+  // false in every row
+  Contradiction,
+  Contingency
+}
+
+impl fmt::Display for Verdict {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match *self {
+      Verdict::Tautology => "tautology",
+      Verdict::Contradiction => "contradiction",
+      Verdict::Contingency => "contingency"
+    };
+    write!(f, "{}", name)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TruthTable {
+  pub expression: String,
+  pub variables: Vec<String>,
+  // the values of the variables and of the expression
+  pub rows: Vec<(Vec<bool>, bool)>
+}
+
+impl TruthTable {
+  pub fn new(expression: &str) -> Result<TruthTable, Diagnostic> {
+    let expression = expression.trim();
+    let variables = free_names(expression)?;
+    if variables.len() > MAX_VARIABLES {
+      let message = format!("Expression has {} variables, a truth table takes at most {}", variables.len(), MAX_VARIABLES);
+      return Err(Diagnostic::error(message, 1));
+    }
+
+    let mut parser = Parser::new();
+    let declarations = if variables.is_empty() {
+      Vec::new()
+    } else {
+      parser.parse_declarations(&format!("var {}: boolean;", variables.join(", ")))?
+    };
+    let expr = parser.parse_expression(expression)?;
+    if expr.category != Category::Boolean {
+      return Err(Diagnostic::error(format!("Expression must be Boolean found `{:?}`", expr.category), expr.line));
+    }
+
+    let block = Block { variables: declarations, procedures: Vec::new(), body: Vec::new() };
+    let mut rows = Vec::new();
+    for row in 0..1usize << variables.len() {
+      let inputs: Vec<bool> = (0..variables.len()).map(|i| row >> (variables.len() - 1 - i) & 1 == 1).collect();
+      let values: Vec<(String, Value)> = variables.iter().cloned().zip(inputs.iter().map(|&b| Value::Boolean(b))).collect();
+
+      let mut interpreter = Interpreter::new();
+      interpreter.run_block(&block, &values)?;
+      let result = interpreter.eval(&expr)?.as_bool(expr.line)?;
+      rows.push((inputs, result));
+    }
+    Ok(TruthTable { expression: expression.to_string(), variables, rows })
+  }
+
+  pub fn verdict(&self) -> Verdict {
+    if self.rows.iter().all(|&(_, result)| result) {
+      Verdict::Tautology
+    } else if self.rows.iter().all(|&(_, result)| !result) {
+      Verdict::Contradiction
+    } else {
+      Verdict::Contingency
+    }
+  }
+
+  // aligned columns followed by the verdict
+  pub fn to_text(&self) -> String {
+    let header = self.header();
+    let widths: Vec<usize> = header.iter().map(|name| name.len().max("false".len())).collect();
+    let line = |cells: Vec<String>| -> String {
+      let padded: Vec<String> = cells.iter().zip(widths.iter()).map(|(cell, &width)| format!("{:width$}", cell, width = width)).collect();
+      padded.join("  ").trim_end().to_string() + "\n"
+    };
+
+    let mut out = line(header.clone());
+    for row in self.cells() {
+      out.push_str(&line(row));
+    }
+    out.push_str(&format!("\n{}\n", self.verdict()));
+    out
+  }
+
+  pub fn to_markdown(&self) -> String {
+    let header: Vec<String> = self.header().iter().map(|name| format!("`{}`", name)).collect();
+    let mut out = format!("| {} |\n|{}\n", header.join(" | "), "---|".repeat(header.len()));
+    for row in self.cells() {
+      out.push_str(&format!("| {} |\n", row.join(" | ")));
+    }
+    out
+  }
+
+  pub fn to_csv(&self) -> String {
+    let mut out = self.header().iter().map(|name| csv_field(name)).collect::<Vec<String>>().join(",") + "\n";
+    for row in self.cells() {
+      out.push_str(&row.join(","));
+      out.push('\n');
+    }
+    out
+  }
+
+  fn header(&self) -> Vec<String> {
+    self.variables.iter().cloned().chain(Some(self.expression.clone())).collect()
+  }
+
+  fn cells(&self) -> Vec<Vec<String>> {
+    self.rows.iter().map(|(inputs, result)| {
+      inputs.iter().chain(Some(result)).map(|b| b.to_string()).collect()
+    }).collect()
+  }
+}
+
+// identifiers in the order they are first written, names differing only
+// in case are the same variable
+fn free_names(expression: &str) -> Result<Vec<String>, Diagnostic> {
+  let mut scanner = Scanner::from_str(expression);
+  let mut names: Vec<String> = Vec::new();
+  loop {
+    let symbol = scanner.try_next_symbol()?;
+    match symbol.token {
+      _ if symbol.category == Type::Eof => return Ok(names),
+      Token::LitStr(name) if symbol.category == Type::Identifier &&
+        !names.iter().any(|known| known.to_lowercase() == name.to_lowercase()) => names.push(name),
+      _ => {}
+    }
+  }
+}
+
+// quoted when it has a comma or a quote, as in `p(a, b)`
+fn csv_field(field: &str) -> String {
+  if field.contains(',') || field.contains('"') {
+    format!("\"{}\"", field.replace('"', "\"\""))
+  } else {
+    field.to_string()
+  }
+}
+
+#[test]
+fn test_truth_table_rows() {
+  let table = TruthTable::new("p -> q").unwrap();
+  assert_eq!(table.variables, vec!["p", "q"]);
+  assert_eq!(table.rows, vec![
+    (vec![false, false], true),
+    (vec![false, true], true),
+    (vec![true, false], false),
+    (vec![true, true], true)
+  ]);
+  assert_eq!(table.verdict(), Verdict::Contingency);
+
+  assert_eq!(TruthTable::new("(p -> q) <-> (not q -> not P)").unwrap().verdict(), Verdict::Tautology);
+  assert_eq!(TruthTable::new("p and not p").unwrap().verdict(), Verdict::Contradiction);
+  assert_eq!(TruthTable::new("1 < 2").unwrap().rows, vec![(vec![], true)]);
+
+  let res = TruthTable::new("p + 1");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Type `Boolean` doesn't support arithmetic operator `+` => line 1");
+  let res = TruthTable::new("2 * 3");
+  assert_eq!(res.unwrap_err().to_string(), "Error: Expression must be Boolean found `Integer` => line 1");
+  let names: Vec<String> = (0..17).map(|i| format!("p{}", i)).collect();
+  let res = TruthTable::new(&names.join(" or "));
+  assert_eq!(res.unwrap_err().to_string(), "Error: Expression has 17 variables, a truth table takes at most 16 => line 1");
+}
+
+#[test]
+fn test_truth_table_output() {
+  let table = TruthTable::new("a xor b").unwrap();
+  assert_eq!(table.to_text(), "a      b      a xor b
+false  false  false
+false  true   true
+true   false  true
+true   true   false
+
+contingency
+");
+  assert_eq!(table.to_markdown(), "| `a` | `b` | `a xor b` |
+|---|---|---|
+| false | false | false |
+| false | true | true |
+| true | false | true |
+| true | true | false |
+");
+  assert_eq!(table.to_csv(), "a,b,a xor b
+false,false,false
+false,true,true
+true,false,true
+true,true,false
+");
+}
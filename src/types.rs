@@ -0,0 +1,365 @@
+use ast::Builtin;
+use parser::{Category, Element};
+use spec::Token;
+
+// Type rules of the language. The parser checks assignments, arguments and
+// operators only through these functions, and `matrix` prints them as the
+// tables of docs/types.md. Integer values are promoted to Real where a Real
+// is expected, no other conversion exists. Integer, Boolean and the
+// enumerations are ordinal types: their values are counted from a first one
+// and compare by that count. A set holds ordinals of one of them, `+ - *`
+// and the comparisons between two sets are the set operations.
+
+// the categories a variable or an expression can have
+pub const VALUES: [Category; 3] = [Category::Integer, Category::Real, Category::Boolean];
+
+// binary operators in the order they are documented
+pub const BINARY: [Token; 18] = [
+  Token::Equal, Token::NotEqual, Token::LessThan, Token::LessThanOrEqual, Token::GreaterThan,
+  Token::GreaterThanOrEqual, Token::Add, Token::Sub, Token::Mult, Token::Div, Token::IntDiv,
+  Token::Mod, Token::Power, Token::And, Token::Or, Token::Xor, Token::Imp, Token::Equiv
+];
+
+pub const UNARY: [Token; 3] = [Token::Sub, Token::Add, Token::Not];
+
+// sets are 64 bits, the ordinals of their elements go from 0 to 63
+pub const SET_SIZE: i32 = 64;
+
+pub fn is_value(category: Category) -> bool {
+  VALUES.contains(&category) || is_enum(category) || is_set(category)
+}
+
+pub fn is_set(category: Category) -> bool {
+  matches!(category, Category::Set(_))
+}
+
+// `set of category`
+pub fn set_of(category: Category) -> Option<Category> {
+  match category {
+    Category::Integer => Some(Category::Set(Element::Integer)),
+    Category::Boolean => Some(Category::Set(Element::Boolean)),
+    Category::Enum(id, count) => Some(Category::Set(Element::Enum(id, count))),
+    _ => None
+  }
+}
+
+// type of the elements of a set, none for `[]`
+pub fn element_of(set: Category) -> Option<Category> {
+  match set {
+    Category::Set(Element::Integer) => Some(Category::Integer),
+    Category::Set(Element::Boolean) => Some(Category::Boolean),
+    Category::Set(Element::Enum(id, count)) => Some(Category::Enum(id, count)),
+    _ => None
+  }
+}
+
+pub fn is_enum(category: Category) -> bool {
+  matches!(category, Category::Enum(..))
+}
+
+pub fn is_ordinal(category: Category) -> bool {
+  category == Category::Integer || category == Category::Boolean || is_enum(category)
+}
+
+pub fn is_numeric(category: Category) -> bool {
+  category == Category::Integer || category == Category::Real
+}
+
+// whether a value of `value` can be stored in a variable or parameter of
+// `target`
+pub fn assignable(target: Category, value: Category) -> bool {
+  (is_value(target) && target == value) || (target == Category::Real && value == Category::Integer) ||
+    (is_set(target) && value == Category::Set(Element::Empty))
+}
+
+// the type both operands are converted to before they are combined
+pub fn promote(a: Category, b: Category) -> Option<Category> {
+  match (a, b) {
+    _ if a == b && is_value(a) => Some(a),
+    (Category::Integer, Category::Real) | (Category::Real, Category::Integer) => Some(Category::Real),
+    _ => None
+  }
+}
+
+// type of `lhs op rhs`, or why the operands are rejected
+pub fn binary(op: &Token, lhs: Category, rhs: Category, legacy_division: bool) -> Result<Category, String> {
+  if let Some(&category) = [lhs, rhs].iter().find(|category| !is_value(**category)) {
+    return Err(unsupported(category, op));
+  }
+  if *op == Token::In || is_set(lhs) || is_set(rhs) {
+    return sets(op, lhs, rhs);
+  }
+
+  match *op {
+    Token::Equal | Token::NotEqual => match promote(lhs, rhs) {
+      Some(_) => Ok(Category::Boolean),
+      None => Err(format!("Mismatched types `{:?}` is different from `{:?}` for `{}`", lhs, rhs, op))
+    },
+    Token::Power => power(lhs, rhs, false),
+    // `false < true`, `red < blue`
+    Token::LessThan | Token::LessThanOrEqual | Token::GreaterThan | Token::GreaterThanOrEqual if is_ordinal(lhs) && !is_numeric(lhs) => {
+      if lhs != rhs {
+        return Err(format!("Mismatched types `{:?}` is different from `{:?}` for `{}`", lhs, rhs, op));
+      }
+      Ok(Category::Boolean)
+    },
+    Token::LessThan | Token::LessThanOrEqual | Token::GreaterThan | Token::GreaterThanOrEqual |
+    Token::Add | Token::Sub | Token::Mult | Token::Div => {
+      if let Some(&category) = [lhs, rhs].iter().find(|category| !is_numeric(**category)) {
+        return Err(unsupported(category, op));
+      }
+      let common = promote(lhs, rhs).unwrap();
+      Ok(match *op {
+        Token::Add | Token::Sub | Token::Mult => common,
+        // `/` is real division unless the legacy rule is on
+        Token::Div if common == Category::Integer && legacy_division => Category::Integer,
+        Token::Div => Category::Real,
+        _ => Category::Boolean
+      })
+    },
+    Token::IntDiv | Token::Mod => {
+      if lhs != Category::Integer || rhs != Category::Integer {
+        return Err(format!("Arithmetic operator `{}` only supports Integer operands", op));
+      }
+      Ok(Category::Integer)
+    },
+    _ => {
+      if lhs != Category::Boolean || rhs != Category::Boolean {
+        return Err(format!("Logic operator `{}` only supports Boolean operands", op));
+      }
+      Ok(Category::Boolean)
+    }
+  }
+}
+
+// `x in s` and `+ - * = <> <= >=` between two sets with elements of the
+// same type, `[]` goes with any set
+fn sets(op: &Token, lhs: Category, rhs: Category) -> Result<Category, String> {
+  if *op == Token::In {
+    return match rhs {
+      Category::Set(Element::Empty) if is_ordinal(lhs) => Ok(Category::Boolean),
+      Category::Set(_) if element_of(rhs) == Some(lhs) => Ok(Category::Boolean),
+      Category::Set(_) => Err(format!("Mismatched types `{:?}` is not an element of `{:?}`", lhs, rhs)),
+      _ => Err(format!("Operator `in` expects a set found `{:?}`", rhs))
+    };
+  }
+
+  let common = match (lhs, rhs) {
+    (Category::Set(a), Category::Set(b)) if a == b || b == Element::Empty => lhs,
+    (Category::Set(Element::Empty), Category::Set(_)) => rhs,
+    _ => return Err(format!("Mismatched types `{:?}` is different from `{:?}` for `{}`", lhs, rhs, op))
+  };
+  match *op {
+    // union, difference and intersection
+    Token::Add | Token::Sub | Token::Mult => Ok(common),
+    // `<=` is a subset, `>=` a superset
+    Token::Equal | Token::NotEqual | Token::LessThanOrEqual | Token::GreaterThanOrEqual => Ok(Category::Boolean),
+    _ => Err(unsupported(lhs, op))
+  }
+}
+
+// `base ** exponent` stays Integer only when the exponent is known not to
+// be negative, as in `x ** 2`; `2 ** n` may be a fraction
+pub fn power(base: Category, exponent: Category, natural: bool) -> Result<Category, String> {
+  if let Some(&category) = [base, exponent].iter().find(|category| !is_numeric(**category)) {
+    return Err(unsupported(category, &Token::Power));
+  }
+  Ok(if base == Category::Integer && exponent == Category::Integer && natural { Category::Integer } else { Category::Real })
+}
+
+// type of `ord(x)`, `succ(x)` and `pred(x)`
+pub fn builtin(function: Builtin, argument: Category) -> Result<Category, String> {
+  if !is_ordinal(argument) {
+    return Err(format!("Function `{}` expects an ordinal argument found `{:?}`", function.name(), argument));
+  }
+  Ok(if function == Builtin::Ord { Category::Integer } else { argument })
+}
+
+// type of `op operand` for `-`, `+` and `not`
+pub fn unary(op: &Token, operand: Category) -> Result<Category, String> {
+  match *op {
+    Token::Not if operand == Category::Boolean => Ok(Category::Boolean),
+    Token::Not => Err(format!("Logic operator `{}` only supports Boolean operands", op)),
+    _ if is_numeric(operand) => Ok(operand),
+    _ => Err(unsupported(operand, op))
+  }
+}
+
+fn unsupported(category: Category, op: &Token) -> String {
+  let family = match *op {
+    Token::Equal | Token::NotEqual | Token::LessThan | Token::LessThanOrEqual |
+    Token::GreaterThan | Token::GreaterThanOrEqual | Token::In => "relational",
+    Token::And | Token::Or | Token::Xor | Token::Imp | Token::Equiv | Token::Not => "logic",
+    _ => "arithmetic"
+  };
+  format!("Type `{:?}` doesn't support {} operator `{}`", category, family, op)
+}
+
+// the levels of the parser, tightest first
+const PRECEDENCE: &str = "## Precedence
+
+| level | operators | associativity |
+|---|---|---|
+| 1 | `not` | prefix |
+| 2 | `**` `^` | right |
+| 3 | `*` `/` `div` `mod` `and` | left |
+| 4 | `+` `-` `or` `xor` | left |
+| 5 | `=` `<>` `<` `<=` `>` `>=` `in` | none |
+| 6 | `->` | right |
+| 7 | `<->` | left |
+
+A sign applies to the whole first term, `-a * b` is `-(a * b)`. As in Pascal
+`and` and `or` bind tighter than the comparisons, so `a < b and c` needs
+parentheses, while `a = b -> c` reads as `(a = b) -> c`.
+";
+
+// the Markdown of docs/types.md, printed by `pascalzim types`
+pub fn matrix() -> String {
+  let mut out = String::from("# Operators and types\n\n\
+    Generated from src/types.rs by `pascalzim types`, do not edit by hand.\n\n\
+    Integer values are promoted to Real where a Real is expected, no other\n\
+    conversion exists. A `-` cell means the combination is rejected.\n\n");
+
+  out.push_str(PRECEDENCE);
+
+  out.push_str("\n## Assignment and arguments\n\n");
+  out.push_str(&table("variable \\ value", &VALUES, |target, value| {
+    (if assignable(target, value) { "yes" } else { "-" }).to_string()
+  }));
+
+  out.push_str("\n## Unary operators\n\n| operator |");
+  for category in VALUES.iter() {
+    out.push_str(&format!(" {:?} |", category));
+  }
+  out.push_str(&format!("\n|---{}|\n", "|---".repeat(VALUES.len())));
+  for op in UNARY.iter() {
+    out.push_str(&format!("| {} |", spelling(op)));
+    for &category in VALUES.iter() {
+      out.push_str(&format!(" {} |", cell(unary(op, category))));
+    }
+    out.push('\n');
+  }
+
+  out.push_str("\n## Binary operators\n\nRows are the left operand, columns the right one. \
+    Operators with the same\ntable are listed together.\n");
+  let tables: Vec<String> = BINARY.iter().map(|op| {
+    table("", &VALUES, |lhs, rhs| cell(binary(op, lhs, rhs, false)))
+  }).collect();
+  let mut i = 0;
+  while i < BINARY.len() {
+    let mut j = i + 1;
+    while j < BINARY.len() && tables[j] == tables[i] {
+      j += 1;
+    }
+    let names: Vec<String> = BINARY[i..j].iter().map(spelling).collect();
+    out.push_str(&format!("\n### {}\n\n{}", names.join(" "), tables[i]));
+    i = j;
+  }
+
+  out.push_str("\nWith `--legacy-division` `/` between two Integers gives an Integer.\n\
+    `**` between two Integers gives an Integer when the exponent is known not\n\
+    to be negative: a literal, or `+`, `*`, `div` and `**` between literals.\n");
+  out.push_str("\n## Ordinal types\n\n\
+    Integer, Boolean and the enumerations declared with\n\
+    `type color = (red, green, blue);` are ordinal. Every enumeration is a type\n\
+    of its own: it is only assigned, compared with `=` `<>` `<` `<=` `>` `>=`\n\
+    and passed where the same enumeration is expected, its values are ordered\n\
+    as they are written and `false < true`.\n\n\
+    | function | argument | result |\n\
+    |---|---|---|\n\
+    | `ord` | ordinal | Integer |\n\
+    | `succ` `pred` | ordinal | the argument type |\n\n\
+    `succ` and `pred` fail at runtime past the first or last value.\n\n\
+    A subrange such as `0..9`, `-5..5` or `red..green` has the type of its\n\
+    bounds. A constant out of the bounds is rejected when it is assigned or\n\
+    passed; other values are checked when the program runs under `{$R+}` or\n\
+    `--range-checks`.\n");
+  out.push_str("\n## Sets\n\n\
+    `set of T` takes an ordinal type whose ordinals are within 0..63: Boolean,\n\
+    an enumeration of at most 64 values or a subrange such as `1..10`. `[1, 3..5]`\n\
+    is a set of Integer and `[]` the empty set, which goes with any set.\n\n\
+    | operator | operands | result |\n\
+    |---|---|---|\n\
+    | `in` | an element and a set | Boolean |\n\
+    | `+` `*` `-` | two sets | union, intersection, difference |\n\
+    | `=` `<>` | two sets | Boolean |\n\
+    | `<=` `>=` | two sets | Boolean, subset and superset |\n\n\
+    The elements of both sets are of the same type. An element out of 0..63\n\
+    is an error when the set is built.\n");
+  out
+}
+
+fn spelling(op: &Token) -> String {
+  match *op {
+    Token::Power => "`**` `^`".to_string(),
+    _ => format!("`{}`", op)
+  }
+}
+
+fn cell(result: Result<Category, String>) -> String {
+  match result {
+    Ok(category) => format!("{:?}", category),
+    Err(_) => "-".to_string()
+  }
+}
+
+fn table<F: Fn(Category, Category) -> String>(corner: &str, categories: &[Category], cell: F) -> String {
+  let mut out = format!("| {} |", corner);
+  for category in categories {
+    out.push_str(&format!(" {:?} |", category));
+  }
+  out.push_str(&format!("\n|---{}|\n", "|---".repeat(categories.len())));
+  for &row in categories {
+    out.push_str(&format!("| {:?} |", row));
+    for &column in categories {
+      out.push_str(&format!(" {} |", cell(row, column)));
+    }
+    out.push('\n');
+  }
+  out
+}
+
+#[test]
+fn test_types_relations() {
+  assert!(assignable(Category::Real, Category::Integer));
+  assert!(!assignable(Category::Integer, Category::Real));
+  assert!(!assignable(Category::Procedure, Category::Procedure));
+  assert_eq!(promote(Category::Integer, Category::Real), Some(Category::Real));
+  assert_eq!(promote(Category::Boolean, Category::Integer), None);
+
+  assert_eq!(binary(&Token::Equal, Category::Integer, Category::Real, false), Ok(Category::Boolean));
+  assert_eq!(binary(&Token::Div, Category::Integer, Category::Integer, false), Ok(Category::Real));
+  assert_eq!(binary(&Token::Div, Category::Integer, Category::Integer, true), Ok(Category::Integer));
+  assert_eq!(binary(&Token::Add, Category::Integer, Category::Procedure, false),
+    Err("Type `Procedure` doesn't support arithmetic operator `+`".to_string()));
+  // Boolean and the enumerations are ordinal, so they are ordered
+  assert_eq!(binary(&Token::LessThan, Category::Boolean, Category::Boolean, false), Ok(Category::Boolean));
+  assert_eq!(binary(&Token::GreaterThanOrEqual, Category::Enum(0, 3), Category::Enum(0, 3), false), Ok(Category::Boolean));
+  assert!(binary(&Token::LessThan, Category::Enum(0, 3), Category::Enum(1, 3), false).is_err());
+  assert!(binary(&Token::Add, Category::Enum(0, 3), Category::Enum(0, 3), false).is_err());
+  assert_eq!(builtin(Builtin::Ord, Category::Boolean), Ok(Category::Integer));
+  assert_eq!(builtin(Builtin::Succ, Category::Real), Err("Function `succ` expects an ordinal argument found `Real`".to_string()));
+  assert_eq!(unary(&Token::Sub, Category::Boolean), Err("Type `Boolean` doesn't support arithmetic operator `-`".to_string()));
+
+  // the operators of sets, `[]` goes with any of them
+  let digits = Category::Set(Element::Integer);
+  let empty = Category::Set(Element::Empty);
+  assert_eq!(binary(&Token::In, Category::Integer, digits, false), Ok(Category::Boolean));
+  assert_eq!(binary(&Token::Mult, digits, digits, false), Ok(digits));
+  assert_eq!(binary(&Token::Add, empty, digits, false), Ok(digits));
+  assert_eq!(binary(&Token::LessThanOrEqual, digits, empty, false), Ok(Category::Boolean));
+  assert!(assignable(digits, empty));
+  assert!(!assignable(digits, Category::Set(Element::Boolean)));
+  assert_eq!(binary(&Token::LessThan, digits, digits, false),
+    Err("Type `Set(Integer)` doesn't support relational operator `<`".to_string()));
+  assert_eq!(binary(&Token::In, Category::Boolean, digits, false),
+    Err("Mismatched types `Boolean` is not an element of `Set(Integer)`".to_string()));
+  assert_eq!(binary(&Token::In, Category::Integer, Category::Integer, false),
+    Err("Operator `in` expects a set found `Integer`".to_string()));
+}
+
+#[test]
+fn test_types_matrix_is_up_to_date() {
+  // regenerate with `pascalzim types > docs/types.md`
+  assert_eq!(matrix(), include_str!("../docs/types.md"));
+}
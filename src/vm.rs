@@ -0,0 +1,358 @@
+use bytecode::{Chunk, Instr};
+use diagnostic::Diagnostic;
+use interpreter::{member, set_bits, Value};
+
+struct Frame {
+  routine: usize,
+  slots: Vec<Value>,
+  // frame of the enclosing routine (static link)
+  parent: Option<usize>,
+  return_to: usize
+}
+
+// runs the code produced by `bytecode::compile` with the same results and
+// runtime errors as the interpreter
+pub struct Vm<'a> {
+  chunk: &'a Chunk,
+  stack: Vec<Value>,
+  frames: Vec<Frame>
+}
+
+impl<'a> Vm<'a> {
+  pub fn new(chunk: &'a Chunk) -> Vm<'a> {
+    Vm { chunk, stack: Vec::new(), frames: Vec::new() }
+  }
+
+  pub fn run(&mut self) -> Result<(), Diagnostic> {
+    self.stack.clear();
+    self.frames.clear();
+
+    let program = self.chunk.routines.first().ok_or_else(|| invalid(0))?;
+    let slots = program.slots.iter().map(|slot| Value::default_of(slot.1, slot.2, 0)).collect::<Result<Vec<Value>, Diagnostic>>()?;
+    self.frames.push(Frame { routine: 0, slots, parent: None, return_to: 0 });
+    let mut pc = program.entry as usize;
+
+    loop {
+      let instr = *self.chunk.code.get(pc).ok_or_else(|| invalid(0))?;
+      let line = self.chunk.lines.get(pc).cloned().unwrap_or(0);
+      pc += 1;
+
+      match instr {
+        Instr::Int(i) => self.stack.push(Value::Integer(i)),
+        Instr::Real(r) => self.stack.push(Value::Real(r)),
+        Instr::Bool(b) => self.stack.push(Value::Boolean(b)),
+        Instr::Load(depth, slot) => {
+          let frame = self.up(depth, line)?;
+          let value = *self.frames[frame].slots.get(slot as usize).ok_or_else(|| invalid(line))?;
+          self.stack.push(value);
+        },
+        Instr::Store(depth, slot) => {
+          let value = self.pop(line)?;
+          let frame = self.up(depth, line)?;
+          *self.frames[frame].slots.get_mut(slot as usize).ok_or_else(|| invalid(line))? = value;
+        },
+        Instr::ToReal => {
+          let value = match self.pop(line)? {
+            Value::Integer(i) => Value::Real(i as f64),
+            _ => return Err(invalid(line))
+          };
+          self.stack.push(value);
+        },
+        Instr::Neg => {
+          let value = match self.pop(line)? {
+            Value::Integer(i) => Value::Integer(checked(i.checked_neg(), line)?),
+            Value::Real(r) => Value::Real(-r),
+            _ => return Err(invalid(line))
+          };
+          self.stack.push(value);
+        },
+        Instr::Ord => {
+          let ordinal = self.pop_ordinal(line)?;
+          self.stack.push(Value::Integer(ordinal));
+        },
+        Instr::Succ(last) | Instr::Pred(last) => {
+          let value = match self.pop(line)? {
+            Value::Real(_) => return Err(invalid(line)),
+            value => value
+          };
+          let by = if let Instr::Succ(_) = instr { 1 } else { -1 };
+          self.stack.push(value.step(by, last, line)?);
+        },
+        Instr::Check(first, last) => {
+          let value = self.pop(line)?;
+          if let Value::Set(set) = value {
+            if set & !set_bits(first, last, line)? != 0 {
+              return Err(Diagnostic::runtime("Set element out of range".to_string(), line));
+            }
+          } else {
+            let ordinal = value.ordinal(line)?;
+            if ordinal < first || ordinal > last {
+              return Err(Diagnostic::runtime("Value out of range".to_string(), line));
+            }
+          }
+          self.stack.push(value);
+        },
+        Instr::EmptySet => self.stack.push(Value::Set(0)),
+        Instr::Include | Instr::IncludeRange => {
+          let last = self.pop_ordinal(line)?;
+          let first = if instr == Instr::IncludeRange { self.pop_ordinal(line)? } else { last };
+          let set = self.pop_set(line)?;
+          self.stack.push(Value::Set(set | set_bits(first, last, line)?));
+        },
+        Instr::In => {
+          let set = self.pop_set(line)?;
+          let ordinal = self.pop_ordinal(line)?;
+          self.stack.push(Value::Boolean(member(ordinal, set)));
+        },
+        Instr::Not => {
+          let b = self.pop_bool(line)?;
+          self.stack.push(Value::Boolean(!b));
+        },
+        Instr::And | Instr::Or | Instr::Imp => {
+          let b = self.pop_bool(line)?;
+          let a = self.pop_bool(line)?;
+          self.stack.push(Value::Boolean(match instr {
+            Instr::And => a && b,
+            Instr::Or => a || b,
+            _ => !a || b
+          }));
+        },
+        Instr::Jump(target) => pc = target as usize,
+        Instr::JumpIfFalse(target) => if !self.pop_bool(line)? { pc = target as usize },
+        Instr::JumpIfTrue(target) => if self.pop_bool(line)? { pc = target as usize },
+        Instr::Call(depth, routine) => {
+          let parent = self.up(depth, line)?;
+          let callee = self.chunk.routines.get(routine as usize).ok_or_else(|| invalid(line))?;
+          let params = callee.params as usize;
+          if self.stack.len() < params || callee.slots.len() < params {
+            return Err(invalid(line));
+          }
+          let mut slots = self.stack.split_off(self.stack.len() - params);
+          for slot in callee.slots[params..].iter() {
+            slots.push(Value::default_of(slot.1, slot.2, line)?);
+          }
+          self.frames.push(Frame { routine: routine as usize, slots, parent: Some(parent), return_to: pc });
+          pc = callee.entry as usize;
+        },
+        Instr::Return => {
+          if self.frames.len() < 2 {
+            return Err(invalid(line));
+          }
+          pc = self.frames.pop().unwrap().return_to;
+        },
+        Instr::Halt => return Ok(()),
+        _ => {
+          let b = self.pop(line)?;
+          let a = self.pop(line)?;
+          let value = binary(instr, a, b, line)?;
+          self.stack.push(value);
+        }
+      }
+    }
+  }
+
+  // value of a variable of the program after `run`
+  pub fn value_of(&self, name: &str) -> Option<Value> {
+    let frame = self.frames.first()?;
+    let program = &self.chunk.routines[frame.routine];
+    let name = name.to_lowercase();
+    program.slots.iter().position(|slot| slot.0.to_lowercase() == name).map(|slot| frame.slots[slot])
+  }
+
+  fn up(&self, depth: u8, line: u32) -> Result<usize, Diagnostic> {
+    let mut frame = self.frames.len() - 1;
+    for _ in 0..depth {
+      frame = self.frames[frame].parent.ok_or_else(|| invalid(line))?;
+    }
+    Ok(frame)
+  }
+
+  fn pop(&mut self, line: u32) -> Result<Value, Diagnostic> {
+    self.stack.pop().ok_or_else(|| invalid(line))
+  }
+
+  fn pop_bool(&mut self, line: u32) -> Result<bool, Diagnostic> {
+    match self.pop(line)? {
+      Value::Boolean(b) => Ok(b),
+      _ => Err(invalid(line))
+    }
+  }
+
+  fn pop_ordinal(&mut self, line: u32) -> Result<i32, Diagnostic> {
+    match self.pop(line)? {
+      Value::Integer(i) => Ok(i),
+      Value::Boolean(b) => Ok(b as i32),
+      _ => Err(invalid(line))
+    }
+  }
+
+  fn pop_set(&mut self, line: u32) -> Result<u64, Diagnostic> {
+    match self.pop(line)? {
+      Value::Set(s) => Ok(s),
+      _ => Err(invalid(line))
+    }
+  }
+}
+
+// the compiler never produces code that ends here
+fn invalid(line: u32) -> Diagnostic {
+  Diagnostic::runtime("Invalid bytecode".to_string(), line)
+}
+
+fn checked(result: Option<i32>, line: u32) -> Result<i32, Diagnostic> {
+  result.ok_or_else(|| Diagnostic::runtime("Integer overflow".to_string(), line))
+}
+
+fn binary(instr: Instr, a: Value, b: Value, line: u32) -> Result<Value, Diagnostic> {
+  let division_by_zero = || Diagnostic::runtime("Division by zero".to_string(), line);
+
+  match (a, b) {
+    (Value::Integer(x), Value::Integer(y)) => Ok(match instr {
+      Instr::Add => Value::Integer(checked(x.checked_add(y), line)?),
+      Instr::Sub => Value::Integer(checked(x.checked_sub(y), line)?),
+      Instr::Mul => Value::Integer(checked(x.checked_mul(y), line)?),
+      Instr::Pow => Value::Integer(checked(if y < 0 { None } else { x.checked_pow(y as u32) }, line)?),
+      Instr::Div | Instr::IntDiv | Instr::Mod => {
+        if y == 0 {
+          return Err(division_by_zero());
+        }
+        let result = if instr == Instr::Mod { x.checked_rem(y) } else { x.checked_div(y) };
+        Value::Integer(checked(result, line)?)
+      },
+      _ => compare(instr, x.partial_cmp(&y), line)?
+    }),
+    (Value::Real(x), Value::Real(y)) => Ok(match instr {
+      Instr::Add => Value::Real(x + y),
+      Instr::Sub => Value::Real(x - y),
+      Instr::Mul => Value::Real(x * y),
+      Instr::Pow => Value::Real(x.powf(y)),
+      Instr::Div => {
+        if y == 0.0 {
+          return Err(division_by_zero());
+        }
+        Value::Real(x / y)
+      },
+      _ => compare(instr, x.partial_cmp(&y), line)?
+    }),
+    (Value::Boolean(x), Value::Boolean(y)) => compare(instr, x.partial_cmp(&y), line),
+    (Value::Set(x), Value::Set(y)) => Ok(match instr {
+      Instr::Add => Value::Set(x | y),
+      Instr::Sub => Value::Set(x & !y),
+      Instr::Mul => Value::Set(x & y),
+      Instr::Equal => Value::Boolean(x == y),
+      Instr::NotEqual => Value::Boolean(x != y),
+      // subset and superset
+      Instr::LessEqual => Value::Boolean(x & !y == 0),
+      Instr::GreaterEqual => Value::Boolean(y & !x == 0),
+      _ => return Err(invalid(line))
+    }),
+    _ => Err(invalid(line))
+  }
+}
+
+fn compare(instr: Instr, ordering: Option<::std::cmp::Ordering>, line: u32) -> Result<Value, Diagnostic> {
+  let ordering = match ordering {
+    Some(ordering) => ordering,
+    None => return Err(Diagnostic::runtime(format!("Invalid comparison `{}`", instr), line))
+  };
+  Ok(Value::Boolean(match instr {
+    Instr::Equal => ordering.is_eq(),
+    Instr::NotEqual => ordering.is_ne(),
+    Instr::Less => ordering.is_lt(),
+    Instr::LessEqual => ordering.is_le(),
+    Instr::Greater => ordering.is_gt(),
+    Instr::GreaterEqual => ordering.is_ge(),
+    _ => return Err(invalid(line))
+  }))
+}
+
+#[cfg(test)]
+fn run_file(path: &str, options: ::options::Options) -> (Result<(), Diagnostic>, Vec<(String, Value)>) {
+  use bytecode::compile;
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::with_options(options);
+  p1.build_ast(path);
+  let chunk = compile(p1.program().unwrap()).unwrap();
+  let mut vm = Vm::new(&chunk);
+  let result = vm.run();
+  let values = chunk.routines[0].slots.iter().map(|slot| (slot.0.clone(), vm.value_of(&slot.0).unwrap())).collect();
+  (result, values)
+}
+
+#[test]
+fn test_vm_program23() {
+  let (result, values) = run_file("files/program23.txt", Default::default());
+
+  assert_eq!(result, Ok(()));
+  assert_eq!(values[1], ("f".to_string(), Value::Integer(120)));
+  assert_eq!(values[2], ("media".to_string(), Value::Real(30.0)));
+}
+
+#[test]
+fn test_vm_boolean_eval() {
+  use options::{BooleanEval, Options};
+
+  let (result, values) = run_file("files/program24.txt", Options { boolean_eval: BooleanEval::ShortCircuit, ..Options::default() });
+  assert_eq!(result, Ok(()));
+  assert_eq!(values[2].1, Value::Boolean(false));
+  assert_eq!(values[3].1, Value::Boolean(true));
+
+  let (result, _) = run_file("files/program24.txt", Options::default());
+  assert_eq!(result, Err(Diagnostic::runtime("Division by zero".to_string(), 8)));
+
+  let (result, values) = run_file("files/program38.txt", Options::default());
+  assert_eq!(result, Err(Diagnostic::runtime("Division by zero".to_string(), 11)));
+  assert_eq!(values[2].1, Value::Boolean(false));
+}
+
+#[test]
+fn test_vm_agrees_with_interpreter() {
+  use interpreter::Interpreter;
+  use parser::Parser;
+
+  for path in ["files/program23.txt", "files/program28.txt", "files/program10.txt", "files/program33.txt",
+    "files/program34.txt", "files/program35.txt", "files/program36.txt", "files/program37.txt"] {
+    let mut p1: Parser = Parser::new();
+    p1.build_ast(path);
+    let mut interpreter = Interpreter::new();
+    let expected = interpreter.try_run(p1.program().unwrap());
+
+    let (result, values) = run_file(path, Default::default());
+    assert_eq!(result, expected);
+    for (name, value) in values {
+      assert_eq!(interpreter.value_of(&name), Some(value), "{} in {}", name, path);
+    }
+  }
+}
+
+#[test]
+fn test_vm_subrange_defaults() {
+  use bytecode::compile;
+  use parser::Parser;
+
+  let source = "program p; var d: 5..9; n: integer; procedure q; var e: -3..-1; begin n := e end; begin q end.";
+  let mut p1: Parser = Parser::new();
+  let chunk = compile(p1.parse_str(source).unwrap()).unwrap();
+  let mut vm = Vm::new(&chunk);
+  assert_eq!(vm.run(), Ok(()));
+  assert_eq!(vm.value_of("d"), Some(Value::Integer(5)));
+  assert_eq!(vm.value_of("n"), Some(Value::Integer(-3)));
+}
+
+#[test]
+fn test_vm_range_checks() {
+  use bytecode::compile;
+  use options::Options;
+  use parser::Parser;
+
+  // --range-checks, the same as {$R+}
+  let mut p1: Parser = Parser::with_options(Options { range_checks: true, ..Options::default() });
+  let program = p1.parse_str("program p; var d: 1..10; n: integer; begin n := 0; d := n end.").unwrap();
+  let chunk = compile(program).unwrap();
+  assert_eq!(Vm::new(&chunk).run(), Err(Diagnostic::runtime("Value out of range".to_string(), 1)));
+
+  let program = p1.parse_str("program p; var s: set of 1..3; n: integer; begin n := 0; s := [n..2] end.").unwrap();
+  let chunk = compile(program).unwrap();
+  assert_eq!(Vm::new(&chunk).run(), Err(Diagnostic::runtime("Set element out of range".to_string(), 1)));
+}
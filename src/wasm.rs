@@ -0,0 +1,1207 @@
+use std::collections::HashMap;
+use std::fmt;
+use ast::*;
+use diagnostic::Diagnostic;
+use interpreter::last_ordinal;
+use options::BooleanEval;
+use parser::Category;
+use spec::Token;
+use types;
+
+// the frames live in linear memory below this address and grow down, the
+// strings the program shows start here
+const STACK: u32 = 1 << 20;
+const PAGE: u32 = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValType {
+  I32,
+  I64,
+  F64
+}
+
+impl ValType {
+  fn byte(self) -> u8 {
+    match self {
+      ValType::I32 => 0x7f,
+      ValType::I64 => 0x7e,
+      ValType::F64 => 0x7c
+    }
+  }
+}
+
+impl fmt::Display for ValType {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", format!("{:?}", self).to_lowercase())
+  }
+}
+
+// integers, booleans and enumerations are i32, sets i64 and reals f64
+fn val_type(category: Category) -> ValType {
+  match category {
+    Category::Real => ValType::F64,
+    Category::Set(_) => ValType::I64,
+    _ => ValType::I32
+  }
+}
+
+// the instructions the generator uses, `Block`, `Loop` and `If` have the
+// type of their result, memory accesses the offset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+  Unreachable,
+  Block(Option<ValType>),
+  Loop(Option<ValType>),
+  If(Option<ValType>),
+  Else,
+  End,
+  Br(u32),
+  BrIf(u32),
+  Call(u32),
+  LocalGet(u32),
+  LocalSet(u32),
+  LocalTee(u32),
+  GlobalGet(u32),
+  GlobalSet(u32),
+  I32Load(u32),
+  I64Load(u32),
+  F64Load(u32),
+  I32Store(u32),
+  I64Store(u32),
+  F64Store(u32),
+  I32Store8(u32),
+  I32Const(i32),
+  I64Const(i64),
+  F64Const(f64),
+  I32Eqz,
+  I32Eq,
+  I32Ne,
+  I32LtS,
+  I32GtU,
+  I32GtS,
+  I32LeS,
+  I32GeS,
+  I64Eqz,
+  I64Eq,
+  I64Ne,
+  I64LtS,
+  I64GtS,
+  F64Eq,
+  F64Ne,
+  F64Lt,
+  F64Gt,
+  F64Le,
+  F64Ge,
+  I32Add,
+  I32Sub,
+  I32Mul,
+  I32RemS,
+  I32And,
+  I32Or,
+  I32ShrU,
+  I64Add,
+  I64Sub,
+  I64Mul,
+  I64DivS,
+  I64RemU,
+  I64And,
+  I64Or,
+  I64Xor,
+  I64Shl,
+  I64ShrU,
+  F64Neg,
+  F64Add,
+  F64Sub,
+  F64Mul,
+  F64Div,
+  I32WrapI64,
+  I64ExtendI32S,
+  I64ExtendI32U,
+  F64ConvertI32S
+}
+
+// opcodes and names of the instructions without immediates
+const SIMPLE: [(Op, u8, &str); 48] = [
+  (Op::Unreachable, 0x00, "unreachable"), (Op::Else, 0x05, "else"), (Op::End, 0x0b, "end"),
+  (Op::I32Eqz, 0x45, "i32.eqz"), (Op::I32Eq, 0x46, "i32.eq"), (Op::I32Ne, 0x47, "i32.ne"),
+  (Op::I32LtS, 0x48, "i32.lt_s"), (Op::I32GtS, 0x4a, "i32.gt_s"), (Op::I32GtU, 0x4b, "i32.gt_u"),
+  (Op::I32LeS, 0x4c, "i32.le_s"), (Op::I32GeS, 0x4e, "i32.ge_s"), (Op::I64Eqz, 0x50, "i64.eqz"),
+  (Op::I64Eq, 0x51, "i64.eq"), (Op::I64Ne, 0x52, "i64.ne"), (Op::I64LtS, 0x53, "i64.lt_s"),
+  (Op::I64GtS, 0x55, "i64.gt_s"), (Op::F64Eq, 0x61, "f64.eq"), (Op::F64Ne, 0x62, "f64.ne"),
+  (Op::F64Lt, 0x63, "f64.lt"), (Op::F64Gt, 0x64, "f64.gt"), (Op::F64Le, 0x65, "f64.le"),
+  (Op::F64Ge, 0x66, "f64.ge"), (Op::I32Add, 0x6a, "i32.add"), (Op::I32Sub, 0x6b, "i32.sub"),
+  (Op::I32Mul, 0x6c, "i32.mul"), (Op::I32RemS, 0x6f, "i32.rem_s"), (Op::I32And, 0x71, "i32.and"),
+  (Op::I32Or, 0x72, "i32.or"), (Op::I32ShrU, 0x76, "i32.shr_u"), (Op::I64Add, 0x7c, "i64.add"),
+  (Op::I64Sub, 0x7d, "i64.sub"), (Op::I64Mul, 0x7e, "i64.mul"), (Op::I64DivS, 0x7f, "i64.div_s"),
+  (Op::I64RemU, 0x82, "i64.rem_u"), (Op::I64And, 0x83, "i64.and"), (Op::I64Or, 0x84, "i64.or"),
+  (Op::I64Xor, 0x85, "i64.xor"), (Op::I64Shl, 0x86, "i64.shl"), (Op::I64ShrU, 0x88, "i64.shr_u"),
+  (Op::F64Neg, 0x9a, "f64.neg"), (Op::F64Add, 0xa0, "f64.add"), (Op::F64Sub, 0xa1, "f64.sub"),
+  (Op::F64Mul, 0xa2, "f64.mul"), (Op::F64Div, 0xa3, "f64.div"), (Op::I32WrapI64, 0xa7, "i32.wrap_i64"),
+  (Op::I64ExtendI32S, 0xac, "i64.extend_i32_s"), (Op::I64ExtendI32U, 0xad, "i64.extend_i32_u"),
+  (Op::F64ConvertI32S, 0xb7, "f64.convert_i32_s")
+];
+
+pub struct Function {
+  pub name: String,
+  pub params: Vec<ValType>,
+  pub result: Option<ValType>,
+  pub locals: Vec<ValType>,
+  pub body: Vec<Op>
+}
+
+// functions the host gives the module, in the module `env`: `write` the
+// bytes at an address to the output, `write_real` the shortest digits that
+// read back as a real, without exponent, `error` shows a runtime error, the
+// message at an address and the line, and ends the program with exit code 3
+// as `pascalzim run` does, `pow` is the power of two reals
+const IMPORTS: [(&str, &[ValType], Option<ValType>); 4] = [
+  ("write", &[ValType::I32, ValType::I32], None),
+  ("write_real", &[ValType::F64], None),
+  ("error", &[ValType::I32, ValType::I32, ValType::I32], None),
+  ("pow", &[ValType::F64, ValType::F64], Some(ValType::F64))
+];
+
+const WRITE: u32 = 0;
+const WRITE_REAL: u32 = 1;
+const ERROR: u32 = 2;
+const POW: u32 = 3;
+
+// the runtime support, numbered after the imports
+const CHECK: u32 = 4;
+const DIV: u32 = 5;
+const MOD: u32 = 6;
+const IPOW: u32 = 7;
+const SUCC: u32 = 8;
+const PRED: u32 = 9;
+const RANGE: u32 = 10;
+const BITS: u32 = 11;
+const RANGE_SET: u32 = 12;
+const IN: u32 = 13;
+const RDIV: u32 = 14;
+const WRITE_INT: u32 = 15;
+const WRITE_NAME: u32 = 16;
+const WRITE_SET: u32 = 17;
+const RUNTIME: u32 = 18;
+
+pub struct Module {
+  pub name: String,
+  // the imports are not in the list
+  pub functions: Vec<Function>,
+  // loaded at `STACK`
+  pub data: Vec<u8>,
+  // index of the function run by the host
+  pub main: u32
+}
+
+struct Scope {
+  // slot and type of each variable
+  variables: HashMap<String, (u32, Category)>,
+  // function and parameter types of each procedure
+  procedures: HashMap<String, (u32, Vec<Category>)>
+}
+
+struct Generator {
+  functions: Vec<Function>,
+  data: Vec<u8>,
+  strings: HashMap<String, (i32, i32)>,
+  scopes: Vec<Scope>,
+  // names of the values of each enumeration and of false and true, as
+  // addresses of (address, length) pairs
+  tables: Vec<i32>,
+  booleans: i32
+}
+
+// WebAssembly module of a program, see `Module::to_bytes` and the text of
+// `Display`. The frames are in linear memory, pointed by the global 0
+// (stack pointer): the frame of the enclosing routine (static link) first,
+// then the variables, 8 bytes each. The host calls the export `main`.
+pub fn generate(program: &Program) -> Result<Module, Diagnostic> {
+  let mut generator = Generator {
+    functions: Vec::new(),
+    data: Vec::new(),
+    strings: HashMap::new(),
+    scopes: Vec::new(),
+    tables: Vec::new(),
+    booleans: 0
+  };
+  generator.booleans = generator.table(&["false".to_string(), "true".to_string()]);
+  for enumeration in program.enums.iter() {
+    let table = generator.table(&enumeration.values);
+    generator.tables.push(table);
+  }
+  generator.runtime();
+  generator.functions.push(Function { name: "main".to_string(), params: Vec::new(), result: None, locals: Vec::new(), body: Vec::new() });
+  generator.routine(RUNTIME, &[], &program.block)?;
+
+  Ok(Module { name: program.name.clone(), functions: generator.functions, data: generator.data, main: RUNTIME })
+}
+
+fn key(name: &str) -> String {
+  name.to_lowercase()
+}
+
+// Pascal names are case-insensitive and may have non-ASCII letters
+fn function_name(name: &str) -> String {
+  key(name).chars().map(|c| {
+    if c.is_ascii_alphanumeric() || c == '_' { c.to_string() } else { format!("_u{:04x}", c as u32) }
+  }).collect()
+}
+
+fn offset(slot: u32) -> u32 {
+  8 + 8 * slot
+}
+
+fn load(category: Category, offset: u32) -> Op {
+  match val_type(category) {
+    ValType::I32 => Op::I32Load(offset),
+    ValType::I64 => Op::I64Load(offset),
+    ValType::F64 => Op::F64Load(offset)
+  }
+}
+
+fn store(category: Category, offset: u32) -> Op {
+  match val_type(category) {
+    ValType::I32 => Op::I32Store(offset),
+    ValType::I64 => Op::I64Store(offset),
+    ValType::F64 => Op::F64Store(offset)
+  }
+}
+
+impl Generator {
+  // address and length of `text` in memory, each text is stored once
+  fn string(&mut self, text: &str) -> (i32, i32) {
+    if let Some(&found) = self.strings.get(text) {
+      return found;
+    }
+    let found = ((STACK as usize + self.data.len()) as i32, text.len() as i32);
+    self.data.extend_from_slice(text.as_bytes());
+    self.strings.insert(text.to_string(), found);
+    found
+  }
+
+  fn table(&mut self, names: &[String]) -> i32 {
+    let strings: Vec<(i32, i32)> = names.iter().map(|name| self.string(name)).collect();
+    while self.data.len() % 4 != 0 {
+      self.data.push(0);
+    }
+    let table = (STACK as usize + self.data.len()) as i32;
+    for (address, length) in strings {
+      self.data.extend_from_slice(&address.to_le_bytes());
+      self.data.extend_from_slice(&length.to_le_bytes());
+    }
+    table
+  }
+
+  fn write(&mut self, text: &str, body: &mut Vec<Op>) {
+    let (address, length) = self.string(text);
+    body.extend_from_slice(&[Op::I32Const(address), Op::I32Const(length), Op::Call(WRITE)]);
+  }
+
+  // does not return, `line` pushes the line
+  fn fail(&mut self, message: &str, line: Op, body: &mut Vec<Op>) {
+    let (address, length) = self.string(message);
+    body.extend_from_slice(&[Op::I32Const(address), Op::I32Const(length), line, Op::Call(ERROR), Op::Unreachable]);
+  }
+
+  fn function(&mut self, name: &str, params: &[ValType], result: Option<ValType>, locals: &[ValType], body: Vec<Op>) {
+    self.functions.push(Function { name: name.to_string(), params: params.to_vec(), result, locals: locals.to_vec(), body });
+  }
+
+  // what the prelude of `cgen` does, in the order of the constants above
+  fn runtime(&mut self) {
+    use self::Op::*;
+    use self::ValType::*;
+
+    // $check (value i64, line i32) -> i32
+    let mut body = vec![LocalGet(0), I64Const(i32::MIN as i64), I64LtS, LocalGet(0), I64Const(i32::MAX as i64), I64GtS, I32Or, If(None)];
+    self.fail("Integer overflow", LocalGet(1), &mut body);
+    body.extend_from_slice(&[End, LocalGet(0), I32WrapI64]);
+    self.function("check", &[I64, I32], Some(I32), &[], body);
+
+    // $div (a i32, b i32, line i32) -> i32
+    let mut body = vec![LocalGet(1), I32Eqz, If(None)];
+    self.fail("Division by zero", LocalGet(2), &mut body);
+    body.extend_from_slice(&[End, LocalGet(0), I64ExtendI32S, LocalGet(1), I64ExtendI32S, I64DivS, LocalGet(2), Call(CHECK)]);
+    self.function("div", &[I32, I32, I32], Some(I32), &[], body);
+
+    // $mod (a i32, b i32, line i32) -> i32
+    let mut body = vec![LocalGet(1), I32Eqz, If(None)];
+    self.fail("Division by zero", LocalGet(2), &mut body);
+    body.extend_from_slice(&[End, LocalGet(0), I32Const(i32::MIN), I32Eq, LocalGet(1), I32Const(-1), I32Eq, I32And, If(None)]);
+    self.fail("Integer overflow", LocalGet(2), &mut body);
+    body.extend_from_slice(&[End, LocalGet(0), LocalGet(1), I32RemS]);
+    self.function("mod", &[I32, I32, I32], Some(I32), &[], body);
+
+    // $ipow (base i32, exp i32, line i32) -> i32, local 3 is the result
+    let mut body = vec![LocalGet(1), I32Const(0), I32LtS, If(None)];
+    self.fail("Integer overflow", LocalGet(2), &mut body);
+    body.extend_from_slice(&[
+      End, I32Const(1), LocalSet(3),
+      LocalGet(1), I32Eqz, If(None), I32Const(1), Br(1), End,
+      Block(None), Loop(None),
+      LocalGet(1), I32Const(1), I32LeS, BrIf(1),
+      LocalGet(1), I32Const(1), I32And, If(None),
+      LocalGet(3), I64ExtendI32S, LocalGet(0), I64ExtendI32S, I64Mul, LocalGet(2), Call(CHECK), LocalSet(3),
+      End,
+      LocalGet(1), I32Const(1), I32ShrU, LocalSet(1),
+      LocalGet(0), I64ExtendI32S, LocalGet(0), I64ExtendI32S, I64Mul, LocalGet(2), Call(CHECK), LocalSet(0),
+      Br(0), End, End,
+      LocalGet(3), I64ExtendI32S, LocalGet(0), I64ExtendI32S, I64Mul, LocalGet(2), Call(CHECK)
+    ]);
+    self.function("ipow", &[I32, I32, I32], Some(I32), &[I32], body);
+
+    // $succ and $pred (value i32, last i32, line i32) -> i32, `last` is the
+    // highest ordinal of the type
+    let mut body = vec![LocalGet(0), LocalGet(1), I32GeS, If(None), LocalGet(1), I32Const(i32::MAX), I32Eq, If(None)];
+    self.fail("Integer overflow", LocalGet(2), &mut body);
+    body.extend_from_slice(&[End]);
+    self.fail("Value out of range in `succ`", LocalGet(2), &mut body);
+    body.extend_from_slice(&[End, LocalGet(0), I32Const(1), I32Add]);
+    self.function("succ", &[I32, I32, I32], Some(I32), &[], body);
+
+    let mut body = vec![LocalGet(1), I32Const(i32::MAX), I32Eq, If(None), LocalGet(0), I32Const(i32::MIN), I32Eq, If(None)];
+    self.fail("Integer overflow", LocalGet(2), &mut body);
+    body.extend_from_slice(&[End, Else, LocalGet(0), I32Const(0), I32LeS, If(None)]);
+    self.fail("Value out of range in `pred`", LocalGet(2), &mut body);
+    body.extend_from_slice(&[End, End, LocalGet(0), I32Const(1), I32Sub]);
+    self.function("pred", &[I32, I32, I32], Some(I32), &[], body);
+
+    // $range (value i32, first i32, last i32, line i32) -> i32, `{$R+}`
+    let mut body = vec![LocalGet(0), LocalGet(1), I32LtS, LocalGet(0), LocalGet(2), I32GtS, I32Or, If(None)];
+    self.fail("Value out of range", LocalGet(3), &mut body);
+    body.extend_from_slice(&[End, LocalGet(0)]);
+    self.function("range", &[I32, I32, I32, I32], Some(I32), &[], body);
+
+    // $bits (first i32, last i32, line i32) -> i64, sets are bitsets, bit
+    // `n` for the ordinal `n`
+    let mut body = vec![LocalGet(1), LocalGet(0), I32LtS, If(None), I64Const(0), Br(1), End,
+      LocalGet(0), I32Const(0), I32LtS, LocalGet(1), I32Const(types::SET_SIZE - 1), I32GtS, I32Or, If(None)];
+    self.fail("Set element out of range", LocalGet(2), &mut body);
+    body.extend_from_slice(&[End,
+      I64Const(-1), I32Const(types::SET_SIZE - 1), LocalGet(1), I32Sub, LocalGet(0), I32Add, I64ExtendI32U, I64ShrU,
+      LocalGet(0), I64ExtendI32U, I64Shl]);
+    self.function("bits", &[I32, I32, I32], Some(I64), &[], body);
+
+    // $range_set (set i64, first i32, last i32, line i32) -> i64
+    let mut body = vec![LocalGet(0), LocalGet(1), LocalGet(2), LocalGet(3), Call(BITS), I64Const(-1), I64Xor, I64And, I64Eqz, I32Eqz, If(None)];
+    self.fail("Set element out of range", LocalGet(3), &mut body);
+    body.extend_from_slice(&[End, LocalGet(0)]);
+    self.function("range_set", &[I64, I32, I32, I32], Some(I64), &[], body);
+
+    // $in (value i32, set i64) -> i32
+    let body = vec![LocalGet(0), I32Const(types::SET_SIZE - 1), I32GtU, If(Some(I32)), I32Const(0), Else,
+      LocalGet(1), LocalGet(0), I64ExtendI32U, I64ShrU, I64Const(1), I64And, I32WrapI64, End];
+    self.function("in", &[I32, I64], Some(I32), &[], body);
+
+    // $rdiv (a f64, b f64, line i32) -> f64
+    let mut body = vec![LocalGet(1), F64Const(0.0), F64Eq, If(None)];
+    self.fail("Division by zero", LocalGet(2), &mut body);
+    body.extend_from_slice(&[End, LocalGet(0), LocalGet(1), F64Div]);
+    self.function("rdiv", &[F64, F64, I32], Some(F64), &[], body);
+
+    // $write_int (value i32), the digits are written backwards in 12 bytes
+    // kept for them; local 1 is the value without sign and local 2 where
+    // the digits start
+    let end = (STACK as usize + self.data.len() + 12) as i32;
+    self.data.extend_from_slice(&[0; 12]);
+    let body = vec![
+      LocalGet(0), I64ExtendI32S, LocalSet(1), I32Const(end), LocalSet(2),
+      LocalGet(0), I32Const(0), I32LtS, If(None), I64Const(0), LocalGet(1), I64Sub, LocalSet(1), End,
+      Loop(None),
+      LocalGet(2), I32Const(1), I32Sub, LocalTee(2),
+      LocalGet(1), I64Const(10), I64RemU, I32WrapI64, I32Const(b'0' as i32), I32Add, I32Store8(0),
+      LocalGet(1), I64Const(10), I64DivS, LocalTee(1), I64Eqz, I32Eqz, BrIf(0),
+      End,
+      LocalGet(0), I32Const(0), I32LtS, If(None),
+      LocalGet(2), I32Const(1), I32Sub, LocalTee(2), I32Const(b'-' as i32), I32Store8(0), End,
+      LocalGet(2), I32Const(end), LocalGet(2), I32Sub, Call(WRITE)
+    ];
+    self.function("write_int", &[I32], None, &[I64, I32], body);
+
+    // $write_name (table i32, index i32)
+    let body = vec![
+      LocalGet(0), LocalGet(1), I32Const(8), I32Mul, I32Add, I32Load(0),
+      LocalGet(0), LocalGet(1), I32Const(8), I32Mul, I32Add, I32Load(4), Call(WRITE)
+    ];
+    self.function("write_name", &[I32, I32], None, &[], body);
+
+    // $write_set (set i64, table i32), the numbers when there is no table;
+    // local 2 is the ordinal and local 3 is 1 before the first element
+    let mut body = vec![I32Const(1), LocalSet(3), Block(None), Loop(None),
+      LocalGet(2), I32Const(types::SET_SIZE), I32GeS, BrIf(1),
+      LocalGet(2), LocalGet(0), Call(IN), If(None),
+      LocalGet(3), I32Eqz, If(None)];
+    self.write(", ", &mut body);
+    body.extend_from_slice(&[End, I32Const(0), LocalSet(3),
+      LocalGet(1), If(None), LocalGet(1), LocalGet(2), Call(WRITE_NAME), Else, LocalGet(2), Call(WRITE_INT), End,
+      End,
+      LocalGet(2), I32Const(1), I32Add, LocalSet(2), Br(0), End, End]);
+    let mut with_brackets = Vec::new();
+    self.write("[", &mut with_brackets);
+    with_brackets.extend(body);
+    self.write("]", &mut with_brackets);
+    self.function("write_set", &[I64, I32], None, &[I32, I32], with_brackets);
+  }
+
+  // `index` is the function of the routine, already in the list, and
+  // RUNTIME for the program
+  fn routine(&mut self, index: u32, params: &[Declaration], block: &Block) -> Result<(), Diagnostic> {
+    let mut scope = Scope { variables: HashMap::new(), procedures: HashMap::new() };
+    let mut slots = Vec::new();
+    for decl in params.iter().chain(block.variables.iter()) {
+      for name in decl.names.iter() {
+        scope.variables.insert(key(name), (slots.len() as u32, decl.category));
+        slots.push(decl);
+      }
+    }
+    for procedure in block.procedures.iter() {
+      let function = (IMPORTS.len() + self.functions.len()) as u32;
+      let categories: Vec<Category> = procedure.params.iter().flat_map(|decl| decl.names.iter().map(move |_| decl.category)).collect();
+      let mut types = vec![ValType::I32];
+      types.extend(categories.iter().map(|&category| val_type(category)));
+      let name = format!("p{}_{}", function, function_name(&procedure.name));
+      self.function(&name, &types, None, &[ValType::I32], Vec::new());
+      scope.procedures.insert(key(&procedure.name), (function, categories));
+    }
+    self.scopes.push(scope);
+
+    // the static link, then the parameters, local `fr` is the frame
+    let count = params.iter().map(|decl| decl.names.len()).sum::<usize>() as u32;
+    let fr = if index == RUNTIME { 0 } else { count + 1 };
+    let size = offset(slots.len() as u32) as i32;
+    let mut body = vec![
+      Op::GlobalGet(0), Op::I32Const(size), Op::I32Sub, Op::LocalTee(fr), Op::GlobalSet(0),
+      Op::LocalGet(fr), if index == RUNTIME { Op::I32Const(0) } else { Op::LocalGet(0) }, Op::I32Store(0)
+    ];
+    for (slot, decl) in slots.iter().enumerate() {
+      let slot = slot as u32;
+      body.push(Op::LocalGet(fr));
+      if slot < count {
+        body.push(Op::LocalGet(slot + 1));
+      } else {
+        // zero for every variable but the subranges that leave out 0,
+        // which start at their lower bound
+        body.push(match (val_type(decl.category), decl.range) {
+          (ValType::F64, _) => Op::F64Const(0.0),
+          (ValType::I64, _) => Op::I64Const(0),
+          (ValType::I32, Some(range)) => Op::I32Const(range.first),
+          (ValType::I32, None) => Op::I32Const(0)
+        });
+      }
+      body.push(store(decl.category, offset(slot)));
+    }
+
+    for command in block.body.iter() {
+      self.command(command, fr, &mut body)?;
+    }
+    if index == RUNTIME {
+      self.show(block, &mut body);
+    }
+    body.extend_from_slice(&[Op::LocalGet(fr), Op::I32Const(size), Op::I32Add, Op::GlobalSet(0)]);
+
+    let function = &mut self.functions[index as usize - IMPORTS.len()];
+    function.body = body;
+    if index == RUNTIME {
+      function.locals = vec![ValType::I32];
+    }
+
+    for procedure in block.procedures.iter() {
+      let function = self.scopes.last().unwrap().procedures[&key(&procedure.name)].0;
+      self.routine(function, &procedure.params, &procedure.block)?;
+    }
+    self.scopes.pop();
+    Ok(())
+  }
+
+  fn show(&mut self, block: &Block, body: &mut Vec<Op>) {
+    for decl in block.variables.iter() {
+      for name in decl.names.iter() {
+        let (slot, _) = self.scopes[0].variables[&key(name)];
+        self.write(&format!("{} = ", name), body);
+        let value = [Op::LocalGet(0), load(decl.category, offset(slot))];
+        match decl.category {
+          Category::Enum(id, _) => body.push(Op::I32Const(self.tables[id as usize])),
+          Category::Boolean => body.push(Op::I32Const(self.booleans)),
+          _ => {}
+        }
+        body.extend_from_slice(&value);
+        match decl.category {
+          Category::Real => body.push(Op::Call(WRITE_REAL)),
+          Category::Enum(..) | Category::Boolean => body.push(Op::Call(WRITE_NAME)),
+          Category::Set(_) => {
+            let table = match types::element_of(decl.category) {
+              Some(Category::Enum(id, _)) => self.tables[id as usize],
+              Some(Category::Boolean) => self.booleans,
+              _ => 0
+            };
+            body.extend_from_slice(&[Op::I32Const(table), Op::Call(WRITE_SET)]);
+          },
+          _ => body.push(Op::Call(WRITE_INT))
+        }
+        self.write("\n", body);
+      }
+    }
+  }
+
+  // pushes the frame `depth` routines out
+  fn frame(&self, depth: usize, fr: u32, body: &mut Vec<Op>) {
+    body.push(Op::LocalGet(fr));
+    for _ in 0..depth {
+      body.push(Op::I32Load(0));
+    }
+  }
+
+  fn variable(&self, name: &str, line: u32) -> Result<(usize, u32, Category), Diagnostic> {
+    for (depth, scope) in self.scopes.iter().rev().enumerate() {
+      if let Some(&(slot, category)) = scope.variables.get(&key(name)) {
+        return Ok((depth, slot, category));
+      }
+    }
+    Err(Diagnostic::error(format!("Identifier `{}` not declared", name), line))
+  }
+
+  // an integer becomes a real where a real is expected
+  fn value(&mut self, expr: &Expr, category: Category, fr: u32, body: &mut Vec<Op>) -> Result<(), Diagnostic> {
+    self.expr(expr, fr, body)?;
+    if category == Category::Real && expr.category == Category::Integer {
+      body.push(Op::F64ConvertI32S);
+    }
+    Ok(())
+  }
+
+  fn command(&mut self, command: &Command, fr: u32, body: &mut Vec<Op>) -> Result<(), Diagnostic> {
+    match *command {
+      Command::Assign { ref name, ref value, line } => {
+        let (depth, slot, category) = self.variable(name, line)?;
+        self.frame(depth, fr, body);
+        self.value(value, category, fr, body)?;
+        body.push(store(category, offset(slot)));
+      },
+      Command::Call { ref name, ref args, line } => {
+        let found = self.scopes.iter().rev().enumerate()
+          .find_map(|(depth, scope)| scope.procedures.get(&key(name)).map(|procedure| (depth, procedure.clone())));
+        let (depth, (function, params)) = found
+          .ok_or_else(|| Diagnostic::error(format!("Procedure `{}` not declared", name), line))?;
+        self.frame(depth, fr, body);
+        for (arg, &category) in args.iter().zip(params.iter()) {
+          self.value(arg, category, fr, body)?;
+        }
+        body.push(Op::Call(function));
+      },
+      Command::Compound(ref commands) => {
+        for command in commands.iter() {
+          self.command(command, fr, body)?;
+        }
+      },
+      Command::If { ref condition, ref then_branch, ref else_branch, .. } => {
+        self.expr(condition, fr, body)?;
+        body.push(Op::If(None));
+        self.command(then_branch, fr, body)?;
+        if let Some(ref command) = *else_branch {
+          body.push(Op::Else);
+          self.command(command, fr, body)?;
+        }
+        body.push(Op::End);
+      },
+      Command::While { ref condition, body: ref command, .. } => {
+        body.extend_from_slice(&[Op::Block(None), Op::Loop(None)]);
+        self.expr(condition, fr, body)?;
+        body.extend_from_slice(&[Op::I32Eqz, Op::BrIf(1)]);
+        self.command(command, fr, body)?;
+        body.extend_from_slice(&[Op::Br(0), Op::End, Op::End]);
+      }
+    }
+    Ok(())
+  }
+
+  fn expr(&mut self, expr: &Expr, fr: u32, body: &mut Vec<Op>) -> Result<(), Diagnostic> {
+    let line = Op::I32Const(expr.line as i32);
+
+    match expr.kind {
+      ExprKind::Integer(i) => body.push(Op::I32Const(i)),
+      ExprKind::Real(r) => body.push(Op::F64Const(r)),
+      ExprKind::Boolean(b) => body.push(Op::I32Const(b as i32)),
+      ExprKind::Constant(_, ordinal) => body.push(Op::I32Const(ordinal)),
+      ExprKind::Variable(ref name) => {
+        let (depth, slot, category) = self.variable(name, expr.line)?;
+        self.frame(depth, fr, body);
+        body.push(load(category, offset(slot)));
+      },
+      ExprKind::Call(ref name, _) => {
+        return Err(Diagnostic::error(format!("Procedure `{}` doesn't return a value", name), expr.line));
+      },
+      ExprKind::Builtin(function, ref argument) => {
+        self.expr(argument, fr, body)?;
+        let last = Op::I32Const(last_ordinal(argument.category));
+        match function {
+          // booleans are already 0 or 1
+          Builtin::Ord => {},
+          Builtin::Succ => body.extend_from_slice(&[last, line, Op::Call(SUCC)]),
+          Builtin::Pred => body.extend_from_slice(&[last, line, Op::Call(PRED)])
+        }
+      },
+      ExprKind::RangeCheck(range, ref value) => {
+        self.expr(value, fr, body)?;
+        let function = if types::is_set(value.category) { RANGE_SET } else { RANGE };
+        body.extend_from_slice(&[Op::I32Const(range.first), Op::I32Const(range.last), line, Op::Call(function)]);
+      },
+      ExprKind::Set(ref items) => {
+        body.push(Op::I64Const(0));
+        for (first, last) in items {
+          self.expr(first, fr, body)?;
+          self.expr(last.as_ref().unwrap_or(first), fr, body)?;
+          body.extend_from_slice(&[line, Op::Call(BITS), Op::I64Or]);
+        }
+      },
+      ExprKind::Unary(ref op, ref operand) => {
+        self.expr(operand, fr, body)?;
+        match *op {
+          Token::Not => body.push(Op::I32Eqz),
+          Token::Sub if operand.category == Category::Integer => {
+            body.extend_from_slice(&[Op::I64ExtendI32S, Op::I64Const(-1), Op::I64Mul, line, Op::Call(CHECK)]);
+          },
+          Token::Sub => body.push(Op::F64Neg),
+          _ => {}
+        }
+      },
+      ExprKind::Logical(ref op, mode, ref lhs, ref rhs) => {
+        self.expr(lhs, fr, body)?;
+        if mode == BooleanEval::Full {
+          if *op == Token::Imp {
+            body.push(Op::I32Eqz);
+          }
+          self.expr(rhs, fr, body)?;
+          body.push(if *op == Token::And { Op::I32And } else { Op::I32Or });
+        } else {
+          // the left operand alone may decide the result
+          body.push(Op::If(Some(ValType::I32)));
+          if *op == Token::Or {
+            body.extend_from_slice(&[Op::I32Const(1), Op::Else]);
+            self.expr(rhs, fr, body)?;
+          } else {
+            self.expr(rhs, fr, body)?;
+            body.extend_from_slice(&[Op::Else, Op::I32Const((*op == Token::Imp) as i32)]);
+          }
+          body.push(Op::End);
+        }
+      },
+      ExprKind::Binary(ref op, ref lhs, ref rhs) => self.binary(op, lhs, rhs, expr, fr, body)?
+    }
+    Ok(())
+  }
+
+  fn binary(&mut self, op: &Token, lhs: &Expr, rhs: &Expr, expr: &Expr, fr: u32, body: &mut Vec<Op>) -> Result<(), Diagnostic> {
+    let line = Op::I32Const(expr.line as i32);
+    // both operands become reals when either side or the result is real
+    let real = [lhs.category, rhs.category, expr.category].contains(&Category::Real);
+    let category = if real { Category::Real } else { lhs.category };
+    // integer arithmetic is done on i64 and then checked
+    let wide = !real && [Token::Add, Token::Sub, Token::Mult].contains(op) && !types::is_set(lhs.category);
+
+    self.value(lhs, category, fr, body)?;
+    if wide {
+      body.push(Op::I64ExtendI32S);
+    }
+    // the superset is the subset the other way around
+    if types::is_set(lhs.category) && *op == Token::GreaterThanOrEqual {
+      body.extend_from_slice(&[Op::I64Const(-1), Op::I64Xor]);
+    }
+    self.value(rhs, category, fr, body)?;
+    if wide {
+      body.push(Op::I64ExtendI32S);
+    }
+
+    if types::is_set(lhs.category) {
+      body.extend_from_slice(match *op {
+        Token::Add => &[Op::I64Or],
+        Token::Mult => &[Op::I64And],
+        Token::Sub => &[Op::I64Const(-1), Op::I64Xor, Op::I64And],
+        Token::Equal => &[Op::I64Eq],
+        Token::NotEqual => &[Op::I64Ne],
+        Token::LessThanOrEqual => &[Op::I64Const(-1), Op::I64Xor, Op::I64And, Op::I64Eqz],
+        _ => &[Op::I64And, Op::I64Eqz]
+      });
+      return Ok(());
+    }
+
+    let ops: &[Op] = match *op {
+      Token::In => &[Op::Call(IN)],
+      Token::Equal | Token::Equiv if real => &[Op::F64Eq],
+      Token::NotEqual | Token::Xor if real => &[Op::F64Ne],
+      Token::LessThan if real => &[Op::F64Lt],
+      Token::LessThanOrEqual if real => &[Op::F64Le],
+      Token::GreaterThan if real => &[Op::F64Gt],
+      Token::GreaterThanOrEqual if real => &[Op::F64Ge],
+      Token::Equal | Token::Equiv => &[Op::I32Eq],
+      Token::NotEqual | Token::Xor => &[Op::I32Ne],
+      Token::LessThan => &[Op::I32LtS],
+      Token::LessThanOrEqual => &[Op::I32LeS],
+      Token::GreaterThan => &[Op::I32GtS],
+      Token::GreaterThanOrEqual => &[Op::I32GeS],
+      Token::Add if real => &[Op::F64Add],
+      Token::Sub if real => &[Op::F64Sub],
+      Token::Mult if real => &[Op::F64Mul],
+      Token::Div if real => &[line, Op::Call(RDIV)],
+      _ if real => &[Op::Call(POW)],
+      Token::Add => &[Op::I64Add, line, Op::Call(CHECK)],
+      Token::Sub => &[Op::I64Sub, line, Op::Call(CHECK)],
+      Token::Mult => &[Op::I64Mul, line, Op::Call(CHECK)],
+      Token::Div | Token::IntDiv => &[line, Op::Call(DIV)],
+      Token::Mod => &[line, Op::Call(MOD)],
+      _ => &[line, Op::Call(IPOW)]
+    };
+    body.extend_from_slice(ops);
+    Ok(())
+  }
+}
+
+fn uleb(out: &mut Vec<u8>, mut n: u64) {
+  loop {
+    let byte = (n & 0x7f) as u8;
+    n >>= 7;
+    if n == 0 {
+      out.push(byte);
+      return;
+    }
+    out.push(byte | 0x80);
+  }
+}
+
+fn sleb(out: &mut Vec<u8>, mut n: i64) {
+  loop {
+    let byte = (n & 0x7f) as u8;
+    n >>= 7;
+    if (n == 0 && byte & 0x40 == 0) || (n == -1 && byte & 0x40 != 0) {
+      out.push(byte);
+      return;
+    }
+    out.push(byte | 0x80);
+  }
+}
+
+fn put_name(out: &mut Vec<u8>, name: &str) {
+  uleb(out, name.len() as u64);
+  out.extend_from_slice(name.as_bytes());
+}
+
+// a section is its id and its contents with their length
+fn put_section(out: &mut Vec<u8>, id: u8, contents: Vec<u8>) {
+  out.push(id);
+  uleb(out, contents.len() as u64);
+  out.extend(contents);
+}
+
+fn put_block_type(out: &mut Vec<u8>, result: Option<ValType>) {
+  out.push(result.map_or(0x40, ValType::byte));
+}
+
+// the alignment of a memory access is its natural one, as a power of 2
+fn put_memory(out: &mut Vec<u8>, opcode: u8, align: u64, offset: u32) {
+  out.push(opcode);
+  uleb(out, align);
+  uleb(out, offset as u64);
+}
+
+fn put_op(out: &mut Vec<u8>, op: &Op) {
+  match *op {
+    Op::Block(result) => { out.push(0x02); put_block_type(out, result); },
+    Op::Loop(result) => { out.push(0x03); put_block_type(out, result); },
+    Op::If(result) => { out.push(0x04); put_block_type(out, result); },
+    Op::Br(depth) => { out.push(0x0c); uleb(out, depth as u64); },
+    Op::BrIf(depth) => { out.push(0x0d); uleb(out, depth as u64); },
+    Op::Call(function) => { out.push(0x10); uleb(out, function as u64); },
+    Op::LocalGet(local) => { out.push(0x20); uleb(out, local as u64); },
+    Op::LocalSet(local) => { out.push(0x21); uleb(out, local as u64); },
+    Op::LocalTee(local) => { out.push(0x22); uleb(out, local as u64); },
+    Op::GlobalGet(global) => { out.push(0x23); uleb(out, global as u64); },
+    Op::GlobalSet(global) => { out.push(0x24); uleb(out, global as u64); },
+    Op::I32Load(offset) => put_memory(out, 0x28, 2, offset),
+    Op::I64Load(offset) => put_memory(out, 0x29, 3, offset),
+    Op::F64Load(offset) => put_memory(out, 0x2b, 3, offset),
+    Op::I32Store(offset) => put_memory(out, 0x36, 2, offset),
+    Op::I64Store(offset) => put_memory(out, 0x37, 3, offset),
+    Op::F64Store(offset) => put_memory(out, 0x39, 3, offset),
+    Op::I32Store8(offset) => put_memory(out, 0x3a, 0, offset),
+    Op::I32Const(i) => { out.push(0x41); sleb(out, i as i64); },
+    Op::I64Const(i) => { out.push(0x42); sleb(out, i); },
+    Op::F64Const(r) => { out.push(0x44); out.extend_from_slice(&r.to_bits().to_le_bytes()); },
+    _ => out.push(SIMPLE.iter().find(|simple| simple.0 == *op).unwrap().1)
+  }
+}
+
+impl Module {
+  fn pages(&self) -> u32 {
+    (STACK + self.data.len() as u32).div_ceil(PAGE)
+  }
+
+  // signatures of the imports and then of the functions
+  fn signatures(&self) -> Vec<(Vec<ValType>, Option<ValType>)> {
+    IMPORTS.iter().map(|&(_, params, result)| (params.to_vec(), result))
+      .chain(self.functions.iter().map(|function| (function.params.clone(), function.result)))
+      .collect()
+  }
+
+  // the binary format, a `.wasm` file
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut out = b"\0asm".to_vec();
+    out.extend_from_slice(&1u32.to_le_bytes());
+
+    // one type per function, in the same order
+    let signatures = self.signatures();
+    let mut types = Vec::new();
+    uleb(&mut types, signatures.len() as u64);
+    for (params, result) in signatures.iter() {
+      types.push(0x60);
+      uleb(&mut types, params.len() as u64);
+      types.extend(params.iter().map(|param| param.byte()));
+      uleb(&mut types, result.iter().count() as u64);
+      types.extend(result.iter().map(|result| result.byte()));
+    }
+    put_section(&mut out, 1, types);
+
+    let mut imports = Vec::new();
+    uleb(&mut imports, IMPORTS.len() as u64);
+    for (i, &(name, _, _)) in IMPORTS.iter().enumerate() {
+      put_name(&mut imports, "env");
+      put_name(&mut imports, name);
+      imports.push(0x00);
+      uleb(&mut imports, i as u64);
+    }
+    put_section(&mut out, 2, imports);
+
+    let mut functions = Vec::new();
+    uleb(&mut functions, self.functions.len() as u64);
+    for i in 0..self.functions.len() {
+      uleb(&mut functions, (IMPORTS.len() + i) as u64);
+    }
+    put_section(&mut out, 3, functions);
+
+    let mut memory = vec![1, 0x00];
+    uleb(&mut memory, self.pages() as u64);
+    put_section(&mut out, 5, memory);
+
+    // the stack pointer
+    let mut globals = vec![1, ValType::I32.byte(), 0x01, 0x41];
+    sleb(&mut globals, STACK as i64);
+    globals.push(0x0b);
+    put_section(&mut out, 6, globals);
+
+    let mut exports = vec![2];
+    put_name(&mut exports, "memory");
+    exports.extend_from_slice(&[0x02, 0]);
+    put_name(&mut exports, "main");
+    exports.push(0x00);
+    uleb(&mut exports, self.main as u64);
+    put_section(&mut out, 7, exports);
+
+    let mut code = Vec::new();
+    uleb(&mut code, self.functions.len() as u64);
+    for function in self.functions.iter() {
+      let mut body = Vec::new();
+      uleb(&mut body, function.locals.len() as u64);
+      for local in function.locals.iter() {
+        body.push(1);
+        body.push(local.byte());
+      }
+      for op in function.body.iter() {
+        put_op(&mut body, op);
+      }
+      body.push(0x0b);
+      uleb(&mut code, body.len() as u64);
+      code.extend(body);
+    }
+    put_section(&mut out, 10, code);
+
+    let mut data = vec![1, 0x00, 0x41];
+    sleb(&mut data, STACK as i64);
+    data.push(0x0b);
+    uleb(&mut data, self.data.len() as u64);
+    data.extend_from_slice(&self.data);
+    put_section(&mut out, 11, data);
+    out
+  }
+
+  fn function_name(&self, index: u32) -> &str {
+    match IMPORTS.get(index as usize) {
+      Some(import) => import.0,
+      None => &self.functions[index as usize - IMPORTS.len()].name
+    }
+  }
+}
+
+fn signature(params: &[ValType], result: Option<ValType>) -> String {
+  let mut out = String::new();
+  if !params.is_empty() {
+    let params: Vec<String> = params.iter().map(ValType::to_string).collect();
+    out.push_str(&format!(" (param {})", params.join(" ")));
+  }
+  if let Some(result) = result {
+    out.push_str(&format!(" (result {})", result));
+  }
+  out
+}
+
+fn block_type(result: Option<ValType>) -> String {
+  result.map_or(String::new(), |result| format!(" (result {})", result))
+}
+
+fn memory(name: &str, offset: u32) -> String {
+  if offset == 0 { name.to_string() } else { format!("{} offset={}", name, offset) }
+}
+
+// the text format, printed by `pascalzim build --target wasm --emit listing`
+impl fmt::Display for Module {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, ";; generated by pascalzim from program {}", self.name)?;
+    writeln!(f, "(module")?;
+    for &(name, params, result) in IMPORTS.iter() {
+      writeln!(f, "  (import \"env\" \"{}\" (func ${}{}))", name, name, signature(params, result))?;
+    }
+    writeln!(f, "  (memory (export \"memory\") {})", self.pages())?;
+    writeln!(f, "  (global $sp (mut i32) (i32.const {}))", STACK)?;
+
+    for function in self.functions.iter() {
+      write!(f, "  (func ${}{}", function.name, signature(&function.params, function.result))?;
+      if !function.locals.is_empty() {
+        let locals: Vec<String> = function.locals.iter().map(ValType::to_string).collect();
+        write!(f, " (local {})", locals.join(" "))?;
+      }
+      writeln!(f)?;
+      let mut depth = 2;
+      for op in function.body.iter() {
+        if *op == Op::End || *op == Op::Else {
+          depth -= 1;
+        }
+        let text = match *op {
+          Op::Block(result) => format!("block{}", block_type(result)),
+          Op::Loop(result) => format!("loop{}", block_type(result)),
+          Op::If(result) => format!("if{}", block_type(result)),
+          Op::Br(depth) => format!("br {}", depth),
+          Op::BrIf(depth) => format!("br_if {}", depth),
+          Op::Call(function) => format!("call ${}", self.function_name(function)),
+          Op::LocalGet(local) => format!("local.get {}", local),
+          Op::LocalSet(local) => format!("local.set {}", local),
+          Op::LocalTee(local) => format!("local.tee {}", local),
+          Op::GlobalGet(_) => "global.get $sp".to_string(),
+          Op::GlobalSet(_) => "global.set $sp".to_string(),
+          Op::I32Load(offset) => memory("i32.load", offset),
+          Op::I64Load(offset) => memory("i64.load", offset),
+          Op::F64Load(offset) => memory("f64.load", offset),
+          Op::I32Store(offset) => memory("i32.store", offset),
+          Op::I64Store(offset) => memory("i64.store", offset),
+          Op::F64Store(offset) => memory("f64.store", offset),
+          Op::I32Store8(offset) => memory("i32.store8", offset),
+          Op::I32Const(i) => format!("i32.const {}", i),
+          Op::I64Const(i) => format!("i64.const {}", i),
+          Op::F64Const(r) if r.is_nan() => "f64.const nan".to_string(),
+          Op::F64Const(r) => format!("f64.const {:?}", r),
+          _ => SIMPLE.iter().find(|simple| simple.0 == *op).unwrap().2.to_string()
+        };
+        writeln!(f, "{}{}", "  ".repeat(depth), text)?;
+        if let Op::Block(_) | Op::Loop(_) | Op::If(_) | Op::Else = *op {
+          depth += 1;
+        }
+      }
+      writeln!(f, "  )")?;
+    }
+
+    writeln!(f, "  (export \"main\" (func ${}))", self.function_name(self.main))?;
+    let data: String = self.data.iter().map(|&b| match b {
+      b'"' | b'\\' => format!("\\{}", b as char),
+      0x20..=0x7e => (b as char).to_string(),
+      _ => format!("\\{:02x}", b)
+    }).collect();
+    writeln!(f, "  (data (i32.const {}) \"{}\")", STACK, data)?;
+    writeln!(f, ")")
+  }
+}
+
+#[test]
+fn test_wasm_program23() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program23.txt");
+  let module = generate(p1.program().unwrap()).unwrap();
+
+  let text = module.to_string();
+  assert!(text.starts_with(";; generated by pascalzim from program fatorial\n(module\n"));
+  assert!(text.contains("  (import \"env\" \"pow\" (func $pow (param f64 f64) (result f64)))\n"));
+  assert!(text.contains("  (func $p19_fat (param i32 i32) (local i32)\n"));
+  assert!(text.contains("    i32.load offset=8\n    i32.const 1\n    i32.gt_s\n    if\n"));
+  assert!(text.contains("    i64.mul\n    i32.const 9\n    call $check\n    i32.store offset=16\n"));
+  assert!(text.contains("  (export \"main\" (func $main))\n  (data (i32.const 1048576) \""));
+
+  let bytes = module.to_bytes();
+  assert!(bytes.starts_with(b"\0asm\x01\0\0\0"));
+  assert_eq!(bytes[8], 1);
+}
+
+#[test]
+fn test_wasm_leb() {
+  let mut out = Vec::new();
+  uleb(&mut out, 624485);
+  assert_eq!(out, [0xe5, 0x8e, 0x26]);
+
+  out.clear();
+  sleb(&mut out, -123456);
+  assert_eq!(out, [0xc0, 0xbb, 0x78]);
+
+  out.clear();
+  sleb(&mut out, 64);
+  assert_eq!(out, [0xc0, 0x00]);
+}
+
+#[test]
+fn test_wasm_boolean_eval() {
+  use options::Options;
+  use parser::Parser;
+
+  // the right side of `and` and `->` is only run by one branch of an `if`
+  let mut p1: Parser = Parser::with_options(Options { boolean_eval: BooleanEval::ShortCircuit, ..Options::default() });
+  p1.build_ast("files/program24.txt");
+  let text = generate(p1.program().unwrap()).unwrap().to_string();
+  assert!(text.contains("\
+    i32.ne
+    if (result i32)
+      local.get 0
+      i32.load offset=8
+      local.get 0
+      i32.load offset=16
+      i32.const 8
+      call $div
+      i32.const 1
+      i32.gt_s
+    else
+      i32.const 0
+    end
+    i32.store offset=24
+"));
+  assert!(text.contains("      i32.const 0\n      i32.eq\n    else\n      i32.const 1\n    end\n    i32.store offset=32\n"));
+
+  let mut p2: Parser = Parser::new();
+  p2.build_ast("files/program24.txt");
+  let text = generate(p2.program().unwrap()).unwrap().to_string();
+  assert!(text.contains("    i32.const 8\n    call $div\n    i32.const 1\n    i32.gt_s\n    i32.and\n    i32.store offset=24\n"));
+  assert!(text.contains("    i32.ne\n    i32.eqz\n    local.get 0\n"));
+
+  // each `and` follows the `{$B}` in force where it is written
+  let mut p3: Parser = Parser::new();
+  p3.build_ast("files/program38.txt");
+  let text = generate(p3.program().unwrap()).unwrap().to_string();
+  assert!(text.contains("      i32.const 9\n      call $div\n"));
+  assert!(text.contains("    i32.const 11\n    call $div\n    i32.const 1\n    i32.gt_s\n    i32.and\n"));
+}
+
+#[test]
+fn test_wasm_power() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program33.txt");
+  let text = generate(p1.program().unwrap()).unwrap().to_string();
+  // `2 ** 3 ** 2` is `2 ** (3 ** 2)`
+  assert!(text.contains("\
+    i32.const 2
+    i32.const 3
+    i32.const 2
+    i32.const 5
+    call $ipow
+    i32.const 5
+    call $ipow
+    i32.store offset=8
+"));
+  // `-2 ** 2` is `-(2 ** 2)`
+  assert!(text.contains("    i32.const 7\n    call $ipow\n    i64.extend_i32_s\n    i64.const -1\n    i64.mul\n"));
+  assert!(text.contains("    i32.const 9\n    call $check\n    f64.convert_i32_s\n    call $pow\n    f64.store offset=40\n"));
+  assert!(text.contains("    f64.const 4.0\n    f64.const 0.5\n    call $pow\n"));
+}
+
+#[test]
+fn test_wasm_logic() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program34.txt");
+  let text = generate(p1.program().unwrap()).unwrap().to_string();
+  // `p -> q -> r` is `not p or (not q or r)`
+  assert!(text.contains("\
+    i32.load offset=8
+    i32.eqz
+    local.get 0
+    i32.load offset=16
+    i32.eqz
+    local.get 0
+    i32.load offset=24
+    i32.or
+    i32.or
+    i32.store offset=32
+"));
+  // `xor` and `<->` compare the two sides
+  assert!(text.contains("    i32.ne\n    local.get 0\n    i32.load offset=24\n    i32.or\n    i32.store offset=48\n"));
+  assert!(text.contains("    i32.eq\n    local.get 0\n    i32.load offset=24\n    i32.eq\n    i32.store offset=56\n"));
+}
+
+#[test]
+fn test_wasm_enumerations() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program35.txt");
+  let module = generate(p1.program().unwrap()).unwrap();
+  // the names and then a table of their offsets and lengths
+  assert!(module.data.windows(16).any(|window| window == b"redgreenblue\x1c\x00\x10\x00"));
+
+  let text = module.to_string();
+  assert!(text.contains("    i32.load offset=8\n    i32.const 2\n    i32.const 13\n    call $succ\n    i32.store offset=16\n"));
+  assert!(text.contains("    i32.const 1048616\n    local.get 0\n    i32.load offset=8\n    call $write_name\n"));
+}
+
+#[test]
+fn test_wasm_subranges() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program36.txt");
+  let text = generate(p1.program().unwrap()).unwrap().to_string();
+  // `t: -5..5` starts at -5, the others at 0
+  assert!(text.contains("    i32.const 0\n    i32.store offset=24\n    local.get 0\n    i32.const -5\n    i32.store offset=32\n"));
+  assert!(text.contains("    i32.const 9\n    i32.store offset=8\n"));
+  // `e := x + 1` is checked against `0..9` under {$R+}
+  assert!(text.contains("    i32.const 16\n    call $check\n    i32.const 0\n    i32.const 9\n    i32.const 16\n    call $range\n    i32.store offset=16\n"));
+}
+
+#[test]
+fn test_wasm_sets() {
+  use parser::Parser;
+
+  let mut p1: Parser = Parser::new();
+  p1.build_ast("files/program37.txt");
+  let text = generate(p1.program().unwrap()).unwrap().to_string();
+  // `[1, 3..5]`
+  assert!(text.contains("\
+    i64.const 0
+    i32.const 1
+    i32.const 1
+    i32.const 20
+    call $bits
+    i64.or
+    i32.const 3
+    i32.const 5
+    i32.const 20
+    call $bits
+    i64.or
+    i64.store offset=8
+"));
+  // `a - b` and `[3, 4] <= a`
+  assert!(text.contains("    i64.load offset=16\n    i64.const -1\n    i64.xor\n    i64.and\n"));
+  assert!(text.contains("    i64.const -1\n    i64.xor\n    i64.and\n    i64.eqz\n"));
+  assert!(text.contains("    call $in\n    i32.eqz\n    i32.and\n    i32.store offset=72\n"));
+  assert!(text.contains("    i64.load offset=48\n    i32.const 1048616\n    call $write_set\n"));
+  assert!(text.contains("    i64.load offset=8\n    i32.const 0\n    call $write_set\n"));
+
+  let mut p2: Parser = Parser::new();
+  let program = p2.parse_str("{$R+} program p; var s: set of 1..3; n: integer; begin s := [n] end.").unwrap();
+  let text = generate(program).unwrap().to_string();
+  assert!(text.contains("    i64.or\n    i32.const 1\n    i32.const 3\n    i32.const 1\n    call $range_set\n    i64.store offset=8\n"));
+}